@@ -35,10 +35,24 @@ use std::sync::{Arc, Mutex};
 use warp::hyper::Body;
 use warp::{http::Response, http::StatusCode, Filter, Rejection, Reply};
 
+use crate::breakpoint;
+use crate::debugger;
+use crate::disasm;
+use crate::events;
+use crate::hook;
 use crate::native_bridge;
+use crate::patch;
+use crate::pointermap;
 use crate::ptrscan;
 use crate::request;
+use crate::signature;
+use crate::symbols;
+use crate::threads;
+use crate::tracer;
+use crate::unwind;
 use crate::util;
+use crate::watch;
+use crate::xref;
 
 lazy_static! {
     static ref GLOBAL_POSITIONS: RwLock<HashMap<String, Vec<(usize, String)>>> =
@@ -55,6 +69,20 @@ lazy_static! {
 pub extern "C" fn native_log(level: c_int, message: *const c_char) {
     let log_message = unsafe { CStr::from_ptr(message).to_string_lossy().into_owned() };
 
+    let level_name = match level {
+        0 => "trace",
+        1 => "debug",
+        3 => "warn",
+        4 => "error",
+        _ => "info",
+    };
+    if events::has_subscribers() {
+        events::publish(
+            events::LOG,
+            json!({ "level": level_name, "message": log_message }),
+        );
+    }
+
     match level {
         0 => trace!("{}", log_message),
         1 => debug!("{}", log_message),
@@ -72,26 +100,67 @@ pub extern "C" fn send_register_json(register_json: *const c_char, pid: i32) {
 
     let mut json_value: Value = serde_json::from_str(rust_str).unwrap();
 
+    // Hits of a "find what accesses" watchpoint are folded into its access log
+    if watch::record_hit(pid, &json_value) {
+        return;
+    }
+
     let pc_address_hex = json_value["pc"]
         .as_str()
         .ok_or("Failed to get 'pc' value")
         .unwrap();
     let pc_address = u64::from_str_radix(pc_address_hex.trim_start_matches("0x"), 16).unwrap();
 
-    let mut buffer = [0u8; 4];
-    native_bridge::read_process_memory(
-        pid,
-        pc_address as *mut libc::c_void,
-        buffer.len(),
-        &mut buffer as &mut [u8],
-    )
-    .unwrap();
-
-    let disassembled = util::disassemble(buffer.as_ptr(), buffer.len(), pc_address);
+    let layout = symbols::cached_layout(pid).ok();
+    let arch = match &layout {
+        Some(layout) => layout.arch(pid, pc_address),
+        None => disasm::detect_arch(pid, pc_address),
+    };
+    let buffer = disasm::read_code(pid, pc_address, arch.max_instruction_size());
+    let disassembled: String = disasm::decode(arch, &buffer, pc_address, 1)
+        .unwrap_or_default()
+        .iter()
+        .map(|insn| format!("{:#x}: {}\n", insn.address, insn.text()))
+        .collect();
 
     json_value["instruction"] = json!(disassembled);
 
+    // Symbolize the addresses the frontend shows next to the register dump
+    let mut keys = Vec::new();
+    let mut addresses = Vec::new();
+    for key in ["pc", "lr", "memory"] {
+        if let Some(address) = json_value[key]
+            .as_str()
+            .and_then(|value| u64::from_str_radix(value.trim_start_matches("0x"), 16).ok())
+        {
+            keys.push(key);
+            addresses.push(address);
+        }
+    }
+    if let Some(layout) = &layout {
+        let infos = layout.describe(pid, &addresses);
+        let mut address_info = serde_json::Map::new();
+        for (key, info) in keys.into_iter().zip(infos) {
+            address_info.insert(key.to_string(), info);
+        }
+        json_value["address_info"] = Value::Object(address_info);
+    }
+
+    // Unwinding reads the stack, so it is only done while a stream client asked for call stacks
+    let mut event = json_value.clone();
+    if events::wants_backtraces() {
+        match unwind::backtrace(pid, &json_value, None, unwind::Method::Auto) {
+            Ok(frames) => event[events::BACKTRACE_FIELD] = frames,
+            Err(e) => log::debug!("Failed to unwind the stack: {}", e),
+        }
+    }
+    events::publish(events::EXCEPTION, event);
+
+    // Kept for polling clients, bounded so a frontend that never polls cannot grow it forever
     let mut queue = JSON_QUEUE.lock().unwrap();
+    if queue.len() >= MAX_QUEUED_EXCEPTIONS {
+        queue.pop_front();
+    }
     queue.push_back(json_value.to_string());
 }
 
@@ -102,6 +171,7 @@ pub fn with_state(
 }
 
 const MAX_RESULTS: usize = 100_000;
+const MAX_QUEUED_EXCEPTIONS: usize = 10_000;
 
 pub async fn get_exception_info_handler() -> Result<impl warp::Reply, warp::Rejection> {
     let mut queue = JSON_QUEUE.lock().unwrap();
@@ -113,6 +183,31 @@ pub async fn get_exception_info_handler() -> Result<impl warp::Reply, warp::Reje
     Ok(warp::reply::json(&exceptions))
 }
 
+// Server-sent events; unlike polling /exceptioninfo, every client receives every event
+pub async fn event_stream_handler(
+    request: request::EventStreamRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let kinds = request.types.map(|types| {
+        types
+            .split(',')
+            .map(|kind| kind.trim().to_string())
+            .filter(|kind| !kind.is_empty())
+            .collect()
+    });
+    let subscription =
+        events::subscribe(kinds, request.capacity, request.backtrace.unwrap_or(false));
+    // The subscription lives as long as the stream, which is dropped when the client disconnects
+    let stream = futures_util::stream::unfold(subscription, |subscription| async move {
+        let event = subscription.next().await?;
+        let event = warp::sse::Event::default()
+            .id(event.id.to_string())
+            .event(event.kind)
+            .data(event.data.to_string());
+        Some((Ok::<_, std::convert::Infallible>(event), subscription))
+    });
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(stream)))
+}
+
 #[derive(Serialize)]
 struct ServerInfo {
     git_hash: String,
@@ -140,61 +235,932 @@ pub async fn server_info_handler() -> Result<impl warp::Reply, warp::Rejection>
 
     let pid = process::id();
 
-    let server_info = ServerInfo {
-        git_hash: git_hash.to_string(),
-        target_os: target_os.to_string(),
-        arch: arch.to_string(),
-        pid: pid,
-        mode: std::env::var("MEMORY_SERVER_RUNNING_MODE").unwrap_or_else(|_| "unknown".to_string()),
+    let server_info = ServerInfo {
+        git_hash: git_hash.to_string(),
+        target_os: target_os.to_string(),
+        arch: arch.to_string(),
+        pid: pid,
+        mode: std::env::var("MEMORY_SERVER_RUNNING_MODE").unwrap_or_else(|_| "unknown".to_string()),
+    };
+
+    Ok(warp::reply::json(&server_info))
+}
+
+pub async fn open_process_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    open_process: request::OpenProcessRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut pid = pid_state.lock().unwrap();
+    *pid = Some(open_process.pid);
+    // Named signatures are resolved in the background so attaching stays fast
+    let opened_pid = open_process.pid;
+    std::thread::spawn(move || signature::resolve_all(opened_pid));
+    events::watch_process_exit(opened_pid);
+    Ok(warp::reply::with_status("OK", warp::http::StatusCode::OK))
+}
+
+pub async fn resolve_addr_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    resolve_addr: request::ResolveAddrRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = pid_state.lock().unwrap();
+
+    if let Some(pid) = *pid {
+        match util::resolve_expression(pid, &resolve_addr.query) {
+            Ok(resolved_address) => {
+                let result = json!({ "address": resolved_address });
+                let result_string = result.to_string();
+                let response = Response::builder()
+                    .header("Content-Type", "application/json")
+                    .body(hyper::Body::from(result_string))
+                    .unwrap();
+                Ok(response)
+            }
+            Err(e) => {
+                let response = Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(hyper::Body::from(format!(
+                        "Failed to resolve address: {}",
+                        e
+                    )))
+                    .unwrap();
+                return Ok(response);
+            }
+        }
+    } else {
+        let response = Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(hyper::Body::from("Pid not set"))
+            .unwrap();
+        Ok(response)
+    }
+}
+
+pub async fn symbol_search_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    request: request::SymbolSearchRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = pid_state.lock().unwrap();
+
+    if let Some(pid) = *pid {
+        match native_bridge::enum_modules(pid) {
+            Ok(modules) => {
+                let result = symbols::search_symbols(
+                    pid,
+                    &modules,
+                    &request.query,
+                    request.module.as_deref(),
+                    request.limit,
+                );
+                let response = Response::builder()
+                    .header("Content-Type", "application/json")
+                    .body(hyper::Body::from(result.to_string()))
+                    .unwrap();
+                Ok(response)
+            }
+            Err(e) => {
+                let response = Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(hyper::Body::from(format!(
+                        "Failed to enumerate modules: {}",
+                        e
+                    )))
+                    .unwrap();
+                Ok(response)
+            }
+        }
+    } else {
+        let response = Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(hyper::Body::from("Pid not set"))
+            .unwrap();
+        Ok(response)
+    }
+}
+
+pub async fn address_info_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    request: request::AddressInfoRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = pid_state.lock().unwrap();
+
+    if let Some(pid) = *pid {
+        match symbols::describe_addresses(pid, &request.addresses) {
+            Ok(result) => {
+                let response = Response::builder()
+                    .header("Content-Type", "application/json")
+                    .body(hyper::Body::from(json!(result).to_string()))
+                    .unwrap();
+                Ok(response)
+            }
+            Err(e) => {
+                let response = Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(hyper::Body::from(format!(
+                        "Failed to describe addresses: {}",
+                        e
+                    )))
+                    .unwrap();
+                Ok(response)
+            }
+        }
+    } else {
+        let response = Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(hyper::Body::from("Pid not set"))
+            .unwrap();
+        Ok(response)
+    }
+}
+
+pub async fn disassemble_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    request: request::DisassembleRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = pid_state.lock().unwrap();
+
+    if let Some(pid) = *pid {
+        match disasm::disassemble(pid, &request) {
+            Ok(result) => {
+                let response = Response::builder()
+                    .header("Content-Type", "application/json")
+                    .body(hyper::Body::from(result.to_string()))
+                    .unwrap();
+                Ok(response)
+            }
+            Err(e) => {
+                let response = Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(hyper::Body::from(format!("Failed to disassemble: {}", e)))
+                    .unwrap();
+                Ok(response)
+            }
+        }
+    } else {
+        let response = Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(hyper::Body::from("Pid not set"))
+            .unwrap();
+        Ok(response)
+    }
+}
+
+pub async fn xref_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    request: request::XrefRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = pid_state.lock().unwrap();
+
+    if let Some(pid) = *pid {
+        match xref::find_xrefs(pid, &request) {
+            Ok(result) => {
+                let response = Response::builder()
+                    .header("Content-Type", "application/json")
+                    .body(hyper::Body::from(result.to_string()))
+                    .unwrap();
+                Ok(response)
+            }
+            Err(e) => {
+                let response = Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(hyper::Body::from(format!(
+                        "Failed to find references: {}",
+                        e
+                    )))
+                    .unwrap();
+                Ok(response)
+            }
+        }
+    } else {
+        let response = Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(hyper::Body::from("Pid not set"))
+            .unwrap();
+        Ok(response)
+    }
+}
+
+pub async fn signature_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    request: request::SignatureRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = pid_state.lock().unwrap();
+
+    if let Some(pid) = *pid {
+        match signature::generate_signature(pid, &request) {
+            Ok(result) => {
+                let response = Response::builder()
+                    .header("Content-Type", "application/json")
+                    .body(hyper::Body::from(result.to_string()))
+                    .unwrap();
+                Ok(response)
+            }
+            Err(e) => {
+                let response = Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(hyper::Body::from(format!(
+                        "Failed to generate signature: {}",
+                        e
+                    )))
+                    .unwrap();
+                Ok(response)
+            }
+        }
+    } else {
+        let response = Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(hyper::Body::from("Pid not set"))
+            .unwrap();
+        Ok(response)
+    }
+}
+
+pub async fn signature_list_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = *pid_state.lock().unwrap();
+    let result = signature::list_signatures(pid);
+    let response = Response::builder()
+        .header("Content-Type", "application/json")
+        .body(hyper::Body::from(result.to_string()))
+        .unwrap();
+    Ok(response)
+}
+
+pub async fn signature_add_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    request: request::SignatureDatabaseRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = *pid_state.lock().unwrap();
+    match signature::add_definitions(pid, request.signatures, request.replace) {
+        Ok(result) => {
+            let response = Response::builder()
+                .header("Content-Type", "application/json")
+                .body(hyper::Body::from(result.to_string()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            let response = Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(hyper::Body::from(format!(
+                    "Failed to add signatures: {}",
+                    e
+                )))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
+
+pub async fn signature_remove_handler(
+    request: request::SignatureRemoveRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if signature::remove_definition(&request.name) {
+        Ok(warp::reply::with_status("OK", StatusCode::OK))
+    } else {
+        Ok(warp::reply::with_status(
+            "Signature not found",
+            StatusCode::NOT_FOUND,
+        ))
+    }
+}
+
+pub async fn signature_diagnostics_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = *pid_state.lock().unwrap();
+    let result = signature::diagnostics(pid);
+    let response = Response::builder()
+        .header("Content-Type", "application/json")
+        .body(hyper::Body::from(result.to_string()))
+        .unwrap();
+    Ok(response)
+}
+
+pub async fn patch_list_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = *pid_state.lock().unwrap();
+    let result = patch::list_patches(pid);
+    let response = Response::builder()
+        .header("Content-Type", "application/json")
+        .body(hyper::Body::from(result.to_string()))
+        .unwrap();
+    Ok(response)
+}
+
+pub async fn patch_create_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    request: request::PatchCreateRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = pid_state.lock().unwrap();
+
+    if let Some(pid) = *pid {
+        match patch::create_patch(pid, &request) {
+            Ok(result) => {
+                let response = Response::builder()
+                    .header("Content-Type", "application/json")
+                    .body(hyper::Body::from(result.to_string()))
+                    .unwrap();
+                Ok(response)
+            }
+            Err(e) => {
+                let response = Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(hyper::Body::from(format!("Failed to create patch: {}", e)))
+                    .unwrap();
+                Ok(response)
+            }
+        }
+    } else {
+        let response = Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(hyper::Body::from("Pid not set"))
+            .unwrap();
+        Ok(response)
+    }
+}
+
+pub async fn patch_toggle_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    request: request::PatchToggleRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = pid_state.lock().unwrap();
+
+    if let Some(pid) = *pid {
+        match patch::set_patch_enabled(pid, &request.name, request.enabled) {
+            Ok(result) => {
+                let response = Response::builder()
+                    .header("Content-Type", "application/json")
+                    .body(hyper::Body::from(result.to_string()))
+                    .unwrap();
+                Ok(response)
+            }
+            Err(e) => {
+                let response = Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(hyper::Body::from(format!("Failed to toggle patch: {}", e)))
+                    .unwrap();
+                Ok(response)
+            }
+        }
+    } else {
+        let response = Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(hyper::Body::from("Pid not set"))
+            .unwrap();
+        Ok(response)
+    }
+}
+
+pub async fn patch_remove_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    request: request::PatchRemoveRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = pid_state.lock().unwrap();
+
+    if let Some(pid) = *pid {
+        match patch::remove_patch(pid, &request.name).map(|_| json!({ "removed": request.name })) {
+            Ok(result) => {
+                let response = Response::builder()
+                    .header("Content-Type", "application/json")
+                    .body(hyper::Body::from(result.to_string()))
+                    .unwrap();
+                Ok(response)
+            }
+            Err(e) => {
+                let response = Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(hyper::Body::from(format!("Failed to remove patch: {}", e)))
+                    .unwrap();
+                Ok(response)
+            }
+        }
+    } else {
+        let response = Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(hyper::Body::from("Pid not set"))
+            .unwrap();
+        Ok(response)
+    }
+}
+
+pub async fn hook_list_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = *pid_state.lock().unwrap();
+    let result = hook::list_hooks(pid);
+    let response = Response::builder()
+        .header("Content-Type", "application/json")
+        .body(hyper::Body::from(result.to_string()))
+        .unwrap();
+    Ok(response)
+}
+
+pub async fn hook_install_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    request: request::HookInstallRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    // Hooks are only removed on a graceful shutdown, which an embedded server never has
+    let mode =
+        std::env::var("MEMORY_SERVER_RUNNING_MODE").unwrap_or_else(|_| "unknown".to_string());
+    if mode == "embedded" {
+        let response = Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(hyper::Body::from(
+                "Hooks are not supported in embedded mode",
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    let pid = pid_state.lock().unwrap();
+
+    if let Some(pid) = *pid {
+        match hook::install_hook(pid, &request) {
+            Ok(result) => {
+                let response = Response::builder()
+                    .header("Content-Type", "application/json")
+                    .body(hyper::Body::from(result.to_string()))
+                    .unwrap();
+                Ok(response)
+            }
+            Err(e) => {
+                let response = Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(hyper::Body::from(format!("Failed to install hook: {}", e)))
+                    .unwrap();
+                Ok(response)
+            }
+        }
+    } else {
+        let response = Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(hyper::Body::from("Pid not set"))
+            .unwrap();
+        Ok(response)
+    }
+}
+
+pub async fn hook_remove_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    request: request::HookRemoveRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = pid_state.lock().unwrap();
+
+    if let Some(pid) = *pid {
+        match hook::remove_hook(pid, &request.name).map(|_| json!({ "removed": request.name })) {
+            Ok(result) => {
+                let response = Response::builder()
+                    .header("Content-Type", "application/json")
+                    .body(hyper::Body::from(result.to_string()))
+                    .unwrap();
+                Ok(response)
+            }
+            Err(e) => {
+                let response = Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(hyper::Body::from(format!("Failed to remove hook: {}", e)))
+                    .unwrap();
+                Ok(response)
+            }
+        }
+    } else {
+        let response = Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(hyper::Body::from("Pid not set"))
+            .unwrap();
+        Ok(response)
+    }
+}
+
+pub async fn watch_accesses_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    request: request::WatchAccessesRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = *pid_state.lock().unwrap();
+    match watch::list_accesses(pid, request.address) {
+        Ok(result) => {
+            let response = Response::builder()
+                .header("Content-Type", "application/json")
+                .body(hyper::Body::from(result.to_string()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            let response = Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(hyper::Body::from(e))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
+
+pub async fn clear_watch_accesses_handler(
+    request: request::ClearWatchAccessesRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match watch::clear_accesses(request.address).map(|_| json!({ "cleared": request.address })) {
+        Ok(result) => {
+            let response = Response::builder()
+                .header("Content-Type", "application/json")
+                .body(hyper::Body::from(result.to_string()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            let response = Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(hyper::Body::from(e))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
+
+pub async fn breakpoint_trace_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    request: request::BreakpointTraceQuery,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = *pid_state.lock().unwrap();
+    let result = match pid {
+        Some(pid) => breakpoint::list_traces(pid, request.address),
+        None => Err("Pid not set".to_string()),
+    };
+    match result {
+        Ok(result) => {
+            let response = Response::builder()
+                .header("Content-Type", "application/json")
+                .body(hyper::Body::from(result.to_string()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            let response = Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(hyper::Body::from(e))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
+
+pub async fn clear_breakpoint_trace_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    request: request::ClearBreakpointTraceRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = *pid_state.lock().unwrap();
+    let result = match pid {
+        Some(pid) => breakpoint::clear_trace(pid, request.address),
+        None => Err("Pid not set".to_string()),
+    };
+    match result.map(|_| json!({ "cleared": request.address })) {
+        Ok(result) => {
+            let response = Response::builder()
+                .header("Content-Type", "application/json")
+                .body(hyper::Body::from(result.to_string()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            let response = Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(hyper::Body::from(e))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
+
+pub async fn debugger_threads_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = *pid_state.lock().unwrap();
+    let result = match pid {
+        Some(pid) => debugger::stopped_threads(pid),
+        None => Err("Pid not set".to_string()),
+    };
+    match result {
+        Ok(result) => {
+            let response = Response::builder()
+                .header("Content-Type", "application/json")
+                .body(hyper::Body::from(result.to_string()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            let response = Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(hyper::Body::from(e))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
+
+pub async fn threads_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = *pid_state.lock().unwrap();
+    let result = match pid {
+        Some(pid) => threads::list(pid),
+        None => Err("Pid not set".to_string()),
+    };
+    match result {
+        Ok(result) => {
+            let response = Response::builder()
+                .header("Content-Type", "application/json")
+                .body(hyper::Body::from(result.to_string()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            let response = Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(hyper::Body::from(e))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
+
+pub async fn suspend_thread_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    request: request::ThreadRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = *pid_state.lock().unwrap();
+    let result = match pid {
+        Some(pid) => {
+            threads::suspend(pid, request.tid).map(|_| json!({ "suspended": request.tid }))
+        }
+        None => Err("Pid not set".to_string()),
+    };
+    match result {
+        Ok(result) => {
+            let response = Response::builder()
+                .header("Content-Type", "application/json")
+                .body(hyper::Body::from(result.to_string()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            let response = Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(hyper::Body::from(e))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
+
+pub async fn resume_thread_handler(
+    request: request::ThreadRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match threads::resume(request.tid).map(|_| json!({ "resumed": request.tid })) {
+        Ok(result) => {
+            let response = Response::builder()
+                .header("Content-Type", "application/json")
+                .body(hyper::Body::from(result.to_string()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            let response = Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(hyper::Body::from(e))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
+
+pub async fn debugger_continue_handler(
+    request: request::DebuggerContinueRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let tid = request.tid.unwrap_or(0);
+    match debugger::continue_thread(tid).map(|_| json!({ "continued": tid })) {
+        Ok(result) => {
+            let response = Response::builder()
+                .header("Content-Type", "application/json")
+                .body(hyper::Body::from(result.to_string()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            let response = Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(hyper::Body::from(e))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
+
+pub async fn debugger_step_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    request: request::DebuggerStepRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = *pid_state.lock().unwrap();
+    // Answer with the stop states so the caller sees where short steps landed
+    let result = match pid {
+        Some(pid) => debugger::StepMode::parse(&request.mode)
+            .and_then(|mode| debugger::step(request.tid, mode))
+            .and_then(|_| debugger::stopped_threads(pid)),
+        None => Err("Pid not set".to_string()),
+    };
+    match result {
+        Ok(result) => {
+            let response = Response::builder()
+                .header("Content-Type", "application/json")
+                .body(hyper::Body::from(result.to_string()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            let response = Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(hyper::Body::from(e))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
+
+pub async fn debugger_trace_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    request: request::DebuggerTraceRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = *pid_state.lock().unwrap();
+    let result = match pid {
+        Some(pid) => tracer::record(
+            pid,
+            request.tid,
+            &tracer::TraceSpec {
+                max_instructions: request.max_instructions,
+                until: request.until,
+                until_return: request.until_return.unwrap_or(false),
+                memory: request.memory.unwrap_or(true),
+            },
+        ),
+        None => Err("Pid not set".to_string()),
+    };
+    match result {
+        Ok(result) => {
+            let response = Response::builder()
+                .header("Content-Type", "application/json")
+                .body(hyper::Body::from(result.to_string()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            let response = Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(hyper::Body::from(e))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
+
+// Without an id, the recorded traces are listed
+pub async fn get_debugger_trace_handler(
+    request: request::DebuggerTraceQuery,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let Some(id) = request.id else {
+        let response = Response::builder()
+            .header("Content-Type", "application/json")
+            .body(hyper::Body::from(tracer::list_traces().to_string()))
+            .unwrap();
+        return Ok(response);
     };
+    match tracer::export(id, request.format.as_deref().unwrap_or("json")) {
+        Ok(tracer::Export::Json(result)) => {
+            let response = Response::builder()
+                .header("Content-Type", "application/json")
+                .body(hyper::Body::from(result.to_string()))
+                .unwrap();
+            Ok(response)
+        }
+        Ok(tracer::Export::Text(text)) => {
+            let response = Response::builder()
+                .header("Content-Type", "text/plain")
+                .body(hyper::Body::from(text))
+                .unwrap();
+            Ok(response)
+        }
+        Ok(tracer::Export::Binary(data)) => {
+            let response = Response::builder()
+                .header("Content-Type", "application/octet-stream")
+                .header(
+                    "Content-Disposition",
+                    format!("attachment; filename=\"trace_{}.drcov\"", id),
+                )
+                .body(hyper::Body::from(data))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            let response = Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(hyper::Body::from(e))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
 
-    Ok(warp::reply::json(&server_info))
+pub async fn delete_debugger_trace_handler(
+    request: request::DeleteDebuggerTraceRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match tracer::delete_trace(request.id).map(|_| json!({ "deleted": request.id })) {
+        Ok(result) => {
+            let response = Response::builder()
+                .header("Content-Type", "application/json")
+                .body(hyper::Body::from(result.to_string()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            let response = Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(hyper::Body::from(e))
+                .unwrap();
+            Ok(response)
+        }
+    }
 }
 
-pub async fn open_process_handler(
-    pid_state: Arc<Mutex<Option<i32>>>,
-    open_process: request::OpenProcessRequest,
+pub async fn debugger_registers_handler(
+    request: request::DebuggerRegistersRequest,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let mut pid = pid_state.lock().unwrap();
-    *pid = Some(open_process.pid);
-    Ok(warp::reply::with_status("OK", warp::http::StatusCode::OK))
+    match debugger::registers(request.tid) {
+        Ok(result) => {
+            let response = Response::builder()
+                .header("Content-Type", "application/json")
+                .body(hyper::Body::from(result.to_string()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            let response = Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(hyper::Body::from(e))
+                .unwrap();
+            Ok(response)
+        }
+    }
 }
 
-pub async fn resolve_addr_handler(
+pub async fn debugger_backtrace_handler(
     pid_state: Arc<Mutex<Option<i32>>>,
-    resolve_addr: request::ResolveAddrRequest,
+    request: request::DebuggerBacktraceRequest,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let pid = pid_state.lock().unwrap();
+    let pid = *pid_state.lock().unwrap();
+    let result = match pid {
+        Some(pid) => request
+            .method
+            .as_deref()
+            .map_or(Ok(unwind::Method::Auto), unwind::Method::parse)
+            .and_then(|method| debugger::backtrace(pid, request.tid, request.max_frames, method)),
+        None => Err("Pid not set".to_string()),
+    };
+    match result {
+        Ok(result) => {
+            let response = Response::builder()
+                .header("Content-Type", "application/json")
+                .body(hyper::Body::from(result.to_string()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            let response = Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(hyper::Body::from(e))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
 
-    if let Some(pid) = *pid {
-        let modules = native_bridge::enum_modules(pid).unwrap();
-        match util::resolve_symbolic_address(pid, &resolve_addr.query, &modules) {
-            Ok(resolved_address) => {
-                let result = json!({ "address": resolved_address });
-                let result_string = result.to_string();
-                let response = Response::builder()
-                    .header("Content-Type", "application/json")
-                    .body(hyper::Body::from(result_string))
-                    .unwrap();
-                Ok(response)
-            }
-            Err(e) => {
-                let response = Response::builder()
-                    .status(StatusCode::BAD_REQUEST)
-                    .body(hyper::Body::from(format!(
-                        "Failed to resolve address: {}",
-                        e
-                    )))
-                    .unwrap();
-                return Ok(response);
-            }
+pub async fn set_debugger_registers_handler(
+    request: request::SetDebuggerRegistersRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match debugger::set_registers(request.tid, &request.registers) {
+        Ok(result) => {
+            let response = Response::builder()
+                .header("Content-Type", "application/json")
+                .body(hyper::Body::from(result.to_string()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            let response = Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(hyper::Body::from(e))
+                .unwrap();
+            Ok(response)
         }
-    } else {
-        let response = Response::builder()
-            .status(StatusCode::BAD_REQUEST)
-            .body(hyper::Body::from("Pid not set"))
-            .unwrap();
-        Ok(response)
     }
 }
 
@@ -336,13 +1302,11 @@ pub async fn memory_scan_handler(
 ) -> Result<impl warp::Reply, warp::Rejection> {
     let pid = pid_state.lock().unwrap();
 
-    let mut is_suspend_success: bool = false;
+    let mut frozen: Option<threads::Freeze> = None;
     let do_suspend = scan_request.do_suspend;
     if let Some(pid) = *pid {
         if do_suspend {
-            unsafe {
-                is_suspend_success = native_bridge::suspend_process(pid);
-            }
+            frozen = threads::freeze(pid, scan_request.suspend_threads.as_deref());
         }
         // Clear global_positions for the given scan_id
         {
@@ -572,10 +1536,8 @@ pub async fn memory_scan_handler(
             })
             .collect();
         let mut do_play = GLOBAL_PROCESS_STATE.write().unwrap();
-        if do_suspend && is_suspend_success && *do_play {
-            unsafe {
-                native_bridge::resume_process(pid);
-            }
+        if let Some(frozen) = &frozen {
+            threads::thaw(pid, frozen, *do_play);
         }
         // println!("{}", found_count.load(Ordering::SeqCst));
 
@@ -677,7 +1639,7 @@ pub async fn memory_filter_handler(
 ) -> Result<impl warp::Reply, warp::Rejection> {
     let pid = pid_state.lock().unwrap();
 
-    let mut is_suspend_success: bool = false;
+    let mut frozen: Option<threads::Freeze> = None;
     let do_suspend = filter_request.do_suspend;
     if let Some(pid) = *pid {
         let mut new_positions = Vec::new();
@@ -712,9 +1674,7 @@ pub async fn memory_filter_handler(
         // unknown search
         if scan_option.find_type == "unknown" {
             if do_suspend {
-                unsafe {
-                    is_suspend_success = native_bridge::suspend_process(pid);
-                }
+                frozen = threads::freeze(pid, filter_request.suspend_threads.as_deref());
             }
 
             let paths = match fs::read_dir(&scan_folder_path) {
@@ -995,9 +1955,7 @@ pub async fn memory_filter_handler(
             new_positions.par_sort_unstable_by_key(|&(address, _)| address);
         } else if let Some(positions) = global_positions.get(&filter_request.scan_id) {
             if do_suspend {
-                unsafe {
-                    is_suspend_success = native_bridge::suspend_process(pid);
-                }
+                frozen = threads::freeze(pid, filter_request.suspend_threads.as_deref());
             }
             let results: Result<Vec<_>, _> = positions
                 .par_iter()
@@ -1214,10 +2172,8 @@ pub async fn memory_filter_handler(
                 }
                 Err(response) => {
                     let mut do_play = GLOBAL_PROCESS_STATE.write().unwrap();
-                    if do_suspend && is_suspend_success && *do_play {
-                        unsafe {
-                            native_bridge::resume_process(pid);
-                        }
+                    if let Some(frozen) = &frozen {
+                        threads::thaw(pid, frozen, *do_play);
                     }
                     return Ok(response);
                 }
@@ -1230,10 +2186,8 @@ pub async fn memory_filter_handler(
             return Ok(response);
         }
         let mut do_play = GLOBAL_PROCESS_STATE.write().unwrap();
-        if do_suspend && is_suspend_success && *do_play {
-            unsafe {
-                native_bridge::resume_process(pid);
-            }
+        if let Some(frozen) = &frozen {
+            threads::thaw(pid, frozen, *do_play);
         }
         global_positions.insert(filter_request.scan_id.clone(), new_positions.clone());
 
@@ -1585,6 +2539,17 @@ pub async fn set_watchpoint_handler(
 
     if let Some(pid) = *pid {
         let _type = match watchpoint._type.as_str() {
+            // x86 debug registers cannot trap reads without also trapping writes
+            "r" if cfg!(all(target_os = "linux", target_arch = "x86_64")) => {
+                return Ok(warp::reply::with_status(
+                    warp::reply::json(&request::SetWatchPointResponse {
+                        success: false,
+                        message: "Read-only watchpoints are not supported on x86_64, use \"a\""
+                            .to_string(),
+                    }),
+                    StatusCode::BAD_REQUEST,
+                ))
+            }
             "r" => 1,
             "w" => 2,
             "a" => 3,
@@ -1598,7 +2563,33 @@ pub async fn set_watchpoint_handler(
                 ))
             }
         };
-        let result = native_bridge::set_watchpoint(pid, watchpoint.address, watchpoint.size, _type);
+        // The log exists before the watchpoint so that its first hits are folded too
+        if watchpoint.aggregate {
+            if let Err(e) = watch::start_session(
+                watchpoint.address as u64,
+                watchpoint.size as u64,
+                watchpoint.stop,
+            ) {
+                return Ok(warp::reply::with_status(
+                    warp::reply::json(&request::SetWatchPointResponse {
+                        success: false,
+                        message: e,
+                    }),
+                    StatusCode::BAD_REQUEST,
+                ));
+            }
+        }
+        let result = native_bridge::set_watchpoint(
+            pid,
+            watchpoint.address,
+            watchpoint.size,
+            _type,
+            watchpoint.stop,
+        );
+        if result.is_err() && watchpoint.aggregate {
+            watch::end_session(watchpoint.address as u64);
+            let _ = watch::clear_accesses(watchpoint.address as u64);
+        }
 
         let ret = match result {
             Ok(_) => Ok(warp::reply::with_status(
@@ -1636,6 +2627,9 @@ pub async fn remove_watchpoint_handler(
 
     if let Some(_pid) = *pid {
         let result = native_bridge::remove_watchpoint(watchpoint.address);
+        if result.is_ok() {
+            watch::end_session(watchpoint.address as u64);
+        }
 
         let ret = match result {
             Ok(_) => Ok(warp::reply::with_status(
@@ -1671,7 +2665,39 @@ pub async fn set_breakpoint_handler(
 ) -> Result<impl warp::Reply, warp::Rejection> {
     let pid = pid_state.lock().unwrap();
     if let Some(pid) = *pid {
-        let result = native_bridge::set_breakpoint(pid, breakpoint.address, breakpoint.hit_count);
+        let conditional = breakpoint.condition.is_some() || breakpoint.trace.is_some();
+        if conditional {
+            let trace = breakpoint.trace.map(|trace| breakpoint::TraceSpec {
+                registers: trace.registers,
+                expressions: trace.expressions,
+                capacity: trace.capacity,
+            });
+            if let Err(e) = breakpoint::register(
+                pid,
+                breakpoint.address as u64,
+                breakpoint.condition.as_deref(),
+                trace,
+                breakpoint.stop,
+            ) {
+                return Ok(warp::reply::with_status(
+                    warp::reply::json(&request::SetBreakPointResponse {
+                        success: false,
+                        message: format!("Failed to set breakpoint. Error: {}", e),
+                    }),
+                    StatusCode::BAD_REQUEST,
+                ));
+            }
+        }
+        let mode = match (conditional, breakpoint.stop) {
+            (true, _) => 2,
+            (false, true) => 1,
+            (false, false) => 0,
+        };
+        let result =
+            native_bridge::set_breakpoint(pid, breakpoint.address, breakpoint.hit_count, mode);
+        if result.is_err() && conditional {
+            breakpoint::unregister(pid, breakpoint.address as u64);
+        }
         let ret = match result {
             Ok(_) => Ok(warp::reply::with_status(
                 warp::reply::json(&request::SetBreakPointResponse {
@@ -1705,16 +2731,19 @@ pub async fn remove_breakpoint_handler(
     breakpoint: request::RemoveBreakPointRequest,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     let pid = pid_state.lock().unwrap();
-    if let Some(_pid) = *pid {
+    if let Some(pid) = *pid {
         let result = native_bridge::remove_breakpoint(breakpoint.address);
         let ret = match result {
-            Ok(_) => Ok(warp::reply::with_status(
-                warp::reply::json(&request::RemoveBreakPointResponse {
-                    success: true,
-                    message: "Breakpoint removed successfully".to_string(),
-                }),
-                StatusCode::OK,
-            )),
+            Ok(_) => {
+                breakpoint::deactivate(pid, breakpoint.address as u64);
+                Ok(warp::reply::with_status(
+                    warp::reply::json(&request::RemoveBreakPointResponse {
+                        success: true,
+                        message: "Breakpoint removed successfully".to_string(),
+                    }),
+                    StatusCode::OK,
+                ))
+            }
             Err(e) => Ok(warp::reply::with_status(
                 warp::reply::json(&request::RemoveBreakPointResponse {
                     success: false,
@@ -1790,7 +2819,7 @@ pub async fn pointermap_generate_handler(
     let pid = pid_state.lock().unwrap();
 
     if let Some(pid) = *pid {
-        let result = ptrscan::generate_pointermap(pid);
+        let result = ptrscan::generate_pointermap(pid, &request.options);
 
         match result {
             Ok(binary_data) => {
@@ -1817,3 +2846,153 @@ pub async fn pointermap_generate_handler(
         Ok(response)
     }
 }
+
+pub async fn pointer_referrers_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    request: request::PointerReferrersRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = pid_state.lock().unwrap();
+
+    if let Some(pid) = *pid {
+        match ptrscan::find_referrers(pid, &request) {
+            Ok(result) => {
+                let response = Response::builder()
+                    .header("Content-Type", "application/json")
+                    .body(hyper::Body::from(result.to_string()))
+                    .unwrap();
+                Ok(response)
+            }
+            Err(e) => {
+                let response = Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(hyper::Body::from(format!(
+                        "Failed to find referrers: {}",
+                        e
+                    )))
+                    .unwrap();
+                Ok(response)
+            }
+        }
+    } else {
+        let response = Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(hyper::Body::from("Pid not set"))
+            .unwrap();
+        Ok(response)
+    }
+}
+
+pub async fn object_graph_handler(
+    pid_state: Arc<Mutex<Option<i32>>>,
+    request: request::ObjectGraphRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pid = pid_state.lock().unwrap();
+
+    if let Some(pid) = *pid {
+        match ptrscan::explore_object_graph(pid, &request) {
+            Ok(graph) => {
+                let response = if request.format == "dot" {
+                    Response::builder()
+                        .header("Content-Type", "text/vnd.graphviz")
+                        .body(hyper::Body::from(ptrscan::object_graph_to_dot(&graph)))
+                        .unwrap()
+                } else {
+                    Response::builder()
+                        .header("Content-Type", "application/json")
+                        .body(hyper::Body::from(graph.to_string()))
+                        .unwrap()
+                };
+                Ok(response)
+            }
+            Err(e) => {
+                let response = Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(hyper::Body::from(format!(
+                        "Failed to explore object graph: {}",
+                        e
+                    )))
+                    .unwrap();
+                Ok(response)
+            }
+        }
+    } else {
+        let response = Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(hyper::Body::from("Pid not set"))
+            .unwrap();
+        Ok(response)
+    }
+}
+
+pub async fn pointermap_load_handler(
+    request: request::PointerMapLoadRequest,
+    body: warp::hyper::body::Bytes,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match pointermap::PointerMap::decode(&body) {
+        Ok(map) => {
+            let map = pointermap::store_loaded(&request.name, map);
+            let mut summary = map.summary();
+            summary["name"] = json!(request.name);
+            let response = Response::builder()
+                .header("Content-Type", "application/json")
+                .body(hyper::Body::from(summary.to_string()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            let response = Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(hyper::Body::from(format!(
+                    "Failed to load pointermap: {}",
+                    e
+                )))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
+
+pub async fn pointermap_loaded_handler() -> Result<impl warp::Reply, warp::Rejection> {
+    let result = json!(pointermap::loaded_summaries());
+    let response = Response::builder()
+        .header("Content-Type", "application/json")
+        .body(hyper::Body::from(result.to_string()))
+        .unwrap();
+    Ok(response)
+}
+
+pub async fn pointermap_unload_handler(
+    request: request::PointerMapUnloadRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if pointermap::unload(&request.name) {
+        Ok(warp::reply::with_status("OK", StatusCode::OK))
+    } else {
+        Ok(warp::reply::with_status(
+            "Pointermap not loaded",
+            StatusCode::NOT_FOUND,
+        ))
+    }
+}
+
+pub async fn pointermap_lookup_handler(
+    request: request::PointerMapLookupRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if let Some(map) = pointermap::get_loaded(&request.name) {
+        let referrers = map.referrers(request.address);
+        let result = json!({"found": referrers.len(), "referrers": referrers});
+        let response = Response::builder()
+            .header("Content-Type", "application/json")
+            .body(hyper::Body::from(result.to_string()))
+            .unwrap();
+        Ok(response)
+    } else {
+        let response = Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(hyper::Body::from(format!(
+                "Pointermap {} is not loaded",
+                request.name
+            )))
+            .unwrap();
+        Ok(response)
+    }
+}
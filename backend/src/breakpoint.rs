@@ -0,0 +1,377 @@
+use crate::disasm::Arch;
+use crate::expression::{self, EvalContext, Expr};
+use crate::native_bridge;
+use crate::util::{self, ProcessContext};
+use crate::watch;
+use lazy_static::lazy_static;
+use libc::c_int;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, VecDeque};
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::sync::{Arc, RwLock};
+
+// Verdicts returned to the native debugger for a hit of a conditional breakpoint
+const CONTINUE: c_int = 0;
+const STOP: c_int = 1;
+const REPORT: c_int = 2;
+
+const DEFAULT_TRACE_CAPACITY: usize = 1024;
+const MAX_TRACE_CAPACITY: usize = 65536;
+
+lazy_static! {
+    // Keyed by (pid, address), since addresses only mean something within one process
+    static ref BREAKPOINTS: RwLock<BTreeMap<(i32, u64), Breakpoint>> =
+        RwLock::new(BTreeMap::new());
+}
+
+// A breakpoint whose hits are filtered by a condition and/or recorded into a trace buffer
+struct Breakpoint {
+    condition: Option<(String, Arc<Expr>)>,
+    trace: Option<Trace>,
+    stop: bool,
+    active: bool,
+    // Modules at the time the breakpoint was set, for `module!symbol` and module names
+    modules: Arc<Vec<Value>>,
+    hits: u64,
+    condition_errors: u64,
+    last_error: Option<String>,
+}
+
+struct Trace {
+    registers: Vec<String>,
+    expressions: Vec<(String, Arc<Expr>)>,
+    capacity: usize,
+    entries: VecDeque<Value>,
+    dropped: u64,
+}
+
+// What a tracepoint records at every hit
+pub struct TraceSpec {
+    pub registers: Vec<String>,
+    pub expressions: Vec<String>,
+    pub capacity: Option<usize>,
+}
+
+// Registers of the hitting thread shadow module names; memory is read from the target
+struct HitContext<'a> {
+    registers: &'a Value,
+    process: ProcessContext<'a>,
+}
+
+impl EvalContext for HitContext<'_> {
+    fn resolve_identifier(&self, name: &str) -> Option<u64> {
+        watch::register_value(Arch::host(), self.registers, &name.to_lowercase())
+            .or_else(|| self.process.resolve_identifier(name))
+    }
+
+    fn read_memory(&self, address: u64, width: usize) -> Result<u64, String> {
+        self.process.read_memory(address, width)
+    }
+}
+
+fn parse_expression(kind: &str, text: &str, names: &[String]) -> Result<Arc<Expr>, String> {
+    expression::parse(text, names)
+        .map(Arc::new)
+        .map_err(|e| format!("Invalid {} '{}': {}", kind, text, e))
+}
+
+// Parses the condition and trace expressions up front, so a typo is reported when the
+// breakpoint is set rather than at its first hit
+pub fn register(
+    pid: i32,
+    address: u64,
+    condition: Option<&str>,
+    trace: Option<TraceSpec>,
+    stop: bool,
+) -> Result<(), String> {
+    let modules = native_bridge::enum_modules(pid).unwrap_or_default();
+    let names = util::module_file_names(&modules);
+    let condition = condition
+        .map(|text| {
+            parse_expression("condition", text, &names).map(|expr| (text.to_string(), expr))
+        })
+        .transpose()?;
+    let trace = trace
+        .map(|spec| {
+            let expressions = spec
+                .expressions
+                .iter()
+                .map(|text| {
+                    parse_expression("trace expression", text, &names)
+                        .map(|expr| (text.clone(), expr))
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            Ok::<_, String>(Trace {
+                registers: spec.registers.iter().map(|r| r.to_lowercase()).collect(),
+                expressions,
+                capacity: spec
+                    .capacity
+                    .unwrap_or(DEFAULT_TRACE_CAPACITY)
+                    .clamp(1, MAX_TRACE_CAPACITY),
+                entries: VecDeque::new(),
+                dropped: 0,
+            })
+        })
+        .transpose()?;
+
+    let mut breakpoints = BREAKPOINTS.write().unwrap();
+    if breakpoints.get(&(pid, address)).is_some_and(|b| b.active) {
+        return Err(format!("Breakpoint already set at 0x{:x}", address));
+    }
+    breakpoints.insert(
+        (pid, address),
+        Breakpoint {
+            condition,
+            trace,
+            stop,
+            active: true,
+            modules: Arc::new(modules),
+            hits: 0,
+            condition_errors: 0,
+            last_error: None,
+        },
+    );
+    Ok(())
+}
+
+// Traces stay queryable after the breakpoint is gone, until they are cleared
+pub fn deactivate(pid: i32, address: u64) {
+    let mut breakpoints = BREAKPOINTS.write().unwrap();
+    if let Some(breakpoint) = breakpoints.get_mut(&(pid, address)) {
+        breakpoint.active = false;
+    }
+}
+
+// Forgets a breakpoint whose native counterpart could not be set
+pub fn unregister(pid: i32, address: u64) {
+    BREAKPOINTS.write().unwrap().remove(&(pid, address));
+}
+
+// Called by the native debugger when it detaches from `pid`, taking its breakpoints with it,
+// e.g. when it is replaced by a debugger for another process
+#[no_mangle]
+pub extern "C" fn debugger_detached(pid: i32) {
+    let mut breakpoints = BREAKPOINTS.write().unwrap();
+    for (_, breakpoint) in breakpoints.range_mut((pid, 0)..=(pid, u64::MAX)) {
+        breakpoint.active = false;
+    }
+}
+
+// Called by the native debugger on its own thread for every hit of a conditional breakpoint.
+// Nothing here calls back into the debugger, which is blocked until the verdict is returned.
+#[no_mangle]
+pub extern "C" fn evaluate_breakpoint_hit(
+    pid: i32,
+    tid: u64,
+    address: u64,
+    register_json: *const c_char,
+) -> c_int {
+    let registers: Value = match unsafe { CStr::from_ptr(register_json) }
+        .to_str()
+        .ok()
+        .and_then(|text| serde_json::from_str(text).ok())
+    {
+        Some(registers) => registers,
+        None => return STOP,
+    };
+
+    // Evaluated without the lock held, since memory reads may be slow
+    let (condition, expressions, modules) = {
+        let breakpoints = BREAKPOINTS.read().unwrap();
+        let Some(breakpoint) = breakpoints.get(&(pid, address)) else {
+            return STOP;
+        };
+        (
+            breakpoint.condition.as_ref().map(|(_, expr)| expr.clone()),
+            breakpoint
+                .trace
+                .as_ref()
+                .map(|trace| trace.expressions.clone()),
+            breakpoint.modules.clone(),
+        )
+    };
+    let context = HitContext {
+        registers: &registers,
+        process: ProcessContext {
+            pid,
+            modules: &modules,
+        },
+    };
+
+    // A condition that cannot be evaluated counts as true, so a broken condition is noticed
+    let condition = condition.map(|expr| expr.evaluate(&context));
+    if let Some(Ok(0)) = condition {
+        return CONTINUE;
+    }
+    let values: Option<Vec<(String, Result<u64, String>)>> = expressions.map(|expressions| {
+        expressions
+            .iter()
+            .map(|(text, expr)| (text.clone(), expr.evaluate(&context)))
+            .collect()
+    });
+
+    let mut breakpoints = BREAKPOINTS.write().unwrap();
+    let Some(breakpoint) = breakpoints.get_mut(&(pid, address)) else {
+        return STOP;
+    };
+    breakpoint.hits += 1;
+    if let Some(Err(e)) = condition {
+        breakpoint.condition_errors += 1;
+        breakpoint.last_error = Some(e);
+    }
+    let hit = breakpoint.hits;
+    match (&mut breakpoint.trace, values) {
+        (Some(trace), Some(values)) => {
+            let recorded: serde_json::Map<String, Value> = trace
+                .registers
+                .iter()
+                .map(|name| {
+                    let value = registers.get(name).cloned().or_else(|| {
+                        watch::register_value(Arch::host(), &registers, name)
+                            .map(|value| json!(format!("0x{:016X}", value)))
+                    });
+                    (name.clone(), value.unwrap_or(Value::Null))
+                })
+                .collect();
+            let expressions: Vec<Value> = values
+                .into_iter()
+                .map(|(text, value)| match value {
+                    Ok(value) => json!({ "expression": text, "value": value }),
+                    Err(e) => json!({ "expression": text, "error": e }),
+                })
+                .collect();
+            if trace.entries.len() >= trace.capacity {
+                trace.entries.pop_front();
+                trace.dropped += 1;
+            }
+            trace.entries.push_back(json!({
+                "hit": hit,
+                "tid": tid,
+                "pc": address,
+                "registers": recorded,
+                "expressions": expressions
+            }));
+            if breakpoint.stop {
+                STOP
+            } else {
+                CONTINUE
+            }
+        }
+        _ => {
+            if breakpoint.stop {
+                STOP
+            } else {
+                REPORT
+            }
+        }
+    }
+}
+
+pub fn list_traces(pid: i32, address: Option<u64>) -> Result<Value, String> {
+    let breakpoints = BREAKPOINTS.read().unwrap();
+    if let Some(address) = address {
+        if !breakpoints.contains_key(&(pid, address)) {
+            return Err(format!("No conditional breakpoint at 0x{:x}", address));
+        }
+    }
+
+    let entries: Vec<Value> = breakpoints
+        .range((pid, 0)..=(pid, u64::MAX))
+        .filter(|((_, breakpoint_address), _)| address.is_none_or(|a| a == *breakpoint_address))
+        .map(|((_, address), breakpoint)| {
+            json!({
+                "address": address,
+                "condition": breakpoint.condition.as_ref().map(|(text, _)| text),
+                "stop": breakpoint.stop,
+                "active": breakpoint.active,
+                "hits": breakpoint.hits,
+                "condition_errors": breakpoint.condition_errors,
+                "last_error": breakpoint.last_error,
+                "trace": breakpoint.trace.as_ref().map(|trace| json!({
+                    "registers": trace.registers,
+                    "expressions": trace
+                        .expressions
+                        .iter()
+                        .map(|(text, _)| text)
+                        .collect::<Vec<_>>(),
+                    "capacity": trace.capacity,
+                    "dropped": trace.dropped,
+                    "entries": trace.entries
+                }))
+            })
+        })
+        .collect();
+    Ok(json!({ "breakpoints": entries }))
+}
+
+pub fn clear_trace(pid: i32, address: u64) -> Result<(), String> {
+    let mut breakpoints = BREAKPOINTS.write().unwrap();
+    let breakpoint = breakpoints
+        .get_mut(&(pid, address))
+        .ok_or_else(|| format!("No conditional breakpoint at 0x{:x}", address))?;
+    if breakpoint.active {
+        breakpoint.hits = 0;
+        breakpoint.condition_errors = 0;
+        breakpoint.last_error = None;
+        if let Some(trace) = &mut breakpoint.trace {
+            trace.entries.clear();
+            trace.dropped = 0;
+        }
+    } else {
+        breakpoints.remove(&(pid, address));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Registers(Value);
+
+    impl EvalContext for Registers {
+        fn resolve_identifier(&self, name: &str) -> Option<u64> {
+            watch::register_value(Arch::X86_64, &self.0, name)
+        }
+
+        fn read_memory(&self, address: u64, _width: usize) -> Result<u64, String> {
+            Err(format!("Failed to read memory at address {:#x}", address))
+        }
+    }
+
+    #[test]
+    fn conditions_see_register_snapshots() {
+        let registers =
+            Registers(json!({ "rdi": "0x0000000000000005", "rsi": "0x00000000FFFFFFFF" }));
+        let condition = parse_expression("condition", "rdi == 5 && esi > 100", &[]).unwrap();
+        assert_eq!(condition.evaluate(&registers), Ok(1));
+        let condition = parse_expression("condition", "rdi != 5 || [rsi] == 0", &[]).unwrap();
+        assert!(condition.evaluate(&registers).is_err());
+        assert!(parse_expression("condition", "rdi ==", &[]).is_err());
+    }
+
+    #[test]
+    fn breakpoints_belong_to_one_process() {
+        // Pids no process has, so nothing is enumerated for them
+        let (first, second, address) = (-101, -102, 0x401000);
+        assert!(register(first, address, Some("rdi == 1"), None, false).is_ok());
+        assert!(register(second, address, Some("rdi == 2"), None, false).is_ok());
+        assert!(register(first, address, Some("rdi == 3"), None, false).is_err());
+
+        let listed = list_traces(second, None).unwrap();
+        assert_eq!(listed["breakpoints"].as_array().unwrap().len(), 1);
+        assert_eq!(listed["breakpoints"][0]["condition"], "rdi == 2");
+
+        // Once its debugger is gone, the address can be used again
+        debugger_detached(first);
+        assert!(register(first, address, Some("rdi == 3"), None, false).is_ok());
+        assert_eq!(
+            list_traces(second, None).unwrap()["breakpoints"][0]["active"],
+            true
+        );
+
+        unregister(first, address);
+        unregister(second, address);
+    }
+}
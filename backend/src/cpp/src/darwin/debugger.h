@@ -9,10 +9,13 @@
 #include <mach/vm_map.h>
 #include <unistd.h>
 
+#include <algorithm>
+#include <chrono>
 #include <cstdint>
 #include <cstring>
 #include <iostream>
 #include <map>
+#include <mutex>
 #include <string>
 #include <thread>
 #include <vector>
@@ -29,6 +32,23 @@ enum class WatchpointType
     READWRITE = 3
 };
 
+enum class BreakpointMode
+{
+    // Reports the first hits and then removes itself
+    Trace = 0,
+    // Halts the thread that hits it
+    Stop = 1,
+    // Stays in place; every hit is filtered and traced by evaluate_breakpoint_hit
+    Conditional = 2
+};
+
+enum class StepMode
+{
+    Into = 0,
+    Over = 1,
+    Out = 2
+};
+
 class Debugger
 {
 public:
@@ -36,10 +56,29 @@ public:
     ~Debugger();
     bool initialize();
     void run();
-    kern_return_t set_watchpoint(mach_vm_address_t address, int size, WatchpointType type);
+    pid_t pid() const
+    {
+        return pid_;
+    }
+    // Whether breakpoints or watchpoints still need the exception port
+    bool in_use();
+    kern_return_t set_watchpoint(mach_vm_address_t address, int size, WatchpointType type,
+                                 bool stop);
     kern_return_t remove_watchpoint(mach_vm_address_t address);
-    kern_return_t set_breakpoint(mach_vm_address_t address, int hit_count);
+    kern_return_t set_breakpoint(mach_vm_address_t address, int hit_count, BreakpointMode mode);
     kern_return_t remove_breakpoint(mach_vm_address_t address);
+    kern_return_t stopped_threads(std::string& json);
+    kern_return_t continue_thread(uint64_t tid);
+    kern_return_t step_thread(uint64_t tid, StepMode mode);
+    // Holds a running thread like a breakpoint stop; continue_thread lets it go again
+    kern_return_t suspend_thread(uint64_t tid);
+    // Single-steps a held thread until record_trace_step ends the trace; the thread is held again
+    // where it ended, without an exception event for every step
+    kern_return_t trace_thread(uint64_t tid);
+    kern_return_t thread_registers(uint64_t tid, std::string& json);
+    kern_return_t set_thread_registers(uint64_t tid, const std::vector<std::string>& names,
+                                       const std::vector<std::vector<uint8_t>>& values);
+    void remove_all();
     kern_return_t handle_exception(mach_port_t exception_port, mach_port_t thread, mach_port_t task,
                                    exception_type_t exception, mach_exception_data_t code,
                                    mach_msg_type_number_t code_count);
@@ -53,10 +92,36 @@ private:
     std::vector<bool> watchpoint_used;
     std::vector<mach_vm_address_t> watchpoint_addresses;
     std::vector<int> watchpoint_sizes;
+    std::vector<bool> watchpoint_stops;
     std::vector<bool> breakpoint_used;
     std::vector<mach_vm_address_t> breakpoint_addresses;
     std::vector<int> breakpoint_hit_counts;
     std::vector<int> breakpoint_target_counts;
+    std::vector<BreakpointMode> breakpoint_modes;
+    // Thread a temporary breakpoint was set for, e.g. the return site of a stepped-over call
+    std::vector<uint64_t> breakpoint_run_to_owners;
+
+    // Thread held suspended, or stepping, through the session API
+    struct ThreadSession
+    {
+        mach_port_t thread = MACH_PORT_NULL;
+        bool stopped = false;
+        std::string stop_reason;
+        bool step_active = false;
+        StepMode step_mode = StepMode::Into;
+        bool step_out_returning = false;
+        int step_count = 0;
+        // Breakpoint disabled while the thread sits on it, re-armed after the next instruction
+        int rearm_breakpoint = -1;
+        int rearm_watchpoint = -1;
+        int run_to_index = -1;
+        uint64_t run_to_sp = 0;
+        // Recording an instruction trace, which decides after every step whether to go on
+        bool tracing = false;
+    };
+
+    std::mutex session_mutex_;
+    std::map<uint64_t, ThreadSession> sessions_;
 
     enum class SingleStepMode
     {
@@ -88,6 +153,27 @@ private:
                                         arm_thread_state64_t& thread_state,
                                         arm_exception_state64_t& exception_state,
                                         int breakpoint_index);
+    kern_return_t handle_session_step(mach_port_t thread, ThreadSession& session,
+                                      arm_debug_state64_t& debug_state,
+                                      arm_thread_state64_t& thread_state);
+    kern_return_t advance_step(mach_port_t thread, ThreadSession& session,
+                               arm_debug_state64_t& debug_state,
+                               arm_thread_state64_t& thread_state);
+    kern_return_t advance_trace(mach_port_t thread, ThreadSession& session,
+                                arm_debug_state64_t& debug_state,
+                                arm_thread_state64_t& thread_state);
+    kern_return_t stop_thread(mach_port_t thread, ThreadSession& session, const char* reason,
+                              arm_thread_state64_t& thread_state);
+    kern_return_t hold_thread(mach_port_t thread, ThreadSession& session, const char* reason);
+    bool run_to(mach_port_t thread, uint64_t tid, ThreadSession& session,
+                arm_debug_state64_t& debug_state, uint64_t address, uint64_t sp);
+    void clear_run_to(ThreadSession& session, arm_debug_state64_t& debug_state);
+    kern_return_t set_single_step(mach_port_t thread, arm_debug_state64_t& debug_state,
+                                  bool enable);
+    void release_session(uint64_t tid);
+    static uint64_t thread_id(mach_port_t thread);
+    static std::vector<std::map<std::string, uint64_t>> register_map(
+        const arm_thread_state64_t& thread_state);
     int find_free_watchpoint();
     int find_watchpoint_index(mach_vm_address_t address);
     int find_free_breakpoint();
@@ -19,6 +19,12 @@ __attribute__((used)) extern "C" kern_return_t catch_exception_raise(
 
 Debugger* g_debugger = nullptr;
 
+// A step out that never reaches a return gives up after this many instructions
+static const int kMaxStepOutInstructions = 1000000;
+// Exception class of a software step completing at EL0/EL1
+static const uint32_t kSoftwareStepLowerEl = 0x32;
+static const uint32_t kSoftwareStepSameEl = 0x33;
+
 Debugger::Debugger(pid_t pid)
     : pid_(pid),
       task_port_(MACH_PORT_NULL),
@@ -26,10 +32,13 @@ Debugger::Debugger(pid_t pid)
       watchpoint_used(MAX_WATCHPOINTS, false),
       watchpoint_addresses(MAX_WATCHPOINTS, 0),
       watchpoint_sizes(MAX_WATCHPOINTS, 0),
+      watchpoint_stops(MAX_WATCHPOINTS, false),
       breakpoint_used(MAX_BREAKPOINTS, false),
       breakpoint_addresses(MAX_BREAKPOINTS, 0),
       breakpoint_hit_counts(MAX_BREAKPOINTS, 0),
-      breakpoint_target_counts(MAX_BREAKPOINTS, 0)
+      breakpoint_target_counts(MAX_BREAKPOINTS, 0),
+      breakpoint_modes(MAX_BREAKPOINTS, BreakpointMode::Trace),
+      breakpoint_run_to_owners(MAX_BREAKPOINTS, 0)
 {
 }
 
@@ -43,6 +52,7 @@ Debugger::~Debugger()
     {
         mach_port_deallocate(mach_task_self(), task_port_);
     }
+    debugger_detached(pid_);
 }
 
 bool Debugger::initialize()
@@ -99,7 +109,8 @@ void Debugger::run()
     }
 }
 
-kern_return_t Debugger::set_watchpoint(mach_vm_address_t address, int size, WatchpointType type)
+kern_return_t Debugger::set_watchpoint(mach_vm_address_t address, int size, WatchpointType type,
+                                       bool stop)
 {
     thread_act_array_t thread_list;
     mach_msg_type_number_t thread_count;
@@ -125,6 +136,7 @@ kern_return_t Debugger::set_watchpoint(mach_vm_address_t address, int size, Watc
         watchpoint_used[index] = true;
         watchpoint_addresses[index] = address;
         watchpoint_sizes[index] = size;
+        watchpoint_stops[index] = stop;
         debug_log(LOG_INFO, "Watchpoint set successfully at address 0x%llx", address);
     }
 
@@ -173,6 +185,7 @@ kern_return_t Debugger::remove_watchpoint(mach_vm_address_t address)
         watchpoint_used[index] = false;
         watchpoint_addresses[index] = 0;
         watchpoint_sizes[index] = 0;
+        watchpoint_stops[index] = false;
         debug_log(LOG_INFO, "Watchpoint removed successfully from address 0x%llx", address);
     }
     else
@@ -189,7 +202,8 @@ kern_return_t Debugger::remove_watchpoint(mach_vm_address_t address)
     return kr;
 }
 
-kern_return_t Debugger::set_breakpoint(mach_vm_address_t address, int hit_count)
+kern_return_t Debugger::set_breakpoint(mach_vm_address_t address, int hit_count,
+                                       BreakpointMode mode)
 {
     thread_act_array_t thread_list;
     mach_msg_type_number_t thread_count;
@@ -229,6 +243,7 @@ kern_return_t Debugger::set_breakpoint(mach_vm_address_t address, int hit_count)
         breakpoint_addresses[index] = address;
         breakpoint_hit_counts[index] = 0;
         breakpoint_target_counts[index] = hit_count;
+        breakpoint_modes[index] = mode;
         debug_log(LOG_INFO, "Breakpoint set successfully at address 0x%llx", address);
     }
     else
@@ -282,6 +297,7 @@ kern_return_t Debugger::remove_breakpoint(mach_vm_address_t address)
         breakpoint_addresses[index] = 0;
         breakpoint_hit_counts[index] = 0;
         breakpoint_target_counts[index] = 0;
+        breakpoint_modes[index] = BreakpointMode::Trace;
         debug_log(LOG_INFO, "Breakpoint removed successfully from address 0x%llx", address);
     }
     else
@@ -338,16 +354,20 @@ kern_return_t Debugger::handle_exception(mach_port_t exception_port, mach_port_t
         return kr;
     }
 
-    std::vector<std::map<std::string, uint64_t>> map_vector;
-    for (int i = 0; i < 30; ++i)
+    std::vector<std::map<std::string, uint64_t>> map_vector = register_map(thread_state);
+
+    uint32_t esr = exception_state.__esr;
+    uint32_t ec = (esr >> 26) & 0x3F;  // Exception Class
+
     {
-        map_vector.push_back({{"x" + std::to_string(i), thread_state.__x[i]}});
+        // Steps requested through the session API belong to one thread, unlike the trace steps
+        std::lock_guard<std::mutex> lock(session_mutex_);
+        auto it = sessions_.find(thread_id(thread));
+        if (it != sessions_.end() && (ec == kSoftwareStepLowerEl || ec == kSoftwareStepSameEl))
+        {
+            return handle_session_step(thread, it->second, debug_state, thread_state);
+        }
     }
-    map_vector.push_back({{"lr", thread_state.__lr}});
-    map_vector.push_back({{"fp", thread_state.__fp}});
-    map_vector.push_back({{"sp", thread_state.__sp}});
-    map_vector.push_back({{"pc", thread_state.__pc}});
-    map_vector.push_back({{"cpsr", thread_state.__cpsr}});
 
     if (single_step_mode != SingleStepMode::None)
     {
@@ -356,9 +376,6 @@ kern_return_t Debugger::handle_exception(mach_port_t exception_port, mach_port_t
         return handle_single_step(thread, debug_state, thread_state, exception_state);
     }
 
-    uint32_t esr = exception_state.__esr;
-    uint32_t ec = (esr >> 26) & 0x3F;  // Exception Class
-
     if (ec == 0x34 || ec == 0x35)
     {
         uint64_t far = exception_state.__far;
@@ -371,6 +388,17 @@ kern_return_t Debugger::handle_exception(mach_port_t exception_port, mach_port_t
             {
                 std::string register_json = map_vector_to_json_string(map_vector);
                 send_register_json(register_json.c_str(), pid_);
+                if (watchpoint_stops[i])
+                {
+                    // The access has not happened yet; it runs once the thread moves on
+                    std::lock_guard<std::mutex> lock(session_mutex_);
+                    ThreadSession& session = sessions_[thread_id(thread)];
+                    debug_state.__wcr[i] &= ~(1ULL << 0);
+                    session.rearm_watchpoint = i;
+                    thread_set_state(thread, ARM_DEBUG_STATE64, (thread_state_t)&debug_state,
+                                     ARM_DEBUG_STATE64_COUNT);
+                    return hold_thread(thread, session, "watchpoint");
+                }
                 return handle_watchpoint_hit(thread, debug_state, thread_state, exception_state, i);
             }
         }
@@ -381,6 +409,49 @@ kern_return_t Debugger::handle_exception(mach_port_t exception_port, mach_port_t
         {
             if (breakpoint_used[i] && thread_state.__pc == breakpoint_addresses[i])
             {
+                if (breakpoint_run_to_owners[i] != 0 ||
+                    breakpoint_modes[i] != BreakpointMode::Trace)
+                {
+                    std::lock_guard<std::mutex> lock(session_mutex_);
+                    uint64_t tid = thread_id(thread);
+                    ThreadSession& session = sessions_[tid];
+                    if (breakpoint_run_to_owners[i] == tid &&
+                        thread_state.__sp >= session.run_to_sp)
+                    {
+                        clear_run_to(session, debug_state);
+                        thread_set_state(thread, ARM_DEBUG_STATE64, (thread_state_t)&debug_state,
+                                         ARM_DEBUG_STATE64_COUNT);
+                        return advance_step(thread, session, debug_state, thread_state);
+                    }
+
+                    // Lifted for one instruction; a stop breakpoint holds the thread first
+                    debug_state.__bcr[i] &= ~1ULL;
+                    session.rearm_breakpoint = i;
+                    if (breakpoint_run_to_owners[i] != 0)
+                    {
+                        return set_single_step(thread, debug_state, true);
+                    }
+                    if (breakpoint_modes[i] == BreakpointMode::Conditional)
+                    {
+                        std::string register_json = map_vector_to_json_string(map_vector);
+                        int verdict = evaluate_breakpoint_hit(pid_, tid, thread_state.__pc,
+                                                              register_json.c_str());
+                        if (verdict == 2)  // report
+                        {
+                            breakpoint_hit_counts[i]++;
+                            send_register_json(register_json.c_str(), pid_);
+                        }
+                        if (verdict != 1)  // anything but stop runs on
+                        {
+                            return set_single_step(thread, debug_state, true);
+                        }
+                    }
+                    thread_set_state(thread, ARM_DEBUG_STATE64, (thread_state_t)&debug_state,
+                                     ARM_DEBUG_STATE64_COUNT);
+                    breakpoint_hit_counts[i]++;
+                    return stop_thread(thread, session, "breakpoint", thread_state);
+                }
+
                 std::string register_json = map_vector_to_json_string(map_vector);
                 send_register_json(register_json.c_str(), pid_);
                 // onetime breakpoint
@@ -517,6 +588,626 @@ kern_return_t Debugger::handle_breakpoint_hit(mach_port_t thread, arm_debug_stat
     return KERN_SUCCESS;
 }
 
+kern_return_t Debugger::handle_session_step(mach_port_t thread, ThreadSession& session,
+                                            arm_debug_state64_t& debug_state,
+                                            arm_thread_state64_t& thread_state)
+{
+    if (session.rearm_breakpoint >= 0)
+    {
+        if (breakpoint_used[session.rearm_breakpoint])
+        {
+            debug_state.__bcr[session.rearm_breakpoint] |= 1ULL;
+        }
+        session.rearm_breakpoint = -1;
+    }
+    if (session.rearm_watchpoint >= 0)
+    {
+        if (watchpoint_used[session.rearm_watchpoint])
+        {
+            debug_state.__wcr[session.rearm_watchpoint] |= 1ULL;
+        }
+        session.rearm_watchpoint = -1;
+    }
+    kern_return_t kr = set_single_step(thread, debug_state, false);
+    if (kr != KERN_SUCCESS)
+    {
+        return kr;
+    }
+
+    if (session.tracing)
+    {
+        return advance_trace(thread, session, debug_state, thread_state);
+    }
+    if (session.run_to_index >= 0)
+    {
+        // Still running to the return site
+        return KERN_SUCCESS;
+    }
+    if (!session.step_active)
+    {
+        // Continued off a breakpoint or watchpoint, which is armed again
+        release_session(thread_id(thread));
+        return KERN_SUCCESS;
+    }
+    return advance_step(thread, session, debug_state, thread_state);
+}
+
+kern_return_t Debugger::advance_step(mach_port_t thread, ThreadSession& session,
+                                     arm_debug_state64_t& debug_state,
+                                     arm_thread_state64_t& thread_state)
+{
+    if (session.step_mode != StepMode::Out || session.step_out_returning)
+    {
+        return stop_thread(thread, session, "step", thread_state);
+    }
+    if (++session.step_count > kMaxStepOutInstructions)
+    {
+        return stop_thread(thread, session, "step_limit", thread_state);
+    }
+
+    uint64_t pc = thread_state.__pc;
+    uint64_t size = 0;
+    switch (classify_instruction(pid_, pc, &size))
+    {
+        case 1:  // call: the callee runs freely until it comes back
+            if (run_to(thread, thread_id(thread), session, debug_state, pc + size,
+                       thread_state.__sp))
+            {
+                return KERN_SUCCESS;
+            }
+            break;
+        case 2:  // return: one more step lands in the caller
+            session.step_out_returning = true;
+            break;
+        case -1:
+            return stop_thread(thread, session, "step", thread_state);
+    }
+    return set_single_step(thread, debug_state, true);
+}
+
+kern_return_t Debugger::advance_trace(mach_port_t thread, ThreadSession& session,
+                                      arm_debug_state64_t& debug_state,
+                                      arm_thread_state64_t& thread_state)
+{
+    std::string register_json = map_vector_to_json_string(register_map(thread_state));
+    if (record_trace_step(pid_, thread_id(thread), register_json.c_str()) != 0)
+    {
+        session.tracing = false;
+        return hold_thread(thread, session, "trace");
+    }
+    return set_single_step(thread, debug_state, true);
+}
+
+kern_return_t Debugger::stop_thread(mach_port_t thread, ThreadSession& session, const char* reason,
+                                    arm_thread_state64_t& thread_state)
+{
+    std::string register_json = map_vector_to_json_string(register_map(thread_state));
+    send_register_json(register_json.c_str(), pid_);
+    return hold_thread(thread, session, reason);
+}
+
+kern_return_t Debugger::hold_thread(mach_port_t thread, ThreadSession& session, const char* reason)
+{
+    if (session.thread == MACH_PORT_NULL)
+    {
+        // Kept past the exception message for the later thread_resume
+        mach_port_mod_refs(mach_task_self(), thread, MACH_PORT_RIGHT_SEND, 1);
+        session.thread = thread;
+    }
+    session.stopped = true;
+    session.stop_reason = reason;
+    session.step_active = false;
+    session.step_out_returning = false;
+    session.step_count = 0;
+
+    // Replying to the exception lets the thread run; the suspension keeps it held
+    return thread_suspend(thread);
+}
+
+bool Debugger::run_to(mach_port_t thread, uint64_t tid, ThreadSession& session,
+                      arm_debug_state64_t& debug_state, uint64_t address, uint64_t sp)
+{
+    int index = find_free_breakpoint();
+    if (index == -1)
+    {
+        debug_log(LOG_ERROR, "No free breakpoints available to step over the call.");
+        return false;
+    }
+
+    debug_state.__bvr[index] = address;
+    debug_state.__bcr[index] = (1ULL << 0) | (2ULL << 1) | (1ULL << 5);
+    debug_state.__mdscr_el1 |= (1ULL << 15);
+    debug_state.__mdscr_el1 &= ~1ULL;
+    kern_return_t kr = thread_set_state(thread, ARM_DEBUG_STATE64, (thread_state_t)&debug_state,
+                                        ARM_DEBUG_STATE64_COUNT);
+    if (kr != KERN_SUCCESS)
+    {
+        debug_log(LOG_ERROR, "Failed to set breakpoint: %s", kern_return_to_string(kr).c_str());
+        return false;
+    }
+
+    breakpoint_used[index] = true;
+    breakpoint_addresses[index] = address;
+    breakpoint_run_to_owners[index] = tid;
+    session.run_to_index = index;
+    session.run_to_sp = sp;
+    return true;
+}
+
+void Debugger::clear_run_to(ThreadSession& session, arm_debug_state64_t& debug_state)
+{
+    if (session.run_to_index < 0)
+    {
+        return;
+    }
+    int index = session.run_to_index;
+    debug_state.__bcr[index] = 0;
+    breakpoint_used[index] = false;
+    breakpoint_addresses[index] = 0;
+    breakpoint_run_to_owners[index] = 0;
+    session.run_to_index = -1;
+    session.run_to_sp = 0;
+}
+
+kern_return_t Debugger::set_single_step(mach_port_t thread, arm_debug_state64_t& debug_state,
+                                        bool enable)
+{
+    if (enable)
+    {
+        debug_state.__mdscr_el1 |= 1ULL;
+    }
+    else
+    {
+        debug_state.__mdscr_el1 &= ~1ULL;
+    }
+    kern_return_t kr = thread_set_state(thread, ARM_DEBUG_STATE64, (thread_state_t)&debug_state,
+                                        ARM_DEBUG_STATE64_COUNT);
+    if (kr != KERN_SUCCESS)
+    {
+        debug_log(LOG_ERROR, "Failed to set single-step mode: %s", mach_error_string(kr));
+    }
+    return kr;
+}
+
+void Debugger::release_session(uint64_t tid)
+{
+    auto it = sessions_.find(tid);
+    if (it == sessions_.end())
+    {
+        return;
+    }
+    if (it->second.thread != MACH_PORT_NULL)
+    {
+        mach_port_deallocate(mach_task_self(), it->second.thread);
+    }
+    sessions_.erase(it);
+}
+
+kern_return_t Debugger::stopped_threads(std::string& json)
+{
+    std::lock_guard<std::mutex> lock(session_mutex_);
+    std::ostringstream out;
+    out << "[";
+    bool first = true;
+    for (auto& entry : sessions_)
+    {
+        if (!entry.second.stopped)
+        {
+            continue;
+        }
+        arm_thread_state64_t thread_state;
+        mach_msg_type_number_t count = ARM_THREAD_STATE64_COUNT;
+        if (thread_get_state(entry.second.thread, ARM_THREAD_STATE64,
+                             (thread_state_t)&thread_state, &count) != KERN_SUCCESS)
+        {
+            continue;
+        }
+        out << (first ? "" : ",") << "{\"tid\":" << entry.first << ",\"reason\":\""
+            << entry.second.stop_reason
+            << "\",\"registers\":" << map_vector_to_json_string(register_map(thread_state))
+            << "}";
+        first = false;
+    }
+    out << "]";
+    json = out.str();
+    return KERN_SUCCESS;
+}
+
+kern_return_t Debugger::continue_thread(uint64_t tid)
+{
+    std::lock_guard<std::mutex> lock(session_mutex_);
+    std::vector<uint64_t> released;
+    kern_return_t result = KERN_INVALID_ARGUMENT;
+    for (auto& entry : sessions_)
+    {
+        ThreadSession& session = entry.second;
+        if (!session.stopped || (tid != 0 && entry.first != tid))
+        {
+            continue;
+        }
+        session.stopped = false;
+        session.stop_reason.clear();
+
+        // A breakpoint under the PC is re-armed by the step exception, which ends the session
+        if (session.rearm_breakpoint >= 0 || session.rearm_watchpoint >= 0)
+        {
+            arm_debug_state64_t debug_state;
+            mach_msg_type_number_t count = ARM_DEBUG_STATE64_COUNT;
+            thread_get_state(session.thread, ARM_DEBUG_STATE64, (thread_state_t)&debug_state,
+                             &count);
+            set_single_step(session.thread, debug_state, true);
+        }
+        else
+        {
+            released.push_back(entry.first);
+        }
+        result = thread_resume(session.thread);
+    }
+    for (uint64_t id : released)
+    {
+        release_session(id);
+    }
+    return result;
+}
+
+kern_return_t Debugger::suspend_thread(uint64_t tid)
+{
+    thread_act_array_t thread_list;
+    mach_msg_type_number_t thread_count;
+    kern_return_t kr = task_threads(task_port_, &thread_list, &thread_count);
+    if (kr != KERN_SUCCESS)
+    {
+        debug_log(LOG_ERROR, "Failed to get threads: %s", kern_return_to_string(kr).c_str());
+        return kr;
+    }
+
+    kr = KERN_INVALID_ARGUMENT;
+    for (mach_msg_type_number_t i = 0; i < thread_count; i++)
+    {
+        if (kr == KERN_INVALID_ARGUMENT && thread_id(thread_list[i]) == tid)
+        {
+            std::lock_guard<std::mutex> lock(session_mutex_);
+            ThreadSession& session = sessions_[tid];
+            kr = session.stopped ? KERN_SUCCESS : hold_thread(thread_list[i], session, "suspended");
+        }
+        mach_port_deallocate(mach_task_self(), thread_list[i]);
+    }
+    vm_deallocate(mach_task_self(), (vm_address_t)thread_list, thread_count * sizeof(thread_act_t));
+    return kr;
+}
+
+kern_return_t Debugger::trace_thread(uint64_t tid)
+{
+    {
+        std::lock_guard<std::mutex> lock(session_mutex_);
+        auto it = sessions_.find(tid);
+        if (it == sessions_.end() || !it->second.stopped)
+        {
+            debug_log(LOG_ERROR, "Thread %llu is not stopped", tid);
+            return KERN_INVALID_ARGUMENT;
+        }
+        ThreadSession& session = it->second;
+        mach_port_t thread = session.thread;
+
+        arm_thread_state64_t thread_state;
+        mach_msg_type_number_t count = ARM_THREAD_STATE64_COUNT;
+        kern_return_t kr =
+            thread_get_state(thread, ARM_THREAD_STATE64, (thread_state_t)&thread_state, &count);
+        if (kr != KERN_SUCCESS)
+        {
+            return kr;
+        }
+        arm_debug_state64_t debug_state;
+        count = ARM_DEBUG_STATE64_COUNT;
+        kr = thread_get_state(thread, ARM_DEBUG_STATE64, (thread_state_t)&debug_state, &count);
+        if (kr != KERN_SUCCESS)
+        {
+            return kr;
+        }
+
+        // The starting state is recorded here, every later one by the step handler
+        std::string register_json = map_vector_to_json_string(register_map(thread_state));
+        if (record_trace_step(pid_, tid, register_json.c_str()) != 0)
+        {
+            session.stop_reason = "trace";
+            return KERN_SUCCESS;
+        }
+        session.stopped = false;
+        session.stop_reason.clear();
+        session.tracing = true;
+        set_single_step(thread, debug_state, true);
+        kr = thread_resume(thread);
+        if (kr != KERN_SUCCESS)
+        {
+            return kr;
+        }
+    }
+
+    // The recorder bounds the trace, so this waits for as long as it keeps asking for steps
+    while (true)
+    {
+        {
+            std::lock_guard<std::mutex> lock(session_mutex_);
+            auto it = sessions_.find(tid);
+            if (it == sessions_.end() || it->second.stopped)
+            {
+                break;
+            }
+        }
+        std::this_thread::sleep_for(std::chrono::milliseconds(1));
+    }
+    return KERN_SUCCESS;
+}
+
+kern_return_t Debugger::step_thread(uint64_t tid, StepMode mode)
+{
+    {
+        std::lock_guard<std::mutex> lock(session_mutex_);
+        auto it = sessions_.find(tid);
+        if (it == sessions_.end() || !it->second.stopped)
+        {
+            debug_log(LOG_ERROR, "Thread %llu is not stopped", tid);
+            return KERN_INVALID_ARGUMENT;
+        }
+        ThreadSession& session = it->second;
+        mach_port_t thread = session.thread;
+
+        arm_thread_state64_t thread_state;
+        mach_msg_type_number_t count = ARM_THREAD_STATE64_COUNT;
+        kern_return_t kr =
+            thread_get_state(thread, ARM_THREAD_STATE64, (thread_state_t)&thread_state, &count);
+        if (kr != KERN_SUCCESS)
+        {
+            return kr;
+        }
+        arm_debug_state64_t debug_state;
+        count = ARM_DEBUG_STATE64_COUNT;
+        kr = thread_get_state(thread, ARM_DEBUG_STATE64, (thread_state_t)&debug_state, &count);
+        if (kr != KERN_SUCCESS)
+        {
+            return kr;
+        }
+
+        session.stopped = false;
+        session.stop_reason.clear();
+        session.step_active = true;
+        session.step_mode = mode;
+        session.step_out_returning = false;
+        session.step_count = 0;
+
+        uint64_t pc = thread_state.__pc;
+        uint64_t size = 0;
+        if (mode == StepMode::Out)
+        {
+            advance_step(thread, session, debug_state, thread_state);
+        }
+        else if (mode == StepMode::Over && classify_instruction(pid_, pc, &size) == 1 &&
+                 run_to(thread, tid, session, debug_state, pc + size, thread_state.__sp))
+        {
+            // A breakpoint lifted at the call still needs its step to be re-armed
+            if (session.rearm_breakpoint >= 0 || session.rearm_watchpoint >= 0)
+            {
+                set_single_step(thread, debug_state, true);
+            }
+        }
+        else
+        {
+            set_single_step(thread, debug_state, true);
+        }
+        kr = thread_resume(thread);
+        if (kr != KERN_SUCCESS)
+        {
+            return kr;
+        }
+    }
+
+    // Short steps report their final state directly; longer ones keep running
+    for (int i = 0; i < 100; i++)
+    {
+        {
+            std::lock_guard<std::mutex> lock(session_mutex_);
+            auto it = sessions_.find(tid);
+            if (it == sessions_.end() || it->second.stopped)
+            {
+                break;
+            }
+        }
+        std::this_thread::sleep_for(std::chrono::milliseconds(1));
+    }
+    return KERN_SUCCESS;
+}
+
+// A register inside the thread or NEON state, addressed by byte offset
+struct RegisterField
+{
+    std::string name;
+    bool neon;
+    size_t offset;
+    size_t size;
+};
+
+// Names accepted for reading and writing, matching the register JSON of exception events
+static const std::vector<RegisterField>& register_fields()
+{
+    static const std::vector<RegisterField> fields = []() {
+        std::vector<RegisterField> fields;
+        for (size_t i = 0; i < 29; i++)
+        {
+            fields.push_back(
+                {"x" + std::to_string(i), false, offsetof(arm_thread_state64_t, __x) + i * 8, 8});
+        }
+        fields.push_back({"fp", false, offsetof(arm_thread_state64_t, __fp), 8});
+        fields.push_back({"lr", false, offsetof(arm_thread_state64_t, __lr), 8});
+        fields.push_back({"sp", false, offsetof(arm_thread_state64_t, __sp), 8});
+        fields.push_back({"pc", false, offsetof(arm_thread_state64_t, __pc), 8});
+        fields.push_back({"cpsr", false, offsetof(arm_thread_state64_t, __cpsr), 4});
+        fields.push_back({"x29", false, offsetof(arm_thread_state64_t, __fp), 8});
+        fields.push_back({"x30", false, offsetof(arm_thread_state64_t, __lr), 8});
+        fields.push_back({"flags", false, offsetof(arm_thread_state64_t, __cpsr), 4});
+        for (size_t i = 0; i < 32; i++)
+        {
+            fields.push_back(
+                {"v" + std::to_string(i), true, offsetof(arm_neon_state64_t, __v) + i * 16, 16});
+        }
+        fields.push_back({"fpsr", true, offsetof(arm_neon_state64_t, __fpsr), 4});
+        fields.push_back({"fpcr", true, offsetof(arm_neon_state64_t, __fpcr), 4});
+        return fields;
+    }();
+    return fields;
+}
+
+// Little-endian register bytes as a hex number, two digits per byte
+static std::string register_hex(const uint8_t* bytes, size_t size)
+{
+    static const char kDigits[] = "0123456789ABCDEF";
+    std::string hex = "0x";
+    for (size_t i = size; i-- > 0;)
+    {
+        hex += kDigits[bytes[i] >> 4];
+        hex += kDigits[bytes[i] & 0xF];
+    }
+    return hex;
+}
+
+kern_return_t Debugger::thread_registers(uint64_t tid, std::string& json)
+{
+    std::lock_guard<std::mutex> lock(session_mutex_);
+    auto it = sessions_.find(tid);
+    if (it == sessions_.end() || !it->second.stopped)
+    {
+        debug_log(LOG_ERROR, "Thread %llu is not stopped", tid);
+        return KERN_INVALID_ARGUMENT;
+    }
+
+    arm_thread_state64_t thread_state;
+    mach_msg_type_number_t count = ARM_THREAD_STATE64_COUNT;
+    kern_return_t kr = thread_get_state(it->second.thread, ARM_THREAD_STATE64,
+                                        (thread_state_t)&thread_state, &count);
+    if (kr != KERN_SUCCESS)
+    {
+        return kr;
+    }
+    arm_neon_state64_t neon_state;
+    count = ARM_NEON_STATE64_COUNT;
+    kr = thread_get_state(it->second.thread, ARM_NEON_STATE64, (thread_state_t)&neon_state,
+                          &count);
+    if (kr != KERN_SUCCESS)
+    {
+        return kr;
+    }
+
+    std::ostringstream general;
+    std::ostringstream fp;
+    for (const RegisterField& field : register_fields())
+    {
+        const uint8_t* base = field.neon ? reinterpret_cast<const uint8_t*>(&neon_state)
+                                         : reinterpret_cast<const uint8_t*>(&thread_state);
+        std::ostringstream& out = field.neon ? fp : general;
+        out << (out.tellp() == 0 ? "" : ",") << "\"" << field.name << "\":\""
+            << register_hex(base + field.offset, field.size) << "\"";
+    }
+    json = "{\"general\":{" + general.str() + "},\"fp\":{" + fp.str() + "}}";
+    return KERN_SUCCESS;
+}
+
+kern_return_t Debugger::set_thread_registers(uint64_t tid, const std::vector<std::string>& names,
+                                             const std::vector<std::vector<uint8_t>>& values)
+{
+    std::lock_guard<std::mutex> lock(session_mutex_);
+    auto it = sessions_.find(tid);
+    if (it == sessions_.end() || !it->second.stopped)
+    {
+        debug_log(LOG_ERROR, "Thread %llu is not stopped", tid);
+        return KERN_INVALID_ARGUMENT;
+    }
+    mach_port_t thread = it->second.thread;
+
+    arm_thread_state64_t thread_state;
+    mach_msg_type_number_t count = ARM_THREAD_STATE64_COUNT;
+    kern_return_t kr =
+        thread_get_state(thread, ARM_THREAD_STATE64, (thread_state_t)&thread_state, &count);
+    if (kr != KERN_SUCCESS)
+    {
+        return kr;
+    }
+    arm_neon_state64_t neon_state;
+    count = ARM_NEON_STATE64_COUNT;
+    kr = thread_get_state(thread, ARM_NEON_STATE64, (thread_state_t)&neon_state, &count);
+    if (kr != KERN_SUCCESS)
+    {
+        return kr;
+    }
+
+    // Every value is checked before either state is written back
+    bool general_changed = false;
+    bool neon_changed = false;
+    for (size_t i = 0; i < names.size(); i++)
+    {
+        const auto& fields = register_fields();
+        auto field = std::find_if(fields.begin(), fields.end(),
+                                  [&](const RegisterField& f) { return f.name == names[i]; });
+        if (field == fields.end())
+        {
+            debug_log(LOG_ERROR, "Unknown register %s", names[i].c_str());
+            return KERN_INVALID_ARGUMENT;
+        }
+        const std::vector<uint8_t>& value = values[i];
+        if (std::any_of(value.begin() + std::min(field->size, value.size()), value.end(),
+                        [](uint8_t byte) { return byte != 0; }))
+        {
+            debug_log(LOG_ERROR, "Value does not fit register %s", names[i].c_str());
+            return KERN_INVALID_ARGUMENT;
+        }
+        uint8_t* base = field->neon ? reinterpret_cast<uint8_t*>(&neon_state)
+                                    : reinterpret_cast<uint8_t*>(&thread_state);
+        std::memset(base + field->offset, 0, field->size);
+        std::memcpy(base + field->offset, value.data(), std::min(field->size, value.size()));
+        (field->neon ? neon_changed : general_changed) = true;
+    }
+    if (general_changed)
+    {
+        kr = thread_set_state(thread, ARM_THREAD_STATE64, (thread_state_t)&thread_state,
+                              ARM_THREAD_STATE64_COUNT);
+        if (kr != KERN_SUCCESS)
+        {
+            return kr;
+        }
+    }
+    if (neon_changed)
+    {
+        kr = thread_set_state(thread, ARM_NEON_STATE64, (thread_state_t)&neon_state,
+                              ARM_NEON_STATE64_COUNT);
+    }
+    return kr;
+}
+
+uint64_t Debugger::thread_id(mach_port_t thread)
+{
+    thread_identifier_info_data_t info;
+    mach_msg_type_number_t count = THREAD_IDENTIFIER_INFO_COUNT;
+    if (thread_info(thread, THREAD_IDENTIFIER_INFO, (thread_info_t)&info, &count) != KERN_SUCCESS)
+    {
+        return 0;
+    }
+    return info.thread_id;
+}
+
+std::vector<std::map<std::string, uint64_t>> Debugger::register_map(
+    const arm_thread_state64_t& thread_state)
+{
+    std::vector<std::map<std::string, uint64_t>> map_vector;
+    for (int i = 0; i < 30; ++i)
+    {
+        map_vector.push_back({{"x" + std::to_string(i), thread_state.__x[i]}});
+    }
+    map_vector.push_back({{"lr", thread_state.__lr}});
+    map_vector.push_back({{"fp", thread_state.__fp}});
+    map_vector.push_back({{"sp", thread_state.__sp}});
+    map_vector.push_back({{"pc", thread_state.__pc}});
+    map_vector.push_back({{"cpsr", thread_state.__cpsr}});
+    return map_vector;
+}
+
 int Debugger::find_free_watchpoint()
 {
     for (int i = 0; i < MAX_WATCHPOINTS; i++)
@@ -657,6 +1348,44 @@ kern_return_t Debugger::set_watchpoint_on_thread(mach_port_t thread, mach_vm_add
     return kr;
 }
 
+void Debugger::remove_all()
+{
+    continue_thread(0);
+    for (int i = 0; i < MAX_WATCHPOINTS; i++)
+    {
+        if (watchpoint_used[i])
+        {
+            remove_watchpoint(watchpoint_addresses[i]);
+        }
+    }
+    for (int i = 0; i < MAX_BREAKPOINTS; i++)
+    {
+        if (breakpoint_used[i])
+        {
+            remove_breakpoint(breakpoint_addresses[i]);
+        }
+    }
+}
+
+bool Debugger::in_use()
+{
+    for (int i = 0; i < MAX_WATCHPOINTS; i++)
+    {
+        if (watchpoint_used[i])
+        {
+            return true;
+        }
+    }
+    for (int i = 0; i < MAX_BREAKPOINTS; i++)
+    {
+        if (breakpoint_used[i])
+        {
+            return true;
+        }
+    }
+    return false;
+}
+
 std::string Debugger::kern_return_to_string(kern_return_t kr)
 {
     return mach_error_string(kr);
@@ -684,11 +1413,31 @@ extern "C"
         return true;
     }
 
-    kern_return_t set_watchpoint_native(mach_vm_address_t address, int size, WatchpointType type)
+    void debugger_free()
+    {
+        // The exception port dies with the server, so no hardware slot may stay armed
+        if (g_debugger)
+        {
+            g_debugger->remove_all();
+        }
+    }
+
+    bool debugger_attached_native(int pid)
+    {
+        return g_debugger != nullptr && g_debugger->pid() == pid;
+    }
+
+    bool debugger_in_use_native()
+    {
+        return g_debugger != nullptr && g_debugger->in_use();
+    }
+
+    kern_return_t set_watchpoint_native(mach_vm_address_t address, int size, WatchpointType type,
+                                        bool stop)
     {
         if (g_debugger)
         {
-            return g_debugger->set_watchpoint(address, size, type);
+            return g_debugger->set_watchpoint(address, size, type, stop);
         }
         return KERN_FAILURE;
     }
@@ -702,11 +1451,12 @@ extern "C"
         return KERN_FAILURE;
     }
 
-    kern_return_t set_breakpoint_native(mach_vm_address_t address, int hit_count)
+    kern_return_t set_breakpoint_native(mach_vm_address_t address, int hit_count, int mode)
     {
         if (g_debugger)
         {
-            return g_debugger->set_breakpoint(address, hit_count);
+            return g_debugger->set_breakpoint(address, hit_count,
+                                              static_cast<BreakpointMode>(mode));
         }
         return KERN_FAILURE;
     }
@@ -719,4 +1469,81 @@ extern "C"
         }
         return KERN_FAILURE;
     }
-}
\ No newline at end of file
+
+    char* debugger_threads_native()
+    {
+        std::string json = "[]";
+        if (g_debugger)
+        {
+            g_debugger->stopped_threads(json);
+        }
+        return strdup(json.c_str());
+    }
+
+    char* debugger_get_registers_native(int tid)
+    {
+        std::string json;
+        if (g_debugger == nullptr || g_debugger->thread_registers(tid, json) != KERN_SUCCESS)
+        {
+            return nullptr;
+        }
+        return strdup(json.c_str());
+    }
+
+    // `values` holds `count` little-endian 16 byte values, one per name
+    kern_return_t debugger_set_registers_native(int tid, const char** names,
+                                                const uint8_t* values, size_t count)
+    {
+        if (g_debugger == nullptr)
+        {
+            return KERN_FAILURE;
+        }
+        std::vector<std::string> register_names;
+        std::vector<std::vector<uint8_t>> register_values;
+        for (size_t i = 0; i < count; i++)
+        {
+            register_names.push_back(names[i]);
+            register_values.emplace_back(values + i * 16, values + (i + 1) * 16);
+        }
+        return g_debugger->set_thread_registers(tid, register_names, register_values);
+    }
+
+    // Hardware breakpoints leave the code untouched
+    void debugger_mask_breakpoints_native(uint64_t address, uint8_t* buffer, size_t size) {}
+
+    kern_return_t debugger_continue_native(int tid)
+    {
+        if (g_debugger)
+        {
+            return g_debugger->continue_thread(tid);
+        }
+        return KERN_FAILURE;
+    }
+
+    kern_return_t debugger_step_native(int tid, int mode)
+    {
+        if (g_debugger)
+        {
+            return g_debugger->step_thread(tid, static_cast<StepMode>(mode));
+        }
+        return KERN_FAILURE;
+    }
+
+    kern_return_t debugger_suspend_native(int tid)
+    {
+        if (g_debugger)
+        {
+            return g_debugger->suspend_thread(tid);
+        }
+        return KERN_FAILURE;
+    }
+
+    kern_return_t debugger_trace_native(int tid)
+    {
+        if (g_debugger)
+        {
+            return g_debugger->trace_thread(tid);
+        }
+        return KERN_FAILURE;
+    }
+}
@@ -30,14 +30,42 @@ typedef struct
     const char *processname;
 } ProcessInfo;
 
+typedef struct
+{
+    uintptr_t start;
+    uintptr_t end;
+    uint64_t file_offset;
+    char protection[8];
+} ModuleSegment;
+
 typedef struct
 {
     uintptr_t base;
-    int size;
+    uint64_t size;
     bool is_64bit;
     char *modulename;
+    ModuleSegment *segments;
+    size_t segment_count;
 } ModuleInfo;
 
+typedef struct
+{
+    int tid;
+    uintptr_t stack_base;
+    uintptr_t stack_top;
+} ThreadStackInfo;
+
+typedef struct
+{
+    int tid;
+    char name[32];
+    // Scheduler state as in /proc/<pid>/stat: R, S, D, T, t, Z, ...
+    char state;
+    uint64_t cpu_time_us;
+    // Only known while the thread is off the CPU; 0 otherwise
+    uintptr_t pc;
+} ThreadInfo;
+
 typedef struct
 {
     int mode;
@@ -59,6 +87,10 @@ extern "C" kern_return_t mach_vm_write(vm_map_t, mach_vm_address_t, vm_offset_t,
 extern "C" kern_return_t mach_vm_protect(vm_map_t, mach_vm_address_t, mach_vm_size_t, boolean_t,
                                          vm_prot_t);
 
+extern "C" kern_return_t mach_vm_allocate(vm_map_t, mach_vm_address_t *, mach_vm_size_t, int);
+
+extern "C" kern_return_t mach_vm_deallocate(vm_map_t, mach_vm_address_t, mach_vm_size_t);
+
 extern "C" kern_return_t mach_vm_region(vm_map_t, mach_vm_address_t *, mach_vm_size_t *,
                                         vm_region_flavor_t, vm_region_info_t,
                                         mach_msg_type_number_t *, mach_port_t *);
@@ -67,12 +99,19 @@ extern "C" int native_init(int mode);
 
 extern "C" pid_t get_pid_native();
 
+extern "C" bool process_alive_native(int pid);
+
 extern "C" ssize_t read_memory_native(int pid, mach_vm_address_t address, mach_vm_size_t size,
                                       unsigned char *buffer);
 
 extern "C" ssize_t write_memory_native(int pid, mach_vm_address_t address, mach_vm_size_t size,
                                        unsigned char *buffer);
 
+extern "C" mach_vm_address_t allocate_memory_native(int pid, mach_vm_address_t hint,
+                                                    mach_vm_size_t size);
+
+extern "C" int free_memory_native(int pid, mach_vm_address_t address, mach_vm_size_t size);
+
 extern "C" void enumerate_regions_to_buffer(pid_t pid, char *buffer, size_t buffer_size);
 
 extern "C" ProcessInfo *enumprocess_native(size_t *count);
@@ -83,11 +122,19 @@ extern "C" bool resume_process(pid_t pid);
 
 extern "C" ModuleInfo *enummodule_native(pid_t pid, size_t *count);
 
+extern "C" ThreadStackInfo *enumthreadstack_native(pid_t pid, size_t *count);
+extern "C" ThreadInfo *enumthread_native(pid_t pid, size_t *count);
+
 int debug_log(LogLevel level, const char *format, ...);
 
 // Rust functions
 extern "C" void native_log(int level, const char *message);
 extern "C" void send_register_json(const char *register_json, pid_t pid);
+extern "C" int classify_instruction(pid_t pid, uint64_t address, uint64_t *size);
+extern "C" int evaluate_breakpoint_hit(pid_t pid, uint64_t tid, uint64_t address,
+                                       const char *register_json);
+extern "C" int record_trace_step(pid_t pid, uint64_t tid, const char *register_json);
+extern "C" void debugger_detached(pid_t pid);
 char *disassemble(const uint8_t *bytecode, size_t length);
 void free_string(char *s);
 #endif
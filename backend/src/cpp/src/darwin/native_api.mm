@@ -5,10 +5,12 @@
 #include <mach-o/dyld_images.h>
 #include <mach-o/fat.h>
 #include <mach-o/loader.h>
+#include <signal.h>
 #include <stdio.h>
 #include <stdlib.h>
 #include <sys/queue.h>
 #include <sys/sysctl.h>
+#include <algorithm>
 #include <iostream>
 #include <string>
 #include <vector>
@@ -44,6 +46,12 @@ pid_t get_pid_native()
     return getpid();
 }
 
+bool process_alive_native(int pid)
+{
+    // EPERM still means the process exists
+    return kill(pid, 0) == 0 || errno != ESRCH;
+}
+
 ssize_t read_memory_native(int pid, mach_vm_address_t address, mach_vm_size_t size,
                            unsigned char *buffer)
 {
@@ -173,6 +181,86 @@ ssize_t write_memory_native(int pid, mach_vm_address_t address, mach_vm_size_t s
     return static_cast<ssize_t>(size);
 }
 
+mach_vm_address_t allocate_memory_native(int pid, mach_vm_address_t hint, mach_vm_size_t size)
+{
+    task_t task;
+    kern_return_t err;
+
+    if (pid == getpid())
+    {
+        task = mach_task_self();
+    }
+    else
+    {
+        err = task_for_pid(mach_task_self(), pid, &task);
+        if (err != KERN_SUCCESS)
+        {
+            debug_log(LOG_ERROR, "task_for_pid failed with error %d (%s)\n", err,
+                      mach_error_string(err));
+            return 0;
+        }
+    }
+
+    mach_vm_address_t address = hint;
+    err = KERN_FAILURE;
+    if (hint != 0)
+    {
+        err = mach_vm_allocate(task, &address, size, VM_FLAGS_FIXED);
+    }
+    if (err != KERN_SUCCESS)
+    {
+        address = 0;
+        err = mach_vm_allocate(task, &address, size, VM_FLAGS_ANYWHERE);
+    }
+    if (err != KERN_SUCCESS)
+    {
+        debug_log(LOG_ERROR, "mach_vm_allocate failed with error %d (%s)\n", err,
+                  mach_error_string(err));
+        return 0;
+    }
+
+    // Code caves only need to be executable; write_memory_native lifts protection while writing.
+    err = mach_vm_protect(task, address, size, false, VM_PROT_READ | VM_PROT_EXECUTE);
+    if (err != KERN_SUCCESS)
+    {
+        debug_log(LOG_ERROR, "mach_vm_protect (execute enable) failed with error %d (%s)\n", err,
+                  mach_error_string(err));
+        mach_vm_deallocate(task, address, size);
+        return 0;
+    }
+    return address;
+}
+
+int free_memory_native(int pid, mach_vm_address_t address, mach_vm_size_t size)
+{
+    task_t task;
+    kern_return_t err;
+
+    if (pid == getpid())
+    {
+        task = mach_task_self();
+    }
+    else
+    {
+        err = task_for_pid(mach_task_self(), pid, &task);
+        if (err != KERN_SUCCESS)
+        {
+            debug_log(LOG_ERROR, "task_for_pid failed with error %d (%s)\n", err,
+                      mach_error_string(err));
+            return -1;
+        }
+    }
+
+    err = mach_vm_deallocate(task, address, size);
+    if (err != KERN_SUCCESS)
+    {
+        debug_log(LOG_ERROR, "mach_vm_deallocate failed with error %d (%s)\n", err,
+                  mach_error_string(err));
+        return -1;
+    }
+    return 0;
+}
+
 void enumerate_regions_to_buffer(pid_t pid, char *buffer, size_t buffer_size)
 {
     task_t task;
@@ -371,7 +459,51 @@ bool resume_process(pid_t pid)
     return true;
 }
 
-static std::uint64_t get_image_size_64(int pid, mach_vm_address_t base_address)
+// Records a segment at its link-time address; slid once the whole header has been read
+static void add_macho_segment(std::vector<ModuleSegment> *segments, std::uint64_t vmaddr,
+                              std::uint64_t vmsize, std::uint64_t fileoff, vm_prot_t initprot)
+{
+    if (segments == nullptr)
+    {
+        return;
+    }
+    ModuleSegment segment;
+    segment.start = vmaddr;
+    segment.end = vmaddr + vmsize;
+    segment.file_offset = fileoff;
+    snprintf(segment.protection, sizeof(segment.protection), "%c%c%cp",
+             (initprot & VM_PROT_READ) ? 'r' : '-', (initprot & VM_PROT_WRITE) ? 'w' : '-',
+             (initprot & VM_PROT_EXECUTE) ? 'x' : '-');
+    segments->push_back(segment);
+}
+
+static void slide_macho_segments(std::vector<ModuleSegment> *segments,
+                                 mach_vm_address_t base_address)
+{
+    if (segments == nullptr || segments->empty())
+    {
+        return;
+    }
+    // __TEXT maps the start of the file, which is where the image was loaded
+    std::uint64_t text_vmaddr = segments->front().start;
+    for (const auto &segment : *segments)
+    {
+        if (segment.file_offset == 0)
+        {
+            text_vmaddr = segment.start;
+            break;
+        }
+    }
+    std::uint64_t slide = base_address - text_vmaddr;
+    for (auto &segment : *segments)
+    {
+        segment.start += slide;
+        segment.end += slide;
+    }
+}
+
+static std::uint64_t get_image_size_64(int pid, mach_vm_address_t base_address,
+                                       std::vector<ModuleSegment> *segments)
 {
     mach_header_64 header;
     if (read_memory_native(pid, base_address, sizeof(mach_header_64),
@@ -403,16 +535,25 @@ static std::uint64_t get_image_size_64(int pid, mach_vm_address_t base_address)
                 debug_log(LOG_ERROR, "Failed to read segment command\n");
                 return 0;
             }
+            // __PAGEZERO reserves address space but is not part of the image
+            if (seg.initprot == VM_PROT_NONE)
+            {
+                current_address += lc.cmdsize;
+                continue;
+            }
             image_size += seg.vmsize;
+            add_macho_segment(segments, seg.vmaddr, seg.vmsize, seg.fileoff, seg.initprot);
         }
 
         current_address += lc.cmdsize;
     }
 
+    slide_macho_segments(segments, base_address);
     return image_size;
 }
 
-static std::uint64_t get_image_size_32(int pid, mach_vm_address_t base_address)
+static std::uint64_t get_image_size_32(int pid, mach_vm_address_t base_address,
+                                       std::vector<ModuleSegment> *segments)
 {
     mach_header header;
     if (read_memory_native(pid, base_address, sizeof(mach_header),
@@ -444,16 +585,25 @@ static std::uint64_t get_image_size_32(int pid, mach_vm_address_t base_address)
                 debug_log(LOG_ERROR, "Failed to read segment command\n");
                 return 0;
             }
+            // __PAGEZERO reserves address space but is not part of the image
+            if (seg.initprot == VM_PROT_NONE)
+            {
+                current_address += lc.cmdsize;
+                continue;
+            }
             image_size += seg.vmsize;
+            add_macho_segment(segments, seg.vmaddr, seg.vmsize, seg.fileoff, seg.initprot);
         }
 
         current_address += lc.cmdsize;
     }
 
+    slide_macho_segments(segments, base_address);
     return image_size;
 }
 
-static std::uint64_t get_module_size(int pid, mach_vm_address_t address, bool *is_64bit)
+static std::uint64_t get_module_size(int pid, mach_vm_address_t address, bool *is_64bit,
+                                     std::vector<ModuleSegment> *segments)
 {
     std::uint32_t magic;
     if (read_memory_native(pid, address, sizeof(std::uint32_t),
@@ -466,12 +616,12 @@ static std::uint64_t get_module_size(int pid, mach_vm_address_t address, bool *i
     if (magic == MH_MAGIC_64)
     {
         *is_64bit = true;
-        return get_image_size_64(pid, address);
+        return get_image_size_64(pid, address, segments);
     }
     else if (magic == MH_MAGIC)
     {
         *is_64bit = false;
-        return get_image_size_32(pid, address);
+        return get_image_size_32(pid, address, segments);
     }
     else if (magic == FAT_MAGIC || magic == FAT_CIGAM)
     {
@@ -503,12 +653,12 @@ static std::uint64_t get_module_size(int pid, mach_vm_address_t address, bool *i
             if (magic == MH_MAGIC_64)
             {
                 *is_64bit = true;
-                return get_image_size_64(pid, address + arch.offset);
+                return get_image_size_64(pid, address + arch.offset, segments);
             }
             else if (magic == MH_MAGIC)
             {
                 *is_64bit = false;
-                return get_image_size_32(pid, address + arch.offset);
+                return get_image_size_32(pid, address + arch.offset, segments);
             }
         }
     }
@@ -592,8 +742,13 @@ ModuleInfo *enummodule_native(pid_t pid, size_t *count)
             }
 
             module.base = reinterpret_cast<std::uintptr_t>(info.imageLoadAddress);
-            module.size = static_cast<std::int32_t>(get_module_size(
-                pid, static_cast<mach_vm_address_t>(module.base), &module.is_64bit));
+            std::vector<ModuleSegment> segments;
+            module.size = get_module_size(pid, static_cast<mach_vm_address_t>(module.base),
+                                          &module.is_64bit, &segments);
+            module.segment_count = segments.size();
+            module.segments =
+                static_cast<ModuleSegment *>(malloc(segments.size() * sizeof(ModuleSegment)));
+            std::copy(segments.begin(), segments.end(), module.segments);
 
             moduleList.push_back(module);
         }
@@ -606,6 +761,184 @@ ModuleInfo *enummodule_native(pid_t pid, size_t *count)
     return result;
 }
 
+ThreadStackInfo *enumthreadstack_native(pid_t pid, size_t *count)
+{
+    task_t task;
+    kern_return_t err;
+    *count = 0;
+
+    if (pid == getpid())
+    {
+        task = mach_task_self();
+    }
+    else
+    {
+        err = task_for_pid(mach_task_self(), pid, &task);
+        if (err != KERN_SUCCESS)
+        {
+            debug_log(LOG_ERROR, "task_for_pid failed with error %d (%s)\n", err,
+                      mach_error_string(err));
+            return nullptr;
+        }
+    }
+
+    thread_act_array_t thread_list;
+    mach_msg_type_number_t thread_count;
+    err = task_threads(task, &thread_list, &thread_count);
+    if (err != KERN_SUCCESS)
+    {
+        debug_log(LOG_ERROR, "task_threads failed with error %d (%s)\n", err,
+                  mach_error_string(err));
+        return nullptr;
+    }
+
+    // task_threads returns threads in creation order, so the main thread comes first
+    std::vector<ThreadStackInfo> stacks;
+    for (mach_msg_type_number_t i = 0; i < thread_count; i++)
+    {
+        arm_thread_state64_t thread_state;
+        mach_msg_type_number_t state_count = ARM_THREAD_STATE64_COUNT;
+        thread_identifier_info_data_t identifier_info;
+        mach_msg_type_number_t info_count = THREAD_IDENTIFIER_INFO_COUNT;
+
+        if (thread_get_state(thread_list[i], ARM_THREAD_STATE64, (thread_state_t)&thread_state,
+                             &state_count) == KERN_SUCCESS &&
+            thread_info(thread_list[i], THREAD_IDENTIFIER_INFO, (thread_info_t)&identifier_info,
+                        &info_count) == KERN_SUCCESS)
+        {
+            mach_vm_address_t sp = thread_state.__sp;
+            mach_vm_address_t address = sp;
+            mach_vm_size_t size = 0;
+            vm_region_basic_info_data_64_t info;
+            mach_msg_type_number_t region_count = VM_REGION_BASIC_INFO_COUNT_64;
+            mach_port_t object_name;
+
+            if (mach_vm_region(task, &address, &size, VM_REGION_BASIC_INFO_64,
+                               (vm_region_info_t)&info, &region_count,
+                               &object_name) == KERN_SUCCESS &&
+                address <= sp)
+            {
+                ThreadStackInfo stack;
+                stack.tid = static_cast<int>(identifier_info.thread_id);
+                stack.stack_base = address;
+                stack.stack_top = address + size;
+                stacks.push_back(stack);
+            }
+        }
+        mach_port_deallocate(mach_task_self(), thread_list[i]);
+    }
+    vm_deallocate(mach_task_self(), (vm_address_t)thread_list, thread_count * sizeof(thread_act_t));
+
+    *count = stacks.size();
+    ThreadStackInfo *result =
+        static_cast<ThreadStackInfo *>(malloc(std::max<size_t>(*count, 1) * sizeof(ThreadStackInfo)));
+    std::copy(stacks.begin(), stacks.end(), result);
+
+    return result;
+}
+
+ThreadInfo *enumthread_native(pid_t pid, size_t *count)
+{
+    task_t task;
+    kern_return_t err;
+    *count = 0;
+
+    if (pid == getpid())
+    {
+        task = mach_task_self();
+    }
+    else
+    {
+        err = task_for_pid(mach_task_self(), pid, &task);
+        if (err != KERN_SUCCESS)
+        {
+            debug_log(LOG_ERROR, "task_for_pid failed with error %d (%s)\n", err,
+                      mach_error_string(err));
+            return nullptr;
+        }
+    }
+
+    thread_act_array_t thread_list;
+    mach_msg_type_number_t thread_count;
+    err = task_threads(task, &thread_list, &thread_count);
+    if (err != KERN_SUCCESS)
+    {
+        debug_log(LOG_ERROR, "task_threads failed with error %d (%s)\n", err,
+                  mach_error_string(err));
+        return nullptr;
+    }
+
+    std::vector<ThreadInfo> threads;
+    for (mach_msg_type_number_t i = 0; i < thread_count; i++)
+    {
+        thread_identifier_info_data_t identifier_info;
+        mach_msg_type_number_t info_count = THREAD_IDENTIFIER_INFO_COUNT;
+        thread_basic_info_data_t basic_info;
+        mach_msg_type_number_t basic_count = THREAD_BASIC_INFO_COUNT;
+        if (thread_info(thread_list[i], THREAD_IDENTIFIER_INFO, (thread_info_t)&identifier_info,
+                        &info_count) == KERN_SUCCESS &&
+            thread_info(thread_list[i], THREAD_BASIC_INFO, (thread_info_t)&basic_info,
+                        &basic_count) == KERN_SUCCESS)
+        {
+            ThreadInfo info = {};
+            info.tid = static_cast<int>(identifier_info.thread_id);
+
+            thread_extended_info_data_t extended_info;
+            mach_msg_type_number_t extended_count = THREAD_EXTENDED_INFO_COUNT;
+            if (thread_info(thread_list[i], THREAD_EXTENDED_INFO, (thread_info_t)&extended_info,
+                            &extended_count) == KERN_SUCCESS)
+            {
+                snprintf(info.name, sizeof(info.name), "%s", extended_info.pth_name);
+            }
+
+            // Mapped onto the letters Linux uses, so both report the same states
+            switch (basic_info.run_state)
+            {
+                case TH_STATE_RUNNING:
+                    info.state = 'R';
+                    break;
+                case TH_STATE_UNINTERRUPTIBLE:
+                    info.state = 'D';
+                    break;
+                case TH_STATE_STOPPED:
+                case TH_STATE_HALTED:
+                    info.state = 'T';
+                    break;
+                default:
+                    info.state = 'S';
+                    break;
+            }
+            if (basic_info.suspend_count > 0)
+            {
+                info.state = 'T';
+            }
+            info.cpu_time_us =
+                (uint64_t)(basic_info.user_time.seconds + basic_info.system_time.seconds) *
+                    1000000 +
+                basic_info.user_time.microseconds + basic_info.system_time.microseconds;
+
+            arm_thread_state64_t thread_state;
+            mach_msg_type_number_t state_count = ARM_THREAD_STATE64_COUNT;
+            if (info.state != 'R' &&
+                thread_get_state(thread_list[i], ARM_THREAD_STATE64, (thread_state_t)&thread_state,
+                                 &state_count) == KERN_SUCCESS)
+            {
+                info.pc = thread_state.__pc;
+            }
+            threads.push_back(info);
+        }
+        mach_port_deallocate(mach_task_self(), thread_list[i]);
+    }
+    vm_deallocate(mach_task_self(), (vm_address_t)thread_list, thread_count * sizeof(thread_act_t));
+
+    *count = threads.size();
+    ThreadInfo *result =
+        static_cast<ThreadInfo *>(malloc(std::max<size_t>(*count, 1) * sizeof(ThreadInfo)));
+    std::copy(threads.begin(), threads.end(), result);
+
+    return result;
+}
+
 int native_init(int mode)
 {
     global_server_state.mode = mode;
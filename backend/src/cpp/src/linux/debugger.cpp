@@ -1,29 +1,2130 @@
 #include "debugger.h"
 
+#if defined(__x86_64__) || defined(__aarch64__)
+
+Debugger* g_debugger = nullptr;
+
+#if defined(__x86_64__)
+// int3; the trap reports the address after the instruction
+static const uint8_t kTrapInstruction[] = {0xCC};
+static const uint64_t kTrapPcOffset = 1;
+#else
+// brk #0
+static const uint8_t kTrapInstruction[] = {0x00, 0x00, 0x20, 0xD4};
+static const uint64_t kTrapPcOffset = 0;
+#endif
+
+#if defined(__x86_64__)
+static const int kMaxWatchpoints = 4;
+
+static long debug_register_offset(int index)
+{
+    return static_cast<long>(offsetof(struct user, u_debugreg) + index * sizeof(long));
+}
+#else
+static const int kMaxWatchpoints = 16;
+
+#ifndef NT_ARM_HW_WATCH
+#define NT_ARM_HW_WATCH 0x403
+#endif
+
+// Mirrors struct user_hwdebug_state from <asm/ptrace.h>, which clashes with the libc headers
+struct HwDebugState
+{
+    uint32_t dbg_info;
+    uint32_t pad;
+    struct
+    {
+        uint64_t addr;
+        uint32_t ctrl;
+        uint32_t pad;
+    } dbg_regs[kMaxWatchpoints];
+};
+#endif
+
+#ifndef TRAP_HWBKPT
+#define TRAP_HWBKPT 4
+#endif
+
+static uint64_t& program_counter(struct user_regs_struct& regs)
+{
+#if defined(__x86_64__)
+    return reinterpret_cast<uint64_t&>(regs.rip);
+#else
+    return reinterpret_cast<uint64_t&>(regs.pc);
+#endif
+}
+
+static uint64_t stack_pointer(const struct user_regs_struct& regs)
+{
+#if defined(__x86_64__)
+    return regs.rsp;
+#else
+    return regs.sp;
+#endif
+}
+
+// Instructions a step out may execute before it gives up, e.g. in a loop that never returns
+static const int kMaxStepOutInstructions = 1000000;
+// A traced instruction taking longer, e.g. a blocking system call, is interrupted
+static const int kTraceStepTimeoutMs = 1000;
+
+Debugger::Debugger(pid_t pid)
+    : pid_(pid),
+      mem_fd_(-1),
+      running_(false),
+      stop_requested_(false),
+      watchpoints_(kMaxWatchpoints)
+{
+}
+
+Debugger::~Debugger()
+{
+    stop();
+    if (mem_fd_ != -1)
+    {
+        close(mem_fd_);
+    }
+    debugger_detached(pid_);
+}
+
+bool Debugger::initialize()
+{
+    char path[64];
+    snprintf(path, sizeof(path), "/proc/%d/mem", pid_);
+    mem_fd_ = open(path, O_RDWR);
+    if (mem_fd_ == -1)
+    {
+        debug_log(LOG_ERROR, "Failed to open %s: %s", path, strerror(errno));
+        return false;
+    }
+
+    std::future<bool> attached = attach_result_.get_future();
+    thread_ = std::thread([this]() { run(); });
+    if (!attached.get())
+    {
+        thread_.join();
+        return false;
+    }
+
+    debug_log(LOG_INFO, "Debugger initialized for process %d", pid_);
+    return true;
+}
+
+void Debugger::run()
+{
+    bool attached = attach_all_threads();
+    {
+        std::lock_guard<std::mutex> lock(command_mutex_);
+        running_ = attached;
+    }
+    attach_result_.set_value(attached);
+    if (!attached)
+    {
+        detach_all_threads();
+        return;
+    }
+
+    while (true)
+    {
+        process_commands();
+        {
+            std::lock_guard<std::mutex> lock(command_mutex_);
+            if (stop_requested_ || threads_.find(pid_) == threads_.end())
+            {
+                break;
+            }
+        }
+
+        if (!poll_threads())
+        {
+            // Single steps finish within microseconds, so stepping threads are polled eagerly
+            auto timeout = any_thread_stepping() ? std::chrono::microseconds(20)
+                                                 : std::chrono::microseconds(1000);
+            std::unique_lock<std::mutex> lock(command_mutex_);
+            command_cv_.wait_for(lock, timeout,
+                                 [this]() { return !commands_.empty() || stop_requested_; });
+        }
+    }
+
+    detach_all_threads();
+
+    // Callers still waiting on a command get ESRCH from the broken promise
+    std::lock_guard<std::mutex> lock(command_mutex_);
+    running_ = false;
+    commands_.clear();
+    debug_log(LOG_INFO, "Debugger detached from process %d", pid_);
+}
+
+void Debugger::stop()
+{
+    {
+        std::lock_guard<std::mutex> lock(command_mutex_);
+        stop_requested_ = true;
+    }
+    command_cv_.notify_one();
+    if (thread_.joinable() && std::this_thread::get_id() != thread_.get_id())
+    {
+        thread_.join();
+    }
+}
+
+bool Debugger::is_running()
+{
+    std::lock_guard<std::mutex> lock(command_mutex_);
+    return running_;
+}
+
+void Debugger::process_commands()
+{
+    std::deque<std::function<void()>> commands;
+    {
+        std::lock_guard<std::mutex> lock(command_mutex_);
+        commands.swap(commands_);
+    }
+    for (auto& command : commands)
+    {
+        command();
+    }
+}
+
+bool Debugger::poll_threads()
+{
+    // Waiting on each traced thread rather than on -1 keeps the server's own children untouched
+    std::vector<pid_t> tids;
+    for (const auto& entry : threads_)
+    {
+        tids.push_back(entry.first);
+    }
+
+    bool handled = false;
+    for (pid_t tid : tids)
+    {
+        int status = 0;
+        pid_t result = waitpid(tid, &status, __WALL | WNOHANG);
+        if (result == tid)
+        {
+            handle_stop(tid, status);
+            handled = true;
+        }
+        else if (result == -1 && errno == ECHILD)
+        {
+            threads_.erase(tid);
+        }
+    }
+    return handled;
+}
+
+bool Debugger::attach_all_threads()
+{
+    char path[64];
+    snprintf(path, sizeof(path), "/proc/%d/task", pid_);
+
+    // Threads created while attaching are missed by one pass, so rescan until nothing new appears
+    bool found_new = true;
+    while (found_new)
+    {
+        found_new = false;
+        DIR* dir = opendir(path);
+        if (dir == nullptr)
+        {
+            debug_log(LOG_ERROR, "Failed to open %s: %s", path, strerror(errno));
+            return false;
+        }
+
+        struct dirent* entry;
+        while ((entry = readdir(dir)) != nullptr)
+        {
+            pid_t tid = atoi(entry->d_name);
+            if (tid <= 0 || threads_.find(tid) != threads_.end())
+            {
+                continue;
+            }
+            if (ptrace(PTRACE_SEIZE, tid, nullptr, PTRACE_O_TRACECLONE) == -1)
+            {
+                debug_log(LOG_WARN, "Failed to attach to thread %d: %s", tid, strerror(errno));
+                continue;
+            }
+            threads_[tid] = ThreadState();
+            found_new = true;
+        }
+        closedir(dir);
+    }
+
+    if (threads_.find(pid_) == threads_.end())
+    {
+        debug_log(LOG_ERROR, "Failed to attach to process %d", pid_);
+        return false;
+    }
+    return true;
+}
+
+void Debugger::detach_all_threads()
+{
+    for (auto& entry : breakpoints_)
+    {
+        restore_breakpoint(entry.first, entry.second);
+    }
+
+    for (auto& entry : threads_)
+    {
+        pid_t tid = entry.first;
+        if (entry.second.stopped)
+        {
+            apply_watchpoints(tid, false);
+            ptrace(PTRACE_DETACH, tid, nullptr, nullptr);
+            continue;
+        }
+        if (!entry.second.stepping && ptrace(PTRACE_INTERRUPT, tid, nullptr, nullptr) == -1)
+        {
+            continue;
+        }
+
+        int status = 0;
+        if (waitpid(tid, &status, __WALL) != tid || !WIFSTOPPED(status))
+        {
+            continue;
+        }
+
+        if (std::any_of(watchpoints_.begin(), watchpoints_.end(),
+                        [](const Watchpoint& watchpoint) { return watchpoint.used; }))
+        {
+            apply_watchpoints(tid, false);
+        }
+
+        int signal = WSTOPSIG(status);
+        if (signal == SIGTRAP)
+        {
+            // A trap taken on a breakpoint that is already gone must not leak into the target
+            struct user_regs_struct regs;
+            if ((status >> 16) == 0 && get_registers(tid, regs))
+            {
+                uint64_t address = program_counter(regs) - kTrapPcOffset;
+                if (breakpoints_.count(address) || retired_breakpoints_.count(address))
+                {
+                    program_counter(regs) = address;
+                    set_registers(tid, regs);
+                }
+            }
+            signal = 0;
+        }
+        else if ((status >> 16) != 0)
+        {
+            signal = 0;
+        }
+        ptrace(PTRACE_DETACH, tid, nullptr, reinterpret_cast<void*>(static_cast<long>(signal)));
+    }
+
+    threads_.clear();
+    breakpoints_.clear();
+    retired_breakpoints_.clear();
+    watchpoints_.assign(kMaxWatchpoints, Watchpoint());
+}
+
+bool Debugger::with_thread_stopped(pid_t tid, const std::function<void()>& f)
+{
+    // A thread held by the session is already in ptrace-stop and reports nothing new
+    auto it = threads_.find(tid);
+    if (it != threads_.end() && it->second.stopped)
+    {
+        f();
+        return true;
+    }
+
+    if (ptrace(PTRACE_INTERRUPT, tid, nullptr, nullptr) == -1)
+    {
+        return false;
+    }
+
+    int status = 0;
+    if (waitpid(tid, &status, __WALL) != tid)
+    {
+        return false;
+    }
+    if (WIFSTOPPED(status))
+    {
+        f();
+    }
+
+    // Whatever stop arrived first is handled as usual; a still pending interrupt stop just
+    // resumes the thread when the loop collects it
+    handle_stop(tid, status);
+    return WIFSTOPPED(status);
+}
+
+void Debugger::handle_stop(pid_t tid, int status)
+{
+    if (WIFEXITED(status) || WIFSIGNALED(status))
+    {
+        threads_.erase(tid);
+        return;
+    }
+    if (!WIFSTOPPED(status))
+    {
+        return;
+    }
+
+    int signal = WSTOPSIG(status);
+    int event = status >> 16;
+
+    if (event == PTRACE_EVENT_CLONE)
+    {
+        unsigned long new_tid = 0;
+        if (ptrace(PTRACE_GETEVENTMSG, tid, nullptr, &new_tid) == 0)
+        {
+            // The new thread is traced automatically and reports its own initial stop
+            threads_.emplace(static_cast<pid_t>(new_tid), ThreadState());
+        }
+        resume(tid, 0);
+    }
+    else if (event == PTRACE_EVENT_STOP)
+    {
+        if (signal == SIGSTOP || signal == SIGTSTP || signal == SIGTTIN || signal == SIGTTOU)
+        {
+            // Group stop: keep the thread stopped without holding it in a ptrace stop
+            ptrace(PTRACE_LISTEN, tid, nullptr, nullptr);
+        }
+        else if (threads_[tid].suspend_requested)
+        {
+            hold_thread(tid, "suspended");
+        }
+        else
+        {
+            // Debug registers are per thread and not inherited, so new threads get them here
+            if (std::any_of(watchpoints_.begin(), watchpoints_.end(),
+                            [](const Watchpoint& watchpoint) { return watchpoint.used; }))
+            {
+                apply_watchpoints(tid, true);
+            }
+            resume(tid, 0);
+        }
+    }
+    else if (event != 0)
+    {
+        resume(tid, 0);
+    }
+    else if (signal == SIGTRAP)
+    {
+        handle_trap(tid);
+    }
+    else
+    {
+        resume(tid, signal);
+    }
+}
+
+void Debugger::handle_trap(pid_t tid)
+{
+    struct user_regs_struct regs;
+    if (!get_registers(tid, regs))
+    {
+        resume(tid, 0);
+        return;
+    }
+
+    ThreadState& state = threads_[tid];
+    bool stop = false;
+    if (state.watchpoint_step)
+    {
+        state.watchpoint_step = false;
+        apply_watchpoints(tid, true);
+        if (!state.stepping)
+        {
+            resume(tid, 0);
+            return;
+        }
+    }
+    else if (handle_watchpoint_hit(tid, regs, stop))
+    {
+        if (stop)
+        {
+            hold_thread(tid, "watchpoint");
+#if defined(__aarch64__)
+            // The access is still ahead and runs with the watchpoints lifted once the thread moves
+            state.watchpoint_pending = true;
+#endif
+            return;
+        }
+#if defined(__aarch64__)
+        // The access has not happened yet; step it with the watchpoints lifted
+        apply_watchpoints(tid, false);
+        state.watchpoint_step = true;
+        if (ptrace(PTRACE_SINGLESTEP, tid, nullptr, nullptr) == -1)
+        {
+            debug_log(LOG_ERROR, "PTRACE_SINGLESTEP failed for thread %d: %s", tid,
+                      strerror(errno));
+            state.watchpoint_step = false;
+            apply_watchpoints(tid, true);
+            resume(tid, 0);
+        }
+        return;
+#else
+        if (!state.stepping)
+        {
+            resume(tid, 0);
+            return;
+        }
+#endif
+    }
+
+    if (state.stepping)
+    {
+        end_single_step(state);
+        if (state.single_step_mode == SingleStepMode::Breakpoint)
+        {
+            send_registers(regs);
+            continue_breakpoint_single_step(tid, state);
+        }
+        else if (state.step_active && state.run_to_address == 0)
+        {
+            advance_step(tid, regs);
+        }
+        else
+        {
+            resume(tid, 0);
+        }
+        return;
+    }
+
+    uint64_t address = program_counter(regs) - kTrapPcOffset;
+
+    if (breakpoints_.find(address) != breakpoints_.end())
+    {
+        handle_breakpoint_hit(tid, regs, address);
+    }
+    else if (retired_breakpoints_.find(address) != retired_breakpoints_.end())
+    {
+        // Trap raced with the breakpoint being removed; rerun the original instruction
+        program_counter(regs) = address;
+        set_registers(tid, regs);
+        resume(tid, 0);
+    }
+    else
+    {
+        resume(tid, SIGTRAP);
+    }
+}
+
+void Debugger::handle_breakpoint_hit(pid_t tid, struct user_regs_struct& regs, uint64_t address)
+{
+    Breakpoint& breakpoint = breakpoints_[address];
+    ThreadState& state = threads_[tid];
+    if (breakpoint.run_to_owner != 0)
+    {
+        program_counter(regs) = address;
+        set_registers(tid, regs);
+        // A deeper frame returning to the same site, e.g. recursion, is not the one awaited
+        if (breakpoint.run_to_owner == tid && stack_pointer(regs) >= state.run_to_sp)
+        {
+            clear_run_to(tid);
+            advance_step(tid, regs);
+        }
+        else
+        {
+            single_step(tid);
+        }
+        return;
+    }
+    if (breakpoint.mode == BreakpointMode::Stop)
+    {
+        program_counter(regs) = address;
+        set_registers(tid, regs);
+        breakpoint.hit_count++;
+        stop_thread(tid, "breakpoint", regs);
+        return;
+    }
+    if (breakpoint.mode == BreakpointMode::Conditional)
+    {
+        program_counter(regs) = address;
+        set_registers(tid, regs);
+        if (state.run_to_address == address && stack_pointer(regs) >= state.run_to_sp)
+        {
+            clear_run_to(tid);
+            advance_step(tid, regs);
+            return;
+        }
+
+        std::string register_json = map_vector_to_json_string(register_map(regs));
+        switch (evaluate_breakpoint_hit(pid_, tid, address, register_json.c_str()))
+        {
+            case 1:  // stop
+                breakpoint.hit_count++;
+                stop_thread(tid, "breakpoint", regs);
+                return;
+            case 2:  // report
+                breakpoint.hit_count++;
+                send_register_json(register_json.c_str(), pid_);
+                break;
+        }
+        if (!single_step(tid))
+        {
+            resume(tid, 0);
+        }
+        return;
+    }
+
+    // onetime breakpoint; restored first so the reported instruction is the original one
+    restore_breakpoint(address, breakpoint);
+    retire_breakpoint(address);
+
+    program_counter(regs) = address;
+    set_registers(tid, regs);
+    send_registers(regs);
+    breakpoint.hit_count++;
+
+    if (breakpoint.hit_count < breakpoint.target_count)
+    {
+        ThreadState& state = threads_[tid];
+        state.single_step_mode = SingleStepMode::Breakpoint;
+        state.single_step_count = 0;
+        state.breakpoint_address = address;
+        if (!single_step(tid))
+        {
+            state.single_step_mode = SingleStepMode::None;
+            breakpoints_.erase(address);
+            resume(tid, 0);
+        }
+    }
+    else
+    {
+        breakpoints_.erase(address);
+        resume(tid, 0);
+    }
+}
+
+void Debugger::continue_breakpoint_single_step(pid_t tid, ThreadState& state)
+{
+    state.single_step_count++;
+    auto it = breakpoints_.find(state.breakpoint_address);
+    if (it == breakpoints_.end() || state.single_step_count >= it->second.target_count + 1)
+    {
+        if (it != breakpoints_.end())
+        {
+            breakpoints_.erase(it);
+        }
+        state.single_step_mode = SingleStepMode::None;
+        state.single_step_count = 0;
+        state.breakpoint_address = 0;
+        resume(tid, 0);
+        return;
+    }
+
+    if (!single_step(tid))
+    {
+        state.single_step_mode = SingleStepMode::None;
+        state.single_step_count = 0;
+        resume(tid, 0);
+    }
+}
+
+void Debugger::stop_thread(pid_t tid, const char* reason, struct user_regs_struct& regs)
+{
+    hold_thread(tid, reason);
+    send_registers(regs);
+}
+
+void Debugger::hold_thread(pid_t tid, const char* reason)
+{
+    clear_run_to(tid);
+    ThreadState& state = threads_[tid];
+    state.stopped = true;
+    state.stop_reason = reason;
+    // Held already; an interrupt still pending just resumes the thread once it is continued
+    state.suspend_requested = false;
+    state.step_active = false;
+    state.step_out_returning = false;
+    state.step_count = 0;
+}
+
+// Decides what a thread stepping through the session API does after reaching `regs`
+void Debugger::advance_step(pid_t tid, struct user_regs_struct& regs)
+{
+    ThreadState& state = threads_[tid];
+    if (state.step_mode != StepMode::Out || state.step_out_returning)
+    {
+        stop_thread(tid, "step", regs);
+        return;
+    }
+    if (++state.step_count > kMaxStepOutInstructions)
+    {
+        stop_thread(tid, "step_limit", regs);
+        return;
+    }
+
+    uint64_t pc = program_counter(regs);
+    uint64_t size = 0;
+    switch (classify_instruction(pid_, pc, &size))
+    {
+        case 1:  // call: the callee runs freely until it comes back
+            if (run_to(tid, pc + size, stack_pointer(regs)))
+            {
+                resume(tid, 0);
+                return;
+            }
+            break;
+        case 2:  // return: one more step lands in the caller
+            state.step_out_returning = true;
+            break;
+        case -1:
+            stop_thread(tid, "step", regs);
+            return;
+    }
+    if (!single_step(tid))
+    {
+        stop_thread(tid, "step", regs);
+    }
+}
+
+bool Debugger::run_to(pid_t tid, uint64_t address, uint64_t sp)
+{
+    if (breakpoints_.find(address) != breakpoints_.end())
+    {
+        // The user's breakpoint at the return site is hit and handled in its place
+        switch (breakpoints_[address].mode)
+        {
+            case BreakpointMode::Stop:
+                return true;
+            case BreakpointMode::Conditional:
+                // Its hit handler recognizes the awaited return before evaluating the condition
+                threads_[tid].run_to_address = address;
+                threads_[tid].run_to_sp = sp;
+                return true;
+            default:
+                return false;
+        }
+    }
+
+    Breakpoint breakpoint;
+    breakpoint.original.resize(sizeof(kTrapInstruction));
+    breakpoint.run_to_owner = tid;
+    if (!read_bytes(address, breakpoint.original.data(), breakpoint.original.size()) ||
+        !insert_breakpoint(address, breakpoint))
+    {
+        return false;
+    }
+    retired_breakpoints_.erase(address);
+    breakpoints_[address] = breakpoint;
+
+    ThreadState& state = threads_[tid];
+    state.run_to_address = address;
+    state.run_to_sp = sp;
+    return true;
+}
+
+void Debugger::clear_run_to(pid_t tid)
+{
+    ThreadState& state = threads_[tid];
+    if (state.run_to_address == 0)
+    {
+        return;
+    }
+    auto it = breakpoints_.find(state.run_to_address);
+    if (it != breakpoints_.end() && it->second.run_to_owner == tid)
+    {
+        restore_breakpoint(it->first, it->second);
+        retire_breakpoint(it->first);
+        breakpoints_.erase(it);
+    }
+    state.run_to_address = 0;
+    state.run_to_sp = 0;
+}
+
+bool Debugger::wait_for_thread_stop(pid_t tid, int timeout_ms)
+{
+    auto deadline = std::chrono::steady_clock::now() + std::chrono::milliseconds(timeout_ms);
+    while (std::chrono::steady_clock::now() < deadline)
+    {
+        int status = 0;
+        pid_t result = waitpid(tid, &status, __WALL | WNOHANG);
+        if (result == tid)
+        {
+            handle_stop(tid, status);
+        }
+        else if (result == -1)
+        {
+            threads_.erase(tid);
+            return false;
+        }
+
+        auto it = threads_.find(tid);
+        if (it == threads_.end())
+        {
+            return false;
+        }
+        if (it->second.stopped)
+        {
+            return true;
+        }
+        std::this_thread::sleep_for(std::chrono::microseconds(20));
+    }
+    return false;
+}
+
+bool Debugger::any_thread_stepping()
+{
+    return std::any_of(threads_.begin(), threads_.end(),
+                       [](const std::pair<const pid_t, ThreadState>& entry) {
+                           return entry.second.stepping || entry.second.watchpoint_step;
+                       });
+}
+
+bool Debugger::single_step(pid_t tid)
+{
+    struct user_regs_struct regs;
+    if (!get_registers(tid, regs))
+    {
+        return false;
+    }
+
+    // Stepping onto an installed breakpoint would execute the trap, so lift it for one instruction
+    ThreadState& state = threads_[tid];
+    uint64_t pc = program_counter(regs);
+    auto it = breakpoints_.find(pc);
+    if (it != breakpoints_.end() && it->second.inserted)
+    {
+        restore_breakpoint(pc, it->second);
+        state.step_over_address = pc;
+    }
+
+    if (ptrace(PTRACE_SINGLESTEP, tid, nullptr, nullptr) == -1)
+    {
+        debug_log(LOG_ERROR, "PTRACE_SINGLESTEP failed for thread %d: %s", tid, strerror(errno));
+        return false;
+    }
+    state.stepping = true;
+    return true;
+}
+
+void Debugger::end_single_step(ThreadState& state)
+{
+    state.stepping = false;
+    if (state.step_over_address != 0)
+    {
+        auto it = breakpoints_.find(state.step_over_address);
+        if (it != breakpoints_.end())
+        {
+            insert_breakpoint(it->first, it->second);
+        }
+        state.step_over_address = 0;
+    }
+}
+
+// Waits for one traced instruction. Signals and ptrace events on the way are handled as usual and
+// the step carries on; false if the thread exited or had to be interrupted.
+bool Debugger::wait_for_traced_step(pid_t tid)
+{
+    auto start = std::chrono::steady_clock::now();
+    bool interrupted = false;
+    while (true)
+    {
+        int status = 0;
+        pid_t result = waitpid(tid, &status, __WALL | WNOHANG);
+        if (result == -1)
+        {
+            threads_.erase(tid);
+            return false;
+        }
+        if (result == tid)
+        {
+            if (WIFSTOPPED(status) && WSTOPSIG(status) == SIGTRAP && (status >> 16) == 0)
+            {
+                end_single_step(threads_[tid]);
+                return true;
+            }
+            handle_stop(tid, status);
+            auto it = threads_.find(tid);
+            if (it == threads_.end())
+            {
+                return false;
+            }
+            if (it->second.stopped && it->second.stop_reason == "suspended")
+            {
+                end_single_step(it->second);
+                return false;
+            }
+            continue;
+        }
+
+        auto elapsed = std::chrono::steady_clock::now() - start;
+        if (!interrupted && elapsed > std::chrono::milliseconds(kTraceStepTimeoutMs))
+        {
+            threads_[tid].suspend_requested = true;
+            ptrace(PTRACE_INTERRUPT, tid, nullptr, nullptr);
+            interrupted = true;
+        }
+        // Most steps finish within microseconds, so only a slow one is slept on
+        if (elapsed < std::chrono::milliseconds(1))
+        {
+            std::this_thread::yield();
+        }
+        else
+        {
+            std::this_thread::sleep_for(std::chrono::microseconds(50));
+        }
+    }
+}
+
+bool Debugger::resume(pid_t tid, int signal)
+{
+    // A thread interrupted in the middle of a step has to finish that step
+    auto it = threads_.find(tid);
+    bool stepping =
+        it != threads_.end() && (it->second.stepping || it->second.watchpoint_step);
+    void* data = reinterpret_cast<void*>(static_cast<long>(signal));
+    long result = stepping ? ptrace(PTRACE_SINGLESTEP, tid, nullptr, data)
+                           : ptrace(PTRACE_CONT, tid, nullptr, data);
+    if (result == -1)
+    {
+        debug_log(LOG_ERROR, "Failed to resume thread %d: %s", tid, strerror(errno));
+        return false;
+    }
+    return true;
+}
+
+bool Debugger::get_registers(pid_t tid, struct user_regs_struct& regs)
+{
+    struct iovec iov = {&regs, sizeof(regs)};
+    if (ptrace(PTRACE_GETREGSET, tid, reinterpret_cast<void*>(NT_PRSTATUS), &iov) == -1)
+    {
+        debug_log(LOG_ERROR, "Failed to get registers of thread %d: %s", tid, strerror(errno));
+        return false;
+    }
+    return true;
+}
+
+bool Debugger::set_registers(pid_t tid, struct user_regs_struct& regs)
+{
+    struct iovec iov = {&regs, sizeof(regs)};
+    if (ptrace(PTRACE_SETREGSET, tid, reinterpret_cast<void*>(NT_PRSTATUS), &iov) == -1)
+    {
+        debug_log(LOG_ERROR, "Failed to set registers of thread %d: %s", tid, strerror(errno));
+        return false;
+    }
+    return true;
+}
+
+std::vector<std::map<std::string, uint64_t>> Debugger::register_map(
+    const struct user_regs_struct& regs)
+{
+    std::vector<std::map<std::string, uint64_t>> map_vector;
+#if defined(__x86_64__)
+    map_vector.push_back({{"rax", regs.rax}});
+    map_vector.push_back({{"rbx", regs.rbx}});
+    map_vector.push_back({{"rcx", regs.rcx}});
+    map_vector.push_back({{"rdx", regs.rdx}});
+    map_vector.push_back({{"rsi", regs.rsi}});
+    map_vector.push_back({{"rdi", regs.rdi}});
+    map_vector.push_back({{"rbp", regs.rbp}});
+    map_vector.push_back({{"rsp", regs.rsp}});
+    map_vector.push_back({{"r8", regs.r8}});
+    map_vector.push_back({{"r9", regs.r9}});
+    map_vector.push_back({{"r10", regs.r10}});
+    map_vector.push_back({{"r11", regs.r11}});
+    map_vector.push_back({{"r12", regs.r12}});
+    map_vector.push_back({{"r13", regs.r13}});
+    map_vector.push_back({{"r14", regs.r14}});
+    map_vector.push_back({{"r15", regs.r15}});
+    map_vector.push_back({{"rip", regs.rip}});
+    map_vector.push_back({{"rflags", regs.eflags}});
+    map_vector.push_back({{"sp", regs.rsp}});
+    map_vector.push_back({{"pc", regs.rip}});
+#else
+    for (int i = 0; i < 30; ++i)
+    {
+        map_vector.push_back({{"x" + std::to_string(i), regs.regs[i]}});
+    }
+    map_vector.push_back({{"lr", regs.regs[30]}});
+    map_vector.push_back({{"fp", regs.regs[29]}});
+    map_vector.push_back({{"sp", regs.sp}});
+    map_vector.push_back({{"pc", regs.pc}});
+    map_vector.push_back({{"cpsr", regs.pstate}});
+#endif
+    return map_vector;
+}
+
+#if defined(__x86_64__)
+typedef struct user_fpregs_struct FpRegisters;
+#else
+typedef struct user_fpsimd_struct FpRegisters;
+#endif
+
+// A register inside one of the two register sets, addressed by byte offset
+struct RegisterField
+{
+    std::string name;
+    bool fp;
+    size_t offset;
+    size_t size;
+};
+
+#define GENERAL_REGISTER(name, field)                                    \
+    RegisterField{name, false, offsetof(struct user_regs_struct, field), \
+                  sizeof(user_regs_struct::field)}
+#define FP_REGISTER(name, field) \
+    RegisterField{name, true, offsetof(FpRegisters, field), sizeof(FpRegisters::field)}
+
+// Names accepted for reading and writing; aliases such as pc and sp come after the architectural
+// names, matching the register JSON of exception events
+static const std::vector<RegisterField>& register_fields()
+{
+    static const std::vector<RegisterField> fields = []() {
+        std::vector<RegisterField> fields;
+#if defined(__x86_64__)
+        fields = {
+            GENERAL_REGISTER("rax", rax),
+            GENERAL_REGISTER("rbx", rbx),
+            GENERAL_REGISTER("rcx", rcx),
+            GENERAL_REGISTER("rdx", rdx),
+            GENERAL_REGISTER("rsi", rsi),
+            GENERAL_REGISTER("rdi", rdi),
+            GENERAL_REGISTER("rbp", rbp),
+            GENERAL_REGISTER("rsp", rsp),
+            GENERAL_REGISTER("r8", r8),
+            GENERAL_REGISTER("r9", r9),
+            GENERAL_REGISTER("r10", r10),
+            GENERAL_REGISTER("r11", r11),
+            GENERAL_REGISTER("r12", r12),
+            GENERAL_REGISTER("r13", r13),
+            GENERAL_REGISTER("r14", r14),
+            GENERAL_REGISTER("r15", r15),
+            GENERAL_REGISTER("rip", rip),
+            GENERAL_REGISTER("rflags", eflags),
+            GENERAL_REGISTER("cs", cs),
+            GENERAL_REGISTER("ss", ss),
+            GENERAL_REGISTER("ds", ds),
+            GENERAL_REGISTER("es", es),
+            GENERAL_REGISTER("fs", fs),
+            GENERAL_REGISTER("gs", gs),
+            GENERAL_REGISTER("fs_base", fs_base),
+            GENERAL_REGISTER("gs_base", gs_base),
+            GENERAL_REGISTER("orig_rax", orig_rax),
+            GENERAL_REGISTER("sp", rsp),
+            GENERAL_REGISTER("pc", rip),
+            GENERAL_REGISTER("flags", eflags),
+            FP_REGISTER("fcw", cwd),
+            FP_REGISTER("fsw", swd),
+            FP_REGISTER("ftw", ftw),
+            FP_REGISTER("mxcsr", mxcsr),
+        };
+        // x87 registers occupy 16 byte slots of which the low 10 bytes are significant
+        for (size_t i = 0; i < 8; i++)
+        {
+            fields.push_back(
+                {"st" + std::to_string(i), true, offsetof(FpRegisters, st_space) + i * 16, 16});
+        }
+        for (size_t i = 0; i < 16; i++)
+        {
+            fields.push_back(
+                {"xmm" + std::to_string(i), true, offsetof(FpRegisters, xmm_space) + i * 16, 16});
+        }
+#else
+        for (size_t i = 0; i < 31; i++)
+        {
+            fields.push_back({"x" + std::to_string(i), false,
+                              offsetof(struct user_regs_struct, regs) + i * 8, 8});
+        }
+        fields.push_back({"fp", false, offsetof(struct user_regs_struct, regs) + 29 * 8, 8});
+        fields.push_back({"lr", false, offsetof(struct user_regs_struct, regs) + 30 * 8, 8});
+        fields.push_back(GENERAL_REGISTER("sp", sp));
+        fields.push_back(GENERAL_REGISTER("pc", pc));
+        fields.push_back(GENERAL_REGISTER("cpsr", pstate));
+        fields.push_back(GENERAL_REGISTER("flags", pstate));
+        for (size_t i = 0; i < 32; i++)
+        {
+            fields.push_back(
+                {"v" + std::to_string(i), true, offsetof(FpRegisters, vregs) + i * 16, 16});
+        }
+        fields.push_back(FP_REGISTER("fpsr", fpsr));
+        fields.push_back(FP_REGISTER("fpcr", fpcr));
+#endif
+        return fields;
+    }();
+    return fields;
+}
+
+#undef GENERAL_REGISTER
+#undef FP_REGISTER
+
+// Little-endian register bytes as a hex number, two digits per byte
+static std::string register_hex(const uint8_t* bytes, size_t size)
+{
+    static const char kDigits[] = "0123456789ABCDEF";
+    std::string hex = "0x";
+    for (size_t i = size; i-- > 0;)
+    {
+        hex += kDigits[bytes[i] >> 4];
+        hex += kDigits[bytes[i] & 0xF];
+    }
+    return hex;
+}
+
+bool Debugger::get_fp_registers(pid_t tid, void* fp_regs)
+{
+    struct iovec iov = {fp_regs, sizeof(FpRegisters)};
+    if (ptrace(PTRACE_GETREGSET, tid, reinterpret_cast<void*>(NT_PRFPREG), &iov) == -1)
+    {
+        debug_log(LOG_ERROR, "Failed to get FP registers for thread %d: %s", tid, strerror(errno));
+        return false;
+    }
+    return true;
+}
+
+bool Debugger::set_fp_registers(pid_t tid, void* fp_regs)
+{
+    struct iovec iov = {fp_regs, sizeof(FpRegisters)};
+    if (ptrace(PTRACE_SETREGSET, tid, reinterpret_cast<void*>(NT_PRFPREG), &iov) == -1)
+    {
+        debug_log(LOG_ERROR, "Failed to set FP registers for thread %d: %s", tid, strerror(errno));
+        return false;
+    }
+    return true;
+}
+
+int Debugger::trace_thread(pid_t tid)
+{
+    return execute([this, tid]() -> int {
+        auto it = threads_.find(tid);
+        if (it == threads_.end() || !it->second.stopped)
+        {
+            debug_log(LOG_ERROR, "Thread %d is not stopped", tid);
+            errno = ESRCH;
+            return -1;
+        }
+        // Watchpoints stay off for the traced thread, so every trap is the end of a step
+        it->second.watchpoint_pending = false;
+        apply_watchpoints(tid, false);
+
+        struct user_regs_struct regs;
+        while (get_registers(tid, regs))
+        {
+            std::string register_json = map_vector_to_json_string(register_map(regs));
+            if (record_trace_step(pid_, tid, register_json.c_str()) != 0 || !single_step(tid) ||
+                !wait_for_traced_step(tid))
+            {
+                break;
+            }
+        }
+
+        if (threads_.find(tid) != threads_.end())
+        {
+            apply_watchpoints(tid, true);
+            hold_thread(tid, "trace");
+        }
+        return 0;
+    });
+}
+
+int Debugger::thread_registers(pid_t tid, std::string& json)
+{
+    return execute([this, tid, &json]() -> int {
+        auto it = threads_.find(tid);
+        if (it == threads_.end() || !it->second.stopped)
+        {
+            debug_log(LOG_ERROR, "Thread %d is not stopped", tid);
+            errno = ESRCH;
+            return -1;
+        }
+        struct user_regs_struct regs;
+        FpRegisters fp_regs;
+        if (!get_registers(tid, regs) || !get_fp_registers(tid, &fp_regs))
+        {
+            return -1;
+        }
+
+        std::ostringstream general;
+        std::ostringstream fp;
+        for (const RegisterField& field : register_fields())
+        {
+            const uint8_t* base = field.fp ? reinterpret_cast<const uint8_t*>(&fp_regs)
+                                           : reinterpret_cast<const uint8_t*>(&regs);
+            std::ostringstream& out = field.fp ? fp : general;
+            out << (out.tellp() == 0 ? "" : ",") << "\"" << field.name << "\":\""
+                << register_hex(base + field.offset, field.size) << "\"";
+        }
+        json = "{\"general\":{" + general.str() + "},\"fp\":{" + fp.str() + "}}";
+        return 0;
+    });
+}
+
+int Debugger::set_thread_registers(pid_t tid, const std::vector<std::string>& names,
+                                   const std::vector<std::vector<uint8_t>>& values)
+{
+    return execute([this, tid, &names, &values]() -> int {
+        auto it = threads_.find(tid);
+        if (it == threads_.end() || !it->second.stopped)
+        {
+            debug_log(LOG_ERROR, "Thread %d is not stopped", tid);
+            errno = ESRCH;
+            return -1;
+        }
+        struct user_regs_struct regs;
+        FpRegisters fp_regs;
+        if (!get_registers(tid, regs) || !get_fp_registers(tid, &fp_regs))
+        {
+            return -1;
+        }
+
+        // Every value is checked before either register set is written back
+        bool general_changed = false;
+        bool fp_changed = false;
+        for (size_t i = 0; i < names.size(); i++)
+        {
+            const auto& fields = register_fields();
+            auto field = std::find_if(fields.begin(), fields.end(), [&](const RegisterField& f) {
+                return f.name == names[i];
+            });
+            if (field == fields.end())
+            {
+                debug_log(LOG_ERROR, "Unknown register %s", names[i].c_str());
+                errno = EINVAL;
+                return -1;
+            }
+            const std::vector<uint8_t>& value = values[i];
+            if (std::any_of(value.begin() + std::min(field->size, value.size()), value.end(),
+                            [](uint8_t byte) { return byte != 0; }))
+            {
+                debug_log(LOG_ERROR, "Value does not fit register %s", names[i].c_str());
+                errno = ERANGE;
+                return -1;
+            }
+            uint8_t* base = field->fp ? reinterpret_cast<uint8_t*>(&fp_regs)
+                                      : reinterpret_cast<uint8_t*>(&regs);
+            std::memset(base + field->offset, 0, field->size);
+            std::memcpy(base + field->offset, value.data(), std::min(field->size, value.size()));
+            (field->fp ? fp_changed : general_changed) = true;
+        }
+        if (general_changed && !set_registers(tid, regs))
+        {
+            return -1;
+        }
+        if (fp_changed && !set_fp_registers(tid, &fp_regs))
+        {
+            return -1;
+        }
+        return 0;
+    });
+}
+
+void Debugger::send_registers(const struct user_regs_struct& regs)
+{
+    std::string register_json = map_vector_to_json_string(register_map(regs));
+    send_register_json(register_json.c_str(), pid_);
+}
+
+bool Debugger::read_bytes(uint64_t address, void* buffer, size_t size)
+{
+    return pread(mem_fd_, buffer, size, static_cast<off_t>(address)) == static_cast<ssize_t>(size);
+}
+
+bool Debugger::write_bytes(uint64_t address, const void* buffer, size_t size)
+{
+    // /proc/pid/mem writes go through the kernel's ptrace path, so read-only text is patchable
+    return pwrite(mem_fd_, buffer, size, static_cast<off_t>(address)) ==
+           static_cast<ssize_t>(size);
+}
+
+bool Debugger::insert_breakpoint(uint64_t address, Breakpoint& breakpoint)
+{
+    if (breakpoint.inserted)
+    {
+        return true;
+    }
+    if (!write_bytes(address, kTrapInstruction, sizeof(kTrapInstruction)))
+    {
+        debug_log(LOG_ERROR, "Failed to insert breakpoint at 0x%llx: %s",
+                  static_cast<unsigned long long>(address), strerror(errno));
+        return false;
+    }
+    breakpoint.inserted = true;
+    std::lock_guard<std::mutex> lock(inserted_mutex_);
+    inserted_code_[address] = breakpoint.original;
+    return true;
+}
+
+bool Debugger::restore_breakpoint(uint64_t address, Breakpoint& breakpoint)
+{
+    if (!breakpoint.inserted)
+    {
+        return true;
+    }
+    if (!write_bytes(address, breakpoint.original.data(), breakpoint.original.size()))
+    {
+        debug_log(LOG_ERROR, "Failed to restore original bytes at 0x%llx: %s",
+                  static_cast<unsigned long long>(address), strerror(errno));
+        return false;
+    }
+    breakpoint.inserted = false;
+    std::lock_guard<std::mutex> lock(inserted_mutex_);
+    inserted_code_.erase(address);
+    return true;
+}
+
+void Debugger::mask_breakpoints(uint64_t address, uint8_t* buffer, size_t size)
+{
+    std::lock_guard<std::mutex> lock(inserted_mutex_);
+    for (const auto& entry : inserted_code_)
+    {
+        for (size_t i = 0; i < entry.second.size(); i++)
+        {
+            uint64_t byte_address = entry.first + i;
+            if (byte_address >= address && byte_address < address + size)
+            {
+                buffer[byte_address - address] = entry.second[i];
+            }
+        }
+    }
+}
+
+bool Debugger::in_use()
+{
+    return execute([this]() -> bool {
+        if (!breakpoints_.empty())
+        {
+            return true;
+        }
+        for (const Watchpoint& watchpoint : watchpoints_)
+        {
+            if (watchpoint.used)
+            {
+                return true;
+            }
+        }
+        for (const auto& entry : threads_)
+        {
+            if (entry.second.stopped)
+            {
+                return true;
+            }
+        }
+        return false;
+    });
+}
+
+void Debugger::retire_breakpoint(uint64_t address)
+{
+    retired_breakpoints_.insert(address);
+    for (auto& entry : threads_)
+    {
+        if (entry.second.step_over_address == address)
+        {
+            entry.second.step_over_address = 0;
+        }
+    }
+}
+
+int Debugger::watchpoint_slot_count()
+{
+#if defined(__x86_64__)
+    return kMaxWatchpoints;
+#else
+    static int slot_count = -1;
+    if (slot_count == -1)
+    {
+        with_thread_stopped(pid_, [this]() {
+            HwDebugState state = {};
+            struct iovec iov = {&state, sizeof(state)};
+            if (ptrace(PTRACE_GETREGSET, pid_, reinterpret_cast<void*>(NT_ARM_HW_WATCH), &iov) ==
+                0)
+            {
+                slot_count = std::min<int>(state.dbg_info & 0xFF, kMaxWatchpoints);
+            }
+        });
+    }
+    return std::max(slot_count, 0);
+#endif
+}
+
+bool Debugger::apply_watchpoints(pid_t tid, bool enable)
+{
+#if defined(__x86_64__)
+    // DR7 goes to zero first so the address writes are never validated against stale controls
+    if (ptrace(PTRACE_POKEUSER, tid, debug_register_offset(7), nullptr) == -1)
+    {
+        debug_log(LOG_ERROR, "Failed to clear DR7 of thread %d: %s", tid, strerror(errno));
+        return false;
+    }
+
+    uint64_t dr7 = 0;
+    for (int i = 0; i < kMaxWatchpoints; i++)
+    {
+        const Watchpoint& watchpoint = watchpoints_[i];
+        bool active = enable && watchpoint.used;
+        void* address = reinterpret_cast<void*>(active ? watchpoint.address : 0);
+        if (ptrace(PTRACE_POKEUSER, tid, debug_register_offset(i), address) == -1)
+        {
+            debug_log(LOG_ERROR, "Failed to set DR%d of thread %d: %s", i, tid, strerror(errno));
+            return false;
+        }
+        if (!active)
+        {
+            continue;
+        }
+
+        // Read-only watchpoints are rejected by set_watchpoint, x86 has no such condition
+        uint64_t condition = watchpoint.type == WatchpointType::WRITE ? 1 : 3;
+        uint64_t length = 0;
+        switch (watchpoint.size)
+        {
+            case 2:
+                length = 1;
+                break;
+            case 4:
+                length = 3;
+                break;
+            case 8:
+                length = 2;
+                break;
+        }
+        dr7 |= 1ULL << (i * 2);
+        dr7 |= condition << (16 + i * 4);
+        dr7 |= length << (18 + i * 4);
+    }
+
+    if (dr7 != 0 &&
+        ptrace(PTRACE_POKEUSER, tid, debug_register_offset(7), reinterpret_cast<void*>(dr7)) == -1)
+    {
+        debug_log(LOG_ERROR, "Failed to set DR7 of thread %d: %s", tid, strerror(errno));
+        return false;
+    }
+    return true;
+#else
+    int slot_count = watchpoint_slot_count();
+    HwDebugState state = {};
+    for (int i = 0; i < slot_count; i++)
+    {
+        const Watchpoint& watchpoint = watchpoints_[i];
+        if (!enable || !watchpoint.used)
+        {
+            continue;
+        }
+
+        uint32_t control = 1;  // Enable
+        control |= 2U << 1;    // EL0
+        switch (watchpoint.type)
+        {
+            case WatchpointType::READ:
+                control |= 1U << 3;
+                break;
+            case WatchpointType::WRITE:
+                control |= 2U << 3;
+                break;
+            case WatchpointType::READWRITE:
+                control |= 3U << 3;
+                break;
+        }
+        // Byte address select within the aligned doubleword
+        uint32_t offset = watchpoint.address & 7;
+        control |= ((1U << watchpoint.size) - 1) << (5 + offset);
+
+        state.dbg_regs[i].addr = watchpoint.address & ~7ULL;
+        state.dbg_regs[i].ctrl = control;
+    }
+
+    struct iovec iov = {&state, offsetof(HwDebugState, dbg_regs) +
+                                    slot_count * sizeof(state.dbg_regs[0])};
+    if (ptrace(PTRACE_SETREGSET, tid, reinterpret_cast<void*>(NT_ARM_HW_WATCH), &iov) == -1)
+    {
+        debug_log(LOG_ERROR, "Failed to set watchpoints of thread %d: %s", tid, strerror(errno));
+        return false;
+    }
+    return true;
+#endif
+}
+
+void Debugger::apply_watchpoints_to_all_threads()
+{
+    std::vector<pid_t> tids;
+    for (const auto& entry : threads_)
+    {
+        tids.push_back(entry.first);
+    }
+    for (pid_t tid : tids)
+    {
+        with_thread_stopped(tid, [this, tid]() { apply_watchpoints(tid, true); });
+    }
+}
+
+bool Debugger::handle_watchpoint_hit(pid_t tid, struct user_regs_struct& regs, bool& stop)
+{
+    int index = -1;
+    uint64_t accessed = 0;
+#if defined(__x86_64__)
+    errno = 0;
+    long dr6 = ptrace(PTRACE_PEEKUSER, tid, debug_register_offset(6), nullptr);
+    if (errno != 0)
+    {
+        return false;
+    }
+    for (int i = 0; i < kMaxWatchpoints; i++)
+    {
+        if ((dr6 & (1L << i)) && watchpoints_[i].used)
+        {
+            index = i;
+            break;
+        }
+    }
+    if (dr6 & 0xF)
+    {
+        ptrace(PTRACE_POKEUSER, tid, debug_register_offset(6), nullptr);
+    }
+    if (index == -1)
+    {
+        return false;
+    }
+    // The trap comes after the access and x86 does not report the accessed address
+    accessed = watchpoints_[index].address;
+#else
+    siginfo_t info;
+    if (ptrace(PTRACE_GETSIGINFO, tid, nullptr, &info) == -1 || info.si_code != TRAP_HWBKPT)
+    {
+        return false;
+    }
+    accessed = reinterpret_cast<uint64_t>(info.si_addr);
+    for (int i = 0; i < kMaxWatchpoints; i++)
+    {
+        const Watchpoint& watchpoint = watchpoints_[i];
+        if (watchpoint.used && accessed >= (watchpoint.address & ~7ULL) &&
+            accessed < watchpoint.address + watchpoint.size)
+        {
+            index = i;
+            break;
+        }
+    }
+    if (index == -1)
+    {
+        // A slot removed while the hit was in flight; the access is stepped over unreported
+        debug_log(LOG_DEBUG, "Unmatched watchpoint hit at 0x%llx in thread %d",
+                  static_cast<unsigned long long>(accessed), tid);
+        stop = false;
+        return true;
+    }
+#endif
+
+    stop = watchpoints_[index].stop;
+    auto map_vector = register_map(regs);
+    map_vector.push_back({{"memory", accessed}});
+    std::string register_json = map_vector_to_json_string(map_vector);
+    send_register_json(register_json.c_str(), pid_);
+    return true;
+}
+
+int Debugger::set_watchpoint(uint64_t address, int size, WatchpointType type, bool stop)
+{
+    return execute([this, address, size, type, stop]() -> int {
+        if (size != 1 && size != 2 && size != 4 && size != 8)
+        {
+            debug_log(LOG_ERROR, "Invalid watchpoint size");
+            errno = EINVAL;
+            return -1;
+        }
+#if defined(__x86_64__)
+        // A read/write watchpoint would report writes as reads
+        if (type == WatchpointType::READ)
+        {
+            debug_log(LOG_ERROR, "Read-only watchpoints are not supported on x86_64");
+            errno = EINVAL;
+            return -1;
+        }
+        if (address % size != 0)
+#else
+        if ((address & 7) + size > 8)
+#endif
+        {
+            debug_log(LOG_ERROR, "Watchpoint at 0x%llx is not aligned to its size",
+                      static_cast<unsigned long long>(address));
+            errno = EINVAL;
+            return -1;
+        }
+
+        int slot_count = watchpoint_slot_count();
+        int index = -1;
+        for (int i = 0; i < slot_count; i++)
+        {
+            if (watchpoints_[i].used && watchpoints_[i].address == address)
+            {
+                errno = EEXIST;
+                return -1;
+            }
+            if (!watchpoints_[i].used && index == -1)
+            {
+                index = i;
+            }
+        }
+        if (index == -1)
+        {
+            debug_log(LOG_ERROR, "No free watchpoints available.");
+            errno = ENOSPC;
+            return -1;
+        }
+
+        Watchpoint& watchpoint = watchpoints_[index];
+        watchpoint.used = true;
+        watchpoint.address = address;
+        watchpoint.size = size;
+        watchpoint.type = type;
+        watchpoint.stop = stop;
+        apply_watchpoints_to_all_threads();
+        debug_log(LOG_INFO, "Watchpoint set successfully at address 0x%llx",
+                  static_cast<unsigned long long>(address));
+        return 0;
+    });
+}
+
+int Debugger::remove_watchpoint(uint64_t address)
+{
+    return execute([this, address]() -> int {
+        for (Watchpoint& watchpoint : watchpoints_)
+        {
+            if (watchpoint.used && watchpoint.address == address)
+            {
+                watchpoint = Watchpoint();
+                apply_watchpoints_to_all_threads();
+                debug_log(LOG_INFO, "Watchpoint removed successfully from address 0x%llx",
+                          static_cast<unsigned long long>(address));
+                return 0;
+            }
+        }
+        debug_log(LOG_ERROR, "Watchpoint not found for address: 0x%llx",
+                  static_cast<unsigned long long>(address));
+        errno = ENOENT;
+        return -1;
+    });
+}
+
+int Debugger::set_breakpoint(uint64_t address, int hit_count, BreakpointMode mode)
+{
+    return execute([this, address, hit_count, mode]() -> int {
+        if (breakpoints_.find(address) != breakpoints_.end())
+        {
+            debug_log(LOG_ERROR, "Breakpoint already set at address 0x%llx",
+                      static_cast<unsigned long long>(address));
+            errno = EEXIST;
+            return -1;
+        }
+
+        Breakpoint breakpoint;
+        breakpoint.original.resize(sizeof(kTrapInstruction));
+        if (!read_bytes(address, breakpoint.original.data(), breakpoint.original.size()))
+        {
+            debug_log(LOG_ERROR, "Failed to read original bytes at 0x%llx: %s",
+                      static_cast<unsigned long long>(address), strerror(errno));
+            return -1;
+        }
+        breakpoint.target_count = hit_count;
+        breakpoint.mode = mode;
+        if (!insert_breakpoint(address, breakpoint))
+        {
+            return -1;
+        }
+
+        retired_breakpoints_.erase(address);
+        breakpoints_[address] = breakpoint;
+        debug_log(LOG_INFO, "Breakpoint set successfully at address 0x%llx",
+                  static_cast<unsigned long long>(address));
+        return 0;
+    });
+}
+
+int Debugger::remove_breakpoint(uint64_t address)
+{
+    return execute([this, address]() -> int {
+        auto it = breakpoints_.find(address);
+        if (it == breakpoints_.end())
+        {
+            debug_log(LOG_ERROR, "Breakpoint not found for address: 0x%llx",
+                      static_cast<unsigned long long>(address));
+            errno = ENOENT;
+            return -1;
+        }
+        if (!restore_breakpoint(address, it->second))
+        {
+            return -1;
+        }
+
+        // Threads tracing this breakpoint stop stepping at their next step
+        breakpoints_.erase(it);
+        retire_breakpoint(address);
+        debug_log(LOG_INFO, "Breakpoint removed successfully from address 0x%llx",
+                  static_cast<unsigned long long>(address));
+        return 0;
+    });
+}
+
+int Debugger::stopped_threads(std::string& json)
+{
+    return execute([this, &json]() -> int {
+        std::ostringstream out;
+        out << "[";
+        bool first = true;
+        for (auto& entry : threads_)
+        {
+            struct user_regs_struct regs;
+            if (!entry.second.stopped || !get_registers(entry.first, regs))
+            {
+                continue;
+            }
+            out << (first ? "" : ",") << "{\"tid\":" << entry.first << ",\"reason\":\""
+                << entry.second.stop_reason
+                << "\",\"registers\":" << map_vector_to_json_string(register_map(regs)) << "}";
+            first = false;
+        }
+        out << "]";
+        json = out.str();
+        return 0;
+    });
+}
+
+int Debugger::continue_thread(pid_t tid)
+{
+    return execute([this, tid]() -> int {
+        bool resumed = false;
+        for (auto& entry : threads_)
+        {
+            ThreadState& state = entry.second;
+            if (!state.stopped || (tid != 0 && entry.first != tid))
+            {
+                continue;
+            }
+            state.stopped = false;
+            state.stop_reason.clear();
+            if (state.watchpoint_pending)
+            {
+                state.watchpoint_pending = false;
+                apply_watchpoints(entry.first, false);
+                state.watchpoint_step = true;
+                resume(entry.first, 0);
+                resumed = true;
+                continue;
+            }
+
+            // Stepping lifts a breakpoint under the PC for one instruction; resume() alone would
+            // trap on it again
+            struct user_regs_struct regs;
+            if (get_registers(entry.first, regs) &&
+                breakpoints_.count(program_counter(regs)) != 0)
+            {
+                single_step(entry.first);
+            }
+            else
+            {
+                resume(entry.first, 0);
+            }
+            resumed = true;
+        }
+        if (!resumed)
+        {
+            errno = ESRCH;
+            return -1;
+        }
+        return 0;
+    });
+}
+
+int Debugger::step_thread(pid_t tid, StepMode mode)
+{
+    return execute([this, tid, mode]() -> int {
+        auto it = threads_.find(tid);
+        if (it == threads_.end() || !it->second.stopped)
+        {
+            debug_log(LOG_ERROR, "Thread %d is not stopped", tid);
+            errno = ESRCH;
+            return -1;
+        }
+        struct user_regs_struct regs;
+        if (!get_registers(tid, regs))
+        {
+            return -1;
+        }
+
+        ThreadState& state = it->second;
+        state.stopped = false;
+        state.stop_reason.clear();
+        state.step_active = true;
+        state.step_mode = mode;
+        state.step_out_returning = false;
+        state.step_count = 0;
+        if (state.watchpoint_pending)
+        {
+            state.watchpoint_pending = false;
+            apply_watchpoints(tid, false);
+            state.watchpoint_step = true;
+        }
+
+        uint64_t pc = program_counter(regs);
+        uint64_t size = 0;
+        if (mode == StepMode::Out)
+        {
+            advance_step(tid, regs);
+        }
+        else if (mode == StepMode::Over && classify_instruction(pid_, pc, &size) == 1 &&
+                 run_to(tid, pc + size, stack_pointer(regs)))
+        {
+            // Step past the call's own breakpoint, if any; the thread then runs to the return
+            if (breakpoints_.count(pc) != 0)
+            {
+                single_step(tid);
+            }
+            else
+            {
+                resume(tid, 0);
+            }
+        }
+        else if (!single_step(tid))
+        {
+            stop_thread(tid, "step", regs);
+            return -1;
+        }
+
+        // Short steps report their final state directly; longer ones keep running
+        wait_for_thread_stop(tid, 100);
+        return 0;
+    });
+}
+
+int Debugger::suspend_thread(pid_t tid)
+{
+    return execute([this, tid]() -> int {
+        auto it = threads_.find(tid);
+        if (it == threads_.end())
+        {
+            debug_log(LOG_ERROR, "Thread %d is not traced", tid);
+            errno = ESRCH;
+            return -1;
+        }
+        if (it->second.stopped)
+        {
+            return 0;
+        }
+        it->second.suspend_requested = true;
+        if (ptrace(PTRACE_INTERRUPT, tid, nullptr, nullptr) == -1)
+        {
+            it->second.suspend_requested = false;
+            return -1;
+        }
+
+        // A thread in a group stop only reports the interrupt once it is continued; the
+        // request stays pending until then
+        if (!wait_for_thread_stop(tid, 1000))
+        {
+            debug_log(LOG_WARN, "Thread %d did not stop yet; it is held once it does", tid);
+        }
+        return 0;
+    });
+}
+
+int Debugger::run_syscall(long number, const long* args, size_t arg_count, long* result)
+{
+    return execute([this, number, args, arg_count, result]() -> int {
+        // A held thread is already stopped; otherwise the main thread is interrupted for it
+        pid_t tid = 0;
+        for (const auto& [id, state] : threads_)
+        {
+            if (state.stopped)
+            {
+                tid = id;
+                break;
+            }
+        }
+        if (tid == 0)
+        {
+            tid = threads_.count(pid_) != 0 || threads_.empty() ? pid_ : threads_.begin()->first;
+        }
+
+        bool success = false;
+        bool stopped = with_thread_stopped(tid, [&]() {
+            success = remote_syscall(tid, number, args, arg_count, result);
+        });
+        if (!stopped || !success)
+        {
+            if (errno == 0 || errno == ENOENT)
+            {
+                errno = ESRCH;
+            }
+            return -1;
+        }
+        return 0;
+    });
+}
+
 extern "C"
 {
     bool debugger_new(int pid)
     {
+        if (g_debugger != nullptr && (g_debugger->pid() != pid || !g_debugger->is_running()))
+        {
+            delete g_debugger;
+            g_debugger = nullptr;
+        }
+        if (g_debugger == nullptr)
+        {
+            g_debugger = new Debugger(pid);
+            if (!g_debugger->initialize())
+            {
+                delete g_debugger;
+                g_debugger = nullptr;
+                return false;
+            }
+        }
         return true;
     }
 
-    int set_watchpoint_native(uint64_t address, int size, WatchpointType type)
+    void debugger_free()
     {
-        return 0;
+        // Detaching puts back the original bytes of every breakpoint still armed
+        delete g_debugger;
+        g_debugger = nullptr;
+    }
+
+    bool debugger_attached_native(int pid)
+    {
+        return g_debugger != nullptr && g_debugger->pid() == pid && g_debugger->is_running();
+    }
+
+    bool debugger_in_use_native()
+    {
+        return g_debugger != nullptr && g_debugger->in_use();
+    }
+
+    int set_watchpoint_native(uint64_t address, int size, WatchpointType type, bool stop)
+    {
+        if (g_debugger)
+        {
+            return g_debugger->set_watchpoint(address, size, type, stop);
+        }
+        errno = ESRCH;
+        return -1;
     }
 
     int remove_watchpoint_native(uint64_t address)
     {
-        return 0;
+        if (g_debugger)
+        {
+            return g_debugger->remove_watchpoint(address);
+        }
+        errno = ESRCH;
+        return -1;
     }
 
-    int set_breakpoint_native(uint64_t address, int hit_count)
+    int set_breakpoint_native(uint64_t address, int hit_count, int mode)
     {
-        return 0;
+        if (g_debugger)
+        {
+            return g_debugger->set_breakpoint(address, hit_count,
+                                              static_cast<BreakpointMode>(mode));
+        }
+        errno = ESRCH;
+        return -1;
     }
 
     int remove_breakpoint_native(uint64_t address)
     {
-        return 0;
+        if (g_debugger)
+        {
+            return g_debugger->remove_breakpoint(address);
+        }
+        errno = ESRCH;
+        return -1;
+    }
+
+    char* debugger_threads_native()
+    {
+        std::string json = "[]";
+        if (g_debugger && g_debugger->stopped_threads(json) != 0)
+        {
+            return nullptr;
+        }
+        return strdup(json.c_str());
+    }
+
+    char* debugger_get_registers_native(int tid)
+    {
+        std::string json;
+        if (!g_debugger)
+        {
+            errno = ESRCH;
+            return nullptr;
+        }
+        if (g_debugger->thread_registers(tid, json) != 0)
+        {
+            return nullptr;
+        }
+        return strdup(json.c_str());
+    }
+
+    // `values` holds `count` little-endian 16 byte values, one per name
+    int debugger_set_registers_native(int tid, const char** names, const uint8_t* values,
+                                      size_t count)
+    {
+        if (!g_debugger)
+        {
+            errno = ESRCH;
+            return -1;
+        }
+        std::vector<std::string> register_names;
+        std::vector<std::vector<uint8_t>> register_values;
+        for (size_t i = 0; i < count; i++)
+        {
+            register_names.push_back(names[i]);
+            register_values.emplace_back(values + i * 16, values + (i + 1) * 16);
+        }
+        return g_debugger->set_thread_registers(tid, register_names, register_values);
     }
-}
\ No newline at end of file
+
+    void debugger_mask_breakpoints_native(uint64_t address, uint8_t* buffer, size_t size)
+    {
+        if (g_debugger)
+        {
+            g_debugger->mask_breakpoints(address, buffer, size);
+        }
+    }
+
+    int debugger_continue_native(int tid)
+    {
+        if (g_debugger)
+        {
+            return g_debugger->continue_thread(tid);
+        }
+        errno = ESRCH;
+        return -1;
+    }
+
+    int debugger_step_native(int tid, int mode)
+    {
+        if (g_debugger)
+        {
+            return g_debugger->step_thread(tid, static_cast<StepMode>(mode));
+        }
+        errno = ESRCH;
+        return -1;
+    }
+
+    int debugger_suspend_native(int tid)
+    {
+        if (g_debugger)
+        {
+            return g_debugger->suspend_thread(tid);
+        }
+        errno = ESRCH;
+        return -1;
+    }
+
+    int debugger_trace_native(int tid)
+    {
+        if (g_debugger)
+        {
+            return g_debugger->trace_thread(tid);
+        }
+        errno = ESRCH;
+        return -1;
+    }
+
+    int debugger_syscall_native(int pid, long number, const long* args, size_t arg_count,
+                                long* result)
+    {
+        if (g_debugger == nullptr || g_debugger->pid() != pid || !g_debugger->is_running())
+        {
+            errno = ENOENT;
+            return -1;
+        }
+        return g_debugger->run_syscall(number, args, arg_count, result);
+    }
+}
+
+#else
+
+extern "C"
+{
+    bool debugger_new(int pid)
+    {
+        return false;
+    }
+
+    void debugger_free() {}
+
+    bool debugger_attached_native(int pid)
+    {
+        return false;
+    }
+
+    bool debugger_in_use_native()
+    {
+        return false;
+    }
+
+    int set_watchpoint_native(uint64_t address, int size, WatchpointType type, bool stop)
+    {
+        errno = ENOTSUP;
+        return -1;
+    }
+
+    int remove_watchpoint_native(uint64_t address)
+    {
+        errno = ENOTSUP;
+        return -1;
+    }
+
+    int set_breakpoint_native(uint64_t address, int hit_count, int mode)
+    {
+        errno = ENOTSUP;
+        return -1;
+    }
+
+    int remove_breakpoint_native(uint64_t address)
+    {
+        errno = ENOTSUP;
+        return -1;
+    }
+
+    char* debugger_threads_native()
+    {
+        return strdup("[]");
+    }
+
+    char* debugger_get_registers_native(int tid)
+    {
+        errno = ENOTSUP;
+        return nullptr;
+    }
+
+    int debugger_set_registers_native(int tid, const char** names, const uint8_t* values,
+                                      size_t count)
+    {
+        errno = ENOTSUP;
+        return -1;
+    }
+
+    void debugger_mask_breakpoints_native(uint64_t address, uint8_t* buffer, size_t size) {}
+
+    int debugger_continue_native(int tid)
+    {
+        errno = ENOTSUP;
+        return -1;
+    }
+
+    int debugger_step_native(int tid, int mode)
+    {
+        errno = ENOTSUP;
+        return -1;
+    }
+
+    int debugger_suspend_native(int tid)
+    {
+        errno = ENOTSUP;
+        return -1;
+    }
+
+    int debugger_trace_native(int tid)
+    {
+        errno = ENOTSUP;
+        return -1;
+    }
+
+    int debugger_syscall_native(int pid, long number, const long* args, size_t arg_count,
+                                long* result)
+    {
+        errno = ENOENT;
+        return -1;
+    }
+}
+
+#endif
@@ -1,6 +1,27 @@
 #ifndef DEBUGGER_H
 #define DEBUGGER_H
 
+#include <signal.h>
+#include <sys/ptrace.h>
+#include <sys/uio.h>
+#include <sys/user.h>
+#include <sys/wait.h>
+
+#include <chrono>
+#include <condition_variable>
+#include <cstdint>
+#include <deque>
+#include <functional>
+#include <future>
+#include <map>
+#include <memory>
+#include <mutex>
+#include <set>
+#include <string>
+#include <thread>
+#include <vector>
+
+#include "../common/util.h"
 #include "native_api.h"
 
 enum class WatchpointType
@@ -10,4 +31,216 @@ enum class WatchpointType
     READWRITE = 3
 };
 
-#endif
\ No newline at end of file
+enum class BreakpointMode
+{
+    // Reports the first hits and then removes itself
+    Trace = 0,
+    // Halts the thread that hits it
+    Stop = 1,
+    // Stays in place; every hit is filtered and traced by evaluate_breakpoint_hit
+    Conditional = 2
+};
+
+enum class StepMode
+{
+    Into = 0,
+    Over = 1,
+    Out = 2
+};
+
+#if defined(__x86_64__) || defined(__aarch64__)
+
+// ptrace can only be driven by the thread that attached, so every operation on the tracee runs
+// on the debugger's own thread; other threads hand work over through execute()
+class Debugger
+{
+public:
+    Debugger(pid_t pid);
+    ~Debugger();
+    bool initialize();
+    void run();
+    void stop();
+    bool is_running();
+    pid_t pid() const
+    {
+        return pid_;
+    }
+    int set_watchpoint(uint64_t address, int size, WatchpointType type, bool stop);
+    int remove_watchpoint(uint64_t address);
+    int set_breakpoint(uint64_t address, int hit_count, BreakpointMode mode);
+    int remove_breakpoint(uint64_t address);
+    int stopped_threads(std::string& json);
+    int continue_thread(pid_t tid);
+    int step_thread(pid_t tid, StepMode mode);
+    // Holds a running thread like a breakpoint stop; continue_thread lets it go again
+    int suspend_thread(pid_t tid);
+    // Single-steps a held thread until record_trace_step ends the trace; the thread is held again
+    // where it ended, without an exception event for every step
+    int trace_thread(pid_t tid);
+    int thread_registers(pid_t tid, std::string& json);
+    int set_thread_registers(pid_t tid, const std::vector<std::string>& names,
+                             const std::vector<std::vector<uint8_t>>& values);
+    // Runs a system call in the process on a thread this debugger holds or briefly interrupts
+    int run_syscall(long number, const long* args, size_t arg_count, long* result);
+    // Puts the original bytes back over any trap instructions inside [address, address + size)
+    void mask_breakpoints(uint64_t address, uint8_t* buffer, size_t size);
+    // Whether breakpoints, watchpoints or held threads still need the attachment
+    bool in_use();
+
+private:
+    enum class SingleStepMode
+    {
+        None,
+        Breakpoint
+    };
+
+    struct Breakpoint
+    {
+        std::vector<uint8_t> original;
+        int hit_count = 0;
+        int target_count = 0;
+        bool inserted = false;
+        BreakpointMode mode = BreakpointMode::Trace;
+        // Temporary breakpoint a stepping thread runs to, e.g. the return site of a call
+        pid_t run_to_owner = 0;
+    };
+
+    struct Watchpoint
+    {
+        bool used = false;
+        uint64_t address = 0;
+        int size = 0;
+        WatchpointType type = WatchpointType::READWRITE;
+        bool stop = false;
+    };
+
+    struct ThreadState
+    {
+        SingleStepMode single_step_mode = SingleStepMode::None;
+        int single_step_count = 0;
+        uint64_t breakpoint_address = 0;
+        bool stepping = false;
+        // Breakpoint lifted for one instruction and put back once the step completes
+        uint64_t step_over_address = 0;
+        // ARM64 watchpoints trap before the access, so the instruction is stepped with them off
+        bool watchpoint_step = false;
+        // Held at a watchpoint whose access has not executed yet
+        bool watchpoint_pending = false;
+        // Held in ptrace-stop until continued or stepped through the session API
+        bool stopped = false;
+        std::string stop_reason;
+        bool step_active = false;
+        StepMode step_mode = StepMode::Into;
+        bool step_out_returning = false;
+        int step_count = 0;
+        uint64_t run_to_address = 0;
+        uint64_t run_to_sp = 0;
+        // Interrupted on request; the interrupt stop holds the thread instead of resuming it
+        bool suspend_requested = false;
+    };
+
+    pid_t pid_;
+    int mem_fd_;
+    std::thread thread_;
+    std::promise<bool> attach_result_;
+    std::mutex command_mutex_;
+    std::condition_variable command_cv_;
+    std::deque<std::function<void()>> commands_;
+    bool running_;
+    bool stop_requested_;
+    std::map<pid_t, ThreadState> threads_;
+    std::map<uint64_t, Breakpoint> breakpoints_;
+    // Removed breakpoints whose trap may still be pending in a thread that hit it concurrently
+    std::set<uint64_t> retired_breakpoints_;
+    std::vector<Watchpoint> watchpoints_;
+    // Original bytes under inserted traps, readable without a round trip to the debugger thread
+    std::mutex inserted_mutex_;
+    std::map<uint64_t, std::vector<uint8_t>> inserted_code_;
+
+    template <typename F>
+    auto execute(F&& f) -> decltype(f());
+    void process_commands();
+    bool poll_threads();
+    bool with_thread_stopped(pid_t tid, const std::function<void()>& f);
+    bool attach_all_threads();
+    void detach_all_threads();
+    void handle_stop(pid_t tid, int status);
+    void handle_trap(pid_t tid);
+    void handle_breakpoint_hit(pid_t tid, struct user_regs_struct& regs, uint64_t address);
+    void continue_breakpoint_single_step(pid_t tid, ThreadState& state);
+    void stop_thread(pid_t tid, const char* reason, struct user_regs_struct& regs);
+    void hold_thread(pid_t tid, const char* reason);
+    void advance_step(pid_t tid, struct user_regs_struct& regs);
+    bool run_to(pid_t tid, uint64_t address, uint64_t sp);
+    void clear_run_to(pid_t tid);
+    bool wait_for_thread_stop(pid_t tid, int timeout_ms);
+    bool any_thread_stepping();
+    bool single_step(pid_t tid);
+    void end_single_step(ThreadState& state);
+    bool wait_for_traced_step(pid_t tid);
+    bool resume(pid_t tid, int signal);
+    bool get_registers(pid_t tid, struct user_regs_struct& regs);
+    bool set_registers(pid_t tid, struct user_regs_struct& regs);
+    bool get_fp_registers(pid_t tid, void* fp_regs);
+    bool set_fp_registers(pid_t tid, void* fp_regs);
+    void send_registers(const struct user_regs_struct& regs);
+    bool read_bytes(uint64_t address, void* buffer, size_t size);
+    bool write_bytes(uint64_t address, const void* buffer, size_t size);
+    bool insert_breakpoint(uint64_t address, Breakpoint& breakpoint);
+    bool restore_breakpoint(uint64_t address, Breakpoint& breakpoint);
+    void retire_breakpoint(uint64_t address);
+    int watchpoint_slot_count();
+    bool apply_watchpoints(pid_t tid, bool enable);
+    void apply_watchpoints_to_all_threads();
+    bool handle_watchpoint_hit(pid_t tid, struct user_regs_struct& regs, bool& stop);
+    std::vector<std::map<std::string, uint64_t>> register_map(
+        const struct user_regs_struct& regs);
+};
+
+template <typename F>
+auto Debugger::execute(F&& f) -> decltype(f())
+{
+    using Result = decltype(f());
+    if (std::this_thread::get_id() == thread_.get_id())
+    {
+        return f();
+    }
+
+    // errno is thread local, so carry it back from the debugger thread with the result
+    auto error = std::make_shared<int>(0);
+    auto task = std::make_shared<std::packaged_task<Result()>>([f, error]() {
+        Result result = f();
+        *error = errno;
+        return result;
+    });
+    auto future = task->get_future();
+    {
+        std::lock_guard<std::mutex> lock(command_mutex_);
+        if (!running_)
+        {
+            errno = ESRCH;
+            return Result(-1);
+        }
+        commands_.push_back([task]() { (*task)(); });
+    }
+    command_cv_.notify_one();
+
+    try
+    {
+        Result result = future.get();
+        errno = *error;
+        return result;
+    }
+    catch (const std::future_error&)
+    {
+        errno = ESRCH;
+        return Result(-1);
+    }
+}
+
+// Global pointer to the Debugger instance
+extern Debugger* g_debugger;
+
+#endif
+
+#endif  // DEBUGGER_H
@@ -1,5 +1,7 @@
 #include "native_api.h"
 
+#include <sys/syscall.h>
+
 #ifdef TARGET_IS_ANDROID
 #include <android/log.h>
 #endif
@@ -42,6 +44,12 @@ pid_t get_pid_native()
     return getpid();
 }
 
+bool process_alive_native(int pid)
+{
+    // EPERM still means the process exists
+    return kill(pid, 0) == 0 || errno != ESRCH;
+}
+
 ssize_t read_memory_native(int pid, uintptr_t address, size_t size, unsigned char *buffer)
 {
     struct iovec local_iov;
@@ -75,6 +83,22 @@ ssize_t read_memory_native(int pid, uintptr_t address, size_t size, unsigned cha
     return nread;
 }
 
+static ssize_t write_memory_proc(int pid, void *address, size_t size, unsigned char *buffer)
+{
+    char path[64];
+    snprintf(path, sizeof(path), "/proc/%d/mem", pid);
+    int fd = open(path, O_RDWR);
+    if (fd == -1)
+    {
+        return -1;
+    }
+    ssize_t written = pwrite(fd, buffer, size, reinterpret_cast<off_t>(address));
+    int saved_errno = errno;
+    close(fd);
+    errno = saved_errno;
+    return written;
+}
+
 ssize_t write_memory_native(int pid, void *address, size_t size, unsigned char *buffer)
 {
     if (pid == get_pid_native())
@@ -118,6 +142,15 @@ ssize_t write_memory_native(int pid, void *address, size_t size, unsigned char *
         // Writing to another process
         if (ptrace(PTRACE_ATTACH, pid, NULL, NULL) == -1)
         {
+            if (errno == EPERM)
+            {
+                // The debugger may already be tracing the process; /proc/pid/mem works for it
+                ssize_t written = write_memory_proc(pid, address, size, buffer);
+                if (written != -1)
+                {
+                    return written;
+                }
+            }
             debug_log(LOG_ERROR, "Failed to attach to process %d. Error: %d (%s)\n", pid, errno,
                       strerror(errno));
             return -1;
@@ -176,6 +209,292 @@ ssize_t write_memory_native(int pid, void *address, size_t size, unsigned char *
     }
 }
 
+#if defined(__x86_64__) || defined(__aarch64__)
+static pid_t thread_group_of(pid_t tid)
+{
+    std::ifstream status("/proc/" + std::to_string(tid) + "/status");
+    std::string line;
+    while (std::getline(status, line))
+    {
+        if (line.compare(0, 5, "Tgid:") == 0)
+        {
+            return static_cast<pid_t>(std::stol(line.substr(5)));
+        }
+    }
+    return tid;
+}
+
+// Runs a single system call inside a stopped tracee by pointing its program counter at a
+// syscall instruction. The thread's registers, and any code bytes borrowed for the instruction,
+// are restored before returning.
+bool remote_syscall(pid_t pid, long number, const long *args, size_t arg_count, long *result)
+{
+    struct user_regs_struct saved_regs;
+    struct iovec iov = {&saved_regs, sizeof(saved_regs)};
+    if (ptrace(PTRACE_GETREGSET, pid, reinterpret_cast<void *>(NT_PRSTATUS), &iov) == -1)
+    {
+        debug_log(LOG_ERROR, "Failed to read registers of process %d. Error: %d (%s)\n", pid,
+                  errno, strerror(errno));
+        return false;
+    }
+
+#if defined(__x86_64__)
+    const uintptr_t pc = saved_regs.rip;
+    const unsigned char syscall_insn[] = {0x0F, 0x05};
+    // A thread stopped inside a system call sits right after its syscall instruction.
+    const uintptr_t candidates[] = {pc - sizeof(syscall_insn)};
+#else
+    const uintptr_t pc = saved_regs.pc;
+    const unsigned char syscall_insn[] = {0x01, 0x00, 0x00, 0xD4};
+    // arm64 rewinds an interrupted call to its svc before reporting the stop.
+    const uintptr_t candidates[] = {pc, pc - sizeof(syscall_insn)};
+#endif
+
+    uintptr_t syscall_address = 0;
+    for (uintptr_t candidate : candidates)
+    {
+        unsigned char current[sizeof(syscall_insn)];
+        if (read_memory_native(pid, candidate, sizeof(current), current) ==
+                static_cast<ssize_t>(sizeof(current)) &&
+            memcmp(current, syscall_insn, sizeof(current)) == 0)
+        {
+            syscall_address = candidate;
+            break;
+        }
+    }
+
+    bool borrowed = false;
+    long original_word = 0;
+    if (syscall_address == 0)
+    {
+        syscall_address = pc;
+        errno = 0;
+        original_word = ptrace(PTRACE_PEEKTEXT, pid, reinterpret_cast<void *>(pc), NULL);
+        if (errno != 0)
+        {
+            debug_log(LOG_ERROR, "ptrace PEEKTEXT failed at 0x%lx. Error: %d (%s)\n", pc, errno,
+                      strerror(errno));
+            return false;
+        }
+        long patched_word = original_word;
+        std::memcpy(&patched_word, syscall_insn, sizeof(syscall_insn));
+        if (ptrace(PTRACE_POKETEXT, pid, reinterpret_cast<void *>(pc), patched_word) == -1)
+        {
+            debug_log(LOG_ERROR, "ptrace POKETEXT failed at 0x%lx. Error: %d (%s)\n", pc, errno,
+                      strerror(errno));
+            return false;
+        }
+        borrowed = true;
+    }
+
+    struct user_regs_struct regs = saved_regs;
+#if defined(__x86_64__)
+    regs.rip = syscall_address;
+    regs.rax = number;
+    regs.orig_rax = -1;
+    unsigned long long *arg_regs[] = {&regs.rdi, &regs.rsi, &regs.rdx,
+                                      &regs.r10, &regs.r8,  &regs.r9};
+    for (size_t i = 0; i < arg_count && i < 6; i++)
+    {
+        *arg_regs[i] = args[i];
+    }
+#else
+    regs.pc = syscall_address;
+    regs.regs[8] = number;
+    for (size_t i = 0; i < arg_count && i < 6; i++)
+    {
+        regs.regs[i] = args[i];
+    }
+#endif
+
+    bool success = false;
+    std::vector<int> pending_signals;
+    iov = {&regs, sizeof(regs)};
+    if (ptrace(PTRACE_SETREGSET, pid, reinterpret_cast<void *>(NT_PRSTATUS), &iov) == -1)
+    {
+        debug_log(LOG_ERROR, "Failed to set registers of process %d. Error: %d (%s)\n", pid,
+                  errno, strerror(errno));
+    }
+    else
+    {
+        int status = 0;
+        bool stepped = false;
+        for (int attempt = 0; attempt < 8 && !stepped; attempt++)
+        {
+            if (ptrace(PTRACE_SINGLESTEP, pid, NULL, NULL) == -1 ||
+                waitpid(pid, &status, __WALL) == -1)
+            {
+                break;
+            }
+            if (!WIFSTOPPED(status))
+            {
+                break;
+            }
+            // Other signals, or a pending interrupt of a seized thread, can stop the tracee
+            // before the instruction runs; step again. Signals meant for the target are
+            // queued again once its registers are back.
+            stepped = WSTOPSIG(status) == SIGTRAP && (status >> 16) == 0;
+            if (!stepped && (status >> 16) == 0)
+            {
+                pending_signals.push_back(WSTOPSIG(status));
+            }
+        }
+
+        iov = {&regs, sizeof(regs)};
+        if (stepped &&
+            ptrace(PTRACE_GETREGSET, pid, reinterpret_cast<void *>(NT_PRSTATUS), &iov) != -1)
+        {
+#if defined(__x86_64__)
+            *result = static_cast<long>(regs.rax);
+#else
+            *result = static_cast<long>(regs.regs[0]);
+#endif
+            success = true;
+        }
+        else
+        {
+            debug_log(LOG_ERROR, "Failed to execute system call in process %d\n", pid);
+        }
+    }
+
+    if (borrowed &&
+        ptrace(PTRACE_POKETEXT, pid, reinterpret_cast<void *>(pc), original_word) == -1)
+    {
+        debug_log(LOG_ERROR, "Failed to restore code at 0x%lx. Error: %d (%s)\n", pc, errno,
+                  strerror(errno));
+    }
+    iov = {&saved_regs, sizeof(saved_regs)};
+    if (ptrace(PTRACE_SETREGSET, pid, reinterpret_cast<void *>(NT_PRSTATUS), &iov) == -1)
+    {
+        debug_log(LOG_ERROR, "Failed to restore registers of process %d. Error: %d (%s)\n", pid,
+                  errno, strerror(errno));
+    }
+    // Delivered when the thread resumes, as if the call had never been made
+    pid_t tgid = pending_signals.empty() ? pid : thread_group_of(pid);
+    for (int signal : pending_signals)
+    {
+        if (syscall(SYS_tgkill, tgid, pid, signal) == -1)
+        {
+            debug_log(LOG_ERROR, "Failed to requeue signal %d for thread %d. Error: %d (%s)\n",
+                      signal, pid, errno, strerror(errno));
+        }
+    }
+    return success;
+}
+
+static bool attached_syscall(pid_t pid, long number, const long *args, size_t arg_count,
+                             long *result)
+{
+    // A debugger already tracing the process makes PTRACE_ATTACH fail with EPERM; it runs the
+    // call on one of its own threads instead
+    if (debugger_syscall_native(pid, number, args, arg_count, result) == 0)
+    {
+        return true;
+    }
+    if (errno != ENOENT)
+    {
+        debug_log(LOG_ERROR, "Debugger failed to run system call in process %d. Error: %d (%s)\n",
+                  pid, errno, strerror(errno));
+        return false;
+    }
+
+    if (ptrace(PTRACE_ATTACH, pid, NULL, NULL) == -1)
+    {
+        debug_log(LOG_ERROR, "Failed to attach to process %d. Error: %d (%s)\n", pid, errno,
+                  strerror(errno));
+        return false;
+    }
+    waitpid(pid, NULL, 0);
+
+    bool success = remote_syscall(pid, number, args, arg_count, result);
+
+    if (ptrace(PTRACE_DETACH, pid, NULL, NULL) == -1)
+    {
+        debug_log(LOG_WARN, "Failed to detach from process %d. Error: %d (%s)\n", pid, errno,
+                  strerror(errno));
+    }
+    return success;
+}
+#endif
+
+#ifndef MAP_FIXED_NOREPLACE
+#define MAP_FIXED_NOREPLACE 0x100000
+#endif
+
+uintptr_t allocate_memory_native(int pid, uintptr_t hint, size_t size)
+{
+    // Kernels without MAP_FIXED_NOREPLACE treat the address as a plain hint.
+    int flags = MAP_PRIVATE | MAP_ANONYMOUS | (hint != 0 ? MAP_FIXED_NOREPLACE : 0);
+    int prot = PROT_READ | PROT_WRITE | PROT_EXEC;
+
+    if (pid == get_pid_native())
+    {
+        void *mapped = mmap(reinterpret_cast<void *>(hint), size, prot, flags, -1, 0);
+        if (mapped == MAP_FAILED)
+        {
+            debug_log(LOG_ERROR, "mmap failed with error %d (%s)\n", errno, strerror(errno));
+            return 0;
+        }
+        return reinterpret_cast<uintptr_t>(mapped);
+    }
+
+#if defined(__x86_64__) || defined(__aarch64__)
+#if defined(__x86_64__)
+    const long mmap_number = 9;
+#else
+    const long mmap_number = 222;
+#endif
+    long args[] = {static_cast<long>(hint), static_cast<long>(size), prot, flags, -1, 0};
+    long result = 0;
+    if (!attached_syscall(pid, mmap_number, args, 6, &result))
+    {
+        return 0;
+    }
+    if (result < 0 && result > -4096)
+    {
+        debug_log(LOG_ERROR, "Remote mmap in process %d failed with error %ld (%s)\n", pid,
+                  -result, strerror(-result));
+        return 0;
+    }
+    return static_cast<uintptr_t>(result);
+#else
+    debug_log(LOG_ERROR, "Remote allocation is not supported on this architecture\n");
+    return 0;
+#endif
+}
+
+int free_memory_native(int pid, uintptr_t address, size_t size)
+{
+    if (pid == get_pid_native())
+    {
+        if (munmap(reinterpret_cast<void *>(address), size) != 0)
+        {
+            debug_log(LOG_ERROR, "munmap failed with error %d (%s)\n", errno, strerror(errno));
+            return -1;
+        }
+        return 0;
+    }
+
+#if defined(__x86_64__) || defined(__aarch64__)
+#if defined(__x86_64__)
+    const long munmap_number = 11;
+#else
+    const long munmap_number = 215;
+#endif
+    long args[] = {static_cast<long>(address), static_cast<long>(size)};
+    long result = 0;
+    if (!attached_syscall(pid, munmap_number, args, 2, &result) || result != 0)
+    {
+        debug_log(LOG_ERROR, "Remote munmap in process %d failed\n", pid);
+        return -1;
+    }
+    return 0;
+#else
+    debug_log(LOG_ERROR, "Remote deallocation is not supported on this architecture\n");
+    return -1;
+#endif
+}
+
 void enumerate_regions_to_buffer(pid_t pid, char *buffer, size_t buffer_size)
 {
     char maps_file_path[64];
@@ -382,9 +701,86 @@ bool compare_elf_headers(int pid, uintptr_t base_address, const char *filename)
     return std::memcmp(&mem_elf_header, &file_elf_header, sizeof(Elf64_Ehdr)) == 0;
 }
 
+// End of the loaded image according to its PT_LOAD segments, or 0 if unknown
+static uintptr_t get_elf_image_end(int pid, uintptr_t base_address)
+{
+    unsigned char ident[EI_NIDENT];
+    if (read_memory_native(pid, base_address, EI_NIDENT, ident) != EI_NIDENT)
+    {
+        return 0;
+    }
+
+    uintptr_t first_vaddr = UINTPTR_MAX;
+    uintptr_t last_vaddr = 0;
+    auto add_load = [&](uint64_t vaddr, uint64_t memsz) {
+        first_vaddr = std::min<uintptr_t>(first_vaddr, vaddr & ~0xfffULL);
+        last_vaddr = std::max<uintptr_t>(last_vaddr, vaddr + memsz);
+    };
+
+    if (ident[EI_CLASS] == ELFCLASS64)
+    {
+        Elf64_Ehdr header;
+        if (read_memory_native(pid, base_address, sizeof(header),
+                               reinterpret_cast<unsigned char *>(&header)) != sizeof(header))
+        {
+            return 0;
+        }
+        std::vector<Elf64_Phdr> phdrs(header.e_phnum);
+        size_t size = phdrs.size() * sizeof(Elf64_Phdr);
+        if (header.e_phentsize != sizeof(Elf64_Phdr) ||
+            read_memory_native(pid, base_address + header.e_phoff, size,
+                               reinterpret_cast<unsigned char *>(phdrs.data())) !=
+                static_cast<ssize_t>(size))
+        {
+            return 0;
+        }
+        for (const auto &phdr : phdrs)
+        {
+            if (phdr.p_type == PT_LOAD) add_load(phdr.p_vaddr, phdr.p_memsz);
+        }
+    }
+    else if (ident[EI_CLASS] == ELFCLASS32)
+    {
+        Elf32_Ehdr header;
+        if (read_memory_native(pid, base_address, sizeof(header),
+                               reinterpret_cast<unsigned char *>(&header)) != sizeof(header))
+        {
+            return 0;
+        }
+        std::vector<Elf32_Phdr> phdrs(header.e_phnum);
+        size_t size = phdrs.size() * sizeof(Elf32_Phdr);
+        if (header.e_phentsize != sizeof(Elf32_Phdr) ||
+            read_memory_native(pid, base_address + header.e_phoff, size,
+                               reinterpret_cast<unsigned char *>(phdrs.data())) !=
+                static_cast<ssize_t>(size))
+        {
+            return 0;
+        }
+        for (const auto &phdr : phdrs)
+        {
+            if (phdr.p_type == PT_LOAD) add_load(phdr.p_vaddr, phdr.p_memsz);
+        }
+    }
+
+    if (last_vaddr == 0)
+    {
+        return 0;
+    }
+    uintptr_t image_end = base_address + (last_vaddr - first_vaddr);
+    return (image_end + 0xfff) & ~static_cast<uintptr_t>(0xfff);
+}
+
 ModuleInfo *enummodule_native(pid_t pid, size_t *count)
 {
-    std::vector<ModuleInfo> modules;
+    struct Module
+    {
+        uintptr_t base;
+        uintptr_t image_end;
+        bool is_64bit;
+        std::string path;
+        std::vector<ModuleSegment> segments;
+    };
+    std::vector<Module> modules;
     std::ostringstream maps_path;
     maps_path << "/proc/" << pid << "/maps";
 
@@ -395,6 +791,9 @@ ModuleInfo *enummodule_native(pid_t pid, size_t *count)
         return nullptr;
     }
 
+    // Index of the module whose later mappings (segments, .bss) are still being collected
+    ssize_t current = -1;
+
     std::string line;
     while (std::getline(maps_file, line))
     {
@@ -403,6 +802,7 @@ ModuleInfo *enummodule_native(pid_t pid, size_t *count)
         char perms[5], dev[6], module_path[PATH_MAX];
         unsigned long inode;
         unsigned long offset;
+        module_path[0] = '\0';
 
         iss >> std::hex >> start;
         iss.ignore(1, '-');
@@ -412,30 +812,332 @@ ModuleInfo *enummodule_native(pid_t pid, size_t *count)
         iss >> dev >> inode;
         iss >> module_path;
 
-        if (perms[0] == 'r' && !std::string(module_path).empty())
+        ModuleSegment segment;
+        segment.start = start;
+        segment.end = end;
+        segment.file_offset = offset;
+        memset(segment.protection, 0, sizeof(segment.protection));
+        strncpy(segment.protection, perms, sizeof(segment.protection) - 1);
+
+        std::string path(module_path);
+        if (perms[0] == 'r' && !path.empty() && is_elf(module_path) &&
+            compare_elf_headers(pid, start, module_path))
         {
-            if (!is_elf(module_path)) continue;
-            if (compare_elf_headers(pid, start, module_path))
-            {
-                ModuleInfo info;
-                info.base = start;
-                info.size = static_cast<int>(end - start);
-                info.is_64bit = is_elf64(module_path);
+            Module module;
+            module.base = start;
+            module.image_end = get_elf_image_end(pid, start);
+            module.is_64bit = is_elf64(module_path);
+            module.path = path;
+            module.segments.push_back(segment);
+            modules.push_back(module);
+            current = modules.size() - 1;
+            continue;
+        }
 
-                size_t nameLength = strlen(module_path) + 1;
-                info.modulename = new char[nameLength];
-                strcpy(info.modulename, module_path);
+        if (current < 0)
+        {
+            continue;
+        }
 
-                modules.push_back(info);
-            }
+        Module &module = modules[current];
+        // Without program headers, fall back to mappings of the same file
+        bool in_image = module.image_end != 0 ? start < module.image_end : path == module.path;
+        if (in_image)
+        {
+            module.segments.push_back(segment);
+        }
+        else
+        {
+            current = -1;
         }
     }
 
     maps_file.close();
 
     *count = modules.size();
-    ModuleInfo *result = new ModuleInfo[*count];
-    std::copy(modules.begin(), modules.end(), result);
+    ModuleInfo *result = static_cast<ModuleInfo *>(malloc(*count * sizeof(ModuleInfo)));
+    for (size_t i = 0; i < modules.size(); i++)
+    {
+        const Module &module = modules[i];
+        ModuleInfo &info = result[i];
+        uintptr_t end = module.segments.back().end;
+        if (module.image_end != 0)
+        {
+            end = std::min(end, module.image_end);
+        }
+        info.base = module.base;
+        info.size = end - module.base;
+        info.is_64bit = module.is_64bit;
+        info.modulename = strdup(module.path.c_str());
+        info.segment_count = module.segments.size();
+        info.segments =
+            static_cast<ModuleSegment *>(malloc(info.segment_count * sizeof(ModuleSegment)));
+        std::copy(module.segments.begin(), module.segments.end(), info.segments);
+    }
+
+    return result;
+}
+
+static uintptr_t get_thread_stack_pointer(pid_t pid, pid_t tid)
+{
+    char path[64];
+
+    // "nr arg1 ... arg6 sp pc" while the thread is blocked in a syscall, "running" otherwise
+    snprintf(path, sizeof(path), "/proc/%d/task/%d/syscall", pid, tid);
+    std::ifstream syscall_file(path);
+    if (syscall_file.is_open())
+    {
+        std::vector<std::string> fields;
+        std::string field;
+        while (syscall_file >> field)
+        {
+            fields.push_back(field);
+        }
+        if (fields.size() >= 3)
+        {
+            return strtoull(fields[fields.size() - 2].c_str(), nullptr, 16);
+        }
+    }
+
+    // Fall back to kstkesp (field 29), which older kernels still report
+    snprintf(path, sizeof(path), "/proc/%d/task/%d/stat", pid, tid);
+    std::ifstream stat_file(path);
+    std::string stat;
+    if (stat_file.is_open() && std::getline(stat_file, stat))
+    {
+        size_t comm_end = stat.rfind(')');
+        if (comm_end != std::string::npos)
+        {
+            std::istringstream iss(stat.substr(comm_end + 2));
+            std::string field;
+            // Fields after comm start at 3 (state)
+            for (int i = 3; i <= 29 && iss >> field; i++)
+            {
+                if (i == 29)
+                {
+                    return strtoull(field.c_str(), nullptr, 10);
+                }
+            }
+        }
+    }
+    return 0;
+}
+
+// The main thread first, the rest in creation (tid) order
+static bool list_threads(pid_t pid, std::vector<pid_t> &tids)
+{
+    char task_path[64];
+    snprintf(task_path, sizeof(task_path), "/proc/%d/task", pid);
+    DIR *task_dir = opendir(task_path);
+    if (!task_dir)
+    {
+        debug_log(LOG_ERROR, "Failed to open %s\n", task_path);
+        return false;
+    }
+
+    struct dirent *entry;
+    while ((entry = readdir(task_dir)) != nullptr)
+    {
+        pid_t tid = atoi(entry->d_name);
+        if (tid > 0)
+        {
+            tids.push_back(tid);
+        }
+    }
+    closedir(task_dir);
+
+    std::sort(tids.begin(), tids.end(), [pid](pid_t a, pid_t b) {
+        if (a == pid || b == pid) return a == pid && b != pid;
+        return a < b;
+    });
+    return true;
+}
+
+ThreadStackInfo *enumthreadstack_native(pid_t pid, size_t *count)
+{
+    struct Region
+    {
+        uintptr_t start;
+        uintptr_t end;
+        std::string name;
+    };
+
+    *count = 0;
+
+    char maps_path[64];
+    snprintf(maps_path, sizeof(maps_path), "/proc/%d/maps", pid);
+    std::ifstream maps_file(maps_path);
+    if (!maps_file.is_open())
+    {
+        debug_log(LOG_ERROR, "Failed to open file: %s\n", maps_path);
+        return nullptr;
+    }
+
+    std::vector<Region> regions;
+    std::string line;
+    while (std::getline(maps_file, line))
+    {
+        std::istringstream iss(line);
+        Region region;
+        std::string perms, offset, dev, inode;
+
+        iss >> std::hex >> region.start;
+        iss.ignore(1, '-');
+        iss >> std::hex >> region.end;
+        iss >> perms >> offset >> dev >> inode;
+        std::getline(iss >> std::ws, region.name);
+        regions.push_back(region);
+    }
+
+    std::vector<pid_t> tids;
+    if (!list_threads(pid, tids))
+    {
+        return nullptr;
+    }
+
+    std::vector<ThreadStackInfo> stacks;
+    for (pid_t tid : tids)
+    {
+        const Region *stack_region = nullptr;
+
+        // Named stacks: [stack] for the main thread, [stack:tid] on old kernels and
+        // [anon:stack_and_tls:tid] for bionic pthreads
+        std::string labels[] = {tid == pid ? "[stack]" : "", "[stack:" + std::to_string(tid) + "]",
+                                "[anon:stack_and_tls:" + std::to_string(tid) + "]"};
+        for (const auto &region : regions)
+        {
+            for (const auto &label : labels)
+            {
+                if (!label.empty() && region.name == label)
+                {
+                    stack_region = &region;
+                }
+            }
+        }
+
+        if (stack_region == nullptr)
+        {
+            uintptr_t sp = get_thread_stack_pointer(pid, tid);
+            for (const auto &region : regions)
+            {
+                if (sp != 0 && region.start <= sp && sp < region.end)
+                {
+                    stack_region = &region;
+                    break;
+                }
+            }
+        }
+
+        if (stack_region == nullptr)
+        {
+            debug_log(LOG_DEBUG, "Could not locate the stack of thread %d\n", tid);
+            continue;
+        }
+
+        ThreadStackInfo info;
+        info.tid = tid;
+        info.stack_base = stack_region->start;
+        info.stack_top = stack_region->end;
+        stacks.push_back(info);
+    }
+
+    *count = stacks.size();
+    ThreadStackInfo *result =
+        static_cast<ThreadStackInfo *>(malloc(std::max<size_t>(*count, 1) * sizeof(ThreadStackInfo)));
+    if (!result)
+    {
+        *count = 0;
+        return nullptr;
+    }
+    std::copy(stacks.begin(), stacks.end(), result);
+
+    return result;
+}
+
+ThreadInfo *enumthread_native(pid_t pid, size_t *count)
+{
+    *count = 0;
+    std::vector<pid_t> tids;
+    if (!list_threads(pid, tids))
+    {
+        return nullptr;
+    }
+
+    long ticks_per_second = sysconf(_SC_CLK_TCK);
+    std::vector<ThreadInfo> threads;
+    for (pid_t tid : tids)
+    {
+        char path[64];
+        snprintf(path, sizeof(path), "/proc/%d/task/%d/stat", pid, tid);
+        std::ifstream stat_file(path);
+        std::string stat;
+        if (!stat_file.is_open() || !std::getline(stat_file, stat))
+        {
+            // Exited since the task directory was read
+            continue;
+        }
+
+        ThreadInfo info = {};
+        info.tid = tid;
+
+        // comm may contain spaces and parentheses, so it ends at the last ')'
+        size_t comm_start = stat.find('(');
+        size_t comm_end = stat.rfind(')');
+        if (comm_start == std::string::npos || comm_end == std::string::npos ||
+            comm_end + 2 > stat.size())
+        {
+            continue;
+        }
+        std::string name = stat.substr(comm_start + 1, comm_end - comm_start - 1);
+        snprintf(info.name, sizeof(info.name), "%s", name.c_str());
+
+        std::istringstream iss(stat.substr(comm_end + 2));
+        std::string field;
+        uint64_t ticks = 0;
+        // Fields after comm start at 3 (state); 14 and 15 are utime and stime
+        for (int i = 3; i <= 15 && iss >> field; i++)
+        {
+            if (i == 3)
+            {
+                info.state = field[0];
+            }
+            else if (i == 14 || i == 15)
+            {
+                ticks += strtoull(field.c_str(), nullptr, 10);
+            }
+        }
+        if (ticks_per_second > 0)
+        {
+            info.cpu_time_us = ticks * 1000000 / ticks_per_second;
+        }
+
+        if (info.state != 'R')
+        {
+            // "nr arg1 ... arg6 sp pc" or "-1 sp pc" while the thread is off the CPU
+            snprintf(path, sizeof(path), "/proc/%d/task/%d/syscall", pid, tid);
+            std::ifstream syscall_file(path);
+            std::vector<std::string> fields;
+            while (syscall_file >> field)
+            {
+                fields.push_back(field);
+            }
+            if (fields.size() >= 3)
+            {
+                info.pc = strtoull(fields.back().c_str(), nullptr, 16);
+            }
+        }
+        threads.push_back(info);
+    }
+
+    *count = threads.size();
+    ThreadInfo *result =
+        static_cast<ThreadInfo *>(malloc(std::max<size_t>(*count, 1) * sizeof(ThreadInfo)));
+    if (!result)
+    {
+        *count = 0;
+        return nullptr;
+    }
+    std::copy(threads.begin(), threads.end(), result);
 
     return result;
 }
@@ -6,6 +6,7 @@
 #include <elf.h>
 #include <errno.h>
 #include <fcntl.h>
+#include <signal.h>
 #include <stdio.h>
 #include <stdlib.h>
 #include <sys/mman.h>
@@ -14,9 +15,11 @@
 #include <sys/stat.h>
 #include <sys/types.h>
 #include <sys/uio.h>
+#include <sys/user.h>
 #include <sys/wait.h>
 #include <unistd.h>
 
+#include <algorithm>
 #include <cstdarg>
 #include <cstdio>
 #include <cstring>
@@ -41,25 +44,72 @@ typedef struct
     char *processname;
 } ProcessInfo;
 
+typedef struct
+{
+    uintptr_t start;
+    uintptr_t end;
+    uint64_t file_offset;
+    char protection[8];
+} ModuleSegment;
+
 typedef struct
 {
     uintptr_t base;
-    int size;
+    uint64_t size;
     bool is_64bit;
     char *modulename;
+    ModuleSegment *segments;
+    size_t segment_count;
 } ModuleInfo;
 
+typedef struct
+{
+    int tid;
+    uintptr_t stack_base;
+    uintptr_t stack_top;
+} ThreadStackInfo;
+
+typedef struct
+{
+    int tid;
+    char name[32];
+    // Scheduler state as in /proc/<pid>/stat: R, S, D, T, t, Z, ...
+    char state;
+    uint64_t cpu_time_us;
+    // Only known while the thread is off the CPU; 0 otherwise
+    uintptr_t pc;
+} ThreadInfo;
+
 extern "C" void native_log(int level, const char *message);
 int debug_log(LogLevel level, const char *format, ...);
 extern "C" pid_t get_pid_native();
+extern "C" bool process_alive_native(int pid);
 extern "C" ssize_t read_memory_native(int pid, uintptr_t address, size_t size,
                                       unsigned char *buffer);
 extern "C" ssize_t write_memory_native(int pid, void *address, size_t size, unsigned char *buffer);
+extern "C" uintptr_t allocate_memory_native(int pid, uintptr_t hint, size_t size);
+extern "C" int free_memory_native(int pid, uintptr_t address, size_t size);
 extern "C" void enumerate_regions_to_buffer(pid_t pid, char *buffer, size_t buffer_size);
 extern "C" ProcessInfo *enumprocess_native(size_t *count);
 extern "C" bool suspend_process(pid_t pid);
 extern "C" bool resume_process(pid_t pid);
 extern "C" ModuleInfo *enummodule_native(pid_t pid, size_t *count);
+extern "C" ThreadStackInfo *enumthreadstack_native(pid_t pid, size_t *count);
+extern "C" ThreadInfo *enumthread_native(pid_t pid, size_t *count);
 extern "C" int native_init(int mode);
+// Runs one system call in a stopped tracee thread, restoring its registers afterwards
+bool remote_syscall(pid_t tid, long number, const long *args, size_t arg_count, long *result);
+// The same through the debugger attached to pid, which holds every thread of it under ptrace;
+// -1 with ENOENT when no debugger is attached to pid
+extern "C" int debugger_syscall_native(int pid, long number, const long *args, size_t arg_count,
+                                       long *result);
+
+// Rust functions
+extern "C" void send_register_json(const char *register_json, pid_t pid);
+extern "C" int classify_instruction(pid_t pid, uint64_t address, uint64_t *size);
+extern "C" int evaluate_breakpoint_hit(pid_t pid, uint64_t tid, uint64_t address,
+                                       const char *register_json);
+extern "C" int record_trace_step(pid_t pid, uint64_t tid, const char *register_json);
+extern "C" void debugger_detached(pid_t pid);
 
 #endif
\ No newline at end of file
@@ -7,7 +7,19 @@ extern "C"
         return true;
     }
 
-    int set_watchpoint_native(uint64_t address, int size, WatchpointType type)
+    void debugger_free() {}
+
+    bool debugger_attached_native(int pid)
+    {
+        return false;
+    }
+
+    bool debugger_in_use_native()
+    {
+        return false;
+    }
+
+    int set_watchpoint_native(uint64_t address, int size, WatchpointType type, bool stop)
     {
         return 0;
     }
@@ -17,7 +29,7 @@ extern "C"
         return 0;
     }
 
-    int set_breakpoint_native(uint64_t address, int hit_count)
+    int set_breakpoint_native(uint64_t address, int hit_count, int mode)
     {
         return 0;
     }
@@ -26,4 +38,42 @@ extern "C"
     {
         return 0;
     }
+
+    char* debugger_threads_native()
+    {
+        return strdup("[]");
+    }
+
+    char* debugger_get_registers_native(int tid)
+    {
+        return nullptr;
+    }
+
+    int debugger_set_registers_native(int tid, const char** names, const uint8_t* values,
+                                      size_t count)
+    {
+        return 0;
+    }
+
+    void debugger_mask_breakpoints_native(uint64_t address, uint8_t* buffer, size_t size) {}
+
+    int debugger_continue_native(int tid)
+    {
+        return 0;
+    }
+
+    int debugger_step_native(int tid, int mode)
+    {
+        return 0;
+    }
+
+    int debugger_suspend_native(int tid)
+    {
+        return 0;
+    }
+
+    int debugger_trace_native(int tid)
+    {
+        return 0;
+    }
 }
\ No newline at end of file
@@ -26,6 +26,21 @@ int get_pid_native()
     return GetCurrentProcessId();
 }
 
+bool process_alive_native(int pid)
+{
+    HANDLE processHandle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid);
+    if (processHandle == NULL)
+    {
+        // A process that cannot be opened may still be running
+        return GetLastError() == ERROR_ACCESS_DENIED;
+    }
+
+    DWORD exitCode = 0;
+    bool alive = GetExitCodeProcess(processHandle, &exitCode) && exitCode == STILL_ACTIVE;
+    CloseHandle(processHandle);
+    return alive;
+}
+
 SSIZE_T read_memory_native(int pid, uintptr_t address, size_t size, unsigned char *buffer)
 {
     HANDLE processHandle = OpenProcess(PROCESS_VM_READ, FALSE, pid);
@@ -100,6 +115,62 @@ SSIZE_T write_memory_native(int pid, void *address, size_t size, unsigned char *
     return bytesWritten;
 }
 
+uintptr_t allocate_memory_native(int pid, uintptr_t hint, size_t size)
+{
+    HANDLE processHandle =
+        OpenProcess(PROCESS_VM_OPERATION | PROCESS_QUERY_INFORMATION, FALSE, pid);
+    if (processHandle == NULL)
+    {
+        debug_log(LOG_ERROR, "Failed to open process %d for allocation. Error code: %lu", pid,
+                  GetLastError());
+        return 0;
+    }
+
+    LPVOID address = NULL;
+    if (hint != 0)
+    {
+        address = VirtualAllocEx(processHandle, reinterpret_cast<LPVOID>(hint), size,
+                                 MEM_COMMIT | MEM_RESERVE, PAGE_EXECUTE_READWRITE);
+    }
+    if (address == NULL)
+    {
+        address = VirtualAllocEx(processHandle, NULL, size, MEM_COMMIT | MEM_RESERVE,
+                                 PAGE_EXECUTE_READWRITE);
+    }
+    if (address == NULL)
+    {
+        debug_log(LOG_ERROR, "VirtualAllocEx failed for process %d. Error code: %lu", pid,
+                  GetLastError());
+    }
+
+    CloseHandle(processHandle);
+    return reinterpret_cast<uintptr_t>(address);
+}
+
+int free_memory_native(int pid, uintptr_t address, size_t size)
+{
+    HANDLE processHandle = OpenProcess(PROCESS_VM_OPERATION, FALSE, pid);
+    if (processHandle == NULL)
+    {
+        debug_log(LOG_ERROR, "Failed to open process %d for deallocation. Error code: %lu", pid,
+                  GetLastError());
+        return -1;
+    }
+
+    // MEM_RELEASE frees the whole reservation and requires a size of zero.
+    (void)size;
+    int result = 0;
+    if (!VirtualFreeEx(processHandle, reinterpret_cast<LPVOID>(address), 0, MEM_RELEASE))
+    {
+        debug_log(LOG_ERROR, "VirtualFreeEx failed for process %d at address 0x%p. Error code: %lu",
+                  pid, reinterpret_cast<LPVOID>(address), GetLastError());
+        result = -1;
+    }
+
+    CloseHandle(processHandle);
+    return result;
+}
+
 void setMemoryProtection(DWORD protect, DWORD type, char *permissions)
 {
     permissions[0] = '-';
@@ -440,7 +511,10 @@ ModuleInfo *enummodule_native(DWORD pid, size_t *count)
     {
         ModuleInfo info;
         info.base = reinterpret_cast<uintptr_t>(me32.modBaseAddr);
-        info.size = static_cast<int>(me32.modBaseSize);
+        info.size = static_cast<uint64_t>(me32.modBaseSize);
+        // Section layout is not reported on Windows yet
+        info.segments = nullptr;
+        info.segment_count = 0;
 
         info.is_64bit = IsPE64Bit(hProcess, me32.modBaseAddr);
 
@@ -461,6 +535,20 @@ ModuleInfo *enummodule_native(DWORD pid, size_t *count)
     return result;
 }
 
+ThreadStackInfo *enumthreadstack_native(DWORD pid, size_t *count)
+{
+    // Thread stack discovery is not supported on Windows yet
+    *count = 0;
+    return nullptr;
+}
+
+ThreadInfo *enumthread_native(DWORD pid, size_t *count)
+{
+    // Thread enumeration is not supported on Windows yet
+    *count = 0;
+    return nullptr;
+}
+
 int native_init(int mode)
 {
     return 1;
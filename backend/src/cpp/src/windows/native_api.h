@@ -27,25 +27,58 @@ typedef struct
     char *processname;
 } ProcessInfo;
 
+typedef struct
+{
+    uintptr_t start;
+    uintptr_t end;
+    uint64_t file_offset;
+    char protection[8];
+} ModuleSegment;
+
 typedef struct
 {
     uintptr_t base;
-    int size;
+    uint64_t size;
     bool is_64bit;
     char *modulename;
+    ModuleSegment *segments;
+    size_t segment_count;
 } ModuleInfo;
 
+typedef struct
+{
+    int tid;
+    uintptr_t stack_base;
+    uintptr_t stack_top;
+} ThreadStackInfo;
+
+typedef struct
+{
+    int tid;
+    char name[32];
+    // Scheduler state as in /proc/<pid>/stat: R, S, D, T, t, Z, ...
+    char state;
+    uint64_t cpu_time_us;
+    // Only known while the thread is off the CPU; 0 otherwise
+    uintptr_t pc;
+} ThreadInfo;
+
 extern "C" void native_log(int level, const char* message);
 int debug_log(LogLevel level, const char *format, ...);
 extern "C" int get_pid_native();
+extern "C" bool process_alive_native(int pid);
 extern "C" SSIZE_T read_memory_native(int pid, uintptr_t address, size_t size,
                                       unsigned char *buffer);
 extern "C" SSIZE_T write_memory_native(int pid, void *address, size_t size, unsigned char *buffer);
+extern "C" uintptr_t allocate_memory_native(int pid, uintptr_t hint, size_t size);
+extern "C" int free_memory_native(int pid, uintptr_t address, size_t size);
 extern "C" void enumerate_regions_to_buffer(DWORD pid, char *buffer, size_t buffer_size);
 extern "C" ProcessInfo *enumprocess_native(size_t *count);
 extern "C" bool suspend_process(int pid);
 extern "C" bool resume_process(int pid);
 extern "C" ModuleInfo *enummodule_native(DWORD pid, size_t *count);
+extern "C" ThreadStackInfo *enumthreadstack_native(DWORD pid, size_t *count);
+extern "C" ThreadInfo *enumthread_native(DWORD pid, size_t *count);
 extern "C" int native_init(int mode);
 
 #endif
\ No newline at end of file
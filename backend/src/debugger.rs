@@ -0,0 +1,188 @@
+use crate::disasm::{self, Arch, Instruction};
+use crate::native_bridge;
+use crate::symbols;
+use crate::unwind;
+use libc::c_int;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+// Instruction classes reported to the native debugger when it plans a step over or step out
+const PLAIN: c_int = 0;
+const CALL: c_int = 1;
+const RETURN: c_int = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepMode {
+    Into = 0,
+    Over = 1,
+    Out = 2,
+}
+
+impl StepMode {
+    pub fn parse(name: &str) -> Result<StepMode, String> {
+        match name.to_lowercase().as_str() {
+            "into" => Ok(StepMode::Into),
+            "over" => Ok(StepMode::Over),
+            "out" => Ok(StepMode::Out),
+            _ => Err(format!("Unknown step mode '{}'", name)),
+        }
+    }
+}
+
+fn classify(insn: &Instruction) -> c_int {
+    if insn.is_call {
+        CALL
+    } else if insn.is_return {
+        RETURN
+    } else {
+        PLAIN
+    }
+}
+
+// Called by the native debugger, which only knows raw bytes, to find calls and their fallthrough
+#[no_mangle]
+pub extern "C" fn classify_instruction(pid: i32, address: u64, size: *mut u64) -> c_int {
+    let arch = Arch::host();
+    let buffer = disasm::read_code(pid, address, arch.max_instruction_size());
+    match disasm::decode(arch, &buffer, address, 1) {
+        Ok(instructions) if !instructions.is_empty() => {
+            let insn = &instructions[0];
+            if !size.is_null() {
+                unsafe { *size = insn.bytes.len() as u64 };
+            }
+            classify(insn)
+        }
+        _ => -1,
+    }
+}
+
+// Threads held stopped by the debugger, each with the instruction and symbol at its PC
+pub fn stopped_threads(pid: i32) -> Result<Value, String> {
+    let threads = native_bridge::debugger_threads().map_err(|e| e.to_string())?;
+    let mut threads: Vec<Value> = serde_json::from_str(&threads).map_err(|e| e.to_string())?;
+
+    let pcs: Vec<u64> = threads
+        .iter()
+        .map(|thread| {
+            thread["registers"]["pc"]
+                .as_str()
+                .and_then(|pc| u64::from_str_radix(pc.trim_start_matches("0x"), 16).ok())
+                .unwrap_or(0)
+        })
+        .collect();
+    let infos = symbols::describe_addresses(pid, &pcs).unwrap_or_default();
+    for (index, thread) in threads.iter_mut().enumerate() {
+        let pc = pcs[index];
+        let arch = disasm::detect_arch(pid, pc);
+        let buffer = disasm::read_code(pid, pc, arch.max_instruction_size());
+        thread["instruction"] = json!(disasm::decode(arch, &buffer, pc, 1)
+            .ok()
+            .and_then(|instructions| instructions.first().map(|insn| insn.text()))
+            .unwrap_or_default());
+        if let Some(info) = infos.get(index) {
+            thread["address_info"] = info.clone();
+        }
+    }
+    Ok(Value::Array(threads))
+}
+
+// A tid of 0 continues every stopped thread
+pub fn continue_thread(tid: i32) -> Result<(), String> {
+    native_bridge::debugger_continue(tid).map_err(|e| e.to_string())
+}
+
+pub fn step(tid: i32, mode: StepMode) -> Result<(), String> {
+    native_bridge::debugger_step(tid, mode as i32).map_err(|e| e.to_string())
+}
+
+// Register values arrive as JSON numbers or as strings, hex with a 0x prefix or decimal, since
+// vector registers are wider than any JSON number
+fn parse_register_value(value: &Value) -> Result<u128, String> {
+    match value {
+        Value::Number(number) => number
+            .as_u64()
+            .map(u128::from)
+            .or_else(|| number.as_i64().map(|v| v as u64 as u128))
+            .ok_or_else(|| format!("Invalid register value {}", number)),
+        Value::String(text) => {
+            let text = text.trim();
+            let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+                Some(hex) => u128::from_str_radix(&hex.replace('_', ""), 16),
+                None => text.parse::<u128>(),
+            };
+            parsed.map_err(|_| format!("Invalid register value '{}'", text))
+        }
+        _ => Err(format!("Invalid register value {}", value)),
+    }
+}
+
+// General purpose (with pc, sp and flags) and FP/SIMD registers of a stopped thread
+pub fn registers(tid: i32) -> Result<Value, String> {
+    let registers = native_bridge::debugger_get_registers(tid)
+        .map_err(|e| format!("Failed to read registers of thread {}: {}", tid, e))?;
+    let registers: Value = serde_json::from_str(&registers).map_err(|e| e.to_string())?;
+    Ok(json!({ "tid": tid, "registers": registers }))
+}
+
+// Call stack of a stopped thread, innermost frame first
+pub fn backtrace(
+    pid: i32,
+    tid: i32,
+    max_frames: Option<usize>,
+    method: unwind::Method,
+) -> Result<Value, String> {
+    let registers = native_bridge::debugger_get_registers(tid)
+        .map_err(|e| format!("Failed to read registers of thread {}: {}", tid, e))?;
+    let registers: Value = serde_json::from_str(&registers).map_err(|e| e.to_string())?;
+    let frames = unwind::backtrace(pid, &registers["general"], max_frames, method)?;
+    Ok(json!({ "tid": tid, "frames": frames }))
+}
+
+// Writes all values or none; the registers read back afterwards are returned
+pub fn set_registers(tid: i32, registers: &BTreeMap<String, Value>) -> Result<Value, String> {
+    let values = registers
+        .iter()
+        .map(|(name, value)| Ok((name.to_lowercase(), parse_register_value(value)?)))
+        .collect::<Result<Vec<_>, String>>()?;
+    native_bridge::debugger_set_registers(tid, &values)
+        .map_err(|e| format!("Failed to set registers of thread {}: {}", tid, e))?;
+    self::registers(tid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_calls_and_returns() {
+        // call rel32; ret; mov rax, rbx
+        let bytes = [0xe8, 0x00, 0x00, 0x00, 0x00, 0xc3, 0x48, 0x89, 0xd8];
+        let instructions = disasm::decode(Arch::X86_64, &bytes, 0x1000, 3).unwrap();
+        let classes: Vec<c_int> = instructions.iter().map(classify).collect();
+        assert_eq!(classes, vec![CALL, RETURN, PLAIN]);
+        assert_eq!(instructions[0].bytes.len(), 5);
+
+        // bl #0x1000; ret; blr x8
+        let bytes = [
+            0x00, 0x00, 0x00, 0x94, 0xc0, 0x03, 0x5f, 0xd6, 0x00, 0x01, 0x3f, 0xd6,
+        ];
+        let instructions = disasm::decode(Arch::Arm64, &bytes, 0x1000, 3).unwrap();
+        let classes: Vec<c_int> = instructions.iter().map(classify).collect();
+        assert_eq!(classes, vec![CALL, RETURN, CALL]);
+    }
+
+    #[test]
+    fn parses_register_values() {
+        assert_eq!(parse_register_value(&json!(42)).unwrap(), 42);
+        assert_eq!(parse_register_value(&json!(-1)).unwrap(), u64::MAX as u128);
+        assert_eq!(parse_register_value(&json!("0x10")).unwrap(), 16);
+        assert_eq!(parse_register_value(&json!("123")).unwrap(), 123);
+        assert_eq!(
+            parse_register_value(&json!("0x0102030405060708090A0B0C0D0E0F10")).unwrap(),
+            0x0102030405060708090A0B0C0D0E0F10
+        );
+        assert!(parse_register_value(&json!("0xZZ")).is_err());
+        assert!(parse_register_value(&json!(1.5)).is_err());
+        assert!(parse_register_value(&json!(null)).is_err());
+    }
+}
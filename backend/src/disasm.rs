@@ -0,0 +1,418 @@
+use crate::native_bridge;
+use crate::request::DisassembleRequest;
+use crate::symbols;
+use crate::util;
+use capstone::arch::ArchOperand;
+use capstone::prelude::*;
+use capstone::InsnGroupType;
+use serde_json::{json, Value};
+
+const MAX_INSTRUCTION_COUNT: usize = 4096;
+const MAX_DISASSEMBLE_LENGTH: usize = 64 * 1024;
+const DEFAULT_INSTRUCTION_COUNT: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arch {
+    X86,
+    X86_64,
+    Arm,
+    Thumb,
+    Arm64,
+}
+
+impl Arch {
+    pub fn parse(name: &str) -> Result<Arch, String> {
+        match name.to_lowercase().as_str() {
+            "x86" | "i386" | "i686" => Ok(Arch::X86),
+            "x86_64" | "x64" | "amd64" => Ok(Arch::X86_64),
+            "arm" | "arm32" => Ok(Arch::Arm),
+            "thumb" => Ok(Arch::Thumb),
+            "arm64" | "aarch64" => Ok(Arch::Arm64),
+            _ => Err(format!("Unknown architecture '{}'", name)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Arch::X86 => "x86",
+            Arch::X86_64 => "x86_64",
+            Arch::Arm => "arm",
+            Arch::Thumb => "thumb",
+            Arch::Arm64 => "arm64",
+        }
+    }
+
+    pub fn host() -> Arch {
+        if cfg!(target_arch = "x86_64") {
+            Arch::X86_64
+        } else if cfg!(target_arch = "x86") {
+            Arch::X86
+        } else if cfg!(target_arch = "arm") {
+            Arch::Arm
+        } else {
+            Arch::Arm64
+        }
+    }
+
+    // Longest encoding, used to size reads for a given instruction count
+    pub fn max_instruction_size(&self) -> usize {
+        match self {
+            Arch::X86 | Arch::X86_64 => 15,
+            _ => 4,
+        }
+    }
+
+    pub fn capstone(&self) -> Result<Capstone, String> {
+        match self {
+            Arch::X86 => Capstone::new()
+                .x86()
+                .mode(arch::x86::ArchMode::Mode32)
+                .detail(true)
+                .build(),
+            Arch::X86_64 => Capstone::new()
+                .x86()
+                .mode(arch::x86::ArchMode::Mode64)
+                .detail(true)
+                .build(),
+            Arch::Arm => Capstone::new()
+                .arm()
+                .mode(arch::arm::ArchMode::Arm)
+                .detail(true)
+                .build(),
+            Arch::Thumb => Capstone::new()
+                .arm()
+                .mode(arch::arm::ArchMode::Thumb)
+                .detail(true)
+                .build(),
+            Arch::Arm64 => Capstone::new()
+                .arm64()
+                .mode(arch::arm64::ArchMode::Arm)
+                .detail(true)
+                .build(),
+        }
+        .map_err(|e| format!("Failed to create Capstone engine: {}", e))
+    }
+}
+
+// Architecture from the ELF, Mach-O or PE header of the image at `base`
+fn image_arch(pid: i32, base: u64) -> Option<Arch> {
+    let mut header = [0u8; 0x40];
+    native_bridge::read_process_memory(pid, base as *mut libc::c_void, header.len(), &mut header)
+        .ok()?;
+
+    if &header[0..4] == b"\x7fELF" {
+        return match u16::from_le_bytes([header[0x12], header[0x13]]) {
+            3 => Some(Arch::X86),
+            62 => Some(Arch::X86_64),
+            40 => Some(Arch::Arm),
+            183 => Some(Arch::Arm64),
+            _ => None,
+        };
+    }
+
+    let magic = u32::from_le_bytes(header[0..4].try_into().unwrap());
+    if magic == 0xfeedfacf || magic == 0xfeedface {
+        return match u32::from_le_bytes(header[4..8].try_into().unwrap()) {
+            0x0100_000c => Some(Arch::Arm64),
+            0x0100_0007 => Some(Arch::X86_64),
+            12 => Some(Arch::Arm),
+            7 => Some(Arch::X86),
+            _ => None,
+        };
+    }
+
+    if &header[0..2] == b"MZ" {
+        let pe_offset = u32::from_le_bytes(header[0x3c..0x40].try_into().unwrap()) as u64;
+        let mut machine = [0u8; 2];
+        native_bridge::read_process_memory(
+            pid,
+            (base + pe_offset + 4) as *mut libc::c_void,
+            machine.len(),
+            &mut machine,
+        )
+        .ok()?;
+        return match u16::from_le_bytes(machine) {
+            0x14c => Some(Arch::X86),
+            0x8664 => Some(Arch::X86_64),
+            0x1c0 | 0x1c4 => Some(Arch::Arm),
+            0xaa64 => Some(Arch::Arm64),
+            _ => None,
+        };
+    }
+    None
+}
+
+// Architecture of the module containing `address`, falling back to the main image and the host
+pub fn detect_arch(pid: i32, address: u64) -> Arch {
+    let modules = native_bridge::enum_modules(pid).unwrap_or_default();
+    detect_arch_in(pid, &modules, address)
+}
+
+pub fn detect_arch_in(pid: i32, modules: &[Value], address: u64) -> Arch {
+    let containing = modules.iter().find(|module| {
+        let base = module["base"].as_u64().unwrap_or(0);
+        let size = module["size"].as_u64().unwrap_or(0);
+        address >= base && address - base < size
+    });
+    containing
+        .or(modules.first())
+        .and_then(|module| image_arch(pid, module["base"].as_u64()?))
+        .unwrap_or_else(Arch::host)
+}
+
+pub struct Instruction {
+    pub address: u64,
+    pub bytes: Vec<u8>,
+    pub mnemonic: String,
+    pub operands: String,
+    pub is_call: bool,
+    pub is_jump: bool,
+    pub is_return: bool,
+    pub branch_target: Option<u64>,
+    // Absolute address of a PC-relative or absolute memory operand (RIP-relative, ADR/ADRP, literal loads)
+    pub memory_target: Option<u64>,
+}
+
+impl Instruction {
+    pub fn text(&self) -> String {
+        if self.operands.is_empty() {
+            self.mnemonic.clone()
+        } else {
+            format!("{} {}", self.mnemonic, self.operands)
+        }
+    }
+}
+
+fn immediate(operand: &ArchOperand) -> Option<u64> {
+    match operand {
+        ArchOperand::X86Operand(op) => match op.op_type {
+            arch::x86::X86OperandType::Imm(value) => Some(value as u64),
+            _ => None,
+        },
+        ArchOperand::Arm64Operand(op) => match op.op_type {
+            arch::arm64::Arm64OperandType::Imm(value) => Some(value as u64),
+            _ => None,
+        },
+        ArchOperand::ArmOperand(op) => match op.op_type {
+            arch::arm::ArmOperandType::Imm(value) => Some(value as u32 as u64),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn memory_target(arch: Arch, insn: &capstone::Insn, operands: &[ArchOperand]) -> Option<u64> {
+    let next = insn.address() + insn.bytes().len() as u64;
+    operands.iter().find_map(|operand| match operand {
+        ArchOperand::X86Operand(op) => match &op.op_type {
+            arch::x86::X86OperandType::Mem(mem) if mem.index().0 == 0 => {
+                if mem.base().0 as u32 == arch::x86::X86Reg::X86_REG_RIP {
+                    Some(next.wrapping_add(mem.disp() as u64))
+                } else if mem.base().0 == 0 {
+                    match arch {
+                        Arch::X86 => Some(mem.disp() as u32 as u64),
+                        _ => Some(mem.disp() as u64),
+                    }
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        },
+        // Capstone already resolves ADR, ADRP and literal loads to an absolute immediate
+        ArchOperand::Arm64Operand(op) => match op.op_type {
+            arch::arm64::Arm64OperandType::Imm(value)
+                if matches!(insn.mnemonic(), Some("adr" | "adrp"))
+                    || insn.mnemonic().is_some_and(|m| m.starts_with("ldr"))
+                        && operands.len() == 2 =>
+            {
+                Some(value as u64)
+            }
+            _ => None,
+        },
+        ArchOperand::ArmOperand(op) => match &op.op_type {
+            arch::arm::ArmOperandType::Mem(mem)
+                if mem.base().0 as u32 == arch::arm::ArmReg::ARM_REG_PC =>
+            {
+                let pc = insn.address() + if arch == Arch::Thumb { 4 } else { 8 };
+                Some((pc & !3).wrapping_add(mem.disp() as i64 as u64))
+            }
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+pub fn decode(
+    arch: Arch,
+    bytes: &[u8],
+    address: u64,
+    max_count: usize,
+) -> Result<Vec<Instruction>, String> {
+    decode_with(&arch.capstone()?, arch, bytes, address, max_count)
+}
+
+// Same as `decode` with a caller-owned engine, for hot loops that decode many short runs
+pub fn decode_with(
+    cs: &Capstone,
+    arch: Arch,
+    bytes: &[u8],
+    address: u64,
+    max_count: usize,
+) -> Result<Vec<Instruction>, String> {
+    let instructions = cs
+        .disasm_count(bytes, address, max_count)
+        .map_err(|e| format!("Failed to disassemble: {}", e))?;
+
+    let mut result = Vec::new();
+    for insn in instructions.iter() {
+        let detail = cs.insn_detail(insn).ok();
+        let has_group = |group: InsnGroupType::Type| {
+            detail
+                .as_ref()
+                .map(|d| {
+                    d.groups()
+                        .iter()
+                        .any(|g| g.0 as InsnGroupType::Type == group)
+                })
+                .unwrap_or(false)
+        };
+        let is_call = has_group(InsnGroupType::CS_GRP_CALL);
+        let is_jump = has_group(InsnGroupType::CS_GRP_JUMP);
+        let is_return = has_group(InsnGroupType::CS_GRP_RET);
+        let operands = detail
+            .as_ref()
+            .map(|d| d.arch_detail().operands())
+            .unwrap_or_default();
+
+        // Direct branches carry their destination as the last immediate operand
+        let (branch_target, memory_target) = if is_call || is_jump {
+            (operands.iter().rev().find_map(immediate), None)
+        } else {
+            (None, memory_target(arch, insn, &operands))
+        };
+
+        result.push(Instruction {
+            address: insn.address(),
+            bytes: insn.bytes().to_vec(),
+            mnemonic: insn.mnemonic().unwrap_or("").to_string(),
+            operands: insn.op_str().unwrap_or("").to_string(),
+            is_call,
+            is_jump,
+            is_return,
+            branch_target,
+            memory_target,
+        });
+    }
+    Ok(result)
+}
+
+// Reads up to `size` bytes, stopping at the first unreadable page; debugger breakpoints are
+// shown as the instructions they replaced
+pub fn read_code(pid: i32, address: u64, size: usize) -> Vec<u8> {
+    let mut buffer = read_code_raw(pid, address, size);
+    native_bridge::mask_breakpoints(address, &mut buffer);
+    buffer
+}
+
+fn read_code_raw(pid: i32, address: u64, size: usize) -> Vec<u8> {
+    let mut buffer = vec![0u8; size];
+    if let Ok(n) =
+        native_bridge::read_process_memory(pid, address as *mut libc::c_void, size, &mut buffer)
+    {
+        if n > 0 {
+            buffer.truncate(n as usize);
+            return buffer;
+        }
+    }
+
+    let mut result = Vec::new();
+    let mut current = address;
+    while result.len() < size {
+        let page_end = (current | 0xfff) + 1;
+        let chunk = ((page_end - current) as usize).min(size - result.len());
+        let mut page = vec![0u8; chunk];
+        match native_bridge::read_process_memory(
+            pid,
+            current as *mut libc::c_void,
+            chunk,
+            &mut page,
+        ) {
+            Ok(n) if n as usize == chunk => result.extend_from_slice(&page),
+            _ => break,
+        }
+        current = page_end;
+    }
+    result
+}
+
+pub fn disassemble(pid: i32, request: &DisassembleRequest) -> Result<Value, String> {
+    let mut address = util::resolve_target(pid, request.address, request.expression.as_deref())?;
+
+    let mut arch = match &request.arch {
+        Some(name) => Arch::parse(name)?,
+        None => detect_arch(pid, address),
+    };
+    // The low bit of an ARM code address selects Thumb
+    if arch == Arch::Arm && address & 1 == 1 {
+        arch = Arch::Thumb;
+    }
+    if arch == Arch::Thumb {
+        address &= !1;
+    }
+
+    let (size, count) = match (request.length, request.count) {
+        (Some(length), _) => (length.min(MAX_DISASSEMBLE_LENGTH), MAX_INSTRUCTION_COUNT),
+        (None, count) => {
+            let count = count
+                .unwrap_or(DEFAULT_INSTRUCTION_COUNT)
+                .min(MAX_INSTRUCTION_COUNT);
+            (count * arch.max_instruction_size(), count)
+        }
+    };
+
+    let bytes = read_code(pid, address, size);
+    if bytes.is_empty() {
+        return Err(format!("Failed to read memory at 0x{:x}", address));
+    }
+    let instructions = decode(arch, &bytes, address, count)?;
+
+    let targets: Vec<u64> = instructions
+        .iter()
+        .filter_map(|insn| insn.branch_target)
+        .collect();
+    let target_infos = symbols::describe_addresses(pid, &targets).unwrap_or_default();
+    let mut target_infos = target_infos.into_iter();
+
+    let instructions: Vec<Value> = instructions
+        .iter()
+        .map(|insn| {
+            let target_symbol = insn.branch_target.and_then(|_| {
+                let info = target_infos.next()?;
+                info["symbol_expression"]
+                    .as_str()
+                    .or(info["expression"].as_str())
+                    .map(|s| s.to_string())
+            });
+            json!({
+                "address": insn.address,
+                "size": insn.bytes.len(),
+                "bytes": hex::encode(&insn.bytes),
+                "mnemonic": insn.mnemonic,
+                "operands": insn.operands,
+                "is_call": insn.is_call,
+                "is_jump": insn.is_jump,
+                "is_return": insn.is_return,
+                "branch_target": insn.branch_target,
+                "branch_target_symbol": target_symbol,
+                "memory_target": insn.memory_target
+            })
+        })
+        .collect();
+
+    Ok(json!({
+        "arch": arch.name(),
+        "address": address,
+        "instructions": instructions
+    }))
+}
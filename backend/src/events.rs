@@ -0,0 +1,235 @@
+use crate::native_bridge;
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+// Event kinds pushed to stream subscribers
+pub const EXCEPTION: &str = "exception";
+pub const LOG: &str = "log";
+pub const PROCESS_EXIT: &str = "process_exit";
+// Sent in place of events a slow subscriber had to drop
+pub const OVERFLOW: &str = "overflow";
+// Call stack field of exception events, kept only for subscribers that asked for it
+pub const BACKTRACE_FIELD: &str = "backtrace";
+
+const DEFAULT_CAPACITY: usize = 1024;
+const MAX_CAPACITY: usize = 65536;
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+lazy_static! {
+    static ref SUBSCRIBERS: Mutex<BTreeMap<u64, Subscriber>> = Mutex::new(BTreeMap::new());
+}
+
+static NEXT_SUBSCRIBER: AtomicU64 = AtomicU64::new(1);
+static NEXT_EVENT: AtomicU64 = AtomicU64::new(1);
+// Bumped whenever another process is opened, which ends the exit watch of the previous one
+static WATCH_GENERATION: AtomicU64 = AtomicU64::new(0);
+// Set on shutdown; open streams would otherwise keep a graceful shutdown waiting forever
+static CLOSED: AtomicBool = AtomicBool::new(false);
+
+pub struct Event {
+    pub id: u64,
+    pub kind: String,
+    pub data: Value,
+}
+
+struct Subscriber {
+    kinds: Option<Vec<String>>,
+    capacity: usize,
+    queue: VecDeque<Event>,
+    dropped: u64,
+    backtraces: bool,
+    notify: Arc<Notify>,
+}
+
+impl Subscriber {
+    fn wants(&self, kind: &str) -> bool {
+        self.kinds
+            .as_ref()
+            .is_none_or(|kinds| kinds.iter().any(|k| k == kind))
+    }
+
+    // The oldest event gives way, so a stalled client holds at most `capacity` events
+    fn push(&mut self, event: Event) {
+        if self.queue.len() >= self.capacity {
+            self.queue.pop_front();
+            self.dropped += 1;
+        }
+        self.queue.push_back(event);
+    }
+
+    fn pop(&mut self) -> Option<Event> {
+        if self.dropped > 0 {
+            let dropped = std::mem::take(&mut self.dropped);
+            return Some(Event {
+                id: NEXT_EVENT.fetch_add(1, Ordering::Relaxed),
+                kind: OVERFLOW.to_string(),
+                data: json!({ "dropped": dropped }),
+            });
+        }
+        self.queue.pop_front()
+    }
+}
+
+// One client's view of the stream; unsubscribes when dropped with the connection
+pub struct Subscription {
+    id: u64,
+    notify: Arc<Notify>,
+}
+
+impl Subscription {
+    // None once the server is shutting down, which ends the stream
+    pub async fn next(&self) -> Option<Event> {
+        loop {
+            if CLOSED.load(Ordering::SeqCst) {
+                return None;
+            }
+            if let Some(event) = SUBSCRIBERS
+                .lock()
+                .unwrap()
+                .get_mut(&self.id)
+                .and_then(Subscriber::pop)
+            {
+                return Some(event);
+            }
+            // A publish between the check and this await leaves a permit, so nothing is missed
+            self.notify.notified().await;
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        SUBSCRIBERS.lock().unwrap().remove(&self.id);
+    }
+}
+
+// `kinds` limits the subscription to those event kinds; overflow notices are always sent
+pub fn subscribe(
+    kinds: Option<Vec<String>>,
+    capacity: Option<usize>,
+    backtraces: bool,
+) -> Subscription {
+    let id = NEXT_SUBSCRIBER.fetch_add(1, Ordering::Relaxed);
+    let notify = Arc::new(Notify::new());
+    SUBSCRIBERS.lock().unwrap().insert(
+        id,
+        Subscriber {
+            kinds,
+            capacity: capacity.unwrap_or(DEFAULT_CAPACITY).clamp(1, MAX_CAPACITY),
+            queue: VecDeque::new(),
+            dropped: 0,
+            backtraces,
+            notify: notify.clone(),
+        },
+    );
+    Subscription { id, notify }
+}
+
+// Ends every open subscription, and any made afterwards, so their connections can close
+pub fn close_all() {
+    CLOSED.store(true, Ordering::SeqCst);
+    for subscriber in SUBSCRIBERS.lock().unwrap().values() {
+        subscriber.notify.notify_one();
+    }
+}
+
+pub fn has_subscribers() -> bool {
+    !SUBSCRIBERS.lock().unwrap().is_empty()
+}
+
+pub fn wants_backtraces() -> bool {
+    SUBSCRIBERS
+        .lock()
+        .unwrap()
+        .values()
+        .any(|subscriber| subscriber.backtraces && subscriber.wants(EXCEPTION))
+}
+
+pub fn publish(kind: &str, data: Value) {
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    if subscribers.is_empty() {
+        return;
+    }
+    let id = NEXT_EVENT.fetch_add(1, Ordering::Relaxed);
+    for subscriber in subscribers.values_mut() {
+        if subscriber.wants(kind) {
+            let mut data = data.clone();
+            if !subscriber.backtraces {
+                if let Some(fields) = data.as_object_mut() {
+                    fields.remove(BACKTRACE_FIELD);
+                }
+            }
+            subscriber.push(Event {
+                id,
+                kind: kind.to_string(),
+                data,
+            });
+            subscriber.notify.notify_one();
+        }
+    }
+}
+
+// Publishes a process_exit event once `pid` is gone, unless another process is opened first
+pub fn watch_process_exit(pid: i32) {
+    let generation = WATCH_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    std::thread::spawn(move || {
+        while WATCH_GENERATION.load(Ordering::SeqCst) == generation {
+            if !native_bridge::is_process_alive(pid) {
+                publish(PROCESS_EXIT, json!({ "pid": pid }));
+                return;
+            }
+            std::thread::sleep(EXIT_POLL_INTERVAL);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscriber(kinds: Option<Vec<String>>, capacity: usize) -> Subscriber {
+        Subscriber {
+            kinds,
+            capacity,
+            queue: VecDeque::new(),
+            dropped: 0,
+            backtraces: false,
+            notify: Arc::new(Notify::new()),
+        }
+    }
+
+    fn event(id: u64) -> Event {
+        Event {
+            id,
+            kind: EXCEPTION.to_string(),
+            data: json!(id),
+        }
+    }
+
+    #[test]
+    fn bounded_queue_reports_overflow_first() {
+        let mut subscriber = subscriber(None, 2);
+        for id in 1..=5 {
+            subscriber.push(event(id));
+        }
+        let overflow = subscriber.pop().unwrap();
+        assert_eq!(overflow.kind, OVERFLOW);
+        assert_eq!(overflow.data, json!({ "dropped": 3 }));
+        assert_eq!(subscriber.pop().unwrap().id, 4);
+        assert_eq!(subscriber.pop().unwrap().id, 5);
+        assert!(subscriber.pop().is_none());
+    }
+
+    #[test]
+    fn filters_by_kind() {
+        assert!(subscriber(None, 1).wants(LOG));
+        let subscriber = subscriber(Some(vec![EXCEPTION.to_string()]), 1);
+        assert!(subscriber.wants(EXCEPTION));
+        assert!(!subscriber.wants(LOG));
+    }
+}
@@ -0,0 +1,1010 @@
+// Address expression parser and evaluator.
+//
+// Grammar, lowest precedence first (binary operators other than comparisons are left
+// associative):
+//
+//   expr        := logical_or
+//   logical_or  := logical_and ('||' logical_and)*
+//   logical_and := comparison ('&&' comparison)*
+//   comparison  := bit_or (('==' | '!=' | '<' | '<=' | '>' | '>=') bit_or)?
+//   bit_or      := bit_xor ('|' bit_xor)*
+//   bit_xor  := bit_and ('^' bit_and)*
+//   bit_and  := shift ('&' shift)*
+//   shift    := additive (('<<' | '>>') additive)*
+//   additive := term (('+' | '-') term)*
+//   term     := unary (('*' | '/' | '%') unary)*
+//   unary    := ('-' | '+' | '~') unary | primary
+//   primary  := number | identifier | '(' expr ')' | '[' (width ':')? expr ']'
+//
+// Numbers are decimal or `0x` hex. Identifiers (module names such as `libc.so.6`,
+// `threadstack0`, `module!symbol` such as `libc.so.6!malloc`, or a named signature such as
+// `sig:PlayerBase`) are resolved through an `EvalContext`. Module and symbol names that are not
+// plain identifiers are written in double quotes, e.g. `"libc++_shared.so"+0x10` or
+// `"ld-linux-x86-64.so.2"!_dl_start`; `quote_module` and `quote_symbol` produce that form. The
+// quotes may be left out for names of loaded modules, which are matched longest first when the
+// expression is parsed with their names. `[expr]` reads 8 bytes at the address;
+// `[4:expr]` reads the given width (1, 2, 4 or 8) zero-extended. Arithmetic is unsigned 64-bit
+// and wraps. Comparisons are unsigned and, like `&&` and `||`, yield 1 or 0;
+// the logical operators short-circuit, so `x1 != 0 && [x1] == 5` never reads address 0.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Number(u64),
+    Identifier(String),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Shl,
+    Shr,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    EqEq,
+    NotEq,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    AndAnd,
+    OrOr,
+    Colon,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    End,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::Number(value) => write!(f, "number 0x{:x}", value),
+            TokenKind::Identifier(name) => write!(f, "'{}'", name),
+            TokenKind::Plus => write!(f, "'+'"),
+            TokenKind::Minus => write!(f, "'-'"),
+            TokenKind::Star => write!(f, "'*'"),
+            TokenKind::Slash => write!(f, "'/'"),
+            TokenKind::Percent => write!(f, "'%'"),
+            TokenKind::Shl => write!(f, "'<<'"),
+            TokenKind::Shr => write!(f, "'>>'"),
+            TokenKind::Ampersand => write!(f, "'&'"),
+            TokenKind::Pipe => write!(f, "'|'"),
+            TokenKind::Caret => write!(f, "'^'"),
+            TokenKind::Tilde => write!(f, "'~'"),
+            TokenKind::EqEq => write!(f, "'=='"),
+            TokenKind::NotEq => write!(f, "'!='"),
+            TokenKind::Less => write!(f, "'<'"),
+            TokenKind::LessEq => write!(f, "'<='"),
+            TokenKind::Greater => write!(f, "'>'"),
+            TokenKind::GreaterEq => write!(f, "'>='"),
+            TokenKind::AndAnd => write!(f, "'&&'"),
+            TokenKind::OrOr => write!(f, "'||'"),
+            TokenKind::Colon => write!(f, "':'"),
+            TokenKind::LParen => write!(f, "'('"),
+            TokenKind::RParen => write!(f, "')'"),
+            TokenKind::LBracket => write!(f, "'['"),
+            TokenKind::RBracket => write!(f, "']'"),
+            TokenKind::End => write!(f, "end of expression"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    position: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Negate,
+    Plus,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Shl,
+    Shr,
+    And,
+    Or,
+    Xor,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    LogicalAnd,
+    LogicalOr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(u64),
+    Identifier {
+        name: String,
+        position: usize,
+    },
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+        position: usize,
+    },
+    Deref {
+        width: usize,
+        address: Box<Expr>,
+        position: usize,
+    },
+}
+
+// Supplies identifier values and memory reads while an expression is evaluated
+pub trait EvalContext {
+    fn resolve_identifier(&self, name: &str) -> Option<u64>;
+    fn read_memory(&self, address: u64, width: usize) -> Result<u64, String>;
+    // Module names recognized without quotes by `evaluate`
+    fn module_names(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$' || c == '@'
+}
+
+// Reads a `"name"` starting at chars[*i], leaving *i after the closing quote
+fn quoted_name(input: &str, chars: &[(usize, char)], i: &mut usize) -> Result<String, String> {
+    let (position, _) = chars[*i];
+    let start = *i + 1;
+    let mut end = start;
+    while end < chars.len() && chars[end].1 != '"' {
+        end += 1;
+    }
+    if end == chars.len() {
+        return Err(format!("Unterminated name at position {}", position));
+    }
+    if end == start {
+        return Err(format!("Empty name at position {}", position));
+    }
+    *i = end + 1;
+    Ok(input[chars[start].0..chars[end].0].to_string())
+}
+
+// Module file names as written in expressions; quoted unless they read as one identifier
+pub fn quote_module(name: &str) -> String {
+    let mut chars = name.chars();
+    let plain = chars.next().is_some_and(is_identifier_start)
+        && chars.all(is_identifier_char)
+        && name != "sig";
+    if plain {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name)
+    }
+}
+
+// Symbol names as written after `module!`
+pub fn quote_symbol(name: &str) -> String {
+    let mut chars = name.chars();
+    if chars.next().is_some_and(is_identifier_start) && chars.all(is_symbol_char) {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name)
+    }
+}
+
+// Longest of `names` written at `position`, as a whole word. Only names that would otherwise
+// need quotes are considered, so plain identifiers lex the same with or without them.
+fn known_name_at<'a>(input: &str, position: usize, names: &'a [String]) -> Option<&'a str> {
+    let rest = &input[position..];
+    names
+        .iter()
+        .filter(|name| quote_module(name).starts_with('"') && !name.contains('"'))
+        .filter(|name| {
+            rest.starts_with(name.as_str())
+                && !rest[name.len()..]
+                    .chars()
+                    .next()
+                    .is_some_and(is_identifier_char)
+        })
+        .max_by_key(|name| name.len())
+        .map(String::as_str)
+}
+
+fn tokenize(input: &str, names: &[String]) -> Result<Vec<Token>, String> {
+    let chars: Vec<(usize, char)> = input.char_indices().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (position, c) = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let known = known_name_at(input, position, names);
+        if c.is_ascii_digit() && known.is_none() {
+            let start = i;
+            while i < chars.len() && is_identifier_char(chars[i].1) {
+                i += 1;
+            }
+            let end = chars.get(i).map(|&(p, _)| p).unwrap_or(input.len());
+            let text = &input[position..end];
+            let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => text.parse::<u64>(),
+            };
+            let value = parsed.map_err(|e| {
+                format!("Invalid number '{}' at position {}: {}", text, position, e)
+            })?;
+            tokens.push(Token {
+                kind: TokenKind::Number(value),
+                position: chars[start].0,
+            });
+            continue;
+        }
+
+        if is_identifier_start(c) || c == '"' || known.is_some() {
+            let mut name = if let Some(known) = known {
+                while i < chars.len() && chars[i].0 < position + known.len() {
+                    i += 1;
+                }
+                known.to_string()
+            } else if c == '"' {
+                quoted_name(input, &chars, &mut i)?
+            } else {
+                while i < chars.len() && is_identifier_char(chars[i].1) {
+                    i += 1;
+                }
+                let word_end = chars.get(i).map(|&(p, _)| p).unwrap_or(input.len());
+                input[position..word_end].to_string()
+            };
+            let separator = chars.get(i).map(|&(_, c)| c);
+            let next = chars.get(i + 1).map(|&(_, c)| c);
+            let continues = next.is_some_and(is_identifier_start);
+            // `sig:Name`
+            if known.is_none() && c != '"' && name == "sig" && separator == Some(':') && continues {
+                let start = i;
+                i += 1;
+                while i < chars.len() && is_identifier_char(chars[i].1) {
+                    i += 1;
+                }
+                let end = chars.get(i).map(|&(p, _)| p).unwrap_or(input.len());
+                name.push_str(&input[chars[start].0..end]);
+            // `module!symbol`
+            } else if separator == Some('!') && next == Some('"') {
+                i += 1;
+                let symbol = quoted_name(input, &chars, &mut i)?;
+                name = format!("{}!{}", name, symbol);
+            } else if separator == Some('!') && continues {
+                i += 1;
+                let start = i;
+                while i < chars.len() && is_symbol_char(chars[i].1) {
+                    i += 1;
+                }
+                let end = chars.get(i).map(|&(p, _)| p).unwrap_or(input.len());
+                name = format!("{}!{}", name, &input[chars[start].0..end]);
+            }
+            tokens.push(Token {
+                kind: TokenKind::Identifier(name),
+                position,
+            });
+            continue;
+        }
+
+        let next = chars.get(i + 1).map(|&(_, c)| c);
+        let (kind, length) = match (c, next) {
+            ('<', Some('<')) => (TokenKind::Shl, 2),
+            ('>', Some('>')) => (TokenKind::Shr, 2),
+            ('<', Some('=')) => (TokenKind::LessEq, 2),
+            ('>', Some('=')) => (TokenKind::GreaterEq, 2),
+            ('=', Some('=')) => (TokenKind::EqEq, 2),
+            ('!', Some('=')) => (TokenKind::NotEq, 2),
+            ('&', Some('&')) => (TokenKind::AndAnd, 2),
+            ('|', Some('|')) => (TokenKind::OrOr, 2),
+            ('<', _) => (TokenKind::Less, 1),
+            ('>', _) => (TokenKind::Greater, 1),
+            ('+', _) => (TokenKind::Plus, 1),
+            ('-', _) => (TokenKind::Minus, 1),
+            ('*', _) => (TokenKind::Star, 1),
+            ('/', _) => (TokenKind::Slash, 1),
+            ('%', _) => (TokenKind::Percent, 1),
+            ('&', _) => (TokenKind::Ampersand, 1),
+            ('|', _) => (TokenKind::Pipe, 1),
+            ('^', _) => (TokenKind::Caret, 1),
+            ('~', _) => (TokenKind::Tilde, 1),
+            (':', _) => (TokenKind::Colon, 1),
+            ('(', _) => (TokenKind::LParen, 1),
+            (')', _) => (TokenKind::RParen, 1),
+            ('[', _) => (TokenKind::LBracket, 1),
+            (']', _) => (TokenKind::RBracket, 1),
+            _ => {
+                return Err(format!(
+                    "Unexpected character '{}' at position {}",
+                    c, position
+                ))
+            }
+        };
+        tokens.push(Token { kind, position });
+        i += length;
+    }
+
+    tokens.push(Token {
+        kind: TokenKind::End,
+        position: input.len(),
+    });
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn peek_kind_at(&self, offset: usize) -> &TokenKind {
+        let idx = (self.pos + offset).min(self.tokens.len() - 1);
+        &self.tokens[idx].kind
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.kind != TokenKind::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> Result<Token, String> {
+        let token = self.peek().clone();
+        if token.kind == kind {
+            Ok(self.advance())
+        } else {
+            Err(format!(
+                "Expected {} but found {} at position {}",
+                kind, token.kind, token.position
+            ))
+        }
+    }
+
+    // Parses one left-associative precedence level
+    fn binary_level(
+        &mut self,
+        operators: &[(TokenKind, BinaryOp)],
+        next: fn(&mut Parser) -> Result<Expr, String>,
+    ) -> Result<Expr, String> {
+        let mut left = next(self)?;
+        loop {
+            let token = self.peek().clone();
+            let op = match operators.iter().find(|(kind, _)| *kind == token.kind) {
+                Some(&(_, op)) => op,
+                None => return Ok(left),
+            };
+            self.advance();
+            let right = next(self)?;
+            left = Expr::Binary {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+                position: token.position,
+            };
+        }
+    }
+
+    fn logical_or(&mut self) -> Result<Expr, String> {
+        self.binary_level(
+            &[(TokenKind::OrOr, BinaryOp::LogicalOr)],
+            Parser::logical_and,
+        )
+    }
+
+    fn logical_and(&mut self) -> Result<Expr, String> {
+        self.binary_level(
+            &[(TokenKind::AndAnd, BinaryOp::LogicalAnd)],
+            Parser::comparison,
+        )
+    }
+
+    // Comparisons do not chain; `a < b < c` is rejected rather than silently comparing a 0 or 1
+    fn comparison(&mut self) -> Result<Expr, String> {
+        const OPERATORS: [(TokenKind, BinaryOp); 6] = [
+            (TokenKind::EqEq, BinaryOp::Eq),
+            (TokenKind::NotEq, BinaryOp::Ne),
+            (TokenKind::Less, BinaryOp::Lt),
+            (TokenKind::LessEq, BinaryOp::Le),
+            (TokenKind::Greater, BinaryOp::Gt),
+            (TokenKind::GreaterEq, BinaryOp::Ge),
+        ];
+        let left = self.bit_or()?;
+        let token = self.peek().clone();
+        let op = match OPERATORS.iter().find(|(kind, _)| *kind == token.kind) {
+            Some(&(_, op)) => op,
+            None => return Ok(left),
+        };
+        self.advance();
+        let right = self.bit_or()?;
+        Ok(Expr::Binary {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+            position: token.position,
+        })
+    }
+
+    fn bit_or(&mut self) -> Result<Expr, String> {
+        self.binary_level(&[(TokenKind::Pipe, BinaryOp::Or)], Parser::bit_xor)
+    }
+
+    fn bit_xor(&mut self) -> Result<Expr, String> {
+        self.binary_level(&[(TokenKind::Caret, BinaryOp::Xor)], Parser::bit_and)
+    }
+
+    fn bit_and(&mut self) -> Result<Expr, String> {
+        self.binary_level(&[(TokenKind::Ampersand, BinaryOp::And)], Parser::shift)
+    }
+
+    fn shift(&mut self) -> Result<Expr, String> {
+        self.binary_level(
+            &[
+                (TokenKind::Shl, BinaryOp::Shl),
+                (TokenKind::Shr, BinaryOp::Shr),
+            ],
+            Parser::additive,
+        )
+    }
+
+    fn additive(&mut self) -> Result<Expr, String> {
+        self.binary_level(
+            &[
+                (TokenKind::Plus, BinaryOp::Add),
+                (TokenKind::Minus, BinaryOp::Sub),
+            ],
+            Parser::term,
+        )
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        self.binary_level(
+            &[
+                (TokenKind::Star, BinaryOp::Mul),
+                (TokenKind::Slash, BinaryOp::Div),
+                (TokenKind::Percent, BinaryOp::Rem),
+            ],
+            Parser::unary,
+        )
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let op = match self.peek().kind {
+            TokenKind::Minus => UnaryOp::Negate,
+            TokenKind::Plus => UnaryOp::Plus,
+            TokenKind::Tilde => UnaryOp::Not,
+            _ => return self.primary(),
+        };
+        self.advance();
+        let operand = self.unary()?;
+        Ok(Expr::Unary {
+            op,
+            operand: Box::new(operand),
+        })
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = self.advance();
+        match token.kind {
+            TokenKind::Number(value) => Ok(Expr::Number(value)),
+            TokenKind::Identifier(name) => Ok(Expr::Identifier {
+                name,
+                position: token.position,
+            }),
+            TokenKind::LParen => {
+                let inner = self.logical_or()?;
+                self.expect(TokenKind::RParen)?;
+                Ok(inner)
+            }
+            TokenKind::LBracket => {
+                let mut width = 8;
+                if let (TokenKind::Number(value), TokenKind::Colon) =
+                    (self.peek_kind_at(0).clone(), self.peek_kind_at(1))
+                {
+                    let width_token = self.advance();
+                    if ![1, 2, 4, 8].contains(&value) {
+                        return Err(format!(
+                            "Invalid dereference width {} at position {} (expected 1, 2, 4 or 8)",
+                            value, width_token.position
+                        ));
+                    }
+                    width = value as usize;
+                    self.advance();
+                }
+                let address = self.logical_or()?;
+                self.expect(TokenKind::RBracket)?;
+                Ok(Expr::Deref {
+                    width,
+                    address: Box::new(address),
+                    position: token.position,
+                })
+            }
+            kind => Err(format!(
+                "Unexpected {} at position {}",
+                kind, token.position
+            )),
+        }
+    }
+}
+
+// `names` (module file names) are also accepted without quotes
+pub fn parse(input: &str, names: &[String]) -> Result<Expr, String> {
+    let tokens = tokenize(input, names)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    if parser.peek().kind == TokenKind::End {
+        return Err("Empty expression".to_string());
+    }
+    let expr = parser.logical_or()?;
+    let token = parser.peek();
+    if token.kind != TokenKind::End {
+        return Err(format!(
+            "Unexpected {} at position {}",
+            token.kind, token.position
+        ));
+    }
+    Ok(expr)
+}
+
+impl Expr {
+    pub fn evaluate(&self, context: &dyn EvalContext) -> Result<u64, String> {
+        match self {
+            Expr::Number(value) => Ok(*value),
+            Expr::Identifier { name, position } => context
+                .resolve_identifier(name)
+                .ok_or_else(|| format!("Unknown identifier '{}' at position {}", name, position)),
+            Expr::Unary { op, operand } => {
+                let value = operand.evaluate(context)?;
+                Ok(match op {
+                    UnaryOp::Negate => value.wrapping_neg(),
+                    UnaryOp::Plus => value,
+                    UnaryOp::Not => !value,
+                })
+            }
+            Expr::Binary {
+                op,
+                left,
+                right,
+                position,
+            } => {
+                let left = left.evaluate(context)?;
+                match op {
+                    BinaryOp::LogicalAnd if left == 0 => return Ok(0),
+                    BinaryOp::LogicalOr if left != 0 => return Ok(1),
+                    _ => {}
+                }
+                let right = right.evaluate(context)?;
+                match op {
+                    BinaryOp::Add => Ok(left.wrapping_add(right)),
+                    BinaryOp::Sub => Ok(left.wrapping_sub(right)),
+                    BinaryOp::Mul => Ok(left.wrapping_mul(right)),
+                    BinaryOp::Div | BinaryOp::Rem if right == 0 => {
+                        Err(format!("Division by zero at position {}", position))
+                    }
+                    BinaryOp::Div => Ok(left / right),
+                    BinaryOp::Rem => Ok(left % right),
+                    BinaryOp::Shl => Ok(left.checked_shl(right as u32).unwrap_or(0)),
+                    BinaryOp::Shr => Ok(left.checked_shr(right as u32).unwrap_or(0)),
+                    BinaryOp::And => Ok(left & right),
+                    BinaryOp::Or => Ok(left | right),
+                    BinaryOp::Xor => Ok(left ^ right),
+                    BinaryOp::Eq => Ok((left == right) as u64),
+                    BinaryOp::Ne => Ok((left != right) as u64),
+                    BinaryOp::Lt => Ok((left < right) as u64),
+                    BinaryOp::Le => Ok((left <= right) as u64),
+                    BinaryOp::Gt => Ok((left > right) as u64),
+                    BinaryOp::Ge => Ok((left >= right) as u64),
+                    BinaryOp::LogicalAnd | BinaryOp::LogicalOr => Ok((right != 0) as u64),
+                }
+            }
+            Expr::Deref {
+                width,
+                address,
+                position,
+            } => {
+                let address = address.evaluate(context)?;
+                context
+                    .read_memory(address, *width)
+                    .map_err(|e| format!("{} (dereference at position {})", e, position))
+            }
+        }
+    }
+}
+
+pub fn evaluate(input: &str, context: &dyn EvalContext) -> Result<u64, String> {
+    parse(input, &context.module_names())?.evaluate(context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    struct TestContext {
+        identifiers: HashMap<&'static str, u64>,
+        memory: HashMap<u64, u8>,
+    }
+
+    impl TestContext {
+        fn new() -> Self {
+            let mut context = TestContext {
+                identifiers: HashMap::new(),
+                memory: HashMap::new(),
+            };
+            context.identifiers.insert("libil2cpp.so", 0x7000_0000);
+            context.identifiers.insert("libc.so.6", 0x7100_0000);
+            context.identifiers.insert("threadstack0", 0x7fff_f000);
+            context.identifiers.insert("libc.so.6!malloc", 0x7100_1230);
+            context
+                .identifiers
+                .insert("libil2cpp.so!il2cpp_domain_get", 0x7000_5000);
+            context.identifiers.insert("sig:PlayerBase", 0x7000_2000);
+            context.write(0x1000, &0x2000u64.to_le_bytes());
+            context.write(0x2010, &0x1122_3344_5566_7788u64.to_le_bytes());
+            context.write(0x7000_0100, &0x1000u64.to_le_bytes());
+            context
+        }
+
+        fn write(&mut self, address: u64, bytes: &[u8]) {
+            for (i, byte) in bytes.iter().enumerate() {
+                self.memory.insert(address + i as u64, *byte);
+            }
+        }
+    }
+
+    impl EvalContext for TestContext {
+        fn resolve_identifier(&self, name: &str) -> Option<u64> {
+            self.identifiers.get(name).copied()
+        }
+
+        fn module_names(&self) -> Vec<String> {
+            self.identifiers
+                .keys()
+                .filter(|name| !name.contains('!') && !name.contains(':'))
+                .map(|name| name.to_string())
+                .collect()
+        }
+
+        fn read_memory(&self, address: u64, width: usize) -> Result<u64, String> {
+            let mut bytes = [0u8; 8];
+            for (i, byte) in bytes.iter_mut().take(width).enumerate() {
+                *byte = *self
+                    .memory
+                    .get(&(address + i as u64))
+                    .ok_or_else(|| format!("Failed to read memory at 0x{:x}", address))?;
+            }
+            Ok(u64::from_le_bytes(bytes))
+        }
+    }
+
+    fn eval(input: &str) -> Result<u64, String> {
+        evaluate(input, &TestContext::new())
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(eval("42"), Ok(42));
+        assert_eq!(eval("0x2a"), Ok(42));
+        assert_eq!(eval("0X2A"), Ok(42));
+        assert_eq!(eval("0xffffffffffffffff"), Ok(u64::MAX));
+        assert_eq!(eval("  7  "), Ok(7));
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval("2 * 3 + 1"), Ok(7));
+        assert_eq!(eval("10 - 4 - 3"), Ok(3));
+        assert_eq!(eval("100 / 10 / 5"), Ok(2));
+        assert_eq!(eval("1 + 1 << 4"), Ok(32));
+        assert_eq!(eval("0xff & 0x0f | 0x30"), Ok(0x3f));
+        assert_eq!(eval("6 ^ 3 & 1"), Ok(7));
+        assert_eq!(eval("17 % 5 * 2"), Ok(4));
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval("((0x10))"), Ok(16));
+        assert_eq!(eval("2 * (3 + (4 - 1))"), Ok(12));
+    }
+
+    #[test]
+    fn unary_operators() {
+        assert_eq!(eval("-1"), Ok(u64::MAX));
+        assert_eq!(eval("0x20 + -0x10"), Ok(0x10));
+        assert_eq!(eval("--5"), Ok(5));
+        assert_eq!(eval("+5"), Ok(5));
+        assert_eq!(eval("~0"), Ok(u64::MAX));
+        assert_eq!(eval("-2 * 3"), Ok(6u64.wrapping_neg()));
+    }
+
+    #[test]
+    fn division_shifts_and_wrapping() {
+        assert_eq!(eval("0x100 / 0x10"), Ok(0x10));
+        assert_eq!(eval("1 << 63"), Ok(1 << 63));
+        assert_eq!(eval("0x8000 >> 15"), Ok(1));
+        assert_eq!(eval("1 << 64"), Ok(0));
+        assert_eq!(eval("0 - 1"), Ok(u64::MAX));
+        assert_eq!(eval("0xffffffffffffffff + 2"), Ok(1));
+    }
+
+    #[test]
+    fn identifiers() {
+        assert_eq!(eval("libil2cpp.so"), Ok(0x7000_0000));
+        assert_eq!(eval("libil2cpp.so+0x100"), Ok(0x7000_0100));
+        assert_eq!(eval("libc.so.6 + 0x10 * 2"), Ok(0x7100_0020));
+        assert_eq!(eval("threadstack0-0x20"), Ok(0x7fff_efe0));
+    }
+
+    #[test]
+    fn module_symbols() {
+        assert_eq!(eval("libc.so.6!malloc"), Ok(0x7100_1230));
+        assert_eq!(eval("libc.so.6!malloc+0x10"), Ok(0x7100_1240));
+        assert_eq!(
+            eval("[libil2cpp.so!il2cpp_domain_get - 0x4f00]"),
+            Ok(0x1000)
+        );
+        assert_eq!(
+            eval("libc.so.6!free"),
+            Err("Unknown identifier 'libc.so.6!free' at position 0".to_string())
+        );
+        assert_eq!(
+            eval("libc.so.6!"),
+            Err("Unexpected character '!' at position 9".to_string())
+        );
+    }
+
+    #[test]
+    fn quoted_module_names() {
+        let mut context = TestContext::new();
+        context.identifiers.insert("libc++_shared.so", 0x7200_0000);
+        context
+            .identifiers
+            .insert("ld-linux-x86-64.so.2", 0x7300_0000);
+        context.identifiers.insert("7zip.so", 0x7400_0000);
+        context
+            .identifiers
+            .insert("libc++_shared.so!operator new(unsigned long)", 0x7200_1000);
+        let eval = |input: &str| evaluate(input, &context);
+
+        assert_eq!(eval("\"libc++_shared.so\"+0x10"), Ok(0x7200_0010));
+        assert_eq!(eval("\"ld-linux-x86-64.so.2\" + 0x100"), Ok(0x7300_0100));
+        assert_eq!(eval("\"7zip.so\"+4"), Ok(0x7400_0004));
+        assert_eq!(
+            eval("\"libc++_shared.so\"!\"operator new(unsigned long)\"+1"),
+            Ok(0x7200_1001)
+        );
+        assert_eq!(eval("\"libc.so.6\"!malloc"), Ok(0x7100_1230));
+        assert_eq!(
+            eval("\"libc++_shared.so"),
+            Err("Unterminated name at position 0".to_string())
+        );
+
+        // What the symbolizer and pointer scanner print reads back as the same address
+        for name in [
+            "libc++_shared.so",
+            "ld-linux-x86-64.so.2",
+            "7zip.so",
+            "libc.so.6",
+        ] {
+            let expression = format!("{}+0x10", quote_module(name));
+            assert_eq!(
+                eval(&expression),
+                Ok(context.identifiers[name] + 0x10),
+                "{}",
+                expression
+            );
+        }
+        // Loaded modules are also found without the quotes
+        assert_eq!(eval("libc++_shared.so+0x10"), Ok(0x7200_0010));
+        assert_eq!(eval("ld-linux-x86-64.so.2 + 0x100"), Ok(0x7300_0100));
+        assert_eq!(eval("ld-linux-x86-64.so.2-0x100"), Ok(0x72ff_ff00));
+        assert_eq!(eval("7zip.so+4"), Ok(0x7400_0004));
+        assert_eq!(
+            eval("libc++_shared.so!\"operator new(unsigned long)\"+1"),
+            Ok(0x7200_1001)
+        );
+        assert_eq!(quote_module("libc.so.6"), "libc.so.6");
+        assert_eq!(quote_module("libc-2.31.so"), "\"libc-2.31.so\"");
+        assert_eq!(quote_symbol("malloc"), "malloc");
+        assert_eq!(
+            quote_symbol("operator new(unsigned long)"),
+            "\"operator new(unsigned long)\""
+        );
+    }
+
+    #[test]
+    fn signatures() {
+        assert_eq!(eval("sig:PlayerBase"), Ok(0x7000_2000));
+        assert!(eval("[sig:PlayerBase + 0x18]")
+            .unwrap_err()
+            .starts_with("Failed to read memory at 0x70002018"));
+        assert_eq!(eval("sig:PlayerBase-libil2cpp.so"), Ok(0x2000));
+        assert_eq!(
+            eval("sig:Missing"),
+            Err("Unknown identifier 'sig:Missing' at position 0".to_string())
+        );
+        assert_eq!(
+            eval("sig:"),
+            Err("Unexpected ':' at position 3".to_string())
+        );
+    }
+
+    #[test]
+    fn dereference() {
+        assert_eq!(eval("[0x1000]"), Ok(0x2000));
+        assert_eq!(eval("[0x1000] + 0x10"), Ok(0x2010));
+        assert_eq!(eval("[[0x1000] + 0x10]"), Ok(0x1122_3344_5566_7788));
+        assert_eq!(eval("[[libil2cpp.so+0x100]]"), Ok(0x2000));
+        assert_eq!(eval("[(0x800 * 2)]"), Ok(0x2000));
+    }
+
+    #[test]
+    fn dereference_width() {
+        assert_eq!(eval("[8:0x2010]"), Ok(0x1122_3344_5566_7788));
+        assert_eq!(eval("[4:0x2010]"), Ok(0x5566_7788));
+        assert_eq!(eval("[2:0x2010]"), Ok(0x7788));
+        assert_eq!(eval("[1:0x2010]"), Ok(0x88));
+        assert_eq!(eval("[4:[0x1000]+0x10]"), Ok(0x5566_7788));
+        assert_eq!(eval("[ 4 : 0x2010 + 4 ]"), Ok(0x1122_3344));
+    }
+
+    #[test]
+    fn dereference_without_width_is_not_confused_with_width() {
+        assert_eq!(eval("[4096]"), Ok(0x2000));
+        assert_eq!(eval("[4096 + 0]"), Ok(0x2000));
+    }
+
+    #[test]
+    fn tokenizer_errors() {
+        assert_eq!(
+            eval("1 + $"),
+            Err("Unexpected character '$' at position 4".to_string())
+        );
+        assert!(eval("0xzz")
+            .unwrap_err()
+            .contains("Invalid number '0xzz' at position 0"));
+        assert!(eval("12abc").unwrap_err().contains("at position 0"));
+        assert!(eval("99999999999999999999")
+            .unwrap_err()
+            .contains("Invalid number"));
+    }
+
+    #[test]
+    fn parser_errors() {
+        assert_eq!(eval(""), Err("Empty expression".to_string()));
+        assert_eq!(eval("   "), Err("Empty expression".to_string()));
+        assert_eq!(
+            eval("1 +"),
+            Err("Unexpected end of expression at position 3".to_string())
+        );
+        assert_eq!(
+            eval("(1 + 2"),
+            Err("Expected ')' but found end of expression at position 6".to_string())
+        );
+        assert_eq!(
+            eval("[0x1000"),
+            Err("Expected ']' but found end of expression at position 7".to_string())
+        );
+        assert_eq!(
+            eval("1 2"),
+            Err("Unexpected number 0x2 at position 2".to_string())
+        );
+        assert_eq!(
+            eval("1 + )"),
+            Err("Unexpected ')' at position 4".to_string())
+        );
+        assert_eq!(eval("* 2"), Err("Unexpected '*' at position 0".to_string()));
+        assert_eq!(
+            eval("[3:0x1000]"),
+            Err("Invalid dereference width 3 at position 1 (expected 1, 2, 4 or 8)".to_string())
+        );
+    }
+
+    #[test]
+    fn evaluation_errors() {
+        assert_eq!(
+            eval("libfoo.so + 1"),
+            Err("Unknown identifier 'libfoo.so' at position 0".to_string())
+        );
+        assert_eq!(
+            eval("1 + 4 / (2 - 2)"),
+            Err("Division by zero at position 6".to_string())
+        );
+        assert_eq!(
+            eval("5 % 0"),
+            Err("Division by zero at position 2".to_string())
+        );
+        assert_eq!(
+            eval("1 + [0xdead]"),
+            Err("Failed to read memory at 0xdead (dereference at position 4)".to_string())
+        );
+    }
+
+    #[test]
+    fn comparisons_and_logic() {
+        assert_eq!(eval("1 == 1"), Ok(1));
+        assert_eq!(eval("1 != 1"), Ok(0));
+        assert_eq!(eval("2 < 3 && 3 <= 3 && 4 > 3 && 4 >= 5"), Ok(0));
+        assert_eq!(eval("-1 > 0"), Ok(1));
+        assert_eq!(eval("1 << 2 == 4"), Ok(1));
+        assert_eq!(eval("0x10 & 0x10 == 0x10"), Ok(1));
+        assert_eq!(eval("0 || 7"), Ok(1));
+        assert_eq!(eval("1 || 0 && 0"), Ok(1));
+        assert_eq!(eval("(1 || 0) && 0"), Ok(0));
+        assert_eq!(eval("[4:0x2010] == 0x55667788 && [0x1000] > 100"), Ok(1));
+        assert_eq!(
+            eval("1 < 2 < 3"),
+            Err("Unexpected '<' at position 6".to_string())
+        );
+    }
+
+    #[test]
+    fn logical_operators_short_circuit() {
+        assert_eq!(eval("0 && [0xdead]"), Ok(0));
+        assert_eq!(eval("1 || [0xdead]"), Ok(1));
+        assert_eq!(eval("0 && 1 / 0"), Ok(0));
+        assert!(eval("1 && [0xdead]").is_err());
+    }
+
+    #[test]
+    fn parse_once_evaluate_many() {
+        let expr = parse("[4:libil2cpp.so + 0x100] * 2", &[]).unwrap();
+        let mut context = TestContext::new();
+        assert_eq!(expr.evaluate(&context), Ok(0x2000));
+        context.write(0x7000_0100, &0x10u32.to_le_bytes());
+        assert_eq!(expr.evaluate(&context), Ok(0x20));
+    }
+
+    #[test]
+    fn ast_shape() {
+        assert_eq!(
+            parse("1 - 2 - 3", &[]).unwrap(),
+            Expr::Binary {
+                op: BinaryOp::Sub,
+                left: Box::new(Expr::Binary {
+                    op: BinaryOp::Sub,
+                    left: Box::new(Expr::Number(1)),
+                    right: Box::new(Expr::Number(2)),
+                    position: 2,
+                }),
+                right: Box::new(Expr::Number(3)),
+                position: 6,
+            }
+        );
+    }
+}
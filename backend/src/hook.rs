@@ -0,0 +1,668 @@
+use crate::disasm::{self, Arch, Instruction};
+use crate::native_bridge;
+use crate::patch;
+use crate::ptrscan::region_bounds;
+use crate::request::HookInstallRequest;
+use crate::signature::locate_x86_field;
+use crate::util;
+use crate::xref::{self, PcRelative};
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+const PAGE_SIZE: u64 = 0x1000;
+const USER_SPACE_END: u64 = 1 << 47;
+// Farthest a cave may be from its target for the entry and return jumps to stay relative
+const X86_64_REACH: u64 = 0x7fff_0000;
+const ARM64_REACH: u64 = 0x7f0_0000;
+const NEAR_CAVE_ATTEMPTS: usize = 8;
+// Bytes reserved in a cave for relocated instructions and the jump back
+const CAVE_SLACK: u64 = 256;
+
+const ARM64_NOP: u32 = 0xd503_201f;
+const ARM64_BR_X16: u32 = 0xd61f_0200;
+const ARM64_BLR_X16: u32 = 0xd63f_0200;
+
+lazy_static! {
+    static ref HOOKS: RwLock<BTreeMap<String, Hook>> = RwLock::new(BTreeMap::new());
+}
+
+struct Hook {
+    pid: i32,
+    address: u64,
+    arch: Arch,
+    original: Vec<u8>,
+    patched: Vec<u8>,
+    cave: u64,
+    cave_size: usize,
+    payload_size: usize,
+}
+
+// Bytes written over the hooked function and into its cave: the user payload, the relocated
+// original instructions and a jump back behind the overwritten range
+pub struct HookLayout {
+    pub patch: Vec<u8>,
+    pub cave: Vec<u8>,
+}
+
+fn fits(value: i64, bits: u32) -> bool {
+    let limit = 1i64 << (bits - 1);
+    (-limit..limit).contains(&value)
+}
+
+fn rel32(end: u64, target: u64) -> Option<[u8; 4]> {
+    i32::try_from(target.wrapping_sub(end) as i64)
+        .ok()
+        .map(i32::to_le_bytes)
+}
+
+// jmp [rip+0] followed by the destination
+fn x86_absolute_jump(target: u64) -> Vec<u8> {
+    [&[0xff, 0x25, 0, 0, 0, 0][..], &target.to_le_bytes()].concat()
+}
+
+fn x86_jump(from: u64, target: u64) -> Vec<u8> {
+    match rel32(from + 5, target) {
+        Some(rel) => [&[0xe9][..], &rel].concat(),
+        None => x86_absolute_jump(target),
+    }
+}
+
+// Re-encodes one x86_64 instruction so it has the same effect when executed at `new_address`
+fn relocate_x86_64(insn: &Instruction, new_address: u64) -> Result<Vec<u8>, String> {
+    let bytes = &insn.bytes;
+    let end = insn.address + bytes.len() as u64;
+    let new_end = new_address + bytes.len() as u64;
+    // Branch hint, notrack and bnd prefixes do not change the encoding that follows
+    let opcode_at = bytes
+        .iter()
+        .position(|b| !matches!(b, 0x2e | 0x3e | 0xf2))
+        .unwrap_or(0);
+    let opcode = &bytes[opcode_at..];
+
+    if let Some(target) = insn.branch_target.filter(|_| insn.is_call || insn.is_jump) {
+        let condition = match opcode {
+            [0x70..=0x7f, ..] => Some(opcode[0] & 0xf),
+            [0x0f, 0x80..=0x8f, ..] => Some(opcode[1] & 0xf),
+            _ => None,
+        };
+        return match (opcode[0], condition) {
+            (0xe8, _) => Ok(match rel32(new_address + 5, target) {
+                Some(rel) => [&[0xe8][..], &rel].concat(),
+                // call [rip+2]; jmp over the pointer
+                None => [
+                    &[0xff, 0x15, 2, 0, 0, 0, 0xeb, 8][..],
+                    &target.to_le_bytes(),
+                ]
+                .concat(),
+            }),
+            (0xe9 | 0xeb, _) => Ok(x86_jump(new_address, target)),
+            (_, Some(condition)) => Ok(match rel32(new_address + 6, target) {
+                Some(rel) => [&[0x0f, 0x80 | condition][..], &rel].concat(),
+                // Inverted short jcc over an absolute jump
+                None => [
+                    &[0x70 | (condition ^ 1), 14][..],
+                    &x86_absolute_jump(target),
+                ]
+                .concat(),
+            }),
+            _ => Err(format!(
+                "Cannot relocate '{}' at 0x{:x}",
+                insn.text(),
+                insn.address
+            )),
+        };
+    }
+
+    // Indirect branches through [rip+disp32] are not reported as memory operands
+    let target = match opcode {
+        [0xff, modrm, disp @ ..] if modrm & 0xc7 == 0x05 && disp.len() >= 4 => {
+            let disp = i32::from_le_bytes(disp[..4].try_into().unwrap());
+            Some(end.wrapping_add(disp as i64 as u64))
+        }
+        _ => insn.memory_target,
+    };
+    if let Some(target) = target {
+        let displacement = i32::try_from(target.wrapping_sub(end) as i64).ok();
+        // Absolute disp32 operands are position independent and copied unchanged
+        if let Some(position) =
+            displacement.and_then(|disp| locate_x86_field(bytes, &disp.to_le_bytes()))
+        {
+            let relocated = rel32(new_end, target).ok_or_else(|| {
+                format!(
+                    "RIP-relative operand of '{}' at 0x{:x} cannot reach 0x{:x} from 0x{:x}",
+                    insn.text(),
+                    insn.address,
+                    target,
+                    new_address
+                )
+            })?;
+            let mut bytes = bytes.clone();
+            bytes[position..position + 4].copy_from_slice(&relocated);
+            return Ok(bytes);
+        }
+    }
+    Ok(bytes.clone())
+}
+
+fn arm64_branch(offset: i64) -> u32 {
+    0x1400_0000 | ((offset >> 2) as u32 & 0x3ff_ffff)
+}
+
+fn arm64_load_literal(register: u32, offset: i64) -> u32 {
+    0x5800_0000 | (((offset >> 2) as u32 & 0x7ffff) << 5) | register
+}
+
+fn arm64_quad(value: u64) -> [u32; 2] {
+    [value as u32, (value >> 32) as u32]
+}
+
+// ldr xN, #8; b #12; .quad value
+fn arm64_load_constant(register: u32, value: u64) -> Vec<u32> {
+    let mut words = vec![arm64_load_literal(register, 8), arm64_branch(12)];
+    words.extend(arm64_quad(value));
+    words
+}
+
+// Splits a 21-bit ADR/ADRP immediate into its immlo and immhi fields
+fn arm64_adr_immediate(value: i64) -> u32 {
+    let value = value as u32 & 0x1f_ffff;
+    ((value & 3) << 29) | ((value >> 2) << 5)
+}
+
+fn arm64_jump(from: u64, target: u64) -> Vec<u32> {
+    let offset = target.wrapping_sub(from) as i64;
+    if fits(offset, 28) {
+        return vec![arm64_branch(offset)];
+    }
+    // x16 is the intra-procedure-call scratch register, free at any branch
+    let mut words = vec![arm64_load_literal(16, 8), ARM64_BR_X16];
+    words.extend(arm64_quad(target));
+    words
+}
+
+// Re-encodes one ARM64 instruction so it has the same effect when executed at `new_pc`,
+// expanding PC-relative forms into literal loads when the new location is out of range
+fn relocate_arm64(word: u32, pc: u64, new_pc: u64) -> Result<Vec<u32>, String> {
+    let register = word & 0x1f;
+    let offset_to = |target: u64| target.wrapping_sub(new_pc) as i64;
+
+    match xref::arm64_pc_relative(word, pc) {
+        None => Ok(vec![word]),
+        Some(PcRelative::Page(page)) => {
+            let delta = (page >> 12) as i64 - (new_pc >> 12) as i64;
+            if fits(delta, 21) {
+                Ok(vec![(word & 0x9f00_001f) | arm64_adr_immediate(delta)])
+            } else {
+                Ok(arm64_load_constant(register, page))
+            }
+        }
+        Some(PcRelative::Address(target)) => {
+            let offset = offset_to(target);
+            if word & 0x9f00_0000 == 0x1000_0000 {
+                // ADR
+                if fits(offset, 21) {
+                    Ok(vec![(word & 0x9f00_001f) | arm64_adr_immediate(offset)])
+                } else {
+                    Ok(arm64_load_constant(register, target))
+                }
+            } else if word & 0x7c00_0000 == 0x1400_0000 {
+                // B, BL
+                if fits(offset, 28) {
+                    Ok(vec![
+                        (word & 0xfc00_0000) | (arm64_branch(offset) & 0x3ff_ffff),
+                    ])
+                } else if word >> 31 == 1 {
+                    // ldr x16, #12; blr x16; b #12; .quad target
+                    let mut words =
+                        vec![arm64_load_literal(16, 12), ARM64_BLR_X16, arm64_branch(12)];
+                    words.extend(arm64_quad(target));
+                    Ok(words)
+                } else {
+                    let mut words = vec![arm64_load_literal(16, 8), ARM64_BR_X16];
+                    words.extend(arm64_quad(target));
+                    Ok(words)
+                }
+            } else {
+                // B.cond, CBZ and CBNZ carry imm19; TBZ and TBNZ carry imm14
+                let (bits, mask) = if word & 0x7e00_0000 == 0x3600_0000 {
+                    (16, 0x3fff)
+                } else {
+                    (21, 0x7ffff)
+                };
+                let encode =
+                    |offset: i64| (word & !(mask << 5)) | (((offset >> 2) as u32 & mask) << 5);
+                if fits(offset, bits) {
+                    return Ok(vec![encode(offset)]);
+                }
+                // Taken: skip to the absolute jump at +8; not taken: branch past it
+                let mut words = vec![encode(8), arm64_branch(20), arm64_load_literal(16, 8)];
+                words.push(ARM64_BR_X16);
+                words.extend(arm64_quad(target));
+                Ok(words)
+            }
+        }
+        Some(PcRelative::Literal { address, .. }) => {
+            let offset = offset_to(address);
+            if fits(offset, 21) {
+                return Ok(vec![
+                    (word & !(0x7ffff << 5)) | (((offset >> 2) as u32 & 0x7ffff) << 5),
+                ]);
+            }
+            if word & (1 << 26) != 0 {
+                return Err(format!(
+                    "SIMD literal load at 0x{:x} cannot reach 0x{:x} from 0x{:x}",
+                    pc, address, new_pc
+                ));
+            }
+            // Load the literal's address, then the value through it
+            let load = match word >> 30 {
+                0 => 0xb940_0000,                // ldr wN, [xN]
+                1 => 0xf940_0000,                // ldr xN, [xN]
+                2 => 0xb980_0000,                // ldrsw xN, [xN]
+                _ => return Ok(vec![ARM64_NOP]), // prfm has no architectural effect
+            };
+            let mut words = arm64_load_constant(register, address);
+            words.push(load | (register << 5) | register);
+            Ok(words)
+        }
+    }
+}
+
+fn words_to_bytes(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+fn jump(arch: Arch, from: u64, target: u64) -> Vec<u8> {
+    match arch {
+        Arch::Arm64 => words_to_bytes(&arm64_jump(from, target)),
+        _ => x86_jump(from, target),
+    }
+}
+
+// Lays out a hook of `address` whose original code starts with `code`: the entry jump to the
+// cave, padded to whole instructions, and the cave holding payload, moved code and return jump
+pub fn build_layout(
+    arch: Arch,
+    address: u64,
+    code: &[u8],
+    cave: u64,
+    payload: &[u8],
+) -> Result<HookLayout, String> {
+    if arch == Arch::Arm64 && !payload.len().is_multiple_of(4) {
+        return Err("ARM64 payload must be a whole number of instructions".to_string());
+    }
+    let entry = jump(arch, address, cave);
+
+    let mut moved = Vec::new();
+    let mut overwritten = 0;
+    for insn in disasm::decode(arch, code, address, usize::MAX)? {
+        if overwritten >= entry.len() {
+            break;
+        }
+        overwritten += insn.bytes.len();
+        let ends_flow = insn.is_return
+            || matches!(
+                insn.mnemonic.as_str(),
+                "jmp" | "b" | "br" | "ud2" | "int3" | "hlt" | "brk" | "udf"
+            );
+        moved.push(insn);
+        if ends_flow && overwritten < entry.len() {
+            return Err(format!(
+                "Function at 0x{:x} ends after {} bytes, {} are needed for the hook jump",
+                address,
+                overwritten,
+                entry.len()
+            ));
+        }
+    }
+    if overwritten < entry.len() {
+        return Err(format!(
+            "Could not decode {} bytes of instructions at 0x{:x}",
+            entry.len(),
+            address
+        ));
+    }
+
+    let moved_range = address..address + overwritten as u64;
+    let mut cave_bytes = payload.to_vec();
+    for insn in &moved {
+        if let Some(target) = insn.branch_target.filter(|t| moved_range.contains(t)) {
+            return Err(format!(
+                "'{}' at 0x{:x} branches into the overwritten bytes at 0x{:x}",
+                insn.text(),
+                insn.address,
+                target
+            ));
+        }
+        let at = cave + cave_bytes.len() as u64;
+        let relocated = match arch {
+            Arch::Arm64 => {
+                let word = u32::from_le_bytes(insn.bytes[..4].try_into().unwrap());
+                words_to_bytes(&relocate_arm64(word, insn.address, at)?)
+            }
+            _ => relocate_x86_64(insn, at)?,
+        };
+        cave_bytes.extend(relocated);
+    }
+    let back = cave + cave_bytes.len() as u64;
+    cave_bytes.extend(jump(arch, back, moved_range.end));
+
+    let mut patch = entry;
+    while patch.len() < overwritten {
+        match arch {
+            Arch::Arm64 => patch.extend(ARM64_NOP.to_le_bytes()),
+            _ => patch.push(0x90),
+        }
+    }
+    Ok(HookLayout {
+        patch,
+        cave: cave_bytes,
+    })
+}
+
+// Maps `size` bytes of executable memory, preferring free gaps that relative jumps from
+// `target` can reach and falling back to any address (the hook then uses absolute jumps)
+fn allocate_cave(pid: i32, arch: Arch, target: u64, size: u64) -> Result<u64, String> {
+    let reach = match arch {
+        Arch::Arm64 => ARM64_REACH,
+        _ => X86_64_REACH,
+    };
+    let mut regions: Vec<(u64, u64)> = native_bridge::enum_regions(pid)?
+        .iter()
+        .map(region_bounds)
+        .collect();
+    regions.sort_unstable();
+
+    let mut candidates: Vec<u64> = regions
+        .windows(2)
+        .filter_map(|pair| {
+            let start = (pair[0].1 + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+            let end = pair[1].0.min(USER_SPACE_END);
+            if end < start + size {
+                return None;
+            }
+            let last = (end - size) & !(PAGE_SIZE - 1);
+            let candidate = (target & !(PAGE_SIZE - 1)).clamp(start, last);
+            (candidate.abs_diff(target) + size <= reach).then_some(candidate)
+        })
+        .collect();
+    candidates.sort_unstable_by_key(|candidate| candidate.abs_diff(target));
+
+    for hint in candidates.into_iter().take(NEAR_CAVE_ATTEMPTS) {
+        if let Ok(address) =
+            native_bridge::allocate_process_memory(pid, hint as usize, size as usize)
+        {
+            let address = address as u64;
+            if address.abs_diff(target) + size <= reach {
+                return Ok(address);
+            }
+            let _ = native_bridge::free_process_memory(pid, address as usize, size as usize);
+        }
+    }
+    native_bridge::allocate_process_memory(pid, 0, size as usize)
+        .map(|address| address as u64)
+        .map_err(|e| e.to_string())
+}
+
+impl Hook {
+    fn state(&self, pid: Option<i32>) -> &'static str {
+        if pid != Some(self.pid) {
+            return "detached";
+        }
+        match patch::read_exact(self.pid, self.address, self.patched.len()) {
+            Ok(current) if current == self.patched => "installed",
+            Ok(current) if current == self.original => "restored",
+            Ok(_) => "modified",
+            Err(_) => "unreadable",
+        }
+    }
+
+    fn to_json(&self, name: &str, pid: Option<i32>) -> Value {
+        json!({
+            "name": name,
+            "pid": self.pid,
+            "address": self.address,
+            "arch": self.arch.name(),
+            "cave": self.cave,
+            "cave_size": self.cave_size,
+            "payload_size": self.payload_size,
+            // Relocated original instructions; calling here runs the unhooked function
+            "trampoline": self.cave + self.payload_size as u64,
+            "original": hex::encode(&self.original),
+            "patched": hex::encode(&self.patched),
+            "state": self.state(pid)
+        })
+    }
+
+    // The cave stays mapped: a thread may still be executing the payload or the moved code
+    fn restore(&self) -> Result<(), String> {
+        patch::replace_bytes(self.pid, self.address, &self.patched, &self.original)
+    }
+}
+
+fn write_hook(
+    pid: i32,
+    request: &HookInstallRequest,
+    address: u64,
+    arch: Arch,
+    bytes: Option<Vec<u8>>,
+    cave: u64,
+    cave_size: usize,
+) -> Result<Hook, String> {
+    let payload = match bytes {
+        Some(bytes) => bytes,
+        None => {
+            let evaluate = |expression: &str| util::resolve_expression(pid, expression);
+            let assembly = request.assembly.as_deref().unwrap_or_default();
+            patch::assemble(arch, assembly, cave, &evaluate)?
+        }
+    };
+
+    let code = disasm::read_code(pid, address, 64);
+    let layout = build_layout(arch, address, &code, cave, &payload)?;
+    if layout.cave.len() > cave_size {
+        return Err(format!(
+            "Hook code of {} bytes does not fit its {} byte cave",
+            layout.cave.len(),
+            cave_size
+        ));
+    }
+
+    native_bridge::write_process_memory(
+        pid,
+        cave as *mut libc::c_void,
+        layout.cave.len(),
+        &layout.cave,
+    )
+    .map_err(|e| format!("Failed to write cave at 0x{:x}: {}", cave, e))?;
+    let original = code[..layout.patch.len()].to_vec();
+    patch::replace_bytes(pid, address, &original, &layout.patch)?;
+
+    Ok(Hook {
+        pid,
+        address,
+        arch,
+        original,
+        patched: layout.patch,
+        cave,
+        cave_size,
+        payload_size: payload.len(),
+    })
+}
+
+pub fn install_hook(pid: i32, request: &HookInstallRequest) -> Result<Value, String> {
+    if HOOKS.read().unwrap().contains_key(&request.name) {
+        return Err(format!("Hook {} already exists", request.name));
+    }
+    let address = util::resolve_target(pid, request.address, request.expression.as_deref())?;
+    let arch = match &request.arch {
+        Some(name) => Arch::parse(name)?,
+        None => disasm::detect_arch(pid, address),
+    };
+    if !matches!(arch, Arch::X86_64 | Arch::Arm64) {
+        return Err(format!("Hooks are not supported for {}", arch.name()));
+    }
+
+    let bytes = request.bytes.as_deref().map(patch::parse_hex).transpose()?;
+    // Assembled statements are at most 16 bytes each
+    let payload_estimate = match (&bytes, &request.assembly) {
+        (Some(bytes), None) => bytes.len(),
+        (None, Some(assembly)) => assembly.split([';', '\n']).count() * 16,
+        _ => return Err("Exactly one of bytes or assembly is required".to_string()),
+    };
+    let cave_size = (payload_estimate as u64 + CAVE_SLACK + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+    let cave = allocate_cave(pid, arch, address, cave_size)?;
+
+    let hook = write_hook(pid, request, address, arch, bytes, cave, cave_size as usize)
+        .inspect_err(|_| {
+            let _ = native_bridge::free_process_memory(pid, cave as usize, cave_size as usize);
+        })?;
+    let result = hook.to_json(&request.name, Some(pid));
+    HOOKS.write().unwrap().insert(request.name.clone(), hook);
+    Ok(result)
+}
+
+pub fn remove_hook(pid: i32, name: &str) -> Result<(), String> {
+    let mut hooks = HOOKS.write().unwrap();
+    let hook = hooks
+        .get(name)
+        .ok_or_else(|| format!("Hook {} not found", name))?;
+    if hook.pid != pid {
+        return Err(format!("Hook {} belongs to process {}", name, hook.pid));
+    }
+    hook.restore()?;
+    hooks.remove(name);
+    Ok(())
+}
+
+// Restores every hooked function so no process keeps jumping into caves nobody manages
+pub fn remove_all_hooks() {
+    let mut hooks = HOOKS.write().unwrap();
+    for (name, hook) in hooks.iter() {
+        if let Err(e) = hook.restore() {
+            log::warn!("Failed to remove hook {}: {}", name, e);
+        }
+    }
+    hooks.clear();
+}
+
+pub fn list_hooks(pid: Option<i32>) -> Value {
+    let hooks = HOOKS.read().unwrap();
+    let entries: Vec<Value> = hooks
+        .iter()
+        .map(|(name, hook)| hook.to_json(name, pid))
+        .collect();
+    json!({ "hooks": entries })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(arch: Arch, bytes: &[u8], address: u64) -> Vec<Instruction> {
+        let instructions = disasm::decode(arch, bytes, address, usize::MAX).unwrap();
+        let decoded: usize = instructions.iter().map(|insn| insn.bytes.len()).sum();
+        assert_eq!(decoded, bytes.len());
+        instructions
+    }
+
+    #[test]
+    fn x86_64_relocation() {
+        // push rbp; mov rax, [rip+0x1000]; je +0x20; call 0x402000
+        let code = [
+            0x55, 0x48, 0x8b, 0x05, 0x00, 0x10, 0x00, 0x00, 0x74, 0x20, 0xe8, 0xf1, 0x1f, 0x00,
+            0x00,
+        ];
+        let address = 0x400000;
+        let payload = [0x90, 0x90];
+
+        let near = build_layout(Arch::X86_64, address, &code, 0x500000, &payload).unwrap();
+        assert_eq!(near.patch.len(), 8);
+        assert_eq!(&near.patch[5..], &[0x90, 0x90, 0x90]);
+        let moved = decode(Arch::X86_64, &near.cave, 0x500000);
+        assert_eq!(moved.len(), 5);
+        assert_eq!(moved[2].text(), "push rbp");
+        assert_eq!(moved[3].memory_target, Some(0x401008));
+        assert_eq!(moved[4].text(), "jmp 0x400008");
+
+        // The RIP-relative load cannot reach 0x401008 from a distant cave
+        let far = 0x7f00_0000_0000;
+        assert!(build_layout(Arch::X86_64, address, &code, far, &payload).is_err());
+
+        // push rbp; je +0x20; call 0x402000; nop x6: the branches become absolute
+        let code = [
+            0x55, 0x74, 0x20, 0xe8, 0xf8, 0x1f, 0x00, 0x00, 0x90, 0x90, 0x90, 0x90, 0x90, 0x90,
+        ];
+        let layout = build_layout(Arch::X86_64, address, &code, far, &[]).unwrap();
+        assert_eq!(&layout.patch[..6], &[0xff, 0x25, 0, 0, 0, 0]);
+        assert_eq!(&layout.patch[6..], &far.to_le_bytes());
+        let moved = decode(Arch::X86_64, &layout.cave[..3], far);
+        assert_eq!(moved[0].text(), "push rbp");
+        assert_eq!(moved[1].text(), format!("jne 0x{:x}", far + 17));
+        assert_eq!(&layout.cave[3..9], &[0xff, 0x25, 0, 0, 0, 0]);
+        assert_eq!(&layout.cave[9..17], &0x400023u64.to_le_bytes());
+        assert_eq!(&layout.cave[17..25], &[0xff, 0x15, 2, 0, 0, 0, 0xeb, 8]);
+        assert_eq!(&layout.cave[25..33], &0x402000u64.to_le_bytes());
+        assert_eq!(&layout.cave[39..45], &[0xff, 0x25, 0, 0, 0, 0]);
+        assert_eq!(&layout.cave[45..], &0x40000eu64.to_le_bytes());
+    }
+
+    #[test]
+    fn arm64_relocation() {
+        let address = 0x10000;
+        let words = [
+            0x9000_0080u32, // adrp x0, 0x20000
+            0x9400_0400,    // bl 0x11004
+            0xb400_0041,    // cbz x1, 0x10010
+            0x5800_0082,    // ldr x2, 0x1001c
+            0xd65f_03c0,    // ret
+        ];
+        let code = words_to_bytes(&words);
+
+        let near = build_layout(Arch::Arm64, address, &code, 0x20000, &[]).unwrap();
+        assert_eq!(near.patch.len(), 4);
+        let moved = decode(Arch::Arm64, &near.cave, 0x20000);
+        assert_eq!(moved[0].text(), "adrp x0, #0x20000");
+        assert_eq!(moved[1].text(), "b #0x10004");
+
+        let cave = 0x10_0000_0000;
+        let layout = build_layout(Arch::Arm64, address, &code, cave, &[]).unwrap();
+        assert_eq!(layout.patch.len(), 16);
+        let cave_words: Vec<u32> = layout
+            .cave
+            .chunks(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        // adrp becomes a literal load of the page
+        assert_eq!(&cave_words[..4], &[0x5800_0040, 0x1400_0003, 0x20000, 0]);
+        // bl through x16
+        assert_eq!(
+            &cave_words[4..9],
+            &[0x5800_0070, ARM64_BLR_X16, 0x1400_0003, 0x11004, 0]
+        );
+        // cbz to an absolute jump
+        assert_eq!(
+            &cave_words[9..15],
+            &[
+                0xb400_0041,
+                0x1400_0005,
+                0x5800_0050,
+                ARM64_BR_X16,
+                0x10010,
+                0
+            ]
+        );
+        // ldr x2 through its own address
+        assert_eq!(
+            &cave_words[15..20],
+            &[0x5800_0042, 0x1400_0003, 0x1001c, 0, 0xf940_0042]
+        );
+        assert_eq!(&cave_words[20..], &[0x5800_0050, ARM64_BR_X16, 0x10010, 0]);
+
+        // A return inside the overwritten range cannot be hooked with an absolute jump
+        let error = build_layout(Arch::Arm64, address, &code[8..], cave, &[]);
+        assert!(error.is_err());
+    }
+}
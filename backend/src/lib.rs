@@ -4,12 +4,27 @@ use std::thread;
 
 mod allocator;
 mod api;
+mod breakpoint;
+mod debugger;
+mod disasm;
+mod events;
+mod expression;
+mod hook;
 mod logger;
 mod native_bridge;
+mod patch;
+mod pointermap;
 mod ptrscan;
 mod request;
 mod serve;
+mod signature;
+mod symbols;
+mod threads;
+mod tracer;
+mod unwind;
 mod util;
+mod watch;
+mod xref;
 
 #[ctor]
 fn main() {
@@ -1,3 +1,4 @@
+use crate::symbols;
 use libc::{self, c_char, c_int, c_void};
 use serde_json::json;
 use std::ffi::{CStr, CString};
@@ -8,8 +9,11 @@ use std::io::{BufRead, BufReader, Error};
 #[link(name = "native", kind = "static")]
 extern "C" {
     pub fn get_pid_native() -> i32;
+    pub fn process_alive_native(pid: i32) -> bool;
     pub fn enumprocess_native(count: *mut usize) -> *mut ProcessInfo;
     pub fn enummodule_native(pid: i32, count: *mut usize) -> *mut ModuleInfo;
+    pub fn enumthreadstack_native(pid: i32, count: *mut usize) -> *mut ThreadStackInfo;
+    pub fn enumthread_native(pid: i32, count: *mut usize) -> *mut ThreadInfo;
     pub fn enumerate_regions_to_buffer(pid: i32, buffer: *mut u8, buffer_size: usize);
     pub fn read_memory_native(
         pid: libc::c_int,
@@ -23,6 +27,12 @@ extern "C" {
         size: libc::size_t,
         buffer: *const u8,
     ) -> libc::ssize_t;
+    pub fn allocate_memory_native(
+        pid: i32,
+        hint: libc::uintptr_t,
+        size: libc::size_t,
+    ) -> libc::uintptr_t;
+    pub fn free_memory_native(pid: i32, address: libc::uintptr_t, size: libc::size_t) -> c_int;
     pub fn suspend_process(pid: i32) -> bool;
     pub fn resume_process(pid: i32) -> bool;
     pub fn native_init(mode: i32) -> libc::c_int;
@@ -34,14 +44,31 @@ extern "C" {
     ) -> *const c_void;
     pub fn get_application_info_native(pid: c_int) -> *const c_char;
     pub fn debugger_new(pid: c_int) -> bool;
+    pub fn debugger_free();
+    pub fn debugger_attached_native(pid: c_int) -> bool;
+    pub fn debugger_in_use_native() -> bool;
     pub fn set_watchpoint_native(
         address: libc::uintptr_t,
         size: libc::size_t,
         _type: libc::c_int,
+        stop: bool,
     ) -> libc::c_int;
     pub fn remove_watchpoint_native(address: libc::uintptr_t) -> libc::c_int;
-    pub fn set_breakpoint_native(address: usize, hit_count: i32) -> i32;
+    pub fn set_breakpoint_native(address: usize, hit_count: i32, mode: i32) -> i32;
     pub fn remove_breakpoint_native(address: usize) -> i32;
+    pub fn debugger_threads_native() -> *mut c_char;
+    pub fn debugger_get_registers_native(tid: c_int) -> *mut c_char;
+    pub fn debugger_set_registers_native(
+        tid: c_int,
+        names: *const *const c_char,
+        values: *const u8,
+        count: usize,
+    ) -> c_int;
+    pub fn debugger_mask_breakpoints_native(address: u64, buffer: *mut u8, size: usize);
+    pub fn debugger_continue_native(tid: c_int) -> c_int;
+    pub fn debugger_step_native(tid: c_int, mode: c_int) -> c_int;
+    pub fn debugger_suspend_native(tid: c_int) -> c_int;
+    pub fn debugger_trace_native(tid: c_int) -> c_int;
 }
 
 #[repr(C)]
@@ -53,9 +80,35 @@ pub struct ProcessInfo {
 #[repr(C)]
 pub struct ModuleInfo {
     pub base: usize,
-    pub size: i32,
+    pub size: u64,
     pub is_64bit: bool,
     pub modulename: *mut c_char,
+    pub segments: *mut ModuleSegment,
+    pub segment_count: usize,
+}
+
+#[repr(C)]
+pub struct ModuleSegment {
+    pub start: usize,
+    pub end: usize,
+    pub file_offset: u64,
+    pub protection: [c_char; 8],
+}
+
+#[repr(C)]
+pub struct ThreadStackInfo {
+    pub tid: i32,
+    pub stack_base: usize,
+    pub stack_top: usize,
+}
+
+#[repr(C)]
+pub struct ThreadInfo {
+    pub tid: i32,
+    pub name: [c_char; 32],
+    pub state: c_char,
+    pub cpu_time_us: u64,
+    pub pc: usize,
 }
 
 pub fn read_process_memory(
@@ -88,7 +141,39 @@ pub fn write_process_memory(
     }
 }
 
-pub fn set_watchpoint(pid: i32, address: usize, size: usize, type_: i32) -> Result<i32, Error> {
+pub fn allocate_process_memory(pid: i32, hint: usize, size: usize) -> Result<usize, Error> {
+    let result = unsafe { allocate_memory_native(pid, hint, size) };
+    symbols::invalidate_layout(pid);
+    if result != 0 {
+        Ok(result)
+    } else {
+        Err(Error::other(format!(
+            "Failed to allocate {} bytes in process {}",
+            size, pid
+        )))
+    }
+}
+
+pub fn free_process_memory(pid: i32, address: usize, size: usize) -> Result<(), Error> {
+    let result = unsafe { free_memory_native(pid, address, size) };
+    symbols::invalidate_layout(pid);
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(Error::other(format!(
+            "Failed to free memory at 0x{:x} in process {}",
+            address, pid
+        )))
+    }
+}
+
+pub fn set_watchpoint(
+    pid: i32,
+    address: usize,
+    size: usize,
+    type_: i32,
+    stop: bool,
+) -> Result<i32, Error> {
     let result: bool = unsafe { debugger_new(pid) };
 
     if !result {
@@ -97,7 +182,7 @@ pub fn set_watchpoint(pid: i32, address: usize, size: usize, type_: i32) -> Resu
             "Failed to create debugger instance",
         ));
     }
-    let result = unsafe { set_watchpoint_native(address, size, type_) };
+    let result = unsafe { set_watchpoint_native(address, size, type_, stop) };
     if result == 0 {
         Ok(result as i32)
     } else {
@@ -114,7 +199,9 @@ pub fn remove_watchpoint(address: usize) -> Result<i32, Error> {
     }
 }
 
-pub fn set_breakpoint(pid: i32, address: usize, hit_count: i32) -> Result<i32, Error> {
+// Mode 0 traces the first `hit_count` hits, 1 stops every hit and 2 defers to the condition
+// and trace registered in breakpoint.rs
+pub fn set_breakpoint(pid: i32, address: usize, hit_count: i32, mode: i32) -> Result<i32, Error> {
     let result: bool = unsafe { debugger_new(pid) };
     if !result {
         return Err(Error::new(
@@ -122,7 +209,7 @@ pub fn set_breakpoint(pid: i32, address: usize, hit_count: i32) -> Result<i32, E
             "Failed to create debugger instance",
         ));
     }
-    let result = unsafe { set_breakpoint_native(address, hit_count) };
+    let result = unsafe { set_breakpoint_native(address, hit_count, mode) };
     if result == 0 {
         Ok(result)
     } else {
@@ -130,6 +217,20 @@ pub fn set_breakpoint(pid: i32, address: usize, hit_count: i32) -> Result<i32, E
     }
 }
 
+pub fn free_debugger() {
+    unsafe { debugger_free() }
+}
+
+// Whether a live debugger is already attached to `pid`
+pub fn debugger_attached(pid: i32) -> bool {
+    unsafe { debugger_attached_native(pid) }
+}
+
+// Whether breakpoints, watchpoints or held threads still depend on the debugger
+pub fn debugger_in_use() -> bool {
+    unsafe { debugger_in_use_native() }
+}
+
 pub fn remove_breakpoint(address: usize) -> Result<i32, Error> {
     let result = unsafe { remove_breakpoint_native(address) };
     if result == 0 {
@@ -139,6 +240,99 @@ pub fn remove_breakpoint(address: usize) -> Result<i32, Error> {
     }
 }
 
+pub fn debugger_threads() -> Result<String, Error> {
+    unsafe {
+        let raw_ptr = debugger_threads_native();
+        if raw_ptr.is_null() {
+            return Err(Error::last_os_error());
+        }
+        let threads = CStr::from_ptr(raw_ptr).to_string_lossy().into_owned();
+        libc::free(raw_ptr as *mut libc::c_void);
+        Ok(threads)
+    }
+}
+
+pub fn debugger_get_registers(tid: i32) -> Result<String, Error> {
+    unsafe {
+        let raw_ptr = debugger_get_registers_native(tid);
+        if raw_ptr.is_null() {
+            return Err(Error::last_os_error());
+        }
+        let registers = CStr::from_ptr(raw_ptr).to_string_lossy().into_owned();
+        libc::free(raw_ptr as *mut libc::c_void);
+        Ok(registers)
+    }
+}
+
+// Values are written as 16 little-endian bytes each; the native side rejects any that do not
+// fit the named register
+pub fn debugger_set_registers(tid: i32, registers: &[(String, u128)]) -> Result<(), Error> {
+    let names = registers
+        .iter()
+        .map(|(name, _)| CString::new(name.as_str()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let name_ptrs: Vec<*const c_char> = names.iter().map(|name| name.as_ptr()).collect();
+    let values: Vec<u8> = registers
+        .iter()
+        .flat_map(|(_, value)| value.to_le_bytes())
+        .collect();
+    let result = unsafe {
+        debugger_set_registers_native(tid, name_ptrs.as_ptr(), values.as_ptr(), registers.len())
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+// Replaces software breakpoint traps in code read from the target with the original bytes
+pub fn mask_breakpoints(address: u64, buffer: &mut [u8]) {
+    unsafe { debugger_mask_breakpoints_native(address, buffer.as_mut_ptr(), buffer.len()) }
+}
+
+pub fn debugger_continue(tid: i32) -> Result<(), Error> {
+    if unsafe { debugger_continue_native(tid) } == 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+pub fn debugger_step(tid: i32, mode: i32) -> Result<(), Error> {
+    if unsafe { debugger_step_native(tid, mode) } == 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+// Holds a running thread for the debugger session, attaching the debugger first if needed
+pub fn debugger_suspend(pid: i32, tid: i32) -> Result<(), Error> {
+    if !unsafe { debugger_new(pid) } {
+        return Err(Error::other("Failed to create debugger instance"));
+    }
+    if unsafe { debugger_suspend_native(tid) } == 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+// Blocks until the trace recorder stops single-stepping the thread
+pub fn debugger_trace(tid: i32) -> Result<(), Error> {
+    if unsafe { debugger_trace_native(tid) } == 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+pub fn is_process_alive(pid: i32) -> bool {
+    unsafe { process_alive_native(pid) }
+}
+
 pub fn native_api_init(mode: i32) {
     unsafe {
         native_init(mode);
@@ -164,14 +358,36 @@ pub fn enum_modules(pid: i32) -> Result<Vec<serde_json::Value>, String> {
                 .into_owned()
         };
 
+        let segments: Vec<serde_json::Value> = if info.segments.is_null() {
+            Vec::new()
+        } else {
+            unsafe { std::slice::from_raw_parts(info.segments, info.segment_count) }
+                .iter()
+                .map(|segment| {
+                    let protection =
+                        unsafe { CStr::from_ptr(segment.protection.as_ptr()) }.to_string_lossy();
+                    json!({
+                        "start": segment.start,
+                        "end": segment.end,
+                        "protection": protection,
+                        "file_offset": segment.file_offset
+                    })
+                })
+                .collect()
+        };
+
         modules.push(json!({
             "base": info.base,
             "size": info.size,
             "is_64bit": info.is_64bit,
-            "modulename": module_name
+            "modulename": module_name,
+            "segments": segments
         }));
 
-        unsafe { libc::free(info.modulename as *mut libc::c_void) };
+        unsafe {
+            libc::free(info.modulename as *mut libc::c_void);
+            libc::free(info.segments as *mut libc::c_void);
+        }
     }
 
     unsafe { libc::free(module_info_ptr as *mut libc::c_void) };
@@ -179,6 +395,65 @@ pub fn enum_modules(pid: i32) -> Result<Vec<serde_json::Value>, String> {
     Ok(modules)
 }
 
+pub fn enum_thread_stacks(pid: i32) -> Result<Vec<serde_json::Value>, String> {
+    let mut count: usize = 0;
+    let stack_info_ptr = unsafe { enumthreadstack_native(pid, &mut count) };
+
+    if stack_info_ptr.is_null() {
+        return Err("Failed to enumerate thread stacks".to_string());
+    }
+
+    let stack_info_slice = unsafe { std::slice::from_raw_parts(stack_info_ptr, count) };
+
+    // The index is what address expressions refer to as threadstackN
+    let stacks = stack_info_slice
+        .iter()
+        .enumerate()
+        .map(|(index, info)| {
+            json!({
+                "name": format!("threadstack{}", index),
+                "tid": info.tid,
+                "stack_base": info.stack_base,
+                "stack_top": info.stack_top
+            })
+        })
+        .collect();
+
+    unsafe { libc::free(stack_info_ptr as *mut libc::c_void) };
+
+    Ok(stacks)
+}
+
+pub fn enum_threads(pid: i32) -> Result<Vec<serde_json::Value>, String> {
+    let mut count: usize = 0;
+    let thread_info_ptr = unsafe { enumthread_native(pid, &mut count) };
+
+    if thread_info_ptr.is_null() {
+        return Err("Failed to enumerate threads".to_string());
+    }
+
+    let thread_info_slice = unsafe { std::slice::from_raw_parts(thread_info_ptr, count) };
+    let threads = thread_info_slice
+        .iter()
+        .map(|info| {
+            let name = unsafe { CStr::from_ptr(info.name.as_ptr()) }
+                .to_string_lossy()
+                .into_owned();
+            json!({
+                "tid": info.tid,
+                "name": name,
+                "state": (info.state as u8 as char).to_string(),
+                "cpu_time_us": info.cpu_time_us,
+                "pc": info.pc
+            })
+        })
+        .collect();
+
+    unsafe { libc::free(thread_info_ptr as *mut libc::c_void) };
+
+    Ok(threads)
+}
+
 pub fn enum_regions(pid: i32) -> Result<Vec<serde_json::Value>, String> {
     let mut buffer = vec![0u8; 1024 * 1024]; // 1MB buffer
 
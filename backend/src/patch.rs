@@ -0,0 +1,503 @@
+use crate::disasm::{self, Arch};
+use crate::native_bridge;
+use crate::request::PatchCreateRequest;
+use crate::util;
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+const X86_64_REGISTERS: [&str; 16] = [
+    "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15",
+];
+const X86_32_REGISTERS: [&str; 16] = [
+    "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "r8d", "r9d", "r10d", "r11d", "r12d",
+    "r13d", "r14d", "r15d",
+];
+
+lazy_static! {
+    static ref PATCHES: RwLock<BTreeMap<String, Patch>> = RwLock::new(BTreeMap::new());
+}
+
+struct Patch {
+    pid: i32,
+    address: u64,
+    original: Vec<u8>,
+    patched: Vec<u8>,
+    assembly: Option<String>,
+    enabled: bool,
+}
+
+// Splits `mnemonic op1, op2` into its lowercase mnemonic and trimmed operands
+fn split_statement(statement: &str) -> (String, Vec<&str>) {
+    let statement = statement.trim();
+    let (mnemonic, rest) = statement
+        .split_once(char::is_whitespace)
+        .unwrap_or((statement, ""));
+    let operands = rest
+        .split(',')
+        .map(str::trim)
+        .filter(|operand| !operand.is_empty())
+        .collect();
+    (mnemonic.to_lowercase(), operands)
+}
+
+fn expect_operands(mnemonic: &str, operands: &[&str], count: usize) -> Result<(), String> {
+    if operands.len() == count {
+        Ok(())
+    } else {
+        Err(format!(
+            "'{}' takes {} operand(s), found {}",
+            mnemonic,
+            count,
+            operands.len()
+        ))
+    }
+}
+
+fn assemble_x86_64(
+    mnemonic: &str,
+    operands: &[&str],
+    address: u64,
+    evaluate: &dyn Fn(&str) -> Result<u64, String>,
+) -> Result<Vec<u8>, String> {
+    match mnemonic {
+        "nop" => expect_operands(mnemonic, operands, 0).map(|_| vec![0x90]),
+        "ret" => expect_operands(mnemonic, operands, 0).map(|_| vec![0xc3]),
+        "jmp" | "call" => {
+            expect_operands(mnemonic, operands, 1)?;
+            let target = evaluate(operands[0])?;
+            let displacement = target.wrapping_sub(address + 5) as i64;
+            let displacement = i32::try_from(displacement)
+                .map_err(|_| format!("Branch target 0x{:x} is out of rel32 range", target))?;
+            let opcode = if mnemonic == "jmp" { 0xe9 } else { 0xe8 };
+            let mut bytes = vec![opcode];
+            bytes.extend_from_slice(&displacement.to_le_bytes());
+            Ok(bytes)
+        }
+        "mov" => {
+            expect_operands(mnemonic, operands, 2)?;
+            let register = operands[0].to_lowercase();
+            let value = evaluate(operands[1])?;
+            let (index, is_64bit) = match X86_64_REGISTERS.iter().position(|r| *r == register) {
+                Some(index) => (index as u8, true),
+                None => match X86_32_REGISTERS.iter().position(|r| *r == register) {
+                    Some(index) => (index as u8, false),
+                    None => return Err(format!("Unknown register '{}'", operands[0])),
+                },
+            };
+            let rex_b = if index >= 8 { 0x01 } else { 0x00 };
+            let mut bytes = Vec::new();
+
+            if !is_64bit || value <= u32::MAX as u64 {
+                // mov r32, imm32 zero-extends into the full register
+                let value = u32::try_from(value)
+                    .or_else(|_| i32::try_from(value as i64).map(|v| v as u32))
+                    .map_err(|_| format!("Immediate 0x{:x} does not fit {}", value, register))?;
+                if rex_b != 0 {
+                    bytes.push(0x40 | rex_b);
+                }
+                bytes.push(0xb8 + (index & 7));
+                bytes.extend_from_slice(&value.to_le_bytes());
+            } else if let Ok(value) = i32::try_from(value as i64) {
+                // mov r64, simm32
+                bytes.extend_from_slice(&[0x48 | rex_b, 0xc7, 0xc0 + (index & 7)]);
+                bytes.extend_from_slice(&value.to_le_bytes());
+            } else {
+                // movabs r64, imm64
+                bytes.extend_from_slice(&[0x48 | rex_b, 0xb8 + (index & 7)]);
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            Ok(bytes)
+        }
+        _ => Err(format!("Unsupported x86_64 instruction '{}'", mnemonic)),
+    }
+}
+
+fn arm64_register(name: &str) -> Option<(u32, bool)> {
+    match name {
+        "fp" => return Some((29, true)),
+        "lr" => return Some((30, true)),
+        _ => {}
+    }
+    let (is_64bit, number) = match name.split_at(1) {
+        ("x", number) => (true, number),
+        ("w", number) => (false, number),
+        _ => return None,
+    };
+    let index: u32 = number.parse().ok()?;
+    (index <= 30).then_some((index, is_64bit))
+}
+
+// MOVZ (or MOVN for mostly-ones values) followed by a MOVK for every remaining halfword
+fn arm64_move_wide(register: u32, is_64bit: bool, value: u64) -> Vec<u32> {
+    let halfwords = if is_64bit { 4 } else { 2 };
+    let value = if is_64bit { value } else { value & 0xffff_ffff };
+    let chunks: Vec<u32> = (0..halfwords)
+        .map(|hw| ((value >> (hw * 16)) & 0xffff) as u32)
+        .collect();
+    let ones = chunks.iter().filter(|&&chunk| chunk == 0xffff).count();
+    let zeros = chunks.iter().filter(|&&chunk| chunk == 0).count();
+    let inverted = ones > zeros;
+    let filler = if inverted { 0xffff } else { 0 };
+
+    let sf = if is_64bit { 1 << 31 } else { 0 };
+    let (first_opcode, movk) = if inverted {
+        (0x1280_0000 | sf, 0x7280_0000 | sf)
+    } else {
+        (0x5280_0000 | sf, 0x7280_0000 | sf)
+    };
+    let encode =
+        |opcode: u32, hw: usize, imm16: u32| opcode | (hw as u32) << 21 | imm16 << 5 | register;
+
+    let first = chunks
+        .iter()
+        .position(|&chunk| chunk != filler)
+        .unwrap_or(0);
+    let first_imm = if inverted {
+        !chunks[first] & 0xffff
+    } else {
+        chunks[first]
+    };
+    let mut words = vec![encode(first_opcode, first, first_imm)];
+    for (hw, &chunk) in chunks.iter().enumerate().skip(first + 1) {
+        if chunk != filler {
+            words.push(encode(movk, hw, chunk));
+        }
+    }
+    words
+}
+
+fn assemble_arm64(
+    mnemonic: &str,
+    operands: &[&str],
+    address: u64,
+    evaluate: &dyn Fn(&str) -> Result<u64, String>,
+) -> Result<Vec<u8>, String> {
+    let words = match mnemonic {
+        "nop" => expect_operands(mnemonic, operands, 0).map(|_| vec![0xd503_201f])?,
+        "ret" => expect_operands(mnemonic, operands, 0).map(|_| vec![0xd65f_03c0])?,
+        "b" | "bl" => {
+            expect_operands(mnemonic, operands, 1)?;
+            let target = evaluate(operands[0].trim_start_matches('#'))?;
+            let offset = target.wrapping_sub(address) as i64;
+            if offset % 4 != 0 || !(-(1 << 27)..(1 << 27)).contains(&offset) {
+                return Err(format!("Branch target 0x{:x} is out of range", target));
+            }
+            let opcode = if mnemonic == "b" {
+                0x1400_0000
+            } else {
+                0x9400_0000
+            };
+            vec![opcode | ((offset >> 2) as u32 & 0x03ff_ffff)]
+        }
+        "mov" => {
+            expect_operands(mnemonic, operands, 2)?;
+            let (register, is_64bit) = arm64_register(&operands[0].to_lowercase())
+                .ok_or_else(|| format!("Unknown register '{}'", operands[0]))?;
+            let value = evaluate(operands[1].trim_start_matches('#'))?;
+            let fits = is_64bit || value <= u32::MAX as u64 || i32::try_from(value as i64).is_ok();
+            if !fits {
+                return Err(format!(
+                    "Immediate 0x{:x} does not fit {}",
+                    value, operands[0]
+                ));
+            }
+            arm64_move_wide(register, is_64bit, value)
+        }
+        _ => return Err(format!("Unsupported arm64 instruction '{}'", mnemonic)),
+    };
+    Ok(words.iter().flat_map(|word| word.to_le_bytes()).collect())
+}
+
+// Encodes `;` or newline separated NOP, RET, branch and mov-immediate statements at `address`.
+// Branch targets and immediates are address expressions evaluated by `evaluate`
+pub fn assemble(
+    arch: Arch,
+    source: &str,
+    address: u64,
+    evaluate: &dyn Fn(&str) -> Result<u64, String>,
+) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    for statement in source.split([';', '\n']).filter(|s| !s.trim().is_empty()) {
+        let (mnemonic, operands) = split_statement(statement);
+        let current = address + bytes.len() as u64;
+        let encoded = match arch {
+            Arch::X86_64 => assemble_x86_64(&mnemonic, &operands, current, evaluate),
+            Arch::Arm64 => assemble_arm64(&mnemonic, &operands, current, evaluate),
+            _ => Err(format!("Assembling is not supported for {}", arch.name())),
+        }
+        .map_err(|e| format!("{}: {}", statement.trim(), e))?;
+        bytes.extend(encoded);
+    }
+    if bytes.is_empty() {
+        return Err("Nothing to assemble".to_string());
+    }
+    Ok(bytes)
+}
+
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let compact: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    hex::decode(&compact).map_err(|e| format!("Invalid hex bytes '{}': {}", text, e))
+}
+
+pub fn read_exact(pid: i32, address: u64, size: usize) -> Result<Vec<u8>, String> {
+    let mut buffer = vec![0u8; size];
+    match native_bridge::read_process_memory(pid, address as *mut libc::c_void, size, &mut buffer) {
+        Ok(n) if n as usize == size => Ok(buffer),
+        _ => Err(format!("Failed to read {} bytes at 0x{:x}", size, address)),
+    }
+}
+
+// Writes `bytes` only if memory still holds `expected`, then reads them back
+pub fn replace_bytes(pid: i32, address: u64, expected: &[u8], bytes: &[u8]) -> Result<(), String> {
+    let current = read_exact(pid, address, bytes.len())?;
+    if current == bytes {
+        return Ok(());
+    }
+    if current != expected {
+        return Err(format!(
+            "Memory at 0x{:x} changed: expected {}, found {}",
+            address,
+            hex::encode(expected),
+            hex::encode(&current)
+        ));
+    }
+    native_bridge::write_process_memory(pid, address as *mut libc::c_void, bytes.len(), bytes)
+        .map_err(|e| format!("Failed to write memory at 0x{:x}: {}", address, e))?;
+    if read_exact(pid, address, bytes.len())? != bytes {
+        return Err(format!("Write to 0x{:x} did not take effect", address));
+    }
+    Ok(())
+}
+
+impl Patch {
+    fn state(&self, pid: Option<i32>) -> &'static str {
+        if pid != Some(self.pid) {
+            return "detached";
+        }
+        match read_exact(self.pid, self.address, self.patched.len()) {
+            Ok(current) if current == self.patched => "applied",
+            Ok(current) if current == self.original => "reverted",
+            Ok(_) => "modified",
+            Err(_) => "unreadable",
+        }
+    }
+
+    fn to_json(&self, name: &str, pid: Option<i32>) -> Value {
+        json!({
+            "name": name,
+            "pid": self.pid,
+            "address": self.address,
+            "size": self.patched.len(),
+            "original": hex::encode(&self.original),
+            "patched": hex::encode(&self.patched),
+            "assembly": self.assembly,
+            "enabled": self.enabled,
+            "state": self.state(pid)
+        })
+    }
+
+    fn set_enabled(&mut self, enabled: bool) -> Result<(), String> {
+        if enabled {
+            replace_bytes(self.pid, self.address, &self.original, &self.patched)?;
+        } else {
+            replace_bytes(self.pid, self.address, &self.patched, &self.original)?;
+        }
+        self.enabled = enabled;
+        Ok(())
+    }
+}
+
+pub fn create_patch(pid: i32, request: &PatchCreateRequest) -> Result<Value, String> {
+    if PATCHES.read().unwrap().contains_key(&request.name) {
+        return Err(format!("Patch {} already exists", request.name));
+    }
+    let address = util::resolve_target(pid, request.address, request.expression.as_deref())?;
+
+    let patched = match (&request.bytes, &request.assembly) {
+        (Some(bytes), None) => parse_hex(bytes)?,
+        (None, Some(assembly)) => {
+            let arch = match &request.arch {
+                Some(name) => Arch::parse(name)?,
+                None => disasm::detect_arch(pid, address),
+            };
+            let evaluate = |expression: &str| util::resolve_expression(pid, expression);
+            assemble(arch, assembly, address, &evaluate)?
+        }
+        _ => return Err("Exactly one of bytes or assembly is required".to_string()),
+    };
+    if patched.is_empty() {
+        return Err("Patch is empty".to_string());
+    }
+
+    // Breakpoint traps are masked so that disabling the patch never writes one back
+    let original = disasm::read_code(pid, address, patched.len());
+    if original.len() != patched.len() {
+        return Err(format!(
+            "Failed to read {} bytes at 0x{:x}",
+            patched.len(),
+            address
+        ));
+    }
+    if let Some(expected) = &request.original {
+        let expected = parse_hex(expected)?;
+        if expected != original {
+            return Err(format!(
+                "Original bytes mismatch at 0x{:x}: expected {}, found {}",
+                address,
+                hex::encode(&expected),
+                hex::encode(&original)
+            ));
+        }
+    }
+
+    let mut patch = Patch {
+        pid,
+        address,
+        original,
+        patched,
+        assembly: request.assembly.clone(),
+        enabled: false,
+    };
+    if request.enabled {
+        patch.set_enabled(true)?;
+    }
+    let result = patch.to_json(&request.name, Some(pid));
+    PATCHES.write().unwrap().insert(request.name.clone(), patch);
+    Ok(result)
+}
+
+fn owned_patch<'a>(
+    patches: &'a mut BTreeMap<String, Patch>,
+    pid: i32,
+    name: &str,
+) -> Result<&'a mut Patch, String> {
+    let patch = patches
+        .get_mut(name)
+        .ok_or_else(|| format!("Patch {} not found", name))?;
+    if patch.pid != pid {
+        return Err(format!("Patch {} belongs to process {}", name, patch.pid));
+    }
+    Ok(patch)
+}
+
+pub fn set_patch_enabled(pid: i32, name: &str, enabled: bool) -> Result<Value, String> {
+    let mut patches = PATCHES.write().unwrap();
+    let patch = owned_patch(&mut patches, pid, name)?;
+    patch.set_enabled(enabled)?;
+    Ok(patch.to_json(name, Some(pid)))
+}
+
+// Reverts the patch if it is applied and forgets it
+pub fn remove_patch(pid: i32, name: &str) -> Result<(), String> {
+    let mut patches = PATCHES.write().unwrap();
+    let patch = owned_patch(&mut patches, pid, name)?;
+    if patch.enabled {
+        patch.set_enabled(false)?;
+    }
+    patches.remove(name);
+    Ok(())
+}
+
+pub fn list_patches(pid: Option<i32>) -> Value {
+    let patches = PATCHES.read().unwrap();
+    let entries: Vec<Value> = patches
+        .iter()
+        .map(|(name, patch)| patch.to_json(name, pid))
+        .collect();
+    json!({ "patches": entries })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(expression: &str) -> Result<u64, String> {
+        let expression = expression.trim();
+        match expression.strip_prefix('-') {
+            Some(rest) => evaluate(rest).map(u64::wrapping_neg),
+            None => match expression.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16).map_err(|e| e.to_string()),
+                None => expression.parse().map_err(|e| format!("{:?}", e)),
+            },
+        }
+    }
+
+    fn disassemble(arch: Arch, bytes: &[u8], address: u64) -> Vec<String> {
+        let instructions = disasm::decode(arch, bytes, address, usize::MAX).unwrap();
+        let decoded: usize = instructions.iter().map(|insn| insn.bytes.len()).sum();
+        assert_eq!(decoded, bytes.len());
+        instructions.iter().map(|insn| insn.text()).collect()
+    }
+
+    #[test]
+    fn x86_64_instructions() {
+        let bytes = assemble(
+            Arch::X86_64,
+            "nop; ret\njmp 0x401000; call 0x400000; mov eax, 1; mov r9, 0x1234; mov rax, -1; mov r12, 0x1122334455667788",
+            0x400000,
+            &evaluate,
+        )
+        .unwrap();
+        assert_eq!(
+            disassemble(Arch::X86_64, &bytes, 0x400000),
+            vec![
+                "nop",
+                "ret",
+                "jmp 0x401000",
+                "call 0x400000",
+                "mov eax, 1",
+                "mov r9d, 0x1234",
+                "mov rax, 0xffffffffffffffff",
+                "movabs r12, 0x1122334455667788",
+            ]
+        );
+    }
+
+    #[test]
+    fn arm64_instructions() {
+        let bytes = assemble(
+            Arch::Arm64,
+            "nop; ret; b #0x10000; bl 0xff00; mov x0, #0; mov w1, #0x12345678; mov x2, -2; mov x3, 0x1234000000005678",
+            0x10000,
+            &evaluate,
+        )
+        .unwrap();
+        assert_eq!(
+            disassemble(Arch::Arm64, &bytes, 0x10000),
+            vec![
+                "nop",
+                "ret",
+                "b #0x10000",
+                "bl #0xff00",
+                "mov x0, #0",
+                "mov w1, #0x5678",
+                "movk w1, #0x1234, lsl #16",
+                "mov x2, #-2",
+                "mov x3, #0x5678",
+                "movk x3, #0x1234, lsl #48",
+            ]
+        );
+    }
+
+    #[test]
+    fn assembler_errors() {
+        assert!(assemble(Arch::X86_64, "jmp 0x200000000", 0, &evaluate)
+            .unwrap_err()
+            .contains("out of rel32 range"));
+        assert!(assemble(Arch::Arm64, "b 0x10002", 0x10000, &evaluate)
+            .unwrap_err()
+            .contains("out of range"));
+        assert!(assemble(Arch::X86_64, "mov xmm0, 1", 0, &evaluate)
+            .unwrap_err()
+            .contains("Unknown register"));
+        assert!(assemble(Arch::Arm64, "mov w0, 0x100000000", 0, &evaluate)
+            .unwrap_err()
+            .contains("does not fit"));
+        assert!(assemble(Arch::X86_64, "ret 4", 0, &evaluate).is_err());
+        assert!(assemble(Arch::X86_64, "push rax", 0, &evaluate).is_err());
+        assert!(assemble(Arch::Arm, "nop", 0, &evaluate).is_err());
+    }
+}
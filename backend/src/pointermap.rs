@@ -0,0 +1,574 @@
+//! Pointermap file format.
+//!
+//! A pointermap is a zlib stream wrapping the following little-endian layout:
+//!
+//! ```text
+//! magic         u8        0xCE
+//! version       u8        0x02
+//! module_count  u32
+//! modules       module_count × { name_length u32, name [u8; name_length], base u64 }
+//! separator     u8        0x00
+//! max_level     u32
+//! pointer_count u64       total number of pointer records in the file
+//! targets       until end of data, sorted by value:
+//!                 { value u64, count u32, records count × record }
+//! record        { address u64, has_static u8, [module_index u32, offset u32] if has_static == 1 }
+//! ```
+//!
+//! Records within a target are sorted by address. Module entries named `threadstackN` are
+//! thread stacks based at the stack top, so their offsets count downwards from `base`.
+//!
+//! Version 1 has the same layout but no thread stack entries. Version 1 files that do contain
+//! them are rejected, since their readers take every offset as counting upwards.
+
+use crate::expression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+pub const MAGIC: u8 = 0xCE;
+pub const VERSION: u8 = 0x02;
+const VERSION_1: u8 = 0x01;
+
+lazy_static! {
+    static ref LOADED_POINTERMAPS: RwLock<HashMap<String, Arc<PointerMap>>> =
+        RwLock::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointerMapModule {
+    pub name: String,
+    pub base: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StaticBase {
+    pub module_index: u32,
+    pub offset: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointerRecord {
+    pub address: u64,
+    pub static_base: Option<StaticBase>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointerTarget {
+    pub value: u64,
+    pub pointers: Vec<PointerRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PointerMap {
+    pub modules: Vec<PointerMapModule>,
+    pub max_level: u32,
+    pub targets: Vec<PointerTarget>,
+}
+
+impl PointerMapModule {
+    pub fn is_thread_stack(&self) -> bool {
+        self.name.starts_with("threadstack")
+    }
+
+    // Formats a static offset the way address expressions accept it
+    pub fn describe(&self, offset: u32) -> String {
+        if self.is_thread_stack() {
+            format!("{}-0x{:x}", self.name, offset)
+        } else {
+            let name = Path::new(&self.name)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| self.name.clone());
+            format!("{}+0x{:x}", expression::quote_module(&name), offset)
+        }
+    }
+}
+
+impl PointerMap {
+    pub fn pointer_count(&self) -> u64 {
+        self.targets.iter().map(|t| t.pointers.len() as u64).sum()
+    }
+
+    pub fn find_target(&self, value: u64) -> Option<&PointerTarget> {
+        self.targets
+            .binary_search_by_key(&value, |target| target.value)
+            .ok()
+            .map(|idx| &self.targets[idx])
+    }
+
+    // Records pointing exactly at `value`, with their static base if any
+    pub fn referrers(&self, value: u64) -> Vec<Value> {
+        self.find_target(value)
+            .map(|target| {
+                target
+                    .pointers
+                    .iter()
+                    .map(|pointer| {
+                        let static_base = pointer.static_base.and_then(|data| {
+                            self.modules
+                                .get(data.module_index as usize)
+                                .map(|module| module.describe(data.offset))
+                        });
+                        json!({"address": pointer.address, "static": static_base})
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn summary(&self) -> Value {
+        json!({
+            "modules": self.modules.len(),
+            "targets": self.targets.len(),
+            "pointers": self.pointer_count(),
+            "max_level": self.max_level
+        })
+    }
+
+    // Uncompressed layout; targets and their records are sorted on the way out
+    pub fn to_raw(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&[MAGIC, VERSION]);
+
+        out.extend_from_slice(&(self.modules.len() as u32).to_le_bytes());
+        for module in &self.modules {
+            out.extend_from_slice(&(module.name.len() as u32).to_le_bytes());
+            out.extend_from_slice(module.name.as_bytes());
+            out.extend_from_slice(&module.base.to_le_bytes());
+        }
+
+        out.push(0);
+        out.extend_from_slice(&self.max_level.to_le_bytes());
+        out.extend_from_slice(&self.pointer_count().to_le_bytes());
+
+        let mut targets: Vec<&PointerTarget> = self.targets.iter().collect();
+        targets.sort_by_key(|target| target.value);
+        for target in targets {
+            out.extend_from_slice(&target.value.to_le_bytes());
+            out.extend_from_slice(&(target.pointers.len() as u32).to_le_bytes());
+
+            let mut pointers: Vec<&PointerRecord> = target.pointers.iter().collect();
+            pointers.sort_by_key(|pointer| pointer.address);
+            for pointer in pointers {
+                out.extend_from_slice(&pointer.address.to_le_bytes());
+                match pointer.static_base {
+                    Some(data) => {
+                        out.push(1);
+                        out.extend_from_slice(&data.module_index.to_le_bytes());
+                        out.extend_from_slice(&data.offset.to_le_bytes());
+                    }
+                    None => out.push(0),
+                }
+            }
+        }
+        out
+    }
+
+    pub fn from_raw(data: &[u8]) -> Result<PointerMap, String> {
+        let mut reader = RawReader { data, pos: 0 };
+
+        let magic = reader.u8()?;
+        if magic != MAGIC {
+            return Err(format!("Invalid pointermap magic 0x{:02x}", magic));
+        }
+        let version = reader.u8()?;
+        if version != VERSION && version != VERSION_1 {
+            return Err(format!("Unsupported pointermap version {}", version));
+        }
+
+        let module_count = reader.u32()?;
+        let mut modules = Vec::new();
+        for _ in 0..module_count {
+            let name_length = reader.u32()? as usize;
+            let name = String::from_utf8(reader.bytes(name_length)?.to_vec())
+                .map_err(|e| format!("Invalid module name at offset {}: {}", reader.pos, e))?;
+            let base = reader.u64()?;
+            modules.push(PointerMapModule { name, base });
+        }
+
+        if version == VERSION_1 {
+            if let Some(module) = modules.iter().find(|module| module.is_thread_stack()) {
+                return Err(format!(
+                    "Version 1 pointermap has thread stack entry {}, whose offsets are ambiguous",
+                    module.name
+                ));
+            }
+        }
+
+        let separator = reader.u8()?;
+        if separator != 0 {
+            return Err(format!(
+                "Expected module table separator at offset {}",
+                reader.pos - 1
+            ));
+        }
+
+        let max_level = reader.u32()?;
+        let pointer_count = reader.u64()?;
+
+        let mut targets = Vec::new();
+        let mut read_count: u64 = 0;
+        while !reader.is_empty() {
+            let value = reader.u64()?;
+            let count = reader.u32()?;
+            let mut pointers = Vec::with_capacity(count.min(1 << 16) as usize);
+            for _ in 0..count {
+                let address = reader.u64()?;
+                let static_base = match reader.u8()? {
+                    0 => None,
+                    1 => {
+                        let module_index = reader.u32()?;
+                        let offset = reader.u32()?;
+                        if module_index as usize >= modules.len() {
+                            return Err(format!(
+                                "Module index {} out of range at offset {}",
+                                module_index,
+                                reader.pos - 8
+                            ));
+                        }
+                        Some(StaticBase {
+                            module_index,
+                            offset,
+                        })
+                    }
+                    flag => {
+                        return Err(format!(
+                            "Invalid static flag {} at offset {}",
+                            flag,
+                            reader.pos - 1
+                        ))
+                    }
+                };
+                pointers.push(PointerRecord {
+                    address,
+                    static_base,
+                });
+            }
+            read_count += count as u64;
+            targets.push(PointerTarget { value, pointers });
+        }
+
+        if read_count != pointer_count {
+            return Err(format!(
+                "Pointer count mismatch: header says {}, found {}",
+                pointer_count, read_count
+            ));
+        }
+
+        Ok(PointerMap {
+            modules,
+            max_level,
+            targets,
+        })
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(&self.to_raw())
+            .map_err(|e| format!("Failed to compress data: {}", e))?;
+        encoder
+            .finish()
+            .map_err(|e| format!("Failed to finish compression: {}", e))
+    }
+
+    pub fn decode(data: &[u8]) -> Result<PointerMap, String> {
+        let mut raw = Vec::new();
+        ZlibDecoder::new(data)
+            .read_to_end(&mut raw)
+            .map_err(|e| format!("Failed to decompress data: {}", e))?;
+        PointerMap::from_raw(&raw)
+    }
+}
+
+struct RawReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> RawReader<'a> {
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() - self.pos < len {
+            return Err(format!(
+                "Unexpected end of pointermap at offset {} (need {} bytes)",
+                self.pos, len
+            ));
+        }
+        let slice = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+}
+
+pub fn store_loaded(name: &str, map: PointerMap) -> Arc<PointerMap> {
+    let map = Arc::new(map);
+    LOADED_POINTERMAPS
+        .write()
+        .unwrap()
+        .insert(name.to_string(), map.clone());
+    map
+}
+
+pub fn get_loaded(name: &str) -> Option<Arc<PointerMap>> {
+    LOADED_POINTERMAPS.read().unwrap().get(name).cloned()
+}
+
+// Loaded maps are kept until unloaded, so this is what releases their memory
+pub fn unload(name: &str) -> bool {
+    LOADED_POINTERMAPS.write().unwrap().remove(name).is_some()
+}
+
+pub fn loaded_summaries() -> Vec<Value> {
+    let loaded = LOADED_POINTERMAPS.read().unwrap();
+    let mut names: Vec<&String> = loaded.keys().collect();
+    names.sort();
+    names
+        .into_iter()
+        .map(|name| {
+            let mut summary = loaded[name].summary();
+            summary["name"] = json!(name);
+            summary
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_map() -> PointerMap {
+        PointerMap {
+            modules: vec![
+                PointerMapModule {
+                    name: "/system/lib64/libc.so".to_string(),
+                    base: 0x7000_0000,
+                },
+                PointerMapModule {
+                    name: "threadstack0".to_string(),
+                    base: 0x7fff_0000,
+                },
+            ],
+            max_level: 8,
+            targets: vec![
+                PointerTarget {
+                    value: 0x1000,
+                    pointers: vec![
+                        PointerRecord {
+                            address: 0x7000_0010,
+                            static_base: Some(StaticBase {
+                                module_index: 0,
+                                offset: 0x10,
+                            }),
+                        },
+                        PointerRecord {
+                            address: 0x5555_0000,
+                            static_base: None,
+                        },
+                    ],
+                },
+                PointerTarget {
+                    value: 0x2000,
+                    pointers: vec![PointerRecord {
+                        address: 0x7ffe_fff0,
+                        static_base: Some(StaticBase {
+                            module_index: 1,
+                            offset: 0x10,
+                        }),
+                    }],
+                },
+            ],
+        }
+    }
+
+    // Targets and records in the order the writer emits them
+    fn sorted(mut map: PointerMap) -> PointerMap {
+        map.targets.sort_by_key(|target| target.value);
+        for target in &mut map.targets {
+            target.pointers.sort_by_key(|pointer| pointer.address);
+        }
+        map
+    }
+
+    #[test]
+    fn round_trip_compressed() {
+        let map = sample_map();
+        let decoded = PointerMap::decode(&map.encode().unwrap()).unwrap();
+        assert_eq!(decoded, sorted(map));
+        assert_eq!(decoded.pointer_count(), 3);
+    }
+
+    #[test]
+    fn round_trip_empty() {
+        let map = PointerMap {
+            max_level: 8,
+            ..Default::default()
+        };
+        let raw = map.to_raw();
+        assert_eq!(raw.len(), 2 + 4 + 1 + 4 + 8);
+        assert_eq!(PointerMap::from_raw(&raw).unwrap(), map);
+    }
+
+    #[test]
+    fn writer_sorts_targets_and_records() {
+        let mut map = sample_map();
+        map.targets.reverse();
+        let decoded = PointerMap::from_raw(&map.to_raw()).unwrap();
+        assert_eq!(decoded.targets[0].value, 0x1000);
+        assert_eq!(decoded.targets[0].pointers[0].address, 0x5555_0000);
+        assert!(decoded.find_target(0x2000).is_some());
+        assert!(decoded.find_target(0x3000).is_none());
+    }
+
+    #[test]
+    fn raw_layout_matches_spec() {
+        let map = PointerMap {
+            modules: vec![PointerMapModule {
+                name: "a".to_string(),
+                base: 0x10,
+            }],
+            max_level: 8,
+            targets: vec![PointerTarget {
+                value: 0x20,
+                pointers: vec![PointerRecord {
+                    address: 0x18,
+                    static_base: Some(StaticBase {
+                        module_index: 0,
+                        offset: 8,
+                    }),
+                }],
+            }],
+        };
+        let mut expected = vec![0xCE, 0x02];
+        expected.extend_from_slice(&1u32.to_le_bytes());
+        expected.extend_from_slice(&1u32.to_le_bytes());
+        expected.push(b'a');
+        expected.extend_from_slice(&0x10u64.to_le_bytes());
+        expected.push(0);
+        expected.extend_from_slice(&8u32.to_le_bytes());
+        expected.extend_from_slice(&1u64.to_le_bytes());
+        expected.extend_from_slice(&0x20u64.to_le_bytes());
+        expected.extend_from_slice(&1u32.to_le_bytes());
+        expected.extend_from_slice(&0x18u64.to_le_bytes());
+        expected.push(1);
+        expected.extend_from_slice(&0u32.to_le_bytes());
+        expected.extend_from_slice(&8u32.to_le_bytes());
+        assert_eq!(map.to_raw(), expected);
+    }
+
+    #[test]
+    fn describes_static_bases() {
+        let map = sample_map();
+        assert_eq!(map.modules[0].describe(0x10), "libc.so+0x10");
+        assert!(map.modules[1].is_thread_stack());
+        assert_eq!(map.modules[1].describe(0x10), "threadstack0-0x10");
+
+        let referrers = map.referrers(0x2000);
+        assert_eq!(referrers.len(), 1);
+        assert_eq!(referrers[0]["static"], "threadstack0-0x10");
+        assert!(map.referrers(0x3000).is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_header() {
+        let mut raw = sample_map().to_raw();
+        raw[0] = 0xCF;
+        assert!(PointerMap::from_raw(&raw)
+            .unwrap_err()
+            .contains("Invalid pointermap magic"));
+
+        let mut raw = sample_map().to_raw();
+        raw[1] = 0x03;
+        assert!(PointerMap::from_raw(&raw)
+            .unwrap_err()
+            .contains("Unsupported pointermap version"));
+    }
+
+    #[test]
+    fn version_1_without_thread_stacks() {
+        let mut map = sample_map();
+        let mut raw = map.to_raw();
+        raw[1] = 0x01;
+        assert!(PointerMap::from_raw(&raw)
+            .unwrap_err()
+            .contains("thread stack entry threadstack0"));
+
+        map.modules[1].name = "libgame.so".to_string();
+        let mut raw = map.to_raw();
+        raw[1] = 0x01;
+        assert_eq!(PointerMap::from_raw(&raw).unwrap(), sorted(map));
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let raw = sample_map().to_raw();
+        for len in [1, 5, 20, raw.len() - 1] {
+            assert!(PointerMap::from_raw(&raw[..len]).is_err(), "length {}", len);
+        }
+    }
+
+    #[test]
+    fn rejects_inconsistent_records() {
+        let mut map = sample_map();
+        map.targets[0].pointers[0].static_base = Some(StaticBase {
+            module_index: 5,
+            offset: 0,
+        });
+        assert!(PointerMap::from_raw(&map.to_raw())
+            .unwrap_err()
+            .contains("out of range"));
+
+        let mut raw = sample_map().to_raw();
+        // pointer_count follows magic, modules, separator and max_level
+        let count_offset = raw.len()
+            - sample_map()
+                .targets
+                .iter()
+                .map(|t| 12 + t.pointers.len() * 9)
+                .sum::<usize>()
+            - 2 * 8
+            - 8;
+        raw[count_offset] = 7;
+        assert!(PointerMap::from_raw(&raw)
+            .unwrap_err()
+            .contains("Pointer count mismatch"));
+    }
+
+    #[test]
+    fn rejects_invalid_zlib() {
+        assert!(PointerMap::decode(&[0xCE, 0x01, 0x00])
+            .unwrap_err()
+            .contains("decompress"));
+    }
+
+    #[test]
+    fn unload_releases_loaded_map() {
+        store_loaded("unload-test", sample_map());
+        assert!(get_loaded("unload-test").is_some());
+        assert!(unload("unload-test"));
+        assert!(get_loaded("unload-test").is_none());
+        assert!(!unload("unload-test"));
+    }
+}
@@ -1,21 +1,41 @@
+use crate::expression;
 use crate::native_bridge;
-use flate2::write::ZlibEncoder;
-use flate2::Compression;
+use crate::pointermap::{PointerMap, PointerMapModule, PointerRecord, PointerTarget, StaticBase};
+use crate::request::{ObjectGraphRequest, PointerMapOptions, PointerReferrersRequest};
+use crate::util;
+use lazy_static::lazy_static;
 use libc;
 use rayon::prelude::*;
-use serde_json::Value;
-use std::collections::HashMap;
-use std::io::{Cursor, Write};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 
+const MAX_REFERRERS: usize = 10_000;
+const MAX_GRAPH_NODES: usize = 10_000;
+
+// (value, source address) pairs sorted by value
+type ReverseMap = Arc<Vec<(u64, u64)>>;
+
+lazy_static! {
+    // Kept between referrer lookups for the same pid and walk options
+    static ref REVERSE_MAP_CACHE: Mutex<Option<(i32, PointerMapOptions, ReverseMap)>> =
+        Mutex::new(None);
+}
+
 #[repr(C)]
 struct ModuleEntry {
     entry_length: u32,
     entry_string: String,
-    memory_size: i32,
+    memory_size: u64,
     memory_address: u64,
 }
 
+struct ThreadStackEntry {
+    stack_base: u64,
+    stack_top: u64,
+}
+
 struct StaticData {
     module_index: u32,
     offset: u32,
@@ -36,7 +56,7 @@ fn find_static_data(address: usize, modules: &[ModuleEntry]) -> Option<StaticDat
     match modules.binary_search_by(|module| {
         if address < module.memory_address {
             std::cmp::Ordering::Greater
-        } else if address >= module.memory_address + module.memory_size as u64 {
+        } else if address >= module.memory_address + module.memory_size {
             std::cmp::Ordering::Less
         } else {
             std::cmp::Ordering::Equal
@@ -50,6 +70,100 @@ fn find_static_data(address: usize, modules: &[ModuleEntry]) -> Option<StaticDat
     }
 }
 
+// Thread stacks are addressed downwards from their top (threadstackN-offset), so the
+// offset stored for them is the distance below stack_top
+fn find_thread_stack_data(
+    address: usize,
+    thread_stacks: &[ThreadStackEntry],
+    first_index: usize,
+) -> Option<StaticData> {
+    let address = address as u64;
+    thread_stacks
+        .iter()
+        .position(|stack| address >= stack.stack_base && address < stack.stack_top)
+        .map(|idx| StaticData {
+            module_index: (first_index + idx) as u32,
+            offset: (thread_stacks[idx].stack_top - address) as u32,
+        })
+}
+
+pub fn region_bounds(region: &Value) -> (u64, u64) {
+    let start =
+        u64::from_str_radix(region["start_address"].as_str().unwrap_or("0"), 16).unwrap_or(0);
+    let end = u64::from_str_radix(region["end_address"].as_str().unwrap_or("0"), 16).unwrap_or(0);
+    (start, end)
+}
+
+// Pseudo mappings such as [heap] or [anon:...] are not modules and are never filtered by name
+fn is_module_path(file_path: &str) -> bool {
+    !file_path.is_empty() && !file_path.starts_with('[')
+}
+
+fn matches_module_globs(file_path: &str, globs: &[String]) -> bool {
+    let file_name = Path::new(file_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    globs
+        .iter()
+        .any(|glob| util::glob_match(glob, file_path) || util::glob_match(glob, &file_name))
+}
+
+fn is_excluded_region(region: &Value, options: &PointerMapOptions) -> bool {
+    let file_path = region["file_path"].as_str().unwrap_or("");
+    is_module_path(file_path) && matches_module_globs(file_path, &options.exclude_modules)
+}
+
+// Whether a region is walked for pointer values
+fn is_source_region(region: &Value, options: &PointerMapOptions) -> bool {
+    let protection = region["protection"].as_str().unwrap_or("");
+    let file_path = region["file_path"].as_str().unwrap_or("");
+
+    if !protection.contains('r') || !protection.contains('p') {
+        return false;
+    }
+    if options.writable_only && !protection.contains('w') {
+        return false;
+    }
+    if is_excluded_region(region, options) {
+        return false;
+    }
+    if !options.include_modules.is_empty()
+        && is_module_path(file_path)
+        && !matches_module_globs(file_path, &options.include_modules)
+    {
+        return false;
+    }
+    true
+}
+
+// Sorted, merged ranges of every readable region a pointer value may land in
+fn collect_target_ranges(regions: &[Value], options: &PointerMapOptions) -> Vec<(u64, u64)> {
+    let mut ranges: Vec<(u64, u64)> = regions
+        .iter()
+        .filter(|region| {
+            region["protection"].as_str().unwrap_or("").contains('r')
+                && !is_excluded_region(region, options)
+        })
+        .map(region_bounds)
+        .collect();
+    ranges.sort_unstable();
+
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+fn is_in_ranges(value: u64, ranges: &[(u64, u64)]) -> bool {
+    let idx = ranges.partition_point(|&(start, _)| start <= value);
+    idx > 0 && value < ranges[idx - 1].1
+}
+
 // Process memory read helper function
 fn read_memory(pid: i32, address: usize, size: usize) -> Result<Vec<u8>, String> {
     let mut buffer = vec![0u8; size];
@@ -67,28 +181,7 @@ fn read_memory(pid: i32, address: usize, size: usize) -> Result<Vec<u8>, String>
     }
 }
 
-pub fn generate_pointermap(pid: i32) -> Result<Vec<u8>, String> {
-    // Get memory regions and calculate valid address range
-    let regions = native_bridge::enum_regions(pid)?;
-    let (min_valid_addr, max_valid_addr) = {
-        let mut min_addr = u64::MAX;
-        let mut max_addr = 0;
-        for region in &regions {
-            let start = u64::from_str_radix(region["start_address"].as_str().unwrap_or("0"), 16)
-                .unwrap_or(0);
-            let end =
-                u64::from_str_radix(region["end_address"].as_str().unwrap_or("0"), 16).unwrap_or(0);
-            let protection = region["protection"].as_str().unwrap_or("");
-
-            if !protection.contains('r') || !protection.contains('p') {
-                continue;
-            }
-            min_addr = min_addr.min(start);
-            max_addr = max_addr.max(end);
-        }
-        (min_addr, max_addr)
-    };
-
+fn load_static_bases(pid: i32) -> Result<(Vec<ModuleEntry>, Vec<ThreadStackEntry>), String> {
     // Get modules
     let modules = match native_bridge::enum_modules(pid) {
         Ok(modules) => {
@@ -96,7 +189,7 @@ pub fn generate_pointermap(pid: i32) -> Result<Vec<u8>, String> {
             for module in modules {
                 let name = module["modulename"].as_str().unwrap_or("");
                 let base = module["base"].as_u64().unwrap_or(0);
-                let size: i32 = module["size"].as_i64().unwrap_or(0) as i32;
+                let size = module["size"].as_u64().unwrap_or(0);
                 module_entries.push(ModuleEntry {
                     entry_length: name.len() as u32,
                     entry_string: name.to_string(),
@@ -109,19 +202,65 @@ pub fn generate_pointermap(pid: i32) -> Result<Vec<u8>, String> {
         Err(e) => return Err(format!("Failed to enumerate modules: {}", e)),
     };
 
-    let mut pointer_map: HashMap<u64, Vec<(u64, Option<StaticData>)>> = HashMap::new();
+    // Thread stacks are optional roots; platforms without support simply have none
+    let thread_stacks: Vec<ThreadStackEntry> = native_bridge::enum_thread_stacks(pid)
+        .unwrap_or_default()
+        .iter()
+        .map(|stack| ThreadStackEntry {
+            stack_base: stack["stack_base"].as_u64().unwrap_or(0),
+            stack_top: stack["stack_top"].as_u64().unwrap_or(0),
+        })
+        .collect();
+
+    Ok((modules, thread_stacks))
+}
+
+fn find_static_base(
+    address: usize,
+    modules: &[ModuleEntry],
+    thread_stacks: &[ThreadStackEntry],
+) -> Option<StaticData> {
+    find_static_data(address, modules)
+        .or_else(|| find_thread_stack_data(address, thread_stacks, modules.len()))
+}
+
+// Walks every source region and calls `visit(source_address, value)` for each aligned
+// 8-byte value that passes the pointer filters
+fn walk_pointers<F>(pid: i32, options: &PointerMapOptions, mut visit: F) -> Result<(), String>
+where
+    F: FnMut(u64, u64),
+{
+    // Get memory regions and calculate valid address range
+    let regions = native_bridge::enum_regions(pid)?;
+    let (min_valid_addr, max_valid_addr) = {
+        let mut min_addr = u64::MAX;
+        let mut max_addr = 0;
+        for region in &regions {
+            let (start, end) = region_bounds(region);
+            let protection = region["protection"].as_str().unwrap_or("");
+
+            if !protection.contains('r') || !protection.contains('p') {
+                continue;
+            }
+            min_addr = min_addr.min(start);
+            max_addr = max_addr.max(end);
+        }
+        (min_addr, max_addr)
+    };
+    let target_ranges = if options.require_readable_target {
+        collect_target_ranges(&regions, options)
+    } else {
+        Vec::new()
+    };
+    let alignment = options.alignment.max(1);
 
     // Process each memory region
-    for region in regions {
-        let start_address = u64::from_str_radix(region["start_address"].as_str().unwrap_or("0"), 16)
-            .unwrap_or(0) as usize;
-        let end_address = u64::from_str_radix(region["end_address"].as_str().unwrap_or("0"), 16)
-            .unwrap_or(0) as usize;
-        let protection = region["protection"].as_str().unwrap_or("");
-
-        if !protection.contains('r') || !protection.contains('p') {
+    for region in &regions {
+        if !is_source_region(region, options) {
             continue;
         }
+        let (start_address, end_address) = region_bounds(region);
+        let (start_address, end_address) = (start_address as usize, end_address as usize);
         const CHUNK_SIZE: usize = 1024 * 1024 * 16; // 16MB chunks
         let mut current_address = start_address;
 
@@ -144,14 +283,13 @@ pub fn generate_pointermap(pid: i32) -> Result<Vec<u8>, String> {
                     }
 
                     let value = u64::from_le_bytes(memory[i..i + 8].try_into().unwrap());
-                    if value >= min_valid_addr && value < max_valid_addr && value % 4 == 0 {
-                        let source_address = current_address + i;
-                        let static_data = find_static_data(source_address, &modules);
-                        pointer_map
-                            .entry(value)
-                            .or_insert_with(Vec::new)
-                            .push((source_address as u64, static_data));
+                    if value < min_valid_addr || value >= max_valid_addr || value % alignment != 0 {
+                        continue;
+                    }
+                    if options.require_readable_target && !is_in_ranges(value, &target_ranges) {
+                        continue;
                     }
+                    visit((current_address + i) as u64, value);
                 }
             }
 
@@ -159,72 +297,406 @@ pub fn generate_pointermap(pid: i32) -> Result<Vec<u8>, String> {
         }
     }
 
-    // Create uncompressed data first
-    let mut uncompressed = Vec::new();
+    Ok(())
+}
 
-    // Write header
-    uncompressed.extend_from_slice(&[0xCE, 0x01]); // Magic number
+pub fn generate_pointermap(pid: i32, options: &PointerMapOptions) -> Result<Vec<u8>, String> {
+    let (modules, thread_stacks) = load_static_bases(pid)?;
 
-    // Write modules
-    uncompressed.extend_from_slice(&(modules.len() as u32).to_le_bytes());
-    for module in &modules {
-        uncompressed.extend_from_slice(&module.entry_length.to_le_bytes());
-        uncompressed.extend_from_slice(module.entry_string.as_bytes());
-        uncompressed.extend_from_slice(&module.memory_address.to_le_bytes());
+    let mut pointer_map: HashMap<u64, Vec<(u64, Option<StaticData>)>> = HashMap::new();
+    walk_pointers(pid, options, |source_address, value| {
+        let static_data = find_static_base(source_address as usize, &modules, &thread_stacks);
+        pointer_map
+            .entry(value)
+            .or_default()
+            .push((source_address, static_data));
+    })?;
+
+    // Modules, followed by thread stacks as "threadstackN" entries based at the stack top
+    let mut map_modules: Vec<PointerMapModule> = modules
+        .iter()
+        .map(|module| PointerMapModule {
+            name: module.entry_string.clone(),
+            base: module.memory_address,
+        })
+        .collect();
+    for (index, stack) in thread_stacks.iter().enumerate() {
+        map_modules.push(PointerMapModule {
+            name: format!("threadstack{}", index),
+            base: stack.stack_top,
+        });
     }
 
-    // Separator
-    uncompressed.push(0);
+    let targets = pointer_map
+        .into_iter()
+        .map(|(value, pointers)| PointerTarget {
+            value,
+            pointers: pointers
+                .into_iter()
+                .map(|(address, static_data)| PointerRecord {
+                    address,
+                    static_base: static_data.map(|data| StaticBase {
+                        module_index: data.module_index,
+                        offset: data.offset,
+                    }),
+                })
+                .collect(),
+        })
+        .collect();
 
-    // Max level
     const MAX_LEVEL: u32 = 8;
-    uncompressed.extend_from_slice(&MAX_LEVEL.to_le_bytes());
-
-    // Convert HashMap into a sorted Vec
-    let mut sorted_entries: Vec<_> = pointer_map.into_iter().collect();
-    sorted_entries.sort_by_key(|&(address, _)| address);
-
-    // Total pointer count (all pointers across all target values)
-    let total_count: u64 = sorted_entries.iter().map(|(_, v)| v.len() as u64).sum();
-    uncompressed.extend_from_slice(&total_count.to_le_bytes());
-
-    // Write all pointer entries in sorted order
-    for (target_value, mut pointers) in sorted_entries {
-        // Sort pointers within each target by address
-        pointers.sort_by_key(|(address, _)| *address);
-
-        // Target value (address being pointed to)
-        uncompressed.extend_from_slice(&target_value.to_le_bytes());
-        // Number of pointers to this target
-        uncompressed.extend_from_slice(&(pointers.len() as u32).to_le_bytes());
-
-        // Write each pointer's data
-        for (address, static_data) in pointers {
-            // Pointer address
-            uncompressed.extend_from_slice(&address.to_le_bytes());
-
-            match static_data {
-                Some(data) => {
-                    uncompressed.push(1); // Has static data
-                    uncompressed.extend_from_slice(&data.module_index.to_le_bytes());
-                    uncompressed.extend_from_slice(&data.offset.to_le_bytes());
-                }
-                None => {
-                    uncompressed.push(0); // No static data
+    PointerMap {
+        modules: map_modules,
+        max_level: MAX_LEVEL,
+        targets,
+    }
+    .encode()
+}
+
+fn build_reverse_map(pid: i32, options: &PointerMapOptions) -> Result<Vec<(u64, u64)>, String> {
+    let mut entries = Vec::new();
+    walk_pointers(pid, options, |source_address, value| {
+        entries.push((value, source_address))
+    })?;
+    entries.par_sort_unstable();
+    Ok(entries)
+}
+
+// Formats a static base the way address expressions accept it
+fn describe_static_base(data: &StaticData, modules: &[ModuleEntry]) -> String {
+    let index = data.module_index as usize;
+    if index < modules.len() {
+        let name = Path::new(&modules[index].entry_string)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| modules[index].entry_string.clone());
+        format!("{}+0x{:x}", expression::quote_module(&name), data.offset)
+    } else {
+        format!("threadstack{}-0x{:x}", index - modules.len(), data.offset)
+    }
+}
+
+// Lists the addresses holding a pointer to `address` or up to `max_offset` bytes before it
+pub fn find_referrers(pid: i32, request: &PointerReferrersRequest) -> Result<Value, String> {
+    let mut cached = false;
+    let reverse_map = if request.use_cache {
+        let hit = match REVERSE_MAP_CACHE.lock().unwrap().as_ref() {
+            Some((cached_pid, options, map))
+                if *cached_pid == pid && *options == request.options && !request.refresh_cache =>
+            {
+                Some(map.clone())
+            }
+            _ => None,
+        };
+        match hit {
+            Some(map) => {
+                cached = true;
+                map
+            }
+            // Built without the lock held, so other lookups are not blocked by the walk
+            None => {
+                let map = Arc::new(build_reverse_map(pid, &request.options)?);
+                *REVERSE_MAP_CACHE.lock().unwrap() =
+                    Some((pid, request.options.clone(), map.clone()));
+                map
+            }
+        }
+    } else {
+        Arc::new(build_reverse_map(pid, &request.options)?)
+    };
+
+    let (modules, thread_stacks) = load_static_bases(pid)?;
+
+    let low = request.address.saturating_sub(request.max_offset);
+    let start = reverse_map.partition_point(|&(value, _)| value < low);
+    let end = reverse_map.partition_point(|&(value, _)| value <= request.address);
+
+    let referrers: Vec<Value> = reverse_map[start..end]
+        .iter()
+        .rev()
+        .take(MAX_REFERRERS)
+        .map(|&(value, source_address)| {
+            let static_base = find_static_base(source_address as usize, &modules, &thread_stacks)
+                .map(|data| describe_static_base(&data, &modules));
+            json!({
+                "address": source_address,
+                "value": value,
+                "offset": request.address - value,
+                "static": static_base
+            })
+        })
+        .collect();
+
+    Ok(json!({
+        "referrers": referrers,
+        "found": end - start,
+        "is_rounded": end - start > MAX_REFERRERS,
+        "cached": cached
+    }))
+}
+
+struct GraphRegion {
+    start: u64,
+    end: u64,
+    protection: String,
+    file_path: String,
+}
+
+fn find_graph_region(address: u64, regions: &[GraphRegion]) -> Option<&GraphRegion> {
+    let idx = regions.partition_point(|region| region.start <= address);
+    if idx > 0 && address < regions[idx - 1].end {
+        Some(&regions[idx - 1])
+    } else {
+        None
+    }
+}
+
+// Follows pointers breadth-first from `request.address`, treating every `struct_size` bytes
+// at a node as its fields; edges are labeled with the field offset
+pub fn explore_object_graph(pid: i32, request: &ObjectGraphRequest) -> Result<Value, String> {
+    let mut regions: Vec<GraphRegion> = native_bridge::enum_regions(pid)?
+        .iter()
+        .filter(|region| region["protection"].as_str().unwrap_or("").contains('r'))
+        .map(|region| {
+            let (start, end) = region_bounds(region);
+            GraphRegion {
+                start,
+                end,
+                protection: region["protection"].as_str().unwrap_or("").to_string(),
+                file_path: region["file_path"].as_str().unwrap_or("").to_string(),
+            }
+        })
+        .collect();
+    regions.sort_unstable_by_key(|region| region.start);
+
+    if find_graph_region(request.address, &regions).is_none() {
+        return Err(format!(
+            "Address 0x{:x} is not in a readable region",
+            request.address
+        ));
+    }
+
+    let (modules, thread_stacks) = load_static_bases(pid)?;
+    let max_nodes = request.max_nodes.clamp(1, MAX_GRAPH_NODES);
+    let alignment = request.alignment.max(1);
+
+    let mut nodes: Vec<Value> = Vec::new();
+    let mut edges: Vec<Value> = Vec::new();
+    let mut seen: HashSet<u64> = HashSet::new();
+    let mut queue: VecDeque<(u64, u32)> = VecDeque::new();
+    let mut truncated = false;
+
+    seen.insert(request.address);
+    queue.push_back((request.address, 0));
+
+    while let Some((address, depth)) = queue.pop_front() {
+        let region = find_graph_region(address, &regions).unwrap();
+        let static_base = find_static_base(address as usize, &modules, &thread_stacks)
+            .map(|data| describe_static_base(&data, &modules));
+        nodes.push(json!({
+            "address": address,
+            "depth": depth,
+            "protection": region.protection,
+            "file_path": region.file_path,
+            "static": static_base
+        }));
+
+        // Leaves and read-only targets (vtables, code, constants) are not expanded
+        if depth >= request.depth || (!request.follow_readonly && !region.protection.contains('w'))
+        {
+            continue;
+        }
+
+        let size = request.struct_size.min((region.end - address) as usize);
+        let memory = match read_memory(pid, address as usize, size) {
+            Ok(memory) => memory,
+            Err(_) => continue,
+        };
+
+        for offset in (0..memory.len().saturating_sub(7)).step_by(8) {
+            let value = u64::from_le_bytes(memory[offset..offset + 8].try_into().unwrap());
+            if value % alignment != 0 || find_graph_region(value, &regions).is_none() {
+                continue;
+            }
+
+            if !seen.contains(&value) {
+                if seen.len() >= max_nodes {
+                    truncated = true;
+                    continue;
                 }
+                seen.insert(value);
+                queue.push_back((value, depth + 1));
             }
+            edges.push(json!({
+                "from": address,
+                "to": value,
+                "offset": offset
+            }));
         }
     }
 
-    // Compress the data using zlib
-    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
-    encoder
-        .write_all(&uncompressed)
-        .map_err(|e| format!("Failed to compress data: {}", e))?;
+    Ok(json!({
+        "root": request.address,
+        "nodes": nodes,
+        "edges": edges,
+        "truncated": truncated
+    }))
+}
 
-    let compressed = encoder
-        .finish()
-        .map_err(|e| format!("Failed to finish compression: {}", e))?;
+// Quotes and backslashes would end or corrupt a DOT string
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
 
-    Ok(compressed)
+// Renders a graph from `explore_object_graph` as Graphviz DOT
+pub fn object_graph_to_dot(graph: &Value) -> String {
+    let mut dot = String::from("digraph objects {\n");
+    dot.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+
+    for node in graph["nodes"].as_array().into_iter().flatten() {
+        let address = node["address"].as_u64().unwrap_or(0);
+        let mut label = format!("0x{:x}", address);
+        if let Some(static_base) = node["static"].as_str() {
+            label.push_str(&format!("\\n{}", dot_escape(static_base)));
+        } else if let Some(file_name) = Path::new(node["file_path"].as_str().unwrap_or(""))
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+        {
+            label.push_str(&format!("\\n{}", dot_escape(&file_name)));
+        }
+        let style = if address == graph["root"].as_u64().unwrap_or(0) {
+            ", style=bold"
+        } else {
+            ""
+        };
+        dot.push_str(&format!(
+            "    \"0x{:x}\" [label=\"{}\"{}];\n",
+            address, label, style
+        ));
+    }
+
+    for edge in graph["edges"].as_array().into_iter().flatten() {
+        dot.push_str(&format!(
+            "    \"0x{:x}\" -> \"0x{:x}\" [label=\"+0x{:x}\"];\n",
+            edge["from"].as_u64().unwrap_or(0),
+            edge["to"].as_u64().unwrap_or(0),
+            edge["offset"].as_u64().unwrap_or(0)
+        ));
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(protection: &str, file_path: &str) -> Value {
+        json!({
+            "start_address": "1000",
+            "end_address": "2000",
+            "protection": protection,
+            "file_path": file_path
+        })
+    }
+
+    #[test]
+    fn source_region_filter() {
+        let options = PointerMapOptions {
+            writable_only: true,
+            include_modules: vec!["libgame*".to_string()],
+            exclude_modules: vec!["*.bak".to_string()],
+            alignment: 8,
+            require_readable_target: false,
+        };
+        let cases = [
+            (region("rw-p", "/data/app/libgame.so"), true),
+            // Matched by the included glob on the file name, not the full path
+            (region("rw-p", "/data/app/libother.so"), false),
+            (region("r--p", "/data/app/libgame.so"), false),
+            (region("rw-s", "/data/app/libgame.so"), false),
+            (region("-w-p", "/data/app/libgame.so"), false),
+            (region("rw-p", "/data/app/libgame.so.bak"), false),
+            // Pseudo mappings and anonymous memory are never filtered by name
+            (region("rw-p", "[heap]"), true),
+            (region("rw-p", ""), true),
+        ];
+        for (region, expected) in cases {
+            assert_eq!(is_source_region(&region, &options), expected, "{}", region);
+        }
+
+        // Excluded modules are not pointer targets either; adjacent target regions are merged
+        let regions = [
+            json!({"start_address": "1000", "end_address": "2000", "protection": "r--p", "file_path": "[heap]"}),
+            json!({"start_address": "2000", "end_address": "3000", "protection": "rw-p", "file_path": "/lib/x.bak"}),
+            json!({"start_address": "3000", "end_address": "4000", "protection": "rw-p", "file_path": ""}),
+            json!({"start_address": "4000", "end_address": "5000", "protection": "rw-p", "file_path": ""}),
+        ];
+        assert_eq!(
+            collect_target_ranges(&regions, &options),
+            vec![(0x1000, 0x2000), (0x3000, 0x5000)]
+        );
+    }
+
+    #[test]
+    fn object_graph_dot_rendering() {
+        let graph = json!({
+            "root": 0x1000,
+            "nodes": [
+                {"address": 0x1000, "static": "\"libgame.so\"+0x10", "file_path": ""},
+                {"address": 0x2000, "static": null, "file_path": "C:\\data\\heap"},
+                {"address": 0x3000, "static": null, "file_path": ""}
+            ],
+            "edges": [
+                {"from": 0x1000, "to": 0x2000, "offset": 0x18},
+                {"from": 0x2000, "to": 0x3000, "offset": 0}
+            ]
+        });
+        let dot = object_graph_to_dot(&graph);
+        let lines: Vec<&str> = dot.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "digraph objects {",
+                "    node [shape=box, fontname=\"monospace\"];",
+                r#"    "0x1000" [label="0x1000\n\"libgame.so\"+0x10", style=bold];"#,
+                r#"    "0x2000" [label="0x2000\nC:\\data\\heap"];"#,
+                r#"    "0x3000" [label="0x3000"];"#,
+                r#"    "0x1000" -> "0x2000" [label="+0x18"];"#,
+                r#"    "0x2000" -> "0x3000" [label="+0x0"];"#,
+                "}",
+            ]
+        );
+    }
+
+    #[test]
+    fn object_graph_stops_at_max_nodes() {
+        // A linked list in this process: the first field of each entry points at the next
+        let mut list = [[0u64; 2]; 6];
+        let base = list.as_ptr() as u64;
+        for (index, entry) in list.iter_mut().enumerate().take(5) {
+            entry[0] = base + 16 * (index as u64 + 1);
+        }
+        let request = ObjectGraphRequest {
+            address: base,
+            depth: 10,
+            struct_size: 16,
+            max_nodes: 3,
+            alignment: 8,
+            follow_readonly: false,
+            format: String::new(),
+        };
+
+        let graph = explore_object_graph(std::process::id() as i32, &request).unwrap();
+        let nodes: Vec<u64> = graph["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|node| node["address"].as_u64().unwrap())
+            .collect();
+        assert_eq!(nodes, vec![base, base + 16, base + 32]);
+        assert_eq!(graph["edges"].as_array().unwrap().len(), 2);
+        assert_eq!(graph["truncated"], json!(true));
+    }
 }
@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
 
 #[derive(Deserialize)]
 pub struct OpenProcessRequest {
@@ -16,6 +18,124 @@ pub struct ResolveAddrRequest {
     pub query: String,
 }
 
+#[derive(Deserialize)]
+pub struct SymbolSearchRequest {
+    pub query: String,
+    pub module: Option<String>,
+    #[serde(default = "default_symbol_limit")]
+    pub limit: usize,
+}
+
+#[derive(Deserialize)]
+pub struct DisassembleRequest {
+    pub address: Option<u64>,
+    pub expression: Option<String>,
+    pub count: Option<usize>,
+    pub length: Option<usize>,
+    pub arch: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct XrefRequest {
+    pub address: Option<u64>,
+    pub expression: Option<String>,
+    pub module: Option<String>,
+    pub arch: Option<String>,
+    #[serde(default = "default_xref_limit")]
+    pub limit: usize,
+}
+
+fn default_xref_limit() -> usize {
+    1000
+}
+
+#[derive(Deserialize)]
+pub struct SignatureRequest {
+    pub address: Option<u64>,
+    pub expression: Option<String>,
+    pub arch: Option<String>,
+    pub max_length: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct SignatureDefinition {
+    pub name: String,
+    pub module: String,
+    pub pattern: String,
+    #[serde(default)]
+    pub offset: i64,
+    // "rel32" or "adrp": resolve to the address referenced by the instruction at the match
+    pub follow: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SignatureDatabaseRequest {
+    pub signatures: Vec<SignatureDefinition>,
+    #[serde(default)]
+    pub replace: bool,
+}
+
+#[derive(Deserialize)]
+pub struct SignatureRemoveRequest {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct PatchCreateRequest {
+    pub name: String,
+    pub address: Option<u64>,
+    pub expression: Option<String>,
+    // Hex encoded replacement bytes, or `;` separated instructions in `assembly`
+    pub bytes: Option<String>,
+    pub assembly: Option<String>,
+    pub arch: Option<String>,
+    // Hex encoded bytes expected at the address before patching
+    pub original: Option<String>,
+    #[serde(default = "default_patch_enabled")]
+    pub enabled: bool,
+}
+
+fn default_patch_enabled() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+pub struct PatchToggleRequest {
+    pub name: String,
+    pub enabled: bool,
+}
+
+#[derive(Deserialize)]
+pub struct PatchRemoveRequest {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct HookInstallRequest {
+    pub name: String,
+    pub address: Option<u64>,
+    pub expression: Option<String>,
+    // Hex encoded payload, or `;` separated instructions in `assembly`; the overwritten
+    // instructions run after it
+    pub bytes: Option<String>,
+    pub assembly: Option<String>,
+    pub arch: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct HookRemoveRequest {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct AddressInfoRequest {
+    pub addresses: Vec<u64>,
+}
+
+fn default_symbol_limit() -> usize {
+    1000
+}
+
 #[derive(Deserialize)]
 pub struct WriteMemoryRequest {
     pub address: usize,
@@ -32,6 +152,8 @@ pub struct MemoryScanRequest {
     pub align: usize,
     pub return_as_json: bool,
     pub do_suspend: bool,
+    // With do_suspend, hold only these threads instead of stopping the whole process
+    pub suspend_threads: Option<Vec<i32>>,
 }
 
 #[derive(Deserialize)]
@@ -42,6 +164,7 @@ pub struct MemoryFilterRequest {
     pub filter_method: String,
     pub return_as_json: bool,
     pub do_suspend: bool,
+    pub suspend_threads: Option<Vec<i32>>,
 }
 
 #[derive(Deserialize)]
@@ -60,6 +183,12 @@ pub struct SetWatchPointRequest {
     pub address: usize,
     pub size: usize,
     pub _type: String,
+    // Fold hits per instruction instead of queueing each one as an exception
+    #[serde(default)]
+    pub aggregate: bool,
+    // Halt the accessing thread for the debugger session
+    #[serde(default)]
+    pub stop: bool,
 }
 
 #[derive(Serialize)]
@@ -68,6 +197,26 @@ pub struct SetWatchPointResponse {
     pub message: String,
 }
 
+#[derive(Deserialize)]
+pub struct WatchAccessesRequest {
+    pub address: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct ClearWatchAccessesRequest {
+    pub address: u64,
+}
+
+#[derive(Deserialize)]
+pub struct EventStreamRequest {
+    // Comma separated event kinds, e.g. "exception,process_exit"; all kinds when absent
+    pub types: Option<String>,
+    // Events buffered for this client before the oldest are dropped
+    pub capacity: Option<usize>,
+    // Attach the call stack of the stopped thread to exception events
+    pub backtrace: Option<bool>,
+}
+
 #[derive(Deserialize)]
 pub struct RemoveWatchPointRequest {
     pub address: usize,
@@ -83,6 +232,33 @@ pub struct RemoveWatchPointResponse {
 pub struct SetBreakPointRequest {
     pub address: usize,
     pub hit_count: i32,
+    // Halt the hitting thread for the debugger session instead of tracing it
+    #[serde(default)]
+    pub stop: bool,
+    // Only hits for which the expression is nonzero count; hit_count is then ignored and the
+    // breakpoint stays until removed
+    pub condition: Option<String>,
+    // Record registers and expressions at every counted hit and continue, unless stop is set
+    pub trace: Option<BreakpointTraceRequest>,
+}
+
+#[derive(Deserialize)]
+pub struct BreakpointTraceRequest {
+    #[serde(default)]
+    pub registers: Vec<String>,
+    #[serde(default)]
+    pub expressions: Vec<String>,
+    pub capacity: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct BreakpointTraceQuery {
+    pub address: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct ClearBreakpointTraceRequest {
+    pub address: u64,
 }
 
 #[derive(Serialize)]
@@ -102,6 +278,66 @@ pub struct RemoveBreakPointResponse {
     pub message: String,
 }
 
+#[derive(Deserialize)]
+pub struct ThreadRequest {
+    pub tid: i32,
+}
+
+#[derive(Deserialize)]
+pub struct DebuggerContinueRequest {
+    pub tid: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct DebuggerStepRequest {
+    pub tid: i32,
+    pub mode: String,
+}
+
+#[derive(Deserialize)]
+pub struct DebuggerRegistersRequest {
+    pub tid: i32,
+}
+
+#[derive(Deserialize)]
+pub struct DebuggerBacktraceRequest {
+    pub tid: i32,
+    pub max_frames: Option<usize>,
+    // "auto" (eh_frame, then frame pointers) or "frame_pointer"
+    pub method: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DebuggerTraceRequest {
+    pub tid: i32,
+    pub max_instructions: Option<usize>,
+    // Stop before the instruction at this address executes
+    pub until: Option<u64>,
+    // Stop once the current function has returned to its caller
+    pub until_return: Option<bool>,
+    // Record the data of memory reads and writes, on unless false
+    pub memory: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct DebuggerTraceQuery {
+    pub id: Option<u64>,
+    // "json", "tenet" or "drcov"
+    pub format: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteDebuggerTraceRequest {
+    pub id: u64,
+}
+
+#[derive(Deserialize)]
+pub struct SetDebuggerRegistersRequest {
+    pub tid: i32,
+    // Register name to a number or a hex/decimal string, for values wider than JSON numbers
+    pub registers: BTreeMap<String, Value>,
+}
+
 #[derive(Deserialize)]
 pub struct ChangeProcessStateRequest {
     pub do_play: bool,
@@ -116,4 +352,98 @@ pub struct ChangeProcessStateResponse {
 #[derive(Deserialize)]
 pub struct PointerMapGenerateRequest {
     pub address: u64,
+    #[serde(flatten)]
+    pub options: PointerMapOptions,
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+pub struct PointerMapOptions {
+    #[serde(default)]
+    pub writable_only: bool,
+    #[serde(default)]
+    pub include_modules: Vec<String>,
+    #[serde(default)]
+    pub exclude_modules: Vec<String>,
+    #[serde(default = "default_pointer_alignment")]
+    pub alignment: u64,
+    #[serde(default)]
+    pub require_readable_target: bool,
+}
+
+#[derive(Deserialize)]
+pub struct PointerReferrersRequest {
+    pub address: u64,
+    #[serde(default)]
+    pub max_offset: u64,
+    #[serde(default)]
+    pub use_cache: bool,
+    #[serde(default)]
+    pub refresh_cache: bool,
+    #[serde(flatten)]
+    pub options: PointerMapOptions,
+}
+
+#[derive(Deserialize)]
+pub struct PointerMapLoadRequest {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct PointerMapUnloadRequest {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct PointerMapLookupRequest {
+    pub name: String,
+    pub address: u64,
+}
+
+#[derive(Deserialize)]
+pub struct ObjectGraphRequest {
+    pub address: u64,
+    #[serde(default = "default_graph_depth")]
+    pub depth: u32,
+    #[serde(default = "default_struct_size")]
+    pub struct_size: usize,
+    #[serde(default = "default_graph_max_nodes")]
+    pub max_nodes: usize,
+    #[serde(default = "default_graph_alignment")]
+    pub alignment: u64,
+    #[serde(default)]
+    pub follow_readonly: bool,
+    #[serde(default)]
+    pub format: String, // "json" or "dot"
+}
+
+fn default_graph_depth() -> u32 {
+    3
+}
+
+fn default_struct_size() -> usize {
+    0x100
+}
+
+fn default_graph_max_nodes() -> usize {
+    1000
+}
+
+fn default_graph_alignment() -> u64 {
+    8
+}
+
+fn default_pointer_alignment() -> u64 {
+    4
+}
+
+impl Default for PointerMapOptions {
+    fn default() -> Self {
+        PointerMapOptions {
+            writable_only: false,
+            include_modules: Vec::new(),
+            exclude_modules: Vec::new(),
+            alignment: default_pointer_alignment(),
+            require_readable_target: false,
+        }
+    }
 }
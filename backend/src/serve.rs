@@ -7,6 +7,8 @@ use warp::path::Tail;
 use warp::Filter;
 
 use crate::api;
+use crate::events;
+use crate::hook;
 use crate::logger;
 use crate::native_bridge;
 use crate::request;
@@ -94,6 +96,117 @@ pub async fn serve(mode: i32, host: IpAddr, port: u16) {
             api::resolve_addr_handler(pid_state, resolve_addr_request).await
         });
 
+    let symbol_search = warp::path!("symbols")
+        .and(warp::get())
+        .and(warp::query::<request::SymbolSearchRequest>())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|request, pid_state| async move {
+            api::symbol_search_handler(pid_state, request).await
+        });
+
+    let address_info = warp::path!("addressinfo")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|request, pid_state| async move {
+            api::address_info_handler(pid_state, request).await
+        });
+
+    let disassemble = warp::path!("disassemble")
+        .and(warp::get())
+        .and(warp::query::<request::DisassembleRequest>())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|request, pid_state| async move {
+            api::disassemble_handler(pid_state, request).await
+        });
+
+    let xrefs = warp::path!("xrefs")
+        .and(warp::get())
+        .and(warp::query::<request::XrefRequest>())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|request, pid_state| async move { api::xref_handler(pid_state, request).await });
+
+    let signature = warp::path!("signature")
+        .and(warp::get())
+        .and(warp::query::<request::SignatureRequest>())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(
+            |request, pid_state| async move { api::signature_handler(pid_state, request).await },
+        );
+
+    let signature_list = warp::path!("signatures")
+        .and(warp::get())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(api::signature_list_handler);
+
+    let signature_add = warp::path!("signatures")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|request, pid_state| async move {
+            api::signature_add_handler(pid_state, request).await
+        });
+
+    let signature_remove = warp::path!("signatures")
+        .and(warp::delete())
+        .and(warp::body::json())
+        .and_then(api::signature_remove_handler);
+
+    let signature_diagnostics = warp::path!("signatures" / "diagnostics")
+        .and(warp::get())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(api::signature_diagnostics_handler);
+
+    let patch_list = warp::path!("patches")
+        .and(warp::get())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(api::patch_list_handler);
+
+    let patch_create = warp::path!("patches")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|request, pid_state| async move {
+            api::patch_create_handler(pid_state, request).await
+        });
+
+    let patch_toggle = warp::path!("patches")
+        .and(warp::put())
+        .and(warp::body::json())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|request, pid_state| async move {
+            api::patch_toggle_handler(pid_state, request).await
+        });
+
+    let patch_remove = warp::path!("patches")
+        .and(warp::delete())
+        .and(warp::body::json())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|request, pid_state| async move {
+            api::patch_remove_handler(pid_state, request).await
+        });
+
+    let hook_list = warp::path!("hooks")
+        .and(warp::get())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(api::hook_list_handler);
+
+    let hook_install = warp::path!("hooks")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|request, pid_state| async move {
+            api::hook_install_handler(pid_state, request).await
+        });
+
+    let hook_remove = warp::path!("hooks")
+        .and(warp::delete())
+        .and(warp::body::json())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|request, pid_state| async move {
+            api::hook_remove_handler(pid_state, request).await
+        });
+
     let explore_directory = warp::path!("directory")
         .and(warp::get())
         .and(warp::query::<request::ExploreDirectoryRequest>())
@@ -133,6 +246,19 @@ pub async fn serve(mode: i32, host: IpAddr, port: u16) {
             api::remove_watchpoint_handler(pid_state, remove_watchpoint_request).await
         });
 
+    let watch_accesses = warp::path!("watchpoint" / "accesses")
+        .and(warp::get())
+        .and(warp::query::<request::WatchAccessesRequest>())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|request, pid_state| async move {
+            api::watch_accesses_handler(pid_state, request).await
+        });
+
+    let clear_watch_accesses = warp::path!("watchpoint" / "accesses")
+        .and(warp::delete())
+        .and(warp::body::json())
+        .and_then(api::clear_watch_accesses_handler);
+
     let set_breakpoint = warp::path!("breakpoint")
         .and(warp::post())
         .and(warp::body::json())
@@ -149,10 +275,103 @@ pub async fn serve(mode: i32, host: IpAddr, port: u16) {
             api::remove_breakpoint_handler(pid_state, remove_breakpoint_request).await
         });
 
+    let breakpoint_trace = warp::path!("breakpoint" / "trace")
+        .and(warp::get())
+        .and(warp::query::<request::BreakpointTraceQuery>())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|request, pid_state| async move {
+            api::breakpoint_trace_handler(pid_state, request).await
+        });
+
+    let clear_breakpoint_trace = warp::path!("breakpoint" / "trace")
+        .and(warp::delete())
+        .and(warp::body::json())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|request, pid_state| async move {
+            api::clear_breakpoint_trace_handler(pid_state, request).await
+        });
+
+    let list_threads = warp::path!("threads")
+        .and(warp::get())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|pid_state| async move { api::threads_handler(pid_state).await });
+
+    let suspend_thread = warp::path!("threads" / "suspend")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|request, pid_state| async move {
+            api::suspend_thread_handler(pid_state, request).await
+        });
+
+    let resume_thread = warp::path!("threads" / "resume")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and_then(api::resume_thread_handler);
+
+    let debugger_threads = warp::path!("debugger" / "threads")
+        .and(warp::get())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|pid_state| async move { api::debugger_threads_handler(pid_state).await });
+
+    let debugger_continue = warp::path!("debugger" / "continue")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and_then(api::debugger_continue_handler);
+
+    let debugger_step = warp::path!("debugger" / "step")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|request, pid_state| async move {
+            api::debugger_step_handler(pid_state, request).await
+        });
+
+    let debugger_registers = warp::path!("debugger" / "registers")
+        .and(warp::get())
+        .and(warp::query::<request::DebuggerRegistersRequest>())
+        .and_then(api::debugger_registers_handler);
+
+    let debugger_backtrace = warp::path!("debugger" / "backtrace")
+        .and(warp::get())
+        .and(warp::query::<request::DebuggerBacktraceRequest>())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|request, pid_state| async move {
+            api::debugger_backtrace_handler(pid_state, request).await
+        });
+
+    let debugger_trace = warp::path!("debugger" / "trace")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|request, pid_state| async move {
+            api::debugger_trace_handler(pid_state, request).await
+        });
+
+    let get_debugger_trace = warp::path!("debugger" / "trace")
+        .and(warp::get())
+        .and(warp::query::<request::DebuggerTraceQuery>())
+        .and_then(api::get_debugger_trace_handler);
+
+    let delete_debugger_trace = warp::path!("debugger" / "trace")
+        .and(warp::delete())
+        .and(warp::body::json())
+        .and_then(api::delete_debugger_trace_handler);
+
+    let set_debugger_registers = warp::path!("debugger" / "registers")
+        .and(warp::put())
+        .and(warp::body::json())
+        .and_then(api::set_debugger_registers_handler);
+
     let get_exception_info = warp::path!("exceptioninfo")
         .and(warp::get())
         .and_then(api::get_exception_info_handler);
 
+    let event_stream = warp::path!("events")
+        .and(warp::get())
+        .and(warp::query::<request::EventStreamRequest>())
+        .and_then(api::event_stream_handler);
+
     let change_process_state = warp::path!("process")
         .and(warp::put())
         .and(warp::body::json())
@@ -169,6 +388,85 @@ pub async fn serve(mode: i32, host: IpAddr, port: u16) {
             api::pointermap_generate_handler(pid_state, request).await
         });
 
+    let object_graph = warp::path!("objectgraph")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|request, pid_state| async move {
+            api::object_graph_handler(pid_state, request).await
+        });
+
+    let pointermap_load = warp::path!("pointermap" / "load")
+        .and(warp::post())
+        .and(warp::query::<request::PointerMapLoadRequest>())
+        .and(warp::body::content_length_limit(1024 * 1024 * 1024)) // 1GB
+        .and(warp::body::bytes())
+        .and_then(|request, body| async move { api::pointermap_load_handler(request, body).await });
+
+    let pointermap_loaded = warp::path!("pointermap" / "loaded")
+        .and(warp::get())
+        .and_then(api::pointermap_loaded_handler);
+
+    let pointermap_unload = warp::path!("pointermap" / "loaded")
+        .and(warp::delete())
+        .and(warp::body::json())
+        .and_then(api::pointermap_unload_handler);
+
+    let pointermap_lookup = warp::path!("pointermap" / "lookup")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and_then(api::pointermap_lookup_handler);
+
+    let pointer_referrers = warp::path!("referrers")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(api::with_state(pid_state.clone()))
+        .and_then(|request, pid_state| async move {
+            api::pointer_referrers_handler(pid_state, request).await
+        });
+
+    let signatures = signature_list
+        .or(signature_add)
+        .or(signature_remove)
+        .or(signature_diagnostics);
+
+    let patches = patch_list
+        .or(patch_create)
+        .or(patch_toggle)
+        .or(patch_remove);
+
+    let hooks = hook_list.or(hook_install).or(hook_remove);
+
+    let events = get_exception_info.or(event_stream);
+
+    let threads = list_threads.or(suspend_thread).or(resume_thread);
+
+    let breakpoints = set_breakpoint
+        .or(remove_breakpoint)
+        .or(breakpoint_trace)
+        .or(clear_breakpoint_trace);
+
+    let watchpoints = set_watchpoint
+        .or(remove_watchpoint)
+        .or(watch_accesses)
+        .or(clear_watch_accesses);
+
+    let debugger = debugger_threads
+        .or(debugger_continue)
+        .or(debugger_step)
+        .or(debugger_registers)
+        .or(set_debugger_registers)
+        .or(debugger_backtrace)
+        .or(debugger_trace)
+        .or(get_debugger_trace)
+        .or(delete_debugger_trace);
+
+    let pointermaps = pointermap_generate
+        .or(pointermap_load)
+        .or(pointermap_loaded)
+        .or(pointermap_unload)
+        .or(pointermap_lookup);
+
     let routes = open_process
         .or(read_memory)
         .or(read_memory_multiple)
@@ -179,23 +477,67 @@ pub async fn serve(mode: i32, host: IpAddr, port: u16) {
         .or(enum_process)
         .or(enum_module)
         .or(resolve_addr)
+        .or(symbol_search)
+        .or(address_info)
+        .or(disassemble)
+        .or(xrefs)
+        .or(signature)
+        .or(signatures)
+        .or(patches)
+        .or(hooks)
         .or(explore_directory)
         .or(read_file)
         .or(get_app_info)
         .or(server_info)
-        .or(set_watchpoint)
-        .or(remove_watchpoint)
-        .or(set_breakpoint)
-        .or(remove_breakpoint)
-        .or(get_exception_info)
+        .or(watchpoints)
+        .or(breakpoints)
+        .or(debugger)
+        .or(threads)
+        .or(events)
         .or(change_process_state)
-        .or(pointermap_generate)
+        .or(pointermaps)
+        .or(pointer_referrers)
+        .or(object_graph)
         .or(static_files)
         .with(cors)
         .with(warp::log::custom(logger::http_log));
 
     native_bridge::native_api_init(mode);
-    warp::serve(routes).run((host, port)).await;
+    if mode == 0 {
+        let (_, server) = warp::serve(routes).bind_with_graceful_shutdown((host, port), async {
+            shutdown_signal().await;
+            events::close_all();
+        });
+        server.await;
+        // Hooked processes outlive the server; leave none of them jumping into its caves
+        hook::remove_all_hooks();
+        native_bridge::free_debugger();
+    } else {
+        // Embedded in the target process, whose signal handling must not be taken over
+        warp::serve(routes).run((host, port)).await;
+    }
+}
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
 }
 
 static STATIC_DIR: Dir = include_dir!("../frontend/out");
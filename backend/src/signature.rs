@@ -0,0 +1,830 @@
+use crate::disasm::{self, Arch};
+use crate::native_bridge;
+use crate::request::{SignatureDefinition, SignatureRequest};
+use crate::util;
+use crate::xref;
+use capstone::arch::ArchOperand;
+use capstone::prelude::*;
+use lazy_static::lazy_static;
+use memchr::memmem;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::RwLock;
+
+const DEFAULT_SIGNATURE_LENGTH: usize = 64;
+const MAX_SIGNATURE_LENGTH: usize = 256;
+// How far before the target a signature may start
+const MAX_LOOKBEHIND: u64 = 64;
+const MAX_START_CANDIDATES: usize = 16;
+// Fixed bytes required before the first full scan, keeps the initial match list small
+const MIN_FIXED_BYTES: usize = 4;
+// Matches listed for an ambiguous database signature
+const MAX_REPORTED_MATCHES: usize = 16;
+
+lazy_static! {
+    static ref SIGNATURE_DATABASE: RwLock<BTreeMap<String, SignatureDefinition>> =
+        RwLock::new(BTreeMap::new());
+    // Resolutions for the process they were computed against
+    static ref RESOLVED_SIGNATURES: RwLock<Option<(i32, HashMap<String, Resolution>)>> =
+        RwLock::new(None);
+}
+
+// Byte pattern where `None` is a wildcard, written as `48 8B 05 ?? ?? ?? ??`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    bytes: Vec<Option<u8>>,
+}
+
+impl Pattern {
+    pub fn parse(text: &str) -> Result<Pattern, String> {
+        let compact: String;
+        let tokens: Vec<&str> = if text.trim().contains(char::is_whitespace) {
+            text.split_whitespace().collect()
+        } else {
+            compact = text.trim().to_string();
+            if !compact.len().is_multiple_of(2) {
+                return Err(format!("Pattern '{}' has an odd number of digits", text));
+            }
+            (0..compact.len())
+                .step_by(2)
+                .map(|i| &compact[i..i + 2])
+                .collect()
+        };
+
+        let bytes = tokens
+            .iter()
+            .map(|token| match *token {
+                "?" | "??" => Ok(None),
+                _ if token.len() != 2 => Err(format!("Invalid pattern byte '{}'", token)),
+                _ => u8::from_str_radix(token, 16)
+                    .map(Some)
+                    .map_err(|_| format!("Invalid pattern byte '{}'", token)),
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if bytes.iter().all(Option::is_none) {
+            return Err("Pattern needs at least one fixed byte".to_string());
+        }
+        Ok(Pattern { bytes })
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn fixed_count(&self) -> usize {
+        self.bytes.iter().filter(|byte| byte.is_some()).count()
+    }
+
+    fn matches_at(&self, data: &[u8], offset: usize) -> bool {
+        data.get(offset..offset + self.bytes.len())
+            .map(|window| {
+                window
+                    .iter()
+                    .zip(&self.bytes)
+                    .all(|(byte, expected)| expected.is_none_or(|expected| *byte == expected))
+            })
+            .unwrap_or(false)
+    }
+
+    // Longest run of fixed bytes, searched with memmem before checking the whole pattern
+    fn anchor(&self) -> (usize, Vec<u8>) {
+        let mut best = (0, Vec::new());
+        let mut start = 0;
+        for (i, byte) in self.bytes.iter().enumerate() {
+            if byte.is_none() {
+                start = i + 1;
+            } else if i + 1 - start > best.1.len() {
+                best = (
+                    start,
+                    self.bytes[start..=i].iter().map(|b| b.unwrap()).collect(),
+                );
+            }
+        }
+        best
+    }
+
+    // Offsets of every match in `data`, stopping after `limit`
+    pub fn find_all(&self, data: &[u8], limit: usize) -> Vec<usize> {
+        let (anchor_offset, anchor) = self.anchor();
+        memmem::find_iter(data, &anchor)
+            .filter_map(|position| position.checked_sub(anchor_offset))
+            .filter(|&offset| self.matches_at(data, offset))
+            .take(limit)
+            .collect()
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tokens: Vec<String> = self
+            .bytes
+            .iter()
+            .map(|byte| match byte {
+                Some(byte) => format!("{:02X}", byte),
+                None => "??".to_string(),
+            })
+            .collect();
+        write!(f, "{}", tokens.join(" "))
+    }
+}
+
+// Readable segments of a loaded module, the search space for signature uniqueness
+pub struct ModuleImage {
+    pub name: String,
+    pub base: u64,
+    pub size: u64,
+    segments: Vec<(u64, Vec<u8>)>,
+}
+
+impl ModuleImage {
+    pub fn read(pid: i32, module: &Value) -> ModuleImage {
+        let base = module["base"].as_u64().unwrap_or(0);
+        let size = module["size"].as_u64().unwrap_or(0);
+        let mut ranges: Vec<(u64, u64)> = module["segments"]
+            .as_array()
+            .map(|segments| {
+                segments
+                    .iter()
+                    .filter(|segment| segment["protection"].as_str().unwrap_or("").contains('r'))
+                    .filter_map(|segment| {
+                        Some((segment["start"].as_u64()?, segment["end"].as_u64()?))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        if ranges.is_empty() {
+            ranges.push((base, base + size));
+        }
+
+        let segments = ranges
+            .iter()
+            .map(|&(start, end)| (start, disasm::read_code(pid, start, (end - start) as usize)))
+            .filter(|(_, bytes)| !bytes.is_empty())
+            .collect();
+        ModuleImage {
+            name: module["modulename"].as_str().unwrap_or("").to_string(),
+            base,
+            size,
+            segments,
+        }
+    }
+
+    pub fn contains(&self, address: u64) -> bool {
+        address >= self.base && address - self.base < self.size
+    }
+
+    pub fn find_all(&self, pattern: &Pattern, limit: usize) -> Vec<u64> {
+        let mut matches = Vec::new();
+        for (start, bytes) in &self.segments {
+            let remaining = limit - matches.len();
+            matches.extend(
+                pattern
+                    .find_all(bytes, remaining)
+                    .into_iter()
+                    .map(|offset| start + offset as u64),
+            );
+            if matches.len() >= limit {
+                break;
+            }
+        }
+        matches
+    }
+
+    fn matches_at(&self, pattern: &Pattern, address: u64) -> bool {
+        self.segments.iter().any(|(start, bytes)| {
+            address >= *start
+                && address - start < bytes.len() as u64
+                && pattern.matches_at(bytes, (address - start) as usize)
+        })
+    }
+}
+
+// Location of a little-endian value inside an x86 encoding; the displacement precedes an
+// immediate of 0, 1, 2 or 4 bytes at the end of the instruction
+pub fn locate_x86_field(bytes: &[u8], value: &[u8]) -> Option<usize> {
+    [0, 1, 2, 4].iter().find_map(|&trailing| {
+        let position = bytes.len().checked_sub(value.len() + trailing)?;
+        (position > 0 && bytes[position..position + value.len()] == *value).then_some(position)
+    })
+}
+
+// Byte ranges of one instruction that are likely to change between builds: rel32 branch
+// offsets, RIP-relative and 32-bit displacements, and immediates that point into the module
+fn x86_wildcards(
+    insn: &capstone::Insn,
+    operands: &[ArchOperand],
+    module: &Range<u64>,
+) -> Vec<Range<usize>> {
+    let bytes = insn.bytes();
+    let next = insn.address() + bytes.len() as u64;
+    let mut wildcards = Vec::new();
+
+    for operand in operands {
+        let ArchOperand::X86Operand(op) = operand else {
+            continue;
+        };
+        match &op.op_type {
+            arch::x86::X86OperandType::Imm(value) => {
+                let value = *value as u64;
+                if bytes.len() >= 5 {
+                    let tail = &bytes[bytes.len() - 4..];
+                    let rel = i32::from_le_bytes(tail.try_into().unwrap()) as i64 as u64;
+                    if next.wrapping_add(rel) == value {
+                        wildcards.push(bytes.len() - 4..bytes.len());
+                        continue;
+                    }
+                }
+                if module.contains(&value) {
+                    let field = locate_x86_field(bytes, &value.to_le_bytes())
+                        .map(|position| position..position + 8)
+                        .or_else(|| {
+                            locate_x86_field(bytes, &(value as u32).to_le_bytes())
+                                .map(|position| position..position + 4)
+                        });
+                    wildcards.extend(field);
+                }
+            }
+            arch::x86::X86OperandType::Mem(mem) => {
+                // RIP-relative operands always encode a 32-bit displacement
+                let disp = mem.disp();
+                let is_rip_relative = mem.base().0 as u32 == arch::x86::X86Reg::X86_REG_RIP;
+                if i8::try_from(disp).is_ok() && !is_rip_relative {
+                    continue;
+                }
+                let field = locate_x86_field(bytes, &(disp as i32).to_le_bytes())
+                    .map(|position| position..position + 4);
+                wildcards.extend(field);
+            }
+            _ => {}
+        }
+    }
+    wildcards
+}
+
+// ARM64 fields holding PC-relative offsets, plus the page offset of an ADD or load/store
+// whose base register was set by an earlier ADRP
+fn arm64_wildcard(word: u32, pc: u64, adrp_registers: &mut Vec<u32>) -> Option<Range<usize>> {
+    let is_adr = word & 0x1f00_0000 == 0x1000_0000;
+    let is_branch = word & 0x7c00_0000 == 0x1400_0000;
+    let base_register = (word >> 5) & 0x1f;
+    let is_add_imm = word & 0x7f80_0000 == 0x1100_0000;
+    let is_load_store_imm = word & 0x3b00_0000 == 0x3900_0000;
+
+    let wildcard = if is_adr || is_branch {
+        // ADR/ADRP keep immlo in the top byte, B/BL keep imm26 bits there
+        Some(0..4)
+    } else if xref::arm64_pc_relative(word, pc).is_some() {
+        Some(0..3)
+    } else if (is_add_imm || is_load_store_imm) && adrp_registers.contains(&base_register) {
+        // imm12 occupies bits 10..21
+        Some(1..3)
+    } else {
+        None
+    };
+
+    if word & 0x9f00_0000 == 0x9000_0000 {
+        adrp_registers.push(word & 0x1f);
+    }
+    wildcard
+}
+
+// Decodes from `start` and masks the operand bytes that are likely relocations or displacements
+fn build_pattern(
+    cs: &Capstone,
+    arch: Arch,
+    code: &[u8],
+    start: u64,
+    module: &Range<u64>,
+) -> Result<(Pattern, Vec<usize>), String> {
+    let instructions = cs
+        .disasm_all(code, start)
+        .map_err(|e| format!("Failed to disassemble: {}", e))?;
+
+    let mut bytes = Vec::new();
+    // Pattern length after each whole instruction
+    let mut boundaries = Vec::new();
+    let mut adrp_registers = Vec::new();
+    for insn in instructions.iter() {
+        let mut masked: Vec<Option<u8>> = insn.bytes().iter().copied().map(Some).collect();
+        let wildcards = match arch {
+            Arch::Arm64 => {
+                let word = u32::from_le_bytes(insn.bytes().try_into().unwrap());
+                arm64_wildcard(word, insn.address(), &mut adrp_registers)
+                    .into_iter()
+                    .collect()
+            }
+            _ => {
+                let operands = cs
+                    .insn_detail(insn)
+                    .map(|detail| detail.arch_detail().operands())
+                    .unwrap_or_default();
+                x86_wildcards(insn, &operands, module)
+            }
+        };
+        for range in wildcards {
+            masked[range].iter_mut().for_each(|byte| *byte = None);
+        }
+        bytes.extend(masked);
+        boundaries.push(bytes.len());
+    }
+    Ok((Pattern { bytes }, boundaries))
+}
+
+// Ends a function or pads between functions; a signature never reaches back across one
+fn is_function_boundary(mnemonic: &str) -> bool {
+    matches!(
+        mnemonic,
+        "ret" | "retn" | "jmp" | "int3" | "hlt" | "ud2" | "b" | "brk" | "udf"
+    ) || mnemonic.starts_with("nop")
+}
+
+// Instruction starts preceding the target, nearest first. On x86 the earliest offset whose
+// linear decode lands on the target is taken as the real instruction stream
+fn start_candidates(arch: Arch, pid: i32, target: u64) -> Vec<u64> {
+    let mut starts = vec![target];
+    let Some(window_start) = target.checked_sub(MAX_LOOKBEHIND) else {
+        return starts;
+    };
+    let window = disasm::read_code(pid, window_start, MAX_LOOKBEHIND as usize);
+    if window.len() != MAX_LOOKBEHIND as usize {
+        return starts;
+    }
+
+    let step = if arch == Arch::Arm64 { 4 } else { 1 };
+    let preceding = (0..window.len()).step_by(step).find_map(|offset| {
+        let address = window_start + offset as u64;
+        let instructions = disasm::decode(arch, &window[offset..], address, usize::MAX).ok()?;
+        let last = instructions.last()?;
+        (last.address + last.bytes.len() as u64 == target).then_some(instructions)
+    });
+
+    for insn in preceding.iter().flatten().rev() {
+        if is_function_boundary(&insn.mnemonic) || starts.len() == MAX_START_CANDIDATES {
+            break;
+        }
+        starts.push(insn.address);
+    }
+    starts
+}
+
+struct Signature {
+    start: u64,
+    pattern: Pattern,
+}
+
+// Shortest prefix of the pattern at `start` that matches nowhere else in the module
+fn shortest_unique(
+    image: &ModuleImage,
+    start: u64,
+    pattern: &Pattern,
+    max_length: usize,
+) -> Option<Pattern> {
+    let mut matches: Option<Vec<u64>> = None;
+    for length in 1..=pattern.len().min(max_length) {
+        let prefix = Pattern {
+            bytes: pattern.bytes[..length].to_vec(),
+        };
+        // A trailing wildcard never narrows the match set
+        if prefix.bytes[length - 1].is_none() {
+            continue;
+        }
+        matches = match matches {
+            Some(matches) => Some(
+                matches
+                    .into_iter()
+                    .filter(|&address| image.matches_at(&prefix, address))
+                    .collect(),
+            ),
+            None if prefix.fixed_count() >= MIN_FIXED_BYTES || length == pattern.len() => {
+                Some(image.find_all(&prefix, usize::MAX))
+            }
+            None => None,
+        };
+        if matches.as_deref() == Some(&[start]) {
+            return Some(prefix);
+        }
+    }
+    None
+}
+
+pub fn generate_signature(pid: i32, request: &SignatureRequest) -> Result<Value, String> {
+    let target = util::resolve_target(pid, request.address, request.expression.as_deref())?;
+    let modules = native_bridge::enum_modules(pid)?;
+    let module = modules
+        .iter()
+        .find(|module| {
+            let base = module["base"].as_u64().unwrap_or(0);
+            let size = module["size"].as_u64().unwrap_or(0);
+            target >= base && target - base < size
+        })
+        .ok_or_else(|| format!("0x{:x} is not inside a module", target))?;
+
+    let arch = match &request.arch {
+        Some(name) => Arch::parse(name)?,
+        None => disasm::detect_arch_in(pid, &modules, target),
+    };
+    if matches!(arch, Arch::Arm | Arch::Thumb) {
+        return Err(format!("Signatures are not supported for {}", arch.name()));
+    }
+    let max_length = request
+        .max_length
+        .unwrap_or(DEFAULT_SIGNATURE_LENGTH)
+        .min(MAX_SIGNATURE_LENGTH);
+
+    let image = ModuleImage::read(pid, module);
+    let module_range = image.base..image.base + image.size;
+    let cs = arch.capstone()?;
+
+    let mut best: Option<Signature> = None;
+    for start in start_candidates(arch, pid, target) {
+        if !image.contains(start) {
+            continue;
+        }
+        let code = disasm::read_code(pid, start, max_length + arch.max_instruction_size());
+        let (pattern, boundaries) = build_pattern(&cs, arch, &code, start, &module_range)?;
+        let usable = boundaries
+            .iter()
+            .copied()
+            .filter(|&boundary| boundary <= max_length)
+            .max()
+            .unwrap_or(0);
+        let limit = match &best {
+            Some(best) => usable.min(best.pattern.len() - 1),
+            None => usable,
+        };
+        if limit == 0 {
+            continue;
+        }
+        if let Some(pattern) = shortest_unique(&image, start, &pattern, limit) {
+            best = Some(Signature { start, pattern });
+        }
+    }
+
+    let signature = best.ok_or_else(|| {
+        format!(
+            "No unique signature within {} bytes of 0x{:x}",
+            max_length, target
+        )
+    })?;
+    Ok(json!({
+        "address": target,
+        "arch": arch.name(),
+        "module": image.name,
+        "module_base": image.base,
+        "signature": signature.pattern.to_string(),
+        "length": signature.pattern.len(),
+        "match_address": signature.start,
+        "match_module_offset": signature.start - image.base,
+        "offset": target - signature.start
+    }))
+}
+
+#[derive(Clone)]
+struct Resolution {
+    status: &'static str,
+    address: Option<u64>,
+    matches: Vec<u64>,
+    error: Option<String>,
+}
+
+impl Resolution {
+    fn failed(status: &'static str, error: String) -> Resolution {
+        Resolution {
+            status,
+            address: None,
+            matches: Vec::new(),
+            error: Some(error),
+        }
+    }
+
+    fn to_json(&self, definition: &SignatureDefinition) -> Value {
+        json!({
+            "name": definition.name,
+            "module": definition.module,
+            "pattern": definition.pattern,
+            "offset": definition.offset,
+            "follow": definition.follow,
+            "status": self.status,
+            "address": self.address,
+            "matches": self.matches,
+            "error": self.error
+        })
+    }
+}
+
+// Follows the PC-relative operand at `address` to the address it refers to
+fn follow_reference(
+    pid: i32,
+    modules: &[Value],
+    address: u64,
+    follow: &str,
+) -> Result<u64, String> {
+    let arch = disasm::detect_arch_in(pid, modules, address);
+    match follow {
+        "rel32" => {
+            let code = disasm::read_code(pid, address, arch.max_instruction_size());
+            let decoded = disasm::decode(arch, &code, address, 1)?;
+            decoded
+                .first()
+                .and_then(|insn| insn.branch_target.or(insn.memory_target))
+                .ok_or_else(|| format!("No PC-relative operand at 0x{:x}", address))
+        }
+        "adrp" => {
+            let code = disasm::read_code(pid, address, 4 * (xref::ADRP_PAIR_WINDOW + 1));
+            let cs = Arch::Arm64.capstone()?;
+            xref::arm64_adrp_pair(&cs, &code, address)
+                .map(|pair| pair.first_address())
+                .ok_or_else(|| format!("No ADRP at 0x{:x}", address))
+        }
+        _ => Err(format!("Unknown follow mode '{}'", follow)),
+    }
+}
+
+fn resolve_definition(
+    pid: i32,
+    modules: &[Value],
+    images: &mut HashMap<String, ModuleImage>,
+    definition: &SignatureDefinition,
+) -> Resolution {
+    let pattern = match Pattern::parse(&definition.pattern) {
+        Ok(pattern) => pattern,
+        Err(e) => return Resolution::failed("invalid", e),
+    };
+    let module = modules.iter().find(|module| {
+        let name = module["modulename"].as_str().unwrap_or("");
+        let file_name = Path::new(name)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        util::glob_match(&definition.module, &file_name)
+    });
+    let Some(module) = module else {
+        return Resolution::failed(
+            "module_not_loaded",
+            format!("Module {} is not loaded", definition.module),
+        );
+    };
+
+    let name = module["modulename"].as_str().unwrap_or("").to_string();
+    let image = images
+        .entry(name)
+        .or_insert_with(|| ModuleImage::read(pid, module));
+    let matches = image.find_all(&pattern, MAX_REPORTED_MATCHES);
+    match matches.as_slice() {
+        [] => Resolution::failed("not_found", "Pattern has no match".to_string()),
+        [found] => {
+            let address = found.wrapping_add(definition.offset as u64);
+            let address = match &definition.follow {
+                Some(follow) => match follow_reference(pid, modules, address, follow) {
+                    Ok(address) => address,
+                    Err(e) => return Resolution::failed("follow_failed", e),
+                },
+                None => address,
+            };
+            Resolution {
+                status: "resolved",
+                address: Some(address),
+                matches,
+                error: None,
+            }
+        }
+        _ => Resolution {
+            status: "ambiguous",
+            address: None,
+            error: Some(if matches.len() == MAX_REPORTED_MATCHES {
+                format!("Pattern matches at least {} locations", matches.len())
+            } else {
+                format!("Pattern matches {} locations", matches.len())
+            }),
+            matches,
+        },
+    }
+}
+
+// Resolves `definitions` against `pid` and merges the results into the resolution cache. The
+// results are dropped from the cache when another process was opened in the meantime
+fn resolve_definitions(pid: i32, definitions: &[SignatureDefinition]) -> Vec<(String, Resolution)> {
+    let modules = native_bridge::enum_modules(pid).unwrap_or_default();
+    let mut images = HashMap::new();
+    let resolved: Vec<(String, Resolution)> = definitions
+        .iter()
+        .map(|definition| {
+            let resolution = resolve_definition(pid, &modules, &mut images, definition);
+            (definition.name.clone(), resolution)
+        })
+        .collect();
+
+    let mut cache = RESOLVED_SIGNATURES.write().unwrap();
+    match cache.as_mut() {
+        Some((cached_pid, resolutions)) if *cached_pid == pid => {
+            resolutions.extend(resolved.iter().cloned());
+        }
+        Some(_) => {}
+        None => *cache = Some((pid, resolved.iter().cloned().collect())),
+    }
+    resolved
+}
+
+// Resolves every stored signature, called when a process is opened
+pub fn resolve_all(pid: i32) {
+    let definitions: Vec<SignatureDefinition> = SIGNATURE_DATABASE
+        .read()
+        .unwrap()
+        .values()
+        .cloned()
+        .collect();
+    *RESOLVED_SIGNATURES.write().unwrap() = Some((pid, HashMap::new()));
+    if !definitions.is_empty() {
+        resolve_definitions(pid, &definitions);
+    }
+}
+
+fn cached_resolution(pid: i32, name: &str) -> Option<Resolution> {
+    match RESOLVED_SIGNATURES.read().unwrap().as_ref() {
+        Some((cached_pid, resolutions)) if *cached_pid == pid => resolutions.get(name).cloned(),
+        _ => None,
+    }
+}
+
+// Address of a named signature for `sig:Name` expressions. Signatures whose module was not
+// loaded yet, or that were never resolved for this process, are retried on lookup
+pub fn lookup(pid: i32, name: &str) -> Option<u64> {
+    let definition = SIGNATURE_DATABASE.read().unwrap().get(name).cloned()?;
+    let resolution = match cached_resolution(pid, name) {
+        Some(resolution) if resolution.status != "module_not_loaded" => resolution,
+        _ => {
+            let (_, resolution) =
+                resolve_definitions(pid, std::slice::from_ref(&definition)).pop()?;
+            resolution
+        }
+    };
+    resolution.address
+}
+
+pub fn add_definitions(
+    pid: Option<i32>,
+    definitions: Vec<SignatureDefinition>,
+    replace: bool,
+) -> Result<Value, String> {
+    for definition in &definitions {
+        Pattern::parse(&definition.pattern)
+            .map_err(|e| format!("Signature {}: {}", definition.name, e))?;
+        if let Some(follow) = &definition.follow {
+            if follow != "rel32" && follow != "adrp" {
+                return Err(format!(
+                    "Signature {}: unknown follow mode '{}'",
+                    definition.name, follow
+                ));
+            }
+        }
+    }
+
+    {
+        let mut database = SIGNATURE_DATABASE.write().unwrap();
+        if replace {
+            database.clear();
+        }
+        for definition in &definitions {
+            database.insert(definition.name.clone(), definition.clone());
+        }
+    }
+    if let Some(pid) = pid {
+        if replace {
+            resolve_all(pid);
+        } else {
+            resolve_definitions(pid, &definitions);
+        }
+    }
+    Ok(list_signatures(pid))
+}
+
+pub fn remove_definition(name: &str) -> bool {
+    let removed = SIGNATURE_DATABASE.write().unwrap().remove(name).is_some();
+    if let Some((_, resolutions)) = RESOLVED_SIGNATURES.write().unwrap().as_mut() {
+        resolutions.remove(name);
+    }
+    removed
+}
+
+fn resolution_entries(pid: Option<i32>) -> Vec<Value> {
+    let pending = Resolution::failed("unresolved", "No process is open".to_string());
+    SIGNATURE_DATABASE
+        .read()
+        .unwrap()
+        .values()
+        .map(|definition| {
+            let resolution = pid
+                .and_then(|pid| cached_resolution(pid, &definition.name))
+                .unwrap_or_else(|| pending.clone());
+            resolution.to_json(definition)
+        })
+        .collect()
+}
+
+pub fn list_signatures(pid: Option<i32>) -> Value {
+    json!({ "pid": pid, "signatures": resolution_entries(pid) })
+}
+
+// Signatures that did not resolve to exactly one address for the open process
+pub fn diagnostics(pid: Option<i32>) -> Value {
+    let entries = resolution_entries(pid);
+    let total = entries.len();
+    let failed: Vec<Value> = entries
+        .into_iter()
+        .filter(|entry| entry["status"] != "resolved")
+        .collect();
+    json!({
+        "pid": pid,
+        "total": total,
+        "resolved": total - failed.len(),
+        "failed": failed
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_format_pattern() {
+        let pattern = Pattern::parse("48 8b 05 ?? ?? ? ?? c3").unwrap();
+        assert_eq!(pattern.to_string(), "48 8B 05 ?? ?? ?? ?? C3");
+        assert_eq!(Pattern::parse("488B05????????C3").unwrap(), pattern);
+        assert!(Pattern::parse("?? ??").is_err());
+        assert!(Pattern::parse("48 8").is_err());
+        assert!(Pattern::parse("48 zz").is_err());
+    }
+
+    #[test]
+    fn find_all_honours_wildcards() {
+        let data = [
+            0x90, 0x48, 0x8b, 0x05, 0x11, 0x22, 0x48, 0x8b, 0x0d, 0x33, 0x44,
+        ];
+        let pattern = Pattern::parse("48 8B ?? ?? 22").unwrap();
+        assert_eq!(pattern.find_all(&data, usize::MAX), vec![1]);
+        let pattern = Pattern::parse("48 8B ??").unwrap();
+        assert_eq!(pattern.find_all(&data, usize::MAX), vec![1, 6]);
+        assert_eq!(pattern.find_all(&data, 1), vec![1]);
+    }
+
+    #[test]
+    fn stale_resolutions_leave_the_cache_alone() {
+        let definition = SignatureDefinition {
+            name: "Stale".to_string(),
+            module: "game".to_string(),
+            pattern: "48 8".to_string(),
+            offset: 0,
+            follow: None,
+        };
+        let (opened, previous) = (i32::MAX - 1, i32::MAX - 2);
+        *RESOLVED_SIGNATURES.write().unwrap() = Some((opened, HashMap::new()));
+
+        let resolved = resolve_definitions(previous, std::slice::from_ref(&definition));
+        assert_eq!(resolved[0].1.status, "invalid");
+        assert!(cached_resolution(previous, "Stale").is_none());
+        assert!(cached_resolution(opened, "Stale").is_none());
+
+        resolve_definitions(opened, std::slice::from_ref(&definition));
+        assert!(cached_resolution(opened, "Stale").is_some());
+    }
+
+    #[test]
+    fn x86_64_masks_displacements_and_branches() {
+        // mov rax, [rip + 0x1000]; mov ecx, [rdi + 0x1a8]; mov edx, [rdi + 8];
+        // call 0x2000; mov rsi, 0x400080
+        let code = [
+            0x48, 0x8b, 0x05, 0x00, 0x10, 0x00, 0x00, 0x8b, 0x8f, 0xa8, 0x01, 0x00, 0x00, 0x8b,
+            0x57, 0x08, 0xe8, 0xeb, 0x1f, 0x00, 0x00, 0x48, 0xc7, 0xc6, 0x80, 0x00, 0x40, 0x00,
+        ];
+        let cs = Arch::X86_64.capstone().unwrap();
+        let (pattern, boundaries) =
+            build_pattern(&cs, Arch::X86_64, &code, 0, &(0x400000..0x500000)).unwrap();
+        assert_eq!(
+            pattern.to_string(),
+            "48 8B 05 ?? ?? ?? ?? 8B 8F ?? ?? ?? ?? 8B 57 08 E8 ?? ?? ?? ?? 48 C7 C6 ?? ?? ?? ??"
+        );
+        assert_eq!(boundaries, vec![7, 13, 16, 21, 28]);
+    }
+
+    #[test]
+    fn arm64_masks_pc_relative_fields() {
+        // adrp x0, #0x10000; ldr x1, [x0, #0x20]; bl #0x100; cbz x1, #0x20; ldr x2, [x3, #8]
+        let words: [u32; 5] = [
+            0x9000_0080,
+            0xf940_1001,
+            0x9400_003e,
+            0xb400_00a1,
+            0xf940_0462,
+        ];
+        let code: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        let cs = Arch::Arm64.capstone().unwrap();
+        let (pattern, _) = build_pattern(&cs, Arch::Arm64, &code, 0, &(0..0)).unwrap();
+        assert_eq!(
+            pattern.to_string(),
+            "?? ?? ?? ?? 01 ?? ?? F9 ?? ?? ?? ?? ?? ?? ?? B4 62 04 40 F9"
+        );
+    }
+}
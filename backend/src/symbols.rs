@@ -0,0 +1,704 @@
+use crate::disasm;
+use crate::expression;
+use crate::native_bridge;
+use crate::ptrscan;
+use crate::util;
+use lazy_static::lazy_static;
+use rayon::prelude::*;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_GNU_EH_FRAME: u32 = 0x6474_e550;
+const SHT_SYMTAB: u32 = 2;
+const SHT_DYNSYM: u32 = 11;
+const DT_NULL: u64 = 0;
+const DT_HASH: u64 = 4;
+const DT_STRTAB: u64 = 5;
+const DT_SYMTAB: u64 = 6;
+const DT_STRSZ: u64 = 10;
+const DT_GNU_HASH: u64 = 0x6fff_fef5;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+const STT_GNU_IFUNC: u8 = 10;
+const MAX_DYNAMIC_SYMBOLS: u64 = 1 << 20;
+// Sizes read from target memory are bounded before anything is allocated for them
+const MAX_HASH_WORDS: u64 = 1 << 20;
+const MAX_DYNAMIC_STRINGS: u64 = 16 << 20;
+
+lazy_static! {
+    // Keyed by (pid, module path, module base)
+    static ref SYMBOL_CACHE: Mutex<HashMap<(i32, String, u64), Arc<ModuleSymbols>>> =
+        Mutex::new(HashMap::new());
+    static ref LAYOUT_CACHE: Mutex<HashMap<i32, Arc<ProcessLayout>>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u64,
+    pub size: u64,
+    pub kind: &'static str,
+}
+
+#[derive(Default)]
+pub struct ModuleSymbols {
+    // Sorted by address
+    pub symbols: Vec<Symbol>,
+    by_name: HashMap<String, usize>,
+}
+
+impl ModuleSymbols {
+    fn new(mut symbols: Vec<Symbol>) -> Self {
+        symbols.sort_by(|a, b| a.address.cmp(&b.address).then(a.name.cmp(&b.name)));
+        symbols.dedup_by(|a, b| a.address == b.address && a.name == b.name);
+        let mut by_name = HashMap::new();
+        for (idx, symbol) in symbols.iter().enumerate() {
+            by_name.entry(symbol.name.clone()).or_insert(idx);
+        }
+        ModuleSymbols { symbols, by_name }
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Symbol> {
+        self.by_name.get(name).map(|&idx| &self.symbols[idx])
+    }
+
+    // Closest symbol at or below `address`
+    pub fn nearest(&self, address: u64) -> Option<&Symbol> {
+        let idx = self
+            .symbols
+            .partition_point(|symbol| symbol.address <= address);
+        idx.checked_sub(1).map(|idx| &self.symbols[idx])
+    }
+}
+
+struct ElfHeader {
+    is_64bit: bool,
+    phoff: u64,
+    phentsize: u64,
+    phnum: u64,
+    shoff: u64,
+    shentsize: u64,
+    shnum: u64,
+}
+
+struct ProgramHeader {
+    p_type: u32,
+    vaddr: u64,
+}
+
+struct SectionHeader {
+    sh_type: u32,
+    offset: u64,
+    size: u64,
+    link: u32,
+    entsize: u64,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u64 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap()) as u64
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u64 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as u64
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+// Reads `size` bytes at an offset (file) or address (memory)
+type Reader<'a> = dyn FnMut(u64, usize) -> Result<Vec<u8>, String> + 'a;
+
+fn parse_header(read: &mut Reader) -> Result<ElfHeader, String> {
+    let ident = read(0, 0x40)?;
+    if ident.len() < 0x34 || &ident[0..4] != b"\x7fELF" {
+        return Err("Not an ELF image".to_string());
+    }
+    if ident[5] != 1 {
+        return Err("Big-endian ELF is not supported".to_string());
+    }
+    match ident[4] {
+        2 if ident.len() >= 0x40 => Ok(ElfHeader {
+            is_64bit: true,
+            phoff: u64_at(&ident, 0x20),
+            shoff: u64_at(&ident, 0x28),
+            phentsize: u16_at(&ident, 0x36),
+            phnum: u16_at(&ident, 0x38),
+            shentsize: u16_at(&ident, 0x3a),
+            shnum: u16_at(&ident, 0x3c),
+        }),
+        1 => Ok(ElfHeader {
+            is_64bit: false,
+            phoff: u32_at(&ident, 0x1c),
+            shoff: u32_at(&ident, 0x20),
+            phentsize: u16_at(&ident, 0x2a),
+            phnum: u16_at(&ident, 0x2c),
+            shentsize: u16_at(&ident, 0x2e),
+            shnum: u16_at(&ident, 0x30),
+        }),
+        class => Err(format!("Unknown ELF class {}", class)),
+    }
+}
+
+fn parse_program_headers(
+    read: &mut Reader,
+    header: &ElfHeader,
+) -> Result<Vec<ProgramHeader>, String> {
+    let entsize = header.phentsize as usize;
+    let min_entsize = if header.is_64bit { 56 } else { 32 };
+    if entsize < min_entsize {
+        return Err("Invalid program header size".to_string());
+    }
+    let data = read(header.phoff, entsize * header.phnum as usize)?;
+    Ok(data
+        .chunks_exact(entsize)
+        .map(|ph| ProgramHeader {
+            p_type: u32_at(ph, 0) as u32,
+            vaddr: if header.is_64bit {
+                u64_at(ph, 0x10)
+            } else {
+                u32_at(ph, 0x08)
+            },
+        })
+        .collect())
+}
+
+// Difference between run-time and link-time addresses
+fn load_bias(base: u64, program_headers: &[ProgramHeader]) -> u64 {
+    let first_load = program_headers
+        .iter()
+        .filter(|ph| ph.p_type == PT_LOAD)
+        .map(|ph| ph.vaddr & !0xfff)
+        .min()
+        .unwrap_or(0);
+    base.wrapping_sub(first_load)
+}
+
+fn c_string(strtab: &[u8], offset: usize) -> Option<String> {
+    let bytes = strtab.get(offset..)?;
+    let end = bytes.iter().position(|&b| b == 0)?;
+    if end == 0 {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&bytes[..end]).into_owned())
+}
+
+fn parse_symbol_table(symtab: &[u8], strtab: &[u8], is_64bit: bool, bias: u64) -> Vec<Symbol> {
+    let entsize = if is_64bit { 24 } else { 16 };
+    symtab
+        .chunks_exact(entsize)
+        .filter_map(|sym| {
+            let (name, info, shndx, value, size) = if is_64bit {
+                (
+                    u32_at(sym, 0),
+                    sym[4],
+                    u16_at(sym, 6),
+                    u64_at(sym, 8),
+                    u64_at(sym, 16),
+                )
+            } else {
+                (
+                    u32_at(sym, 0),
+                    sym[12],
+                    u16_at(sym, 14),
+                    u32_at(sym, 4),
+                    u32_at(sym, 8),
+                )
+            };
+            let kind = match info & 0xf {
+                STT_FUNC => "function",
+                STT_OBJECT => "object",
+                STT_GNU_IFUNC => "ifunc",
+                _ => return None,
+            };
+            // Undefined symbols are imports resolved in another module
+            if shndx == 0 || value == 0 {
+                return None;
+            }
+            Some(Symbol {
+                name: c_string(strtab, name as usize)?,
+                address: bias.wrapping_add(value),
+                size,
+                kind,
+            })
+        })
+        .collect()
+}
+
+// .symtab and .dynsym from the section headers of the file on disk
+fn symbols_from_file(path: &str, base: u64) -> Result<Vec<Symbol>, String> {
+    let mut file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let file_size = file.metadata().map(|m| m.len()).unwrap_or(0);
+    let mut read = |offset: u64, size: usize| -> Result<Vec<u8>, String> {
+        if offset.saturating_add(size as u64) > file_size {
+            return Err(format!("Read past end of {}", path));
+        }
+        let mut buffer = vec![0u8; size];
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.read_exact(&mut buffer))
+            .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        Ok(buffer)
+    };
+
+    let header = parse_header(&mut read)?;
+    let bias = load_bias(base, &parse_program_headers(&mut read, &header)?);
+
+    let entsize = header.shentsize as usize;
+    let min_entsize = if header.is_64bit { 64 } else { 40 };
+    if header.shnum == 0 || entsize < min_entsize {
+        return Err("No section headers".to_string());
+    }
+    let data = read(header.shoff, entsize * header.shnum as usize)?;
+    let sections: Vec<SectionHeader> = data
+        .chunks_exact(entsize)
+        .map(|sh| {
+            if header.is_64bit {
+                SectionHeader {
+                    sh_type: u32_at(sh, 4) as u32,
+                    offset: u64_at(sh, 0x18),
+                    size: u64_at(sh, 0x20),
+                    link: u32_at(sh, 0x28) as u32,
+                    entsize: u64_at(sh, 0x38),
+                }
+            } else {
+                SectionHeader {
+                    sh_type: u32_at(sh, 4) as u32,
+                    offset: u32_at(sh, 0x10),
+                    size: u32_at(sh, 0x14),
+                    link: u32_at(sh, 0x18) as u32,
+                    entsize: u32_at(sh, 0x24),
+                }
+            }
+        })
+        .collect();
+
+    let mut symbols = Vec::new();
+    for section in &sections {
+        if section.sh_type != SHT_SYMTAB && section.sh_type != SHT_DYNSYM {
+            continue;
+        }
+        if section.entsize == 0 {
+            continue;
+        }
+        let strtab_section = match sections.get(section.link as usize) {
+            Some(strtab) => strtab,
+            None => continue,
+        };
+        let symtab = read(section.offset, section.size as usize)?;
+        let strtab = read(strtab_section.offset, strtab_section.size as usize)?;
+        symbols.extend(parse_symbol_table(&symtab, &strtab, header.is_64bit, bias));
+    }
+
+    if symbols.is_empty() {
+        return Err("No symbols in section headers".to_string());
+    }
+    Ok(symbols)
+}
+
+// Number of .dynsym entries, which the dynamic section only gives through the hash tables
+fn dynamic_symbol_count(
+    read: &mut Reader,
+    hash: Option<u64>,
+    gnu_hash: Option<u64>,
+    is_64bit: bool,
+) -> Result<u64, String> {
+    if let Some(hash) = hash {
+        return Ok(u32_at(&read(hash, 8)?, 4));
+    }
+    let gnu_hash = gnu_hash.ok_or("No hash table in dynamic section")?;
+    let table = read(gnu_hash, 16)?;
+    let (nbuckets, symoffset, bloom_size) =
+        (u32_at(&table, 0), u32_at(&table, 4), u32_at(&table, 8));
+    if nbuckets > MAX_HASH_WORDS || bloom_size > MAX_HASH_WORDS {
+        return Err(format!(
+            "Implausible DT_GNU_HASH table: {} buckets, {} bloom words",
+            nbuckets, bloom_size
+        ));
+    }
+    let buckets_address = gnu_hash + 16 + bloom_size * if is_64bit { 8 } else { 4 };
+    let buckets = read(buckets_address, (nbuckets * 4) as usize)?;
+    let max_bucket = (0..nbuckets as usize)
+        .map(|i| u32_at(&buckets, i * 4))
+        .max()
+        .unwrap_or(0);
+    if max_bucket < symoffset {
+        return Ok(symoffset);
+    }
+
+    let chain_address = buckets_address + nbuckets * 4;
+    let mut index = max_bucket;
+    loop {
+        let entry = u32_at(&read(chain_address + (index - symoffset) * 4, 4)?, 0);
+        if entry & 1 != 0 || index >= MAX_DYNAMIC_SYMBOLS {
+            return Ok(index + 1);
+        }
+        index += 1;
+    }
+}
+
+// Reads the mapped image; addresses below `base` are taken as offsets into it
+fn memory_reader(pid: i32, base: u64) -> impl FnMut(u64, usize) -> Result<Vec<u8>, String> {
+    move |address: u64, size: usize| -> Result<Vec<u8>, String> {
+        let address = if address < base {
+            base + address
+        } else {
+            address
+        };
+        let mut buffer = vec![0u8; size];
+        match native_bridge::read_process_memory(
+            pid,
+            address as *mut libc::c_void,
+            size,
+            &mut buffer,
+        ) {
+            Ok(n) if n as usize == size => Ok(buffer),
+            _ => Err(format!("Failed to read memory at 0x{:x}", address)),
+        }
+    }
+}
+
+// .dynsym through PT_DYNAMIC of the mapped image, for modules without a readable file
+fn symbols_from_memory(pid: i32, base: u64) -> Result<Vec<Symbol>, String> {
+    let mut read = memory_reader(pid, base);
+    let header = parse_header(&mut read)?;
+    let program_headers = parse_program_headers(&mut read, &header)?;
+    let bias = load_bias(base, &program_headers);
+    let dynamic = program_headers
+        .iter()
+        .find(|ph| ph.p_type == PT_DYNAMIC)
+        .ok_or("No dynamic segment")?;
+
+    // glibc relocates d_ptr entries in place while bionic leaves them link-time relative
+    let to_address = |ptr: u64| if ptr >= base { ptr } else { bias + ptr };
+
+    let entsize = if header.is_64bit { 16 } else { 8 };
+    let mut dynamic_address = bias + dynamic.vaddr;
+    let (mut hash, mut gnu_hash, mut symtab, mut strtab, mut strsz) = (None, None, None, None, 0);
+    loop {
+        let entry = read(dynamic_address, entsize)?;
+        let (tag, value) = if header.is_64bit {
+            (u64_at(&entry, 0), u64_at(&entry, 8))
+        } else {
+            (u32_at(&entry, 0), u32_at(&entry, 4))
+        };
+        match tag {
+            DT_NULL => break,
+            DT_HASH => hash = Some(to_address(value)),
+            DT_GNU_HASH => gnu_hash = Some(to_address(value)),
+            DT_SYMTAB => symtab = Some(to_address(value)),
+            DT_STRTAB => strtab = Some(to_address(value)),
+            DT_STRSZ => strsz = value,
+            _ => {}
+        }
+        dynamic_address += entsize as u64;
+    }
+
+    let symtab = symtab.ok_or("No DT_SYMTAB")?;
+    let strtab = strtab.ok_or("No DT_STRTAB")?;
+    let count =
+        dynamic_symbol_count(&mut read, hash, gnu_hash, header.is_64bit)?.min(MAX_DYNAMIC_SYMBOLS);
+    let symbol_size = if header.is_64bit { 24 } else { 16 };
+    let symtab = read(symtab, (count * symbol_size) as usize)?;
+    if strsz > MAX_DYNAMIC_STRINGS {
+        return Err(format!("Implausible DT_STRSZ of {} bytes", strsz));
+    }
+    let strtab = read(strtab, strsz as usize)?;
+    Ok(parse_symbol_table(&symtab, &strtab, header.is_64bit, bias))
+}
+
+// Run-time address of the module's .eh_frame_hdr, which the loader maps with PT_GNU_EH_FRAME
+pub fn eh_frame_hdr(pid: i32, base: u64) -> Result<u64, String> {
+    let mut read = memory_reader(pid, base);
+    let header = parse_header(&mut read)?;
+    let program_headers = parse_program_headers(&mut read, &header)?;
+    let bias = load_bias(base, &program_headers);
+    program_headers
+        .iter()
+        .find(|ph| ph.p_type == PT_GNU_EH_FRAME)
+        .map(|ph| bias.wrapping_add(ph.vaddr))
+        .ok_or_else(|| "No PT_GNU_EH_FRAME segment".to_string())
+}
+
+pub fn module_symbols(pid: i32, module_name: &str, base: u64) -> Arc<ModuleSymbols> {
+    let key = (pid, module_name.to_string(), base);
+    if let Some(symbols) = SYMBOL_CACHE.lock().unwrap().get(&key) {
+        return symbols.clone();
+    }
+
+    let symbols = match symbols_from_file(module_name, base) {
+        Ok(symbols) => ModuleSymbols::new(symbols),
+        Err(file_error) => match symbols_from_memory(pid, base) {
+            Ok(symbols) => ModuleSymbols::new(symbols),
+            Err(memory_error) => {
+                log::debug!(
+                    "No symbols for {}: {}; {}",
+                    module_name,
+                    file_error,
+                    memory_error
+                );
+                ModuleSymbols::default()
+            }
+        },
+    };
+    let symbols = Arc::new(symbols);
+    SYMBOL_CACHE.lock().unwrap().insert(key, symbols.clone());
+    symbols
+}
+
+fn module_file_name(module: &Value) -> Option<String> {
+    let name = module["modulename"].as_str()?;
+    Some(Path::new(name).file_name()?.to_string_lossy().into_owned())
+}
+
+// Resolves `module!symbol` where `module` is the file name of a loaded module
+pub fn resolve_symbol(pid: i32, modules: &[Value], module: &str, symbol: &str) -> Option<u64> {
+    modules
+        .iter()
+        .filter(|m| module_file_name(m).as_deref() == Some(module))
+        .find_map(|m| {
+            let symbols = module_symbols(pid, m["modulename"].as_str()?, m["base"].as_u64()?);
+            symbols.find(symbol).map(|s| s.address)
+        })
+}
+
+// Case-insensitive substring match, or a glob when the query contains `*` or `?`
+pub fn search_symbols(
+    pid: i32,
+    modules: &[Value],
+    query: &str,
+    module_filter: Option<&str>,
+    limit: usize,
+) -> Value {
+    let query = query.to_lowercase();
+    let is_glob = query.contains('*') || query.contains('?');
+    let matches = |name: &str| {
+        let name = name.to_lowercase();
+        if is_glob {
+            util::glob_match(&query, &name)
+        } else {
+            name.contains(&query)
+        }
+    };
+
+    let targets: Vec<(&str, u64)> = modules
+        .iter()
+        .filter(|m| match module_filter {
+            Some(filter) => {
+                let file_name = module_file_name(m).unwrap_or_default();
+                util::glob_match(filter, &file_name)
+            }
+            None => true,
+        })
+        .filter_map(|m| Some((m["modulename"].as_str()?, m["base"].as_u64()?)))
+        .collect();
+
+    let per_module: Vec<(String, Vec<Symbol>)> = targets
+        .par_iter()
+        .map(|&(name, base)| {
+            let symbols = module_symbols(pid, name, base);
+            let found = symbols
+                .symbols
+                .iter()
+                .filter(|symbol| matches(&symbol.name))
+                .cloned()
+                .collect();
+            (name.to_string(), found)
+        })
+        .collect();
+
+    let total: usize = per_module.iter().map(|(_, found)| found.len()).sum();
+    let results: Vec<Value> = per_module
+        .iter()
+        .flat_map(|(module, found)| {
+            let file_name = Path::new(module)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            found.iter().map(move |symbol| {
+                json!({
+                    "module": module,
+                    "name": symbol.name,
+                    "expression": format!(
+                        "{}!{}",
+                        expression::quote_module(&file_name),
+                        expression::quote_symbol(&symbol.name)
+                    ),
+                    "address": symbol.address,
+                    "size": symbol.size,
+                    "type": symbol.kind
+                })
+            })
+        })
+        .take(limit)
+        .collect();
+
+    json!({
+        "symbols": results,
+        "found": total,
+        "is_rounded": total > limit
+    })
+}
+
+struct AddressRegion {
+    start: u64,
+    end: u64,
+    protection: String,
+    file_path: String,
+}
+
+// Module and region lists of a process, with the architecture of each module looked up lazily
+pub struct ProcessLayout {
+    taken: Instant,
+    modules: Vec<Value>,
+    // Sorted by start address
+    regions: Vec<AddressRegion>,
+    arches: Mutex<HashMap<u64, disasm::Arch>>,
+}
+
+impl ProcessLayout {
+    fn load(pid: i32) -> Result<Self, String> {
+        let modules = native_bridge::enum_modules(pid)?;
+        let mut regions: Vec<AddressRegion> = native_bridge::enum_regions(pid)?
+            .iter()
+            .map(|region| {
+                let (start, end) = ptrscan::region_bounds(region);
+                AddressRegion {
+                    start,
+                    end,
+                    protection: region["protection"].as_str().unwrap_or("").to_string(),
+                    file_path: region["file_path"].as_str().unwrap_or("").to_string(),
+                }
+            })
+            .collect();
+        regions.sort_unstable_by_key(|region| region.start);
+        Ok(ProcessLayout {
+            taken: Instant::now(),
+            modules,
+            regions,
+            arches: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn module_at(&self, address: u64) -> Option<&Value> {
+        self.modules.iter().find(|m| {
+            let base = m["base"].as_u64().unwrap_or(0);
+            let size = m["size"].as_u64().unwrap_or(0);
+            address >= base && address - base < size
+        })
+    }
+
+    // Same result as `disasm::detect_arch`, but the image header is read once per module
+    pub fn arch(&self, pid: i32, address: u64) -> disasm::Arch {
+        let key = self
+            .module_at(address)
+            .or(self.modules.first())
+            .and_then(|module| module["base"].as_u64())
+            .unwrap_or(0);
+        if let Some(arch) = self.arches.lock().unwrap().get(&key) {
+            return *arch;
+        }
+        let arch = disasm::detect_arch_in(pid, &self.modules, address);
+        self.arches.lock().unwrap().insert(key, arch);
+        arch
+    }
+
+    // Maps each address to its region, `module+offset` and the nearest preceding symbol
+    pub fn describe(&self, pid: i32, addresses: &[u64]) -> Vec<Value> {
+        addresses
+            .iter()
+            .map(|&address| self.describe_one(pid, address))
+            .collect()
+    }
+
+    fn describe_one(&self, pid: i32, address: u64) -> Value {
+        let regions = &self.regions;
+        let idx = regions.partition_point(|region| region.start <= address);
+        let region = idx
+            .checked_sub(1)
+            .map(|idx| &regions[idx])
+            .filter(|region| address < region.end);
+
+        let module = self.module_at(address);
+
+        let mut info = json!({
+            "address": address,
+            "region": region.map(|region| json!({
+                "start_address": region.start,
+                "end_address": region.end,
+                "protection": region.protection,
+                "file_path": region.file_path
+            })),
+            "module": null,
+            "offset": null,
+            "expression": null,
+            "symbol": null,
+            "symbol_displacement": null,
+            "symbol_expression": null
+        });
+
+        if let Some(module) = module {
+            let name = module["modulename"].as_str().unwrap_or("");
+            let base = module["base"].as_u64().unwrap_or(0);
+            let file_name = module_file_name(module).unwrap_or_default();
+            let offset = address.wrapping_sub(base);
+            info["module"] = json!(name);
+            info["offset"] = json!(offset);
+            info["expression"] = json!(format!(
+                "{}+0x{:x}",
+                expression::quote_module(&file_name),
+                offset
+            ));
+
+            let symbols = module_symbols(pid, name, base);
+            if let Some(symbol) = symbols.nearest(address) {
+                let displacement = address - symbol.address;
+                info["symbol"] = json!(symbol.name);
+                info["symbol_displacement"] = json!(displacement);
+                let name = format!(
+                    "{}!{}",
+                    expression::quote_module(&file_name),
+                    expression::quote_symbol(&symbol.name)
+                );
+                info["symbol_expression"] = json!(if displacement == 0 {
+                    name
+                } else {
+                    format!("{}+0x{:x}", name, displacement)
+                });
+            }
+        }
+        info
+    }
+}
+
+// Reading the module and region lists on every breakpoint or watchpoint hit is the most
+// expensive part of reporting it, so hits share a layout for a short time
+const LAYOUT_TTL: Duration = Duration::from_millis(500);
+
+// Layout for the hit path. Mappings that change within the TTL show up on the next refresh, or
+// right away after `invalidate_layout`.
+pub fn cached_layout(pid: i32) -> Result<Arc<ProcessLayout>, String> {
+    if let Some(layout) = LAYOUT_CACHE.lock().unwrap().get(&pid) {
+        if layout.taken.elapsed() < LAYOUT_TTL {
+            return Ok(layout.clone());
+        }
+    }
+    let layout = Arc::new(ProcessLayout::load(pid)?);
+    LAYOUT_CACHE.lock().unwrap().insert(pid, layout.clone());
+    Ok(layout)
+}
+
+// Called after the server itself maps or unmaps memory in the process
+pub fn invalidate_layout(pid: i32) {
+    LAYOUT_CACHE.lock().unwrap().remove(&pid);
+}
+
+pub fn describe_addresses(pid: i32, addresses: &[u64]) -> Result<Vec<Value>, String> {
+    Ok(ProcessLayout::load(pid)?.describe(pid, addresses))
+}
@@ -0,0 +1,161 @@
+use crate::native_bridge;
+use crate::symbols;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+// What a scan froze, so that exactly this is undone afterwards
+pub enum Freeze {
+    Process,
+    // `attached` records that the freeze itself attached the debugger
+    Threads { tids: Vec<i32>, attached: bool },
+}
+
+fn state_name(state: &str) -> &'static str {
+    match state {
+        "R" => "running",
+        "S" => "sleeping",
+        "D" => "disk_sleep",
+        "T" => "stopped",
+        "t" => "tracing_stop",
+        "Z" => "zombie",
+        "X" | "x" => "dead",
+        "I" => "idle",
+        "W" => "waking",
+        "P" => "parked",
+        _ => "unknown",
+    }
+}
+
+fn parse_hex(value: &Value) -> Option<u64> {
+    value
+        .as_str()
+        .and_then(|value| u64::from_str_radix(value.trim_start_matches("0x"), 16).ok())
+}
+
+// Threads the debugger holds, with why they were stopped and where
+fn held_threads() -> BTreeMap<i32, (String, Option<u64>)> {
+    native_bridge::debugger_threads()
+        .ok()
+        .and_then(|threads| serde_json::from_str::<Vec<Value>>(&threads).ok())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|thread| {
+            let tid = thread["tid"].as_i64()? as i32;
+            let reason = thread["reason"].as_str().unwrap_or_default().to_string();
+            Some((tid, (reason, parse_hex(&thread["registers"]["pc"]))))
+        })
+        .collect()
+}
+
+// Every thread of the process; the PC is only reported while a thread is off the CPU
+pub fn list(pid: i32) -> Result<Value, String> {
+    let mut threads = native_bridge::enum_threads(pid)?;
+    let held = held_threads();
+
+    for thread in threads.iter_mut() {
+        let state = thread["state"].as_str().unwrap_or_default().to_string();
+        thread["state"] = json!(state_name(&state));
+        let tid = thread["tid"].as_i64().unwrap_or_default() as i32;
+        match held.get(&tid) {
+            Some((reason, pc)) => {
+                thread["held"] = json!(true);
+                thread["stop_reason"] = json!(reason);
+                if let Some(pc) = pc {
+                    thread["pc"] = json!(pc);
+                }
+            }
+            None => thread["held"] = json!(false),
+        }
+        if thread["pc"].as_u64() == Some(0) {
+            thread["pc"] = Value::Null;
+        }
+    }
+
+    let pcs: Vec<(usize, u64)> = threads
+        .iter()
+        .enumerate()
+        .filter_map(|(index, thread)| thread["pc"].as_u64().map(|pc| (index, pc)))
+        .collect();
+    let addresses: Vec<u64> = pcs.iter().map(|(_, pc)| *pc).collect();
+    if let Ok(infos) = symbols::describe_addresses(pid, &addresses) {
+        for ((index, _), info) in pcs.into_iter().zip(infos) {
+            threads[index]["address_info"] = info;
+        }
+    }
+    Ok(json!({ "threads": threads }))
+}
+
+// The thread is held like a breakpoint stop and shows up in the debugger's stopped threads
+pub fn suspend(pid: i32, tid: i32) -> Result<(), String> {
+    native_bridge::debugger_suspend(pid, tid)
+        .map_err(|e| format!("Failed to suspend thread {}: {}", tid, e))
+}
+
+pub fn resume(tid: i32) -> Result<(), String> {
+    native_bridge::debugger_continue(tid)
+        .map_err(|e| format!("Failed to resume thread {}: {}", tid, e))
+}
+
+// Stops the whole process, or only the given threads, for the duration of a scan. Threads the
+// debugger already holds are left out so that thawing does not let them go.
+pub fn freeze(pid: i32, tids: Option<&[i32]>) -> Option<Freeze> {
+    match tids {
+        None => unsafe { native_bridge::suspend_process(pid) }.then_some(Freeze::Process),
+        Some(tids) => {
+            let held = held_threads();
+            let attached = !native_bridge::debugger_attached(pid);
+            let frozen = tids
+                .iter()
+                .copied()
+                .filter(|tid| !held.contains_key(tid))
+                .filter(|tid| match suspend(pid, *tid) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        log::warn!("{}", e);
+                        false
+                    }
+                })
+                .collect();
+            Some(Freeze::Threads {
+                tids: frozen,
+                attached,
+            })
+        }
+    }
+}
+
+// A frozen process is only resumed when it is meant to be running; frozen threads always are,
+// since holding them is not what pauses the process
+pub fn thaw(pid: i32, freeze: &Freeze, running: bool) {
+    match freeze {
+        Freeze::Process => {
+            if running {
+                unsafe { native_bridge::resume_process(pid) };
+            }
+        }
+        Freeze::Threads { tids, attached } => {
+            for tid in tids {
+                if let Err(e) = resume(*tid) {
+                    log::warn!("{}", e);
+                }
+            }
+            // Leave the process untraced again unless breakpoints or watchpoints came to need it
+            if *attached && !native_bridge::debugger_in_use() {
+                native_bridge::free_debugger();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_scheduler_states() {
+        assert_eq!(state_name("R"), "running");
+        assert_eq!(state_name("t"), "tracing_stop");
+        assert_eq!(state_name("T"), "stopped");
+        assert_eq!(state_name("?"), "unknown");
+    }
+}
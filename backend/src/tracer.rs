@@ -0,0 +1,726 @@
+use crate::disasm::{self, Arch};
+use crate::native_bridge;
+use crate::unwind;
+use crate::watch;
+use capstone::arch::ArchOperand;
+use capstone::prelude::*;
+use capstone::RegAccessType;
+use lazy_static::lazy_static;
+use libc::c_int;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::sync::Mutex;
+
+// Verdicts returned to the native debugger after every recorded step
+const CONTINUE: c_int = 0;
+const STOP: c_int = 1;
+
+const DEFAULT_MAX_INSTRUCTIONS: usize = 10_000;
+const MAX_INSTRUCTIONS: usize = 1_000_000;
+// Finished traces kept for export; the oldest is dropped first
+const MAX_TRACES: usize = 32;
+// Bytes recorded per memory access, enough for a vector register
+const MAX_ACCESS_SIZE: usize = 64;
+
+// Register names as Tenet expects them, the program counter last
+const X86_64_REGISTERS: [&str; 17] = [
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15", "rip",
+];
+
+const ARM64_REGISTERS: [&str; 33] = [
+    "x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7", "x8", "x9", "x10", "x11", "x12", "x13", "x14",
+    "x15", "x16", "x17", "x18", "x19", "x20", "x21", "x22", "x23", "x24", "x25", "x26", "x27",
+    "x28", "x29", "x30", "sp", "pc",
+];
+
+lazy_static! {
+    // The trace being recorded; the native debugger calls back into it from its own thread
+    static ref RECORDER: Mutex<Option<Recorder>> = Mutex::new(None);
+    static ref TRACES: Mutex<BTreeMap<u64, Trace>> = Mutex::new(BTreeMap::new());
+}
+
+thread_local! {
+    // Capstone handles are not Send, so every stepping thread keeps its own
+    static DECODER: RefCell<Option<(Arch, Capstone)>> = const { RefCell::new(None) };
+}
+
+fn registers_of(arch: Arch) -> &'static [&'static str] {
+    match arch {
+        Arch::Arm64 => &ARM64_REGISTERS,
+        _ => &X86_64_REGISTERS,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Access {
+    write: bool,
+    address: u64,
+    size: usize,
+    // Read before the instruction for reads, after it for writes
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Step {
+    pc: u64,
+    // 0 if the instruction could not be decoded
+    size: u8,
+    // Registers changed since the previous step; all of them for the first one
+    registers: Vec<(&'static str, u64)>,
+    accesses: Vec<Access>,
+}
+
+struct Trace {
+    pid: i32,
+    tid: i32,
+    arch: Arch,
+    stop_reason: String,
+    steps: Vec<Step>,
+    // Modules when the trace ended, for drcov offsets
+    modules: Vec<Value>,
+}
+
+// What ends a trace, checked before each instruction
+pub struct TraceSpec {
+    pub max_instructions: Option<usize>,
+    pub until: Option<u64>,
+    pub until_return: bool,
+    pub memory: bool,
+}
+
+struct Recorder {
+    pid: i32,
+    arch: Arch,
+    max_instructions: usize,
+    until: Option<u64>,
+    until_return: bool,
+    // Return address and stack pointer after the return, found at the first step
+    return_site: Option<(u64, u64)>,
+    memory: bool,
+    previous: BTreeMap<&'static str, u64>,
+    steps: Vec<Step>,
+    stop_reason: Option<&'static str>,
+}
+
+impl Recorder {
+    fn new(pid: i32, arch: Arch, spec: &TraceSpec) -> Recorder {
+        Recorder {
+            pid,
+            arch,
+            max_instructions: spec
+                .max_instructions
+                .unwrap_or(DEFAULT_MAX_INSTRUCTIONS)
+                .clamp(1, MAX_INSTRUCTIONS),
+            until: spec.until,
+            until_return: spec.until_return,
+            return_site: None,
+            memory: spec.memory,
+            previous: BTreeMap::new(),
+            steps: Vec::new(),
+            stop_reason: None,
+        }
+    }
+
+    // Records the instruction about to execute, or decides that the trace ends before it
+    fn record(&mut self, registers: &Value, memory: &dyn Fn(u64, usize) -> Vec<u8>) -> c_int {
+        // Accesses that cannot be read in full are recorded without data
+        let read = |address: u64, size: usize| {
+            let data = memory(address, size);
+            if data.len() == size {
+                data
+            } else {
+                Vec::new()
+            }
+        };
+        if let Some(last) = self.steps.last_mut() {
+            for access in last.accesses.iter_mut().filter(|access| access.write) {
+                access.data = read(access.address, access.size);
+            }
+        }
+
+        let arch = self.arch;
+        let value = |name: &str| watch::register_value(arch, registers, name);
+        let (Some(pc), Some(sp)) = (value("pc"), value("sp")) else {
+            self.stop_reason = Some("no_registers");
+            return STOP;
+        };
+        if self.steps.is_empty() && self.until_return {
+            self.return_site =
+                unwind::backtrace(self.pid, registers, Some(2), unwind::Method::Auto)
+                    .ok()
+                    .and_then(|frames| {
+                        let caller = frames.get(1)?;
+                        Some((caller["pc"].as_u64()?, caller["sp"].as_u64()?))
+                    });
+            if self.return_site.is_none() {
+                self.stop_reason = Some("no_return_address");
+                return STOP;
+            }
+        }
+        if self.steps.len() >= self.max_instructions {
+            self.stop_reason = Some("max_instructions");
+            return STOP;
+        }
+        if !self.steps.is_empty() && self.until == Some(pc) {
+            self.stop_reason = Some("until");
+            return STOP;
+        }
+        if let Some((return_pc, return_sp)) = self.return_site {
+            if pc == return_pc && sp >= return_sp {
+                self.stop_reason = Some("returned");
+                return STOP;
+            }
+        }
+
+        let code = memory(pc, arch.max_instruction_size());
+        let (size, mut accesses) = decode_step(arch, pc, &code, registers);
+        if !self.memory {
+            accesses.clear();
+        }
+        for access in accesses.iter_mut().filter(|access| !access.write) {
+            access.data = read(access.address, access.size);
+        }
+
+        let mut changed = Vec::new();
+        for name in registers_of(arch) {
+            if let Some(current) = value(name) {
+                if self.previous.insert(name, current) != Some(current) {
+                    changed.push((*name, current));
+                }
+            }
+        }
+        self.steps.push(Step {
+            pc,
+            size,
+            registers: changed,
+            accesses,
+        });
+        CONTINUE
+    }
+}
+
+// Size in bytes of an arm64 load or store, from the mnemonic and the register transferred, the
+// last one before the memory operand (stxr names its status register first)
+fn arm64_access_size(cs: &Capstone, mnemonic: &str, operands: &[ArchOperand]) -> usize {
+    let mut register = None;
+    for operand in operands {
+        if let ArchOperand::Arm64Operand(op) = operand {
+            match op.op_type {
+                capstone::arch::arm64::Arm64OperandType::Reg(reg) => register = cs.reg_name(reg),
+                capstone::arch::arm64::Arm64OperandType::Mem(_) => break,
+                _ => {}
+            }
+        }
+    }
+    let base = mnemonic.trim_end_matches(|c: char| c.is_ascii_digit());
+    let width = if base.ends_with('b') {
+        1
+    } else if base.ends_with('h') {
+        2
+    } else if base.ends_with("sw") {
+        4
+    } else {
+        match register.as_deref().and_then(|name| name.chars().next()) {
+            Some('w') | Some('s') => 4,
+            Some('q') => 16,
+            Some('h') => 2,
+            Some('b') => 1,
+            _ => 8,
+        }
+    };
+    if mnemonic.starts_with("ldp")
+        || mnemonic.starts_with("stp")
+        || mnemonic.starts_with("ldnp")
+        || mnemonic.starts_with("stnp")
+    {
+        width * 2
+    } else {
+        width
+    }
+}
+
+// Length of the instruction at `pc` and the memory it accesses, evaluated with the registers
+// before it executes. Data is filled in by the caller.
+fn decode_step(arch: Arch, pc: u64, code: &[u8], registers: &Value) -> (u8, Vec<Access>) {
+    DECODER.with(|decoder| {
+        let mut decoder = decoder.borrow_mut();
+        if decoder.as_ref().map(|(cached, _)| *cached) != Some(arch) {
+            *decoder = arch.capstone().ok().map(|cs| (arch, cs));
+        }
+        let Some((_, cs)) = decoder.as_ref() else {
+            return (0, Vec::new());
+        };
+        let Ok(instructions) = cs.disasm_count(code, pc, 1) else {
+            return (0, Vec::new());
+        };
+        let Some(insn) = instructions.iter().next() else {
+            return (0, Vec::new());
+        };
+        let size = insn.bytes().len() as u8;
+        let Ok(detail) = cs.insn_detail(insn) else {
+            return (size, Vec::new());
+        };
+        let operands = detail.arch_detail().operands();
+        let mnemonic = insn.mnemonic().unwrap_or("");
+        let access = |write: bool, address: u64, size: usize| Access {
+            write,
+            address,
+            size: size.min(MAX_ACCESS_SIZE),
+            data: Vec::new(),
+        };
+
+        let mut accesses = Vec::new();
+        match arch {
+            Arch::Arm64 => {
+                let load = mnemonic.starts_with("ld");
+                if !load && !mnemonic.starts_with("st") {
+                    return (size, accesses);
+                }
+                let width = arm64_access_size(cs, mnemonic, &operands);
+                for operand in &operands {
+                    if let Some(address) =
+                        watch::operand_address(cs, arch, insn, operand, registers)
+                    {
+                        accesses.push(access(!load, address, width));
+                    }
+                }
+            }
+            _ => {
+                // lea and long nops name memory without touching it
+                if mnemonic == "lea" || mnemonic.starts_with("nop") {
+                    return (size, accesses);
+                }
+                for operand in &operands {
+                    let ArchOperand::X86Operand(op) = operand else {
+                        continue;
+                    };
+                    let Some(address) = watch::operand_address(cs, arch, insn, operand, registers)
+                    else {
+                        continue;
+                    };
+                    let width = op.size as usize;
+                    if width == 0 {
+                        continue;
+                    }
+                    match op.access {
+                        Some(RegAccessType::WriteOnly) => {
+                            accesses.push(access(true, address, width))
+                        }
+                        Some(RegAccessType::ReadWrite) => {
+                            accesses.push(access(false, address, width));
+                            accesses.push(access(true, address, width));
+                        }
+                        _ => accesses.push(access(false, address, width)),
+                    }
+                }
+                // The stack slot a push, pop, call or ret moves through is not an operand
+                let sp = watch::register_value(arch, registers, "rsp").unwrap_or(0);
+                if mnemonic.starts_with("push") || mnemonic.starts_with("call") {
+                    accesses.push(access(true, sp.wrapping_sub(8), 8));
+                } else if mnemonic.starts_with("pop") || mnemonic.starts_with("ret") {
+                    accesses.push(access(false, sp, 8));
+                }
+            }
+        }
+        (size, accesses)
+    })
+}
+
+// Called by the native debugger with the registers before each traced instruction
+#[no_mangle]
+pub extern "C" fn record_trace_step(pid: i32, _tid: u64, register_json: *const c_char) -> c_int {
+    if register_json.is_null() {
+        return STOP;
+    }
+    let text = unsafe { CStr::from_ptr(register_json) }.to_string_lossy();
+    let Ok(registers) = serde_json::from_str::<Value>(&text) else {
+        return STOP;
+    };
+    let mut recorder = RECORDER.lock().unwrap();
+    match recorder.as_mut() {
+        // Memory is seen without the debugger's breakpoints, as the traced code would see it
+        Some(recorder) if recorder.pid == pid => recorder.record(&registers, &|address, size| {
+            disasm::read_code(pid, address, size)
+        }),
+        _ => STOP,
+    }
+}
+
+fn summary(id: u64, trace: &Trace) -> Value {
+    let unique: HashSet<u64> = trace.steps.iter().map(|step| step.pc).collect();
+    json!({
+        "id": id,
+        "pid": trace.pid,
+        "tid": trace.tid,
+        "arch": trace.arch.name(),
+        "instructions": trace.steps.len(),
+        "unique_instructions": unique.len(),
+        "memory_accesses": trace.steps.iter().map(|step| step.accesses.len()).sum::<usize>(),
+        "start_pc": trace.steps.first().map(|step| step.pc),
+        "end_pc": trace.steps.last().map(|step| step.pc),
+        "stop_reason": trace.stop_reason,
+    })
+}
+
+// Threads the debugger holds, each with its register snapshot
+fn held_threads() -> Vec<Value> {
+    native_bridge::debugger_threads()
+        .ok()
+        .and_then(|threads| serde_json::from_str(&threads).ok())
+        .unwrap_or_default()
+}
+
+// Single-steps a stopped thread, recording every instruction until `spec` ends the trace. The
+// thread is left stopped after the last recorded instruction.
+pub fn record(pid: i32, tid: i32, spec: &TraceSpec) -> Result<Value, String> {
+    let pc = held_threads()
+        .iter()
+        .find(|thread| thread["tid"] == tid)
+        .and_then(|thread| thread["registers"]["pc"].as_str())
+        .and_then(|pc| u64::from_str_radix(pc.trim_start_matches("0x"), 16).ok())
+        .ok_or_else(|| format!("Thread {} is not stopped", tid))?;
+    let arch = disasm::detect_arch(pid, pc);
+    if !matches!(arch, Arch::X86_64 | Arch::Arm64) {
+        return Err(format!("Tracing is not supported for {}", arch.name()));
+    }
+    {
+        let mut recorder = RECORDER.lock().unwrap();
+        if recorder.is_some() {
+            return Err("A trace is already being recorded".to_string());
+        }
+        *recorder = Some(Recorder::new(pid, arch, spec));
+    }
+    let result = native_bridge::debugger_trace(tid);
+    let recorder = RECORDER.lock().unwrap().take();
+    result.map_err(|e| format!("Failed to trace thread {}: {}", tid, e))?;
+    let recorder = recorder.ok_or("Trace recorder disappeared")?;
+
+    let stop_reason = match recorder.stop_reason {
+        Some(reason) => reason.to_string(),
+        None => {
+            if held_threads().iter().any(|thread| thread["tid"] == tid) {
+                "interrupted".to_string()
+            } else {
+                "thread_exited".to_string()
+            }
+        }
+    };
+    let trace = Trace {
+        pid,
+        tid,
+        arch,
+        stop_reason,
+        steps: recorder.steps,
+        modules: native_bridge::enum_modules(pid).unwrap_or_default(),
+    };
+
+    let mut traces = TRACES.lock().unwrap();
+    let id = traces.keys().next_back().map_or(1, |id| id + 1);
+    let result = summary(id, &trace);
+    traces.insert(id, trace);
+    while traces.len() > MAX_TRACES {
+        traces.pop_first();
+    }
+    Ok(result)
+}
+
+pub fn list_traces() -> Value {
+    let traces = TRACES.lock().unwrap();
+    Value::Array(
+        traces
+            .iter()
+            .map(|(id, trace)| summary(*id, trace))
+            .collect(),
+    )
+}
+
+pub fn delete_trace(id: u64) -> Result<(), String> {
+    match TRACES.lock().unwrap().remove(&id) {
+        Some(_) => Ok(()),
+        None => Err(format!("No trace {}", id)),
+    }
+}
+
+fn to_json(id: u64, trace: &Trace) -> Value {
+    let mut result = summary(id, trace);
+    result["steps"] = Value::Array(
+        trace
+            .steps
+            .iter()
+            .map(|step| {
+                let registers: serde_json::Map<String, Value> = step
+                    .registers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), json!(format!("0x{:x}", value))))
+                    .collect();
+                let memory: Vec<Value> = step
+                    .accesses
+                    .iter()
+                    .map(|access| {
+                        json!({
+                            "access": if access.write { "write" } else { "read" },
+                            "address": access.address,
+                            "size": access.size,
+                            "data": hex::encode(&access.data),
+                        })
+                    })
+                    .collect();
+                json!({
+                    "pc": step.pc,
+                    "size": step.size,
+                    "registers": registers,
+                    "memory": memory,
+                })
+            })
+            .collect(),
+    );
+    result
+}
+
+// Tenet text trace: one line per instruction with the registers changed before it and the
+// memory it read and wrote
+fn to_tenet(steps: &[Step]) -> String {
+    let mut text = String::new();
+    for step in steps {
+        let mut fields: Vec<String> = step
+            .registers
+            .iter()
+            .map(|(name, value)| format!("{}=0x{:x}", name, value))
+            .collect();
+        for access in step
+            .accesses
+            .iter()
+            .filter(|access| !access.data.is_empty())
+        {
+            fields.push(format!(
+                "{}=0x{:x}:{}",
+                if access.write { "mw" } else { "mr" },
+                access.address,
+                hex::encode_upper(&access.data)
+            ));
+        }
+        text.push_str(&fields.join(","));
+        text.push('\n');
+    }
+    text
+}
+
+// drcov version 2 coverage: the module table, then every distinct basic block executed as a
+// run of consecutive instructions, relative to its module
+fn to_drcov(steps: &[Step], modules: &[Value]) -> Vec<u8> {
+    let ranges: Vec<(u64, u64, &str)> = modules
+        .iter()
+        .filter_map(|module| {
+            let base = module["base"].as_u64()?;
+            let size = module["size"].as_u64()?;
+            Some((
+                base,
+                base + size,
+                module["modulename"].as_str().unwrap_or(""),
+            ))
+        })
+        .collect();
+    let module_of = |address: u64| {
+        ranges
+            .iter()
+            .position(|(start, end, _)| (*start..*end).contains(&address))
+    };
+
+    let mut blocks: Vec<(u64, u64)> = Vec::new();
+    for step in steps.iter().filter(|step| step.size > 0) {
+        match blocks.last_mut() {
+            Some((start, end))
+                if *end == step.pc
+                    && step.pc - *start + (step.size as u64) <= u16::MAX as u64
+                    && module_of(*start) == module_of(step.pc) =>
+            {
+                *end += step.size as u64
+            }
+            _ => blocks.push((step.pc, step.pc + step.size as u64)),
+        }
+    }
+    let mut seen = HashSet::new();
+    let mut table = Vec::new();
+    let mut count = 0;
+    for (start, end) in blocks {
+        let Some(module) = module_of(start) else {
+            continue;
+        };
+        if !seen.insert((start, end)) {
+            continue;
+        }
+        table.extend_from_slice(&((start - ranges[module].0) as u32).to_le_bytes());
+        table.extend_from_slice(&((end - start) as u16).to_le_bytes());
+        table.extend_from_slice(&(module as u16).to_le_bytes());
+        count += 1;
+    }
+
+    let mut header = String::from("DRCOV VERSION: 2\nDRCOV FLAVOR: drcov\n");
+    header.push_str(&format!(
+        "Module Table: version 2, count {}\n",
+        ranges.len()
+    ));
+    header.push_str("Columns: id, base, end, entry, checksum, timestamp, path\n");
+    for (id, (start, end, path)) in ranges.iter().enumerate() {
+        header.push_str(&format!(
+            "{:3}, 0x{:016x}, 0x{:016x}, 0x{:016x}, 0x{:08x}, 0x{:08x}, {}\n",
+            id, start, end, 0, 0, 0, path
+        ));
+    }
+    header.push_str(&format!("BB Table: {} bbs\n", count));
+    let mut output = header.into_bytes();
+    output.extend_from_slice(&table);
+    output
+}
+
+pub enum Export {
+    Json(Value),
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+pub fn export(id: u64, format: &str) -> Result<Export, String> {
+    let traces = TRACES.lock().unwrap();
+    let trace = traces.get(&id).ok_or_else(|| format!("No trace {}", id))?;
+    match format.to_lowercase().as_str() {
+        "json" => Ok(Export::Json(to_json(id, trace))),
+        "tenet" => Ok(Export::Text(to_tenet(&trace.steps))),
+        "drcov" => Ok(Export::Binary(to_drcov(&trace.steps, &trace.modules))),
+        _ => Err(format!("Unknown trace format '{}'", format)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registers(values: &[(&str, u64)]) -> Value {
+        let mut registers = json!({});
+        for name in X86_64_REGISTERS {
+            registers[name] = json!("0x0");
+        }
+        for (name, value) in values {
+            registers[*name] = json!(format!("0x{:x}", value));
+        }
+        registers["pc"] = registers["rip"].clone();
+        registers["sp"] = registers["rsp"].clone();
+        registers
+    }
+
+    #[test]
+    fn records_changed_registers_and_memory() {
+        // mov rax, [rbx]; push rax; mov [rcx], eax
+        let code = [0x48, 0x8b, 0x03, 0x50, 0x89, 0x01];
+        let memory = RefCell::new(BTreeMap::from([
+            (0x1000u64, code.to_vec()),
+            (0x2000, vec![0x11, 0x22, 0x33, 0x44, 0, 0, 0, 0]),
+            (0x3000, vec![0; 4]),
+            (0x7ff8, vec![0; 8]),
+        ]));
+        let read = |address: u64, size: usize| {
+            let memory = memory.borrow();
+            let Some((start, bytes)) = memory.range(..=address).next_back() else {
+                return Vec::new();
+            };
+            let offset = (address - start) as usize;
+            let end = (offset + size).min(bytes.len());
+            bytes
+                .get(offset..end)
+                .map(|b| b.to_vec())
+                .unwrap_or_default()
+        };
+        let spec = TraceSpec {
+            max_instructions: Some(3),
+            until: None,
+            until_return: false,
+            memory: true,
+        };
+        let mut recorder = Recorder::new(1, Arch::X86_64, &spec);
+
+        let base = [("rbx", 0x2000), ("rcx", 0x3000), ("rsp", 0x8000)];
+        let state = |extra: &[(&'static str, u64)]| {
+            let mut values = base.to_vec();
+            values.extend_from_slice(extra);
+            registers(&values)
+        };
+        assert_eq!(recorder.record(&state(&[("rip", 0x1000)]), &read), CONTINUE);
+        let rax = ("rax", 0x44332211);
+        assert_eq!(
+            recorder.record(&state(&[("rip", 0x1003), rax]), &read),
+            CONTINUE
+        );
+        // The push lands in memory before the next step fills in its data
+        memory
+            .borrow_mut()
+            .insert(0x7ff8, 0x44332211u64.to_le_bytes().to_vec());
+        let pushed = [("rip", 0x1004), rax, ("rsp", 0x7ff8)];
+        assert_eq!(recorder.record(&state(&pushed), &read), CONTINUE);
+        memory
+            .borrow_mut()
+            .insert(0x3000, vec![0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(recorder.record(&state(&pushed), &read), STOP);
+        assert_eq!(recorder.stop_reason, Some("max_instructions"));
+
+        let steps = &recorder.steps;
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps[0].registers.len(), X86_64_REGISTERS.len());
+        assert_eq!(steps[0].size, 3);
+        assert_eq!(
+            steps[0].accesses,
+            vec![Access {
+                write: false,
+                address: 0x2000,
+                size: 8,
+                data: vec![0x11, 0x22, 0x33, 0x44, 0, 0, 0, 0],
+            }]
+        );
+        assert_eq!(
+            steps[1].registers,
+            vec![("rax", 0x44332211), ("rip", 0x1003)]
+        );
+        assert_eq!(steps[1].accesses.len(), 1);
+        assert!(steps[1].accesses[0].write);
+        assert_eq!(steps[1].accesses[0].address, 0x7ff8);
+        assert_eq!(steps[1].accesses[0].data, 0x44332211u64.to_le_bytes());
+        assert_eq!(steps[2].registers, vec![("rsp", 0x7ff8), ("rip", 0x1004)]);
+        assert_eq!(steps[2].accesses[0].address, 0x3000);
+        assert_eq!(steps[2].accesses[0].size, 4);
+
+        let tenet = to_tenet(&steps[1..]);
+        assert_eq!(
+            tenet,
+            "rax=0x44332211,rip=0x1003,mw=0x7ff8:1122334400000000\nrsp=0x7ff8,rip=0x1004,mw=0x3000:11223344\n"
+        );
+    }
+
+    #[test]
+    fn drcov_blocks_are_module_relative_and_unique() {
+        let step = |pc: u64, size: u8| Step {
+            pc,
+            size,
+            registers: Vec::new(),
+            accesses: Vec::new(),
+        };
+        // A two-instruction loop body run twice, then a jump outside every module
+        let steps = [
+            step(0x401000, 2),
+            step(0x401002, 3),
+            step(0x401000, 2),
+            step(0x401002, 3),
+            step(0x900000, 1),
+        ];
+        let modules = [json!({"base": 0x400000, "size": 0x2000, "modulename": "/bin/target"})];
+        let output = to_drcov(&steps, &modules);
+
+        let header = "DRCOV VERSION: 2\nDRCOV FLAVOR: drcov\nModule Table: version 2, count 1\n\
+            Columns: id, base, end, entry, checksum, timestamp, path\n  \
+            0, 0x0000000000400000, 0x0000000000402000, 0x0000000000000000, 0x00000000, \
+            0x00000000, /bin/target\nBB Table: 1 bbs\n";
+        assert_eq!(&output[..header.len()], header.as_bytes());
+        assert_eq!(&output[header.len()..], [0x00, 0x10, 0, 0, 5, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,975 @@
+use crate::disasm::{self, Arch};
+use crate::native_bridge;
+use crate::symbols;
+use crate::watch;
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_MAX_FRAMES: usize = 64;
+const MAX_FRAMES: usize = 1024;
+const MAX_RECORD_SIZE: u64 = 1 << 20;
+const MAX_TABLE_ENTRIES: u64 = 1 << 20;
+const MAX_REMEMBERED_STATES: usize = 64;
+
+// Pointer encodings used by .eh_frame and .eh_frame_hdr
+const DW_EH_PE_OMIT: u8 = 0xff;
+const DW_EH_PE_PCREL: u8 = 0x10;
+const DW_EH_PE_DATAREL: u8 = 0x30;
+// datarel | sdata4, the only search table encoding linkers emit
+const HDR_TABLE_ENCODING: u8 = 0x3b;
+
+// Return addresses signed with pointer authentication carry the PAC in the upper bits
+const ARM64_ADDRESS_MASK: u64 = 0x0000_ffff_ffff_ffff;
+
+// Keyed by (pid, module path, module base); None for modules without a usable table
+type TableCache = HashMap<(i32, String, u64), Option<Arc<UnwindTable>>>;
+
+lazy_static! {
+    static ref TABLE_CACHE: Mutex<TableCache> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    // Call frame information from .eh_frame, frame pointers for code it does not cover
+    Auto,
+    FramePointer,
+}
+
+impl Method {
+    pub fn parse(name: &str) -> Result<Method, String> {
+        match name.to_lowercase().as_str() {
+            "auto" | "eh_frame" | "cfi" => Ok(Method::Auto),
+            "frame_pointer" | "fp" => Ok(Method::FramePointer),
+            _ => Err(format!("Unknown unwind method '{}'", name)),
+        }
+    }
+}
+
+// DWARF register numbers, indexing `names`
+struct Layout {
+    names: &'static [&'static str],
+    sp: u16,
+    fp: u16,
+    return_address: u16,
+}
+
+const X86_64_REGISTERS: [&str; 16] = [
+    "rax", "rdx", "rcx", "rbx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15",
+];
+
+const ARM64_REGISTERS: [&str; 32] = [
+    "x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7", "x8", "x9", "x10", "x11", "x12", "x13", "x14",
+    "x15", "x16", "x17", "x18", "x19", "x20", "x21", "x22", "x23", "x24", "x25", "x26", "x27",
+    "x28", "x29", "x30", "sp",
+];
+
+fn layout(arch: Arch) -> Layout {
+    match arch {
+        Arch::Arm64 => Layout {
+            names: &ARM64_REGISTERS,
+            sp: 31,
+            fp: 29,
+            return_address: 30,
+        },
+        _ => Layout {
+            names: &X86_64_REGISTERS,
+            sp: 7,
+            fp: 6,
+            return_address: 16,
+        },
+    }
+}
+
+fn strip_return_address(arch: Arch, address: u64) -> u64 {
+    match arch {
+        Arch::Arm64 => address & ARM64_ADDRESS_MASK,
+        _ => address,
+    }
+}
+
+// Register values of one frame, by DWARF number
+#[derive(Clone)]
+struct Frame {
+    pc: u64,
+    registers: BTreeMap<u16, u64>,
+}
+
+enum Step {
+    Caller(Frame),
+    // The unwind information marks the frame as the last one, as in _start or a thread entry
+    Outermost,
+}
+
+// Sorted search table of .eh_frame_hdr as (function start, FDE address)
+struct UnwindTable {
+    entries: Vec<(u64, u64)>,
+}
+
+impl UnwindTable {
+    fn find(&self, pc: u64) -> Option<u64> {
+        let idx = self.entries.partition_point(|&(start, _)| start <= pc);
+        idx.checked_sub(1).map(|idx| self.entries[idx].1)
+    }
+}
+
+trait Target {
+    fn read(&self, address: u64, size: usize) -> Option<Vec<u8>>;
+
+    // Unwind table of the module containing `pc`
+    fn unwind_table(&self, pc: u64) -> Option<Arc<UnwindTable>>;
+
+    fn read_u64(&self, address: u64) -> Option<u64> {
+        let bytes = self.read(address, 8)?;
+        Some(u64::from_le_bytes(bytes.try_into().ok()?))
+    }
+}
+
+struct ProcessTarget {
+    pid: i32,
+    modules: Vec<Value>,
+}
+
+impl Target for ProcessTarget {
+    fn read(&self, address: u64, size: usize) -> Option<Vec<u8>> {
+        let mut buffer = vec![0u8; size];
+        match native_bridge::read_process_memory(
+            self.pid,
+            address as *mut libc::c_void,
+            size,
+            &mut buffer,
+        ) {
+            Ok(n) if n as usize == size => Some(buffer),
+            _ => None,
+        }
+    }
+
+    fn unwind_table(&self, pc: u64) -> Option<Arc<UnwindTable>> {
+        let module = self.modules.iter().find(|m| {
+            let base = m["base"].as_u64().unwrap_or(0);
+            let size = m["size"].as_u64().unwrap_or(0);
+            pc >= base && pc - base < size
+        })?;
+        let name = module["modulename"].as_str()?;
+        let base = module["base"].as_u64()?;
+        let key = (self.pid, name.to_string(), base);
+        if let Some(table) = TABLE_CACHE.lock().unwrap().get(&key) {
+            return table.clone();
+        }
+
+        let table = symbols::eh_frame_hdr(self.pid, base)
+            .and_then(|hdr| {
+                load_table(self, hdr).ok_or_else(|| "Unreadable .eh_frame_hdr".to_string())
+            })
+            .map(Arc::new)
+            .map_err(|e| log::debug!("No unwind table for {}: {}", name, e))
+            .ok();
+        TABLE_CACHE.lock().unwrap().insert(key, table.clone());
+        table
+    }
+}
+
+// Little-endian reader over bytes that were copied from `address` in the target
+struct Cursor<'a> {
+    data: &'a [u8],
+    address: u64,
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(data: &'a [u8], address: u64) -> Self {
+        Cursor {
+            data,
+            address,
+            pos: 0,
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn current_address(&self) -> u64 {
+        self.address.wrapping_add(self.pos as u64)
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.data[self.pos.min(self.data.len())..]
+    }
+
+    fn bytes(&mut self, count: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(count)?)?;
+        self.pos += count;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes(4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.bytes(8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    fn uleb128(&mut self) -> Option<u64> {
+        let mut result = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                result |= ((byte & 0x7f) as u64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Some(result);
+            }
+        }
+    }
+
+    fn sleb128(&mut self) -> Option<i64> {
+        let mut result = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                result |= ((byte & 0x7f) as i64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    result |= -1i64 << shift;
+                }
+                return Some(result);
+            }
+        }
+    }
+
+    fn c_string(&mut self) -> Option<&'a [u8]> {
+        let end = self.rest().iter().position(|&b| b == 0)?;
+        let text = self.bytes(end)?;
+        self.pos += 1;
+        Some(text)
+    }
+
+    // A DW_EH_PE encoded pointer; `data_base` is the start of .eh_frame_hdr for datarel.
+    // The indirect bit is ignored, since only personality routines use it.
+    fn encoded(&mut self, encoding: u8, data_base: u64) -> Option<u64> {
+        if encoding == DW_EH_PE_OMIT {
+            return None;
+        }
+        let field = self.current_address();
+        let value = match encoding & 0x0f {
+            0x00 | 0x04 | 0x0c => self.u64()?,
+            0x01 => self.uleb128()?,
+            0x02 => self.u16()? as u64,
+            0x03 => self.u32()? as u64,
+            0x09 => self.sleb128()? as u64,
+            0x0a => self.u16()? as i16 as u64,
+            0x0b => self.u32()? as i32 as u64,
+            _ => return None,
+        };
+        let base = match encoding & 0x70 {
+            0x00 => 0,
+            DW_EH_PE_PCREL => field,
+            DW_EH_PE_DATAREL => data_base,
+            _ => return None,
+        };
+        Some(base.wrapping_add(value))
+    }
+}
+
+fn encoded_size(encoding: u8) -> Option<usize> {
+    match encoding & 0x0f {
+        0x02 | 0x0a => Some(2),
+        0x03 | 0x0b => Some(4),
+        0x00 | 0x04 | 0x0c => Some(8),
+        _ => None,
+    }
+}
+
+// The binary search table of .eh_frame_hdr, resolved to absolute addresses
+fn load_table(target: &dyn Target, hdr: u64) -> Option<UnwindTable> {
+    let header = target.read(hdr, 4)?;
+    let (version, pointer_encoding, count_encoding, table_encoding) =
+        (header[0], header[1], header[2], header[3]);
+    if version != 1 || table_encoding != HDR_TABLE_ENCODING {
+        return None;
+    }
+    let pointer_size = encoded_size(pointer_encoding)?;
+    let fields_size = pointer_size + encoded_size(count_encoding)?;
+    let fields = target.read(hdr + 4, fields_size)?;
+    let mut cursor = Cursor::new(&fields, hdr + 4);
+    cursor.bytes(pointer_size)?;
+    let count = cursor.encoded(count_encoding, hdr)?;
+    if count > MAX_TABLE_ENTRIES {
+        return None;
+    }
+
+    let table = target.read(hdr + 4 + fields_size as u64, count as usize * 8)?;
+    let entries = table
+        .chunks_exact(8)
+        .map(|entry| {
+            let start = i32::from_le_bytes(entry[0..4].try_into().unwrap()) as i64 as u64;
+            let fde = i32::from_le_bytes(entry[4..8].try_into().unwrap()) as i64 as u64;
+            (hdr.wrapping_add(start), hdr.wrapping_add(fde))
+        })
+        .collect();
+    Some(UnwindTable { entries })
+}
+
+// Body of the CIE or FDE at `address` and where it starts, after the length field
+fn read_record(target: &dyn Target, address: u64) -> Option<(Vec<u8>, u64)> {
+    let length = u32::from_le_bytes(target.read(address, 4)?.try_into().ok()?) as u64;
+    // 0xffffffff introduces the 64-bit format, which .eh_frame does not use
+    if length == 0 || length == 0xffff_ffff || length > MAX_RECORD_SIZE {
+        return None;
+    }
+    Some((target.read(address + 4, length as usize)?, address + 4))
+}
+
+struct Cie {
+    code_alignment: u64,
+    data_alignment: i64,
+    return_address: u16,
+    fde_encoding: u8,
+    has_augmentation_data: bool,
+    instructions: Vec<u8>,
+    instructions_address: u64,
+}
+
+fn parse_cie(target: &dyn Target, address: u64) -> Option<Cie> {
+    let (body, start) = read_record(target, address)?;
+    let mut cursor = Cursor::new(&body, start);
+    if cursor.u32()? != 0 {
+        return None;
+    }
+    let version = cursor.u8()?;
+    let augmentation = cursor.c_string()?;
+    if augmentation.starts_with(b"eh") {
+        cursor.u64()?;
+    }
+    let code_alignment = cursor.uleb128()?;
+    let data_alignment = cursor.sleb128()?;
+    let return_address = if version == 1 {
+        cursor.u8()? as u64
+    } else {
+        cursor.uleb128()?
+    } as u16;
+
+    let mut fde_encoding = 0;
+    let has_augmentation_data = augmentation.first() == Some(&b'z');
+    if has_augmentation_data {
+        let length = cursor.uleb128()? as usize;
+        let data_address = cursor.current_address();
+        let mut data = Cursor::new(cursor.bytes(length)?, data_address);
+        for &c in &augmentation[1..] {
+            match c {
+                b'L' => {
+                    data.u8()?;
+                }
+                b'P' => {
+                    let encoding = data.u8()?;
+                    data.encoded(encoding, 0)?;
+                }
+                b'R' => fde_encoding = data.u8()?,
+                b'S' | b'B' => {}
+                _ => break,
+            }
+        }
+    } else if !augmentation.is_empty() && augmentation != b"eh" {
+        return None;
+    }
+
+    Some(Cie {
+        code_alignment,
+        data_alignment,
+        return_address,
+        fde_encoding,
+        has_augmentation_data,
+        instructions_address: cursor.current_address(),
+        instructions: cursor.rest().to_vec(),
+    })
+}
+
+struct Fde {
+    cie: Cie,
+    pc_begin: u64,
+    pc_end: u64,
+    instructions: Vec<u8>,
+    instructions_address: u64,
+}
+
+fn parse_fde(target: &dyn Target, address: u64) -> Option<Fde> {
+    let (body, start) = read_record(target, address)?;
+    let mut cursor = Cursor::new(&body, start);
+    // Relative to the field itself; zero would make this record a CIE
+    let cie_pointer = cursor.u32()? as u64;
+    if cie_pointer == 0 {
+        return None;
+    }
+    let cie = parse_cie(target, start.wrapping_sub(cie_pointer))?;
+    let pc_begin = cursor.encoded(cie.fde_encoding, 0)?;
+    let pc_range = cursor.encoded(cie.fde_encoding & 0x0f, 0)?;
+    if cie.has_augmentation_data {
+        let length = cursor.uleb128()? as usize;
+        cursor.bytes(length)?;
+    }
+    Some(Fde {
+        cie,
+        pc_begin,
+        pc_end: pc_begin.wrapping_add(pc_range),
+        instructions_address: cursor.current_address(),
+        instructions: cursor.rest().to_vec(),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Rule {
+    Undefined,
+    SameValue,
+    Offset(i64),
+    ValOffset(i64),
+    Register(u16),
+}
+
+// Where the caller's registers are, as of one instruction; the CFA is a register plus offset
+#[derive(Clone, Default)]
+struct Row {
+    cfa: Option<(u16, i64)>,
+    rules: BTreeMap<u16, Rule>,
+}
+
+// Runs CFA instructions for locations up to and including `pc`. `initial` is the row set up by
+// the CIE, which DW_CFA_restore returns to. DWARF expressions are not evaluated: a CFA given as
+// an expression fails the frame, which then falls back to frame pointers.
+fn execute(
+    instructions: &[u8],
+    address: u64,
+    cie: &Cie,
+    mut location: u64,
+    pc: u64,
+    row: &mut Row,
+    initial: &Row,
+) -> Option<()> {
+    let mut cursor = Cursor::new(instructions, address);
+    let mut remembered: Vec<Row> = Vec::new();
+    let restore = |row: &mut Row, register: u16| match initial.rules.get(&register) {
+        Some(&rule) => {
+            row.rules.insert(register, rule);
+        }
+        None => {
+            row.rules.remove(&register);
+        }
+    };
+
+    while !cursor.at_end() {
+        let opcode = cursor.u8()?;
+        let operand = opcode & 0x3f;
+        let advance = match opcode & 0xc0 {
+            0x40 => Some(operand as u64),
+            0x80 => {
+                let offset = cursor.uleb128()? as i64 * cie.data_alignment;
+                row.rules.insert(operand as u16, Rule::Offset(offset));
+                None
+            }
+            0xc0 => {
+                restore(row, operand as u16);
+                None
+            }
+            _ => match opcode {
+                // DW_CFA_nop
+                0x00 => None,
+                // DW_CFA_set_loc
+                0x01 => {
+                    location = cursor.encoded(cie.fde_encoding, 0)?;
+                    if location > pc {
+                        return Some(());
+                    }
+                    None
+                }
+                // DW_CFA_advance_loc1, 2 and 4
+                0x02 => Some(cursor.u8()? as u64),
+                0x03 => Some(cursor.u16()? as u64),
+                0x04 => Some(cursor.u32()? as u64),
+                // DW_CFA_offset_extended
+                0x05 => {
+                    let register = cursor.uleb128()? as u16;
+                    let offset = cursor.uleb128()? as i64 * cie.data_alignment;
+                    row.rules.insert(register, Rule::Offset(offset));
+                    None
+                }
+                // DW_CFA_restore_extended
+                0x06 => {
+                    restore(row, cursor.uleb128()? as u16);
+                    None
+                }
+                // DW_CFA_undefined
+                0x07 => {
+                    row.rules.insert(cursor.uleb128()? as u16, Rule::Undefined);
+                    None
+                }
+                // DW_CFA_same_value
+                0x08 => {
+                    row.rules.insert(cursor.uleb128()? as u16, Rule::SameValue);
+                    None
+                }
+                // DW_CFA_register
+                0x09 => {
+                    let register = cursor.uleb128()? as u16;
+                    let other = cursor.uleb128()? as u16;
+                    row.rules.insert(register, Rule::Register(other));
+                    None
+                }
+                // DW_CFA_remember_state
+                0x0a => {
+                    if remembered.len() >= MAX_REMEMBERED_STATES {
+                        return None;
+                    }
+                    remembered.push(row.clone());
+                    None
+                }
+                // DW_CFA_restore_state
+                0x0b => {
+                    *row = remembered.pop()?;
+                    None
+                }
+                // DW_CFA_def_cfa
+                0x0c => {
+                    let register = cursor.uleb128()? as u16;
+                    let offset = cursor.uleb128()? as i64;
+                    row.cfa = Some((register, offset));
+                    None
+                }
+                // DW_CFA_def_cfa_register
+                0x0d => {
+                    let register = cursor.uleb128()? as u16;
+                    row.cfa = Some((register, row.cfa.map_or(0, |(_, offset)| offset)));
+                    None
+                }
+                // DW_CFA_def_cfa_offset
+                0x0e => {
+                    let offset = cursor.uleb128()? as i64;
+                    row.cfa = Some((row.cfa?.0, offset));
+                    None
+                }
+                // DW_CFA_offset_extended_sf
+                0x11 => {
+                    let register = cursor.uleb128()? as u16;
+                    let offset = cursor.sleb128()? * cie.data_alignment;
+                    row.rules.insert(register, Rule::Offset(offset));
+                    None
+                }
+                // DW_CFA_def_cfa_sf
+                0x12 => {
+                    let register = cursor.uleb128()? as u16;
+                    let offset = cursor.sleb128()? * cie.data_alignment;
+                    row.cfa = Some((register, offset));
+                    None
+                }
+                // DW_CFA_def_cfa_offset_sf
+                0x13 => {
+                    let offset = cursor.sleb128()? * cie.data_alignment;
+                    row.cfa = Some((row.cfa?.0, offset));
+                    None
+                }
+                // DW_CFA_val_offset and DW_CFA_val_offset_sf
+                0x14 => {
+                    let register = cursor.uleb128()? as u16;
+                    let offset = cursor.uleb128()? as i64 * cie.data_alignment;
+                    row.rules.insert(register, Rule::ValOffset(offset));
+                    None
+                }
+                0x15 => {
+                    let register = cursor.uleb128()? as u16;
+                    let offset = cursor.sleb128()? * cie.data_alignment;
+                    row.rules.insert(register, Rule::ValOffset(offset));
+                    None
+                }
+                // DW_CFA_expression and DW_CFA_val_expression; the register is lost
+                0x10 | 0x16 => {
+                    let register = cursor.uleb128()? as u16;
+                    let length = cursor.uleb128()? as usize;
+                    cursor.bytes(length)?;
+                    row.rules.insert(register, Rule::Undefined);
+                    None
+                }
+                // DW_CFA_AARCH64_negate_ra_state; return addresses are stripped regardless
+                0x2d => None,
+                // DW_CFA_GNU_args_size
+                0x2e => {
+                    cursor.uleb128()?;
+                    None
+                }
+                // DW_CFA_GNU_negative_offset_extended
+                0x2f => {
+                    let register = cursor.uleb128()? as u16;
+                    let offset = cursor.uleb128()? as i64 * cie.data_alignment;
+                    row.rules.insert(register, Rule::Offset(-offset));
+                    None
+                }
+                // DW_CFA_def_cfa_expression and anything unknown
+                _ => return None,
+            },
+        };
+        if let Some(delta) = advance {
+            location = location.wrapping_add(delta.wrapping_mul(cie.code_alignment));
+            if location > pc {
+                return Some(());
+            }
+        }
+    }
+    Some(())
+}
+
+// Return addresses point after the call, which may be past the end of a noreturn caller, so
+// callers are looked up by the address before
+fn step_cfi(target: &dyn Target, arch: Arch, frame: &Frame, is_caller: bool) -> Option<Step> {
+    let layout = layout(arch);
+    let lookup = if is_caller {
+        frame.pc.wrapping_sub(1)
+    } else {
+        frame.pc
+    };
+    let table = target.unwind_table(lookup)?;
+    let fde = parse_fde(target, table.find(lookup)?)?;
+    if lookup < fde.pc_begin || lookup >= fde.pc_end {
+        return None;
+    }
+
+    let mut row = Row::default();
+    execute(
+        &fde.cie.instructions,
+        fde.cie.instructions_address,
+        &fde.cie,
+        fde.pc_begin,
+        u64::MAX,
+        &mut row,
+        &Row::default(),
+    )?;
+    let initial = row.clone();
+    execute(
+        &fde.instructions,
+        fde.instructions_address,
+        &fde.cie,
+        fde.pc_begin,
+        lookup,
+        &mut row,
+        &initial,
+    )?;
+
+    let (cfa_register, cfa_offset) = row.cfa?;
+    let cfa = frame
+        .registers
+        .get(&cfa_register)?
+        .wrapping_add(cfa_offset as u64);
+    let mut registers = frame.registers.clone();
+    for (&register, &rule) in &row.rules {
+        match rule {
+            Rule::Undefined => {
+                registers.remove(&register);
+            }
+            Rule::SameValue => {}
+            Rule::Offset(offset) => {
+                let value = target.read_u64(cfa.wrapping_add(offset as u64))?;
+                registers.insert(register, value);
+            }
+            Rule::ValOffset(offset) => {
+                registers.insert(register, cfa.wrapping_add(offset as u64));
+            }
+            Rule::Register(other) => match frame.registers.get(&other) {
+                Some(&value) => {
+                    registers.insert(register, value);
+                }
+                None => {
+                    registers.remove(&register);
+                }
+            },
+        }
+    }
+    if row.rules.get(&fde.cie.return_address) == Some(&Rule::Undefined) {
+        return Some(Step::Outermost);
+    }
+    let return_address = *registers.get(&fde.cie.return_address)?;
+    registers.insert(layout.sp, cfa);
+    Some(Step::Caller(Frame {
+        pc: strip_return_address(arch, return_address),
+        registers,
+    }))
+}
+
+// Frame records are [saved frame pointer, return address] on both architectures. A function
+// stopped before its prologue has run is skipped over, as its caller's record is still current.
+fn step_frame_pointer(target: &dyn Target, arch: Arch, frame: &Frame) -> Option<Step> {
+    let layout = layout(arch);
+    let fp = *frame.registers.get(&layout.fp)?;
+    let sp = frame.registers.get(&layout.sp).copied().unwrap_or(0);
+    if fp == 0 {
+        return Some(Step::Outermost);
+    }
+    if fp % 8 != 0 || fp < sp {
+        return None;
+    }
+    let caller_fp = target.read_u64(fp)?;
+    let return_address = target.read_u64(fp.wrapping_add(8))?;
+    let mut registers = frame.registers.clone();
+    registers.insert(layout.fp, caller_fp);
+    registers.insert(layout.sp, fp.wrapping_add(16));
+    registers.insert(layout.return_address, return_address);
+    Some(Step::Caller(Frame {
+        pc: strip_return_address(arch, return_address),
+        registers,
+    }))
+}
+
+// Frames innermost first, each with how it was recovered. Walking stops at the outermost frame,
+// a null return address, or a stack pointer that does not move up the stack.
+fn unwind(
+    target: &dyn Target,
+    arch: Arch,
+    context: Frame,
+    max_frames: usize,
+    method: Method,
+) -> Vec<(Frame, &'static str)> {
+    let layout = layout(arch);
+    let mut frames = vec![(context, "context")];
+    while frames.len() < max_frames {
+        let frame = &frames.last().unwrap().0;
+        let is_caller = frames.len() > 1;
+        let step = match method {
+            Method::Auto => step_cfi(target, arch, frame, is_caller)
+                .map(|step| (step, "cfi"))
+                .or_else(|| {
+                    step_frame_pointer(target, arch, frame).map(|step| (step, "frame_pointer"))
+                }),
+            Method::FramePointer => {
+                step_frame_pointer(target, arch, frame).map(|step| (step, "frame_pointer"))
+            }
+        };
+        let Some((Step::Caller(caller), how)) = step else {
+            break;
+        };
+        let sp = frame.registers.get(&layout.sp).copied().unwrap_or(0);
+        let caller_sp = caller.registers.get(&layout.sp).copied().unwrap_or(0);
+        if caller.pc == 0 || caller_sp <= sp {
+            break;
+        }
+        frames.push((caller, how));
+    }
+    frames
+}
+
+// Call stack from a register snapshot named as in exception events, innermost frame first, each
+// frame with its module+offset and nearest symbol
+pub fn backtrace(
+    pid: i32,
+    registers: &Value,
+    max_frames: Option<usize>,
+    method: Method,
+) -> Result<Value, String> {
+    let pc = registers["pc"]
+        .as_str()
+        .and_then(|pc| u64::from_str_radix(pc.trim_start_matches("0x"), 16).ok())
+        .ok_or("No pc in register snapshot")?;
+    let modules = native_bridge::enum_modules(pid)?;
+    let arch = disasm::detect_arch_in(pid, &modules, pc);
+    if !matches!(arch, Arch::X86_64 | Arch::Arm64) {
+        return Err(format!("Unwinding is not supported for {}", arch.name()));
+    }
+    let layout = layout(arch);
+    let values: BTreeMap<u16, u64> = layout
+        .names
+        .iter()
+        .enumerate()
+        .filter_map(|(number, name)| {
+            Some((number as u16, watch::register_value(arch, registers, name)?))
+        })
+        .collect();
+
+    let target = ProcessTarget { pid, modules };
+    let max_frames = max_frames
+        .unwrap_or(DEFAULT_MAX_FRAMES)
+        .clamp(1, MAX_FRAMES);
+    let frames = unwind(
+        &target,
+        arch,
+        Frame {
+            pc,
+            registers: values,
+        },
+        max_frames,
+        method,
+    );
+
+    let pcs: Vec<u64> = frames.iter().map(|(frame, _)| frame.pc).collect();
+    let infos = symbols::describe_addresses(pid, &pcs).unwrap_or_default();
+    Ok(Value::Array(
+        frames
+            .iter()
+            .enumerate()
+            .map(|(index, (frame, how))| {
+                let mut entry = json!({
+                    "index": index,
+                    "pc": frame.pc,
+                    "sp": frame.registers.get(&layout.sp),
+                    "method": how
+                });
+                if let Some(info) = infos.get(index) {
+                    entry["address_info"] = info.clone();
+                }
+                entry
+            })
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HDR: u64 = 0x5000;
+    const EH_FRAME: u64 = 0x5100;
+    const FUNCTION: u64 = 0x1000;
+    const FUNCTION_SIZE: u64 = 0x20;
+
+    // Code at 0x1000 with unwind tables, code elsewhere without, and a stack
+    struct FakeTarget {
+        memory: BTreeMap<u64, Vec<u8>>,
+    }
+
+    impl Target for FakeTarget {
+        fn read(&self, address: u64, size: usize) -> Option<Vec<u8>> {
+            let (start, bytes) = self.memory.range(..=address).next_back()?;
+            let offset = (address - start) as usize;
+            bytes.get(offset..offset + size).map(|b| b.to_vec())
+        }
+
+        fn unwind_table(&self, pc: u64) -> Option<Arc<UnwindTable>> {
+            if (FUNCTION..FUNCTION + FUNCTION_SIZE).contains(&pc) {
+                load_table(self, HDR).map(Arc::new)
+            } else {
+                None
+            }
+        }
+    }
+
+    fn record(body: Vec<u8>) -> Vec<u8> {
+        let mut bytes = (body.len() as u32).to_le_bytes().to_vec();
+        bytes.extend(body);
+        bytes
+    }
+
+    // push rbp; mov rbp, rsp; ... as GCC describes it
+    fn fake_target(stack: &[(u64, u64)]) -> FakeTarget {
+        let mut cie = vec![0, 0, 0, 0, 1, b'z', b'R', 0, 1, 0x78, 16, 1, 0x1b];
+        cie.extend([0x0c, 7, 8, 0x90, 1]);
+        let cie = record(cie);
+
+        let fde_address = EH_FRAME + cie.len() as u64;
+        let field = fde_address + 4;
+        let mut fde = ((field - EH_FRAME) as u32).to_le_bytes().to_vec();
+        fde.extend(((FUNCTION as i64 - (field + 4) as i64) as i32).to_le_bytes());
+        fde.extend((FUNCTION_SIZE as u32).to_le_bytes());
+        fde.push(0);
+        fde.extend([0x41, 0x0e, 16, 0x86, 2, 0x43, 0x0d, 6]);
+        let mut eh_frame = cie;
+        eh_frame.extend(record(fde));
+
+        let mut hdr = vec![1, 0x1b, 0x03, 0x3b];
+        hdr.extend(((EH_FRAME as i64 - (HDR + 4) as i64) as i32).to_le_bytes());
+        hdr.extend(1u32.to_le_bytes());
+        hdr.extend(((FUNCTION as i64 - HDR as i64) as i32).to_le_bytes());
+        hdr.extend(((fde_address - HDR) as i32).to_le_bytes());
+
+        let mut stack_bytes = vec![0u8; 0x100];
+        for &(address, value) in stack {
+            let offset = (address - 0x7000) as usize;
+            stack_bytes[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        }
+        FakeTarget {
+            memory: BTreeMap::from([(HDR, hdr), (EH_FRAME, eh_frame), (0x7000, stack_bytes)]),
+        }
+    }
+
+    fn context(pc: u64, rsp: u64, rbp: u64) -> Frame {
+        Frame {
+            pc,
+            registers: BTreeMap::from([(6, rbp), (7, rsp)]),
+        }
+    }
+
+    fn walk(target: &FakeTarget, frame: Frame, method: Method) -> Vec<(u64, &'static str)> {
+        unwind(target, Arch::X86_64, frame, 16, method)
+            .into_iter()
+            .map(|(frame, how)| (frame.pc, how))
+            .collect()
+    }
+
+    #[test]
+    fn unwinds_with_cfi_and_falls_back_to_frame_pointers() {
+        // Inside the body: frame record at rbp, the caller has no unwind table
+        let target = fake_target(&[(0x7010, 0x7040), (0x7018, 0x2005), (0x7048, 0x3000)]);
+        assert_eq!(
+            walk(&target, context(0x1010, 0x7000, 0x7010), Method::Auto),
+            vec![
+                (0x1010, "context"),
+                (0x2005, "cfi"),
+                (0x3000, "frame_pointer")
+            ]
+        );
+
+        // At the entry nothing is pushed yet, which only the CFI knows
+        let target = fake_target(&[(0x7000, 0x2005), (0x7040, 0), (0x7048, 0x3000)]);
+        assert_eq!(
+            walk(&target, context(0x1000, 0x7000, 0x7040), Method::Auto),
+            vec![
+                (0x1000, "context"),
+                (0x2005, "cfi"),
+                (0x3000, "frame_pointer")
+            ]
+        );
+        assert_eq!(
+            walk(
+                &target,
+                context(0x1000, 0x7000, 0x7040),
+                Method::FramePointer
+            ),
+            vec![(0x1000, "context"), (0x3000, "frame_pointer")]
+        );
+    }
+
+    #[test]
+    fn stops_on_corrupt_frame_records() {
+        // A saved frame pointer that points down the stack would loop
+        let target = fake_target(&[(0x7040, 0x7010), (0x7048, 0x3000), (0x7018, 0x4000)]);
+        assert_eq!(
+            walk(
+                &target,
+                context(0x2000, 0x7000, 0x7040),
+                Method::FramePointer
+            ),
+            vec![(0x2000, "context"), (0x3000, "frame_pointer")]
+        );
+    }
+
+    #[test]
+    fn decodes_leb128() {
+        let bytes = [0xe5, 0x8e, 0x26, 0x7f, 0x80, 0x7f];
+        let mut cursor = Cursor::new(&bytes, 0);
+        assert_eq!(cursor.uleb128(), Some(624485));
+        assert_eq!(cursor.sleb128(), Some(-1));
+        assert_eq!(cursor.sleb128(), Some(-128));
+        assert!(cursor.at_end());
+    }
+}
@@ -1,12 +1,11 @@
+use crate::expression::{self, EvalContext};
 use crate::native_bridge;
-use capstone::prelude::*;
+use crate::signature;
+use crate::symbols;
 use libc::{self};
-use regex::Regex;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::num::ParseIntError;
+use serde_json::{json, Value};
 use std::path::Path;
-use std::slice;
 use std::str;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -18,19 +17,6 @@ pub struct FileItem {
     children: Option<Vec<FileItem>>,
 }
 
-pub fn read_memory_64(pid: i32, address: u64) -> Result<u64, String> {
-    let mut buffer = [0u8; 8];
-    native_bridge::read_process_memory(pid, address as *mut libc::c_void, 8, &mut buffer).map_err(
-        |e| {
-            format!(
-                "Failed to read 64-bit memory at address {:#x}: {}",
-                address, e
-            )
-        },
-    )?;
-    Ok(u64::from_le_bytes(buffer))
-}
-
 pub fn _read_memory_32(pid: i32, address: u32) -> Result<u32, String> {
     let mut buffer = [0u8; 4];
     native_bridge::read_process_memory(pid, address as *mut libc::c_void, 4, &mut buffer).map_err(
@@ -44,150 +30,128 @@ pub fn _read_memory_32(pid: i32, address: u32) -> Result<u32, String> {
     Ok(u32::from_le_bytes(buffer))
 }
 
-pub fn _evaluate_expression(expr: &str) -> Result<isize, String> {
-    let re = Regex::new(r"(\d+)\s*([+\-*/])\s*(\d+)").unwrap();
-    if let Some(caps) = re.captures(expr) {
-        let a: isize = caps[1]
-            .parse()
-            .map_err(|_| "Invalid number in expression".to_string())?;
-        let b: isize = caps[3]
-            .parse()
-            .map_err(|_| "Invalid number in expression".to_string())?;
-        match &caps[2] {
-            "+" => Ok(a + b),
-            "-" => Ok(a - b),
-            "*" => Ok(a * b),
-            "/" => Ok(a / b),
-            _ => Err("Unsupported operation".to_string()),
-        }
-    } else {
-        expr.parse().map_err(|_| "Invalid expression".to_string())
-    }
-}
+// Shell-style wildcard match supporting `*` (any run of characters) and `?` (one character)
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
 
-pub fn resolve_nested_address(
-    pid: i32,
-    nested_addr: &str,
-    modules: &[serde_json::Value],
-) -> Result<u64, String> {
-    let re = Regex::new(r"(\[)|(\])|([^\[\]]+)").map_err(|e| format!("Regex error: {}", e))?;
-    let mut stack = Vec::new();
-    let mut current_expr = String::new();
-
-    for cap in re.captures_iter(nested_addr) {
-        if cap.get(1).is_some() {
-            if !current_expr.is_empty() {
-                stack.push(current_expr);
-                current_expr = String::new();
-            }
-            current_expr.push('[');
-        } else if cap.get(2).is_some() {
-            if !current_expr.is_empty() {
-                let inner_value = resolve_single_level_address(&current_expr, modules)?;
-                let memory_value = read_memory_64(pid, inner_value)?;
-                if let Some(mut prev_expr) = stack.pop() {
-                    prev_expr.push_str(&format!("0x{:X}", memory_value));
-                    current_expr = prev_expr;
-                } else {
-                    current_expr = format!("0x{:X}", memory_value);
-                }
-            }
-            current_expr.push(']');
-        } else if let Some(m) = cap.get(3) {
-            current_expr.push_str(m.as_str());
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
         }
     }
 
-    resolve_single_level_address(&current_expr, modules)
+    pattern[p..].iter().all(|&c| c == '*')
 }
 
-pub fn resolve_single_level_address(
-    addr: &str,
-    modules: &[serde_json::Value],
-) -> Result<u64, String> {
-    let resolved_addr = preemptive_module_resolution(addr, modules)?;
-
-    let re = Regex::new(r"(?:([+\-*])?\s*)(0x[\da-fA-F]+|\d+)")
-        .map_err(|e| format!("Regex error: {}", e))?;
-
-    let mut current_address: u64 = 0;
-    let mut first_item = true;
+// File names by which modules are written in expressions
+pub fn module_file_names(modules: &[serde_json::Value]) -> Vec<String> {
+    modules
+        .iter()
+        .filter_map(|module| {
+            let module_name = module["modulename"].as_str()?;
+            Some(
+                Path::new(module_name)
+                    .file_name()?
+                    .to_string_lossy()
+                    .into_owned(),
+            )
+        })
+        .collect()
+}
 
-    for cap in re.captures_iter(&resolved_addr) {
-        let op = cap.get(1).map(|m| m.as_str());
-        let value_str = cap.get(2).unwrap().as_str();
-        let value = parse_number(value_str)?;
+// Resolves module names (by file name), `module!symbol` and `sig:Name`, and reads target memory for address expressions
+pub struct ProcessContext<'a> {
+    pub pid: i32,
+    pub modules: &'a [serde_json::Value],
+}
 
-        if first_item {
-            current_address = value;
-            first_item = false;
-        } else if let Some(operator) = op {
-            match operator {
-                "+" => current_address = current_address.wrapping_add(value),
-                "-" => current_address = current_address.wrapping_sub(value),
-                "*" => current_address = current_address.wrapping_mul(value),
-                _ => return Err(format!("Invalid operation: {}", operator)),
-            }
-        } else {
-            return Err("Expected operator, but none found".to_string());
+impl EvalContext for ProcessContext<'_> {
+    fn resolve_identifier(&self, name: &str) -> Option<u64> {
+        if let Some(signature) = name.strip_prefix("sig:") {
+            return signature::lookup(self.pid, signature);
         }
+        if let Some((module, symbol)) = name.split_once('!') {
+            return symbols::resolve_symbol(self.pid, self.modules, module, symbol);
+        }
+        self.modules.iter().find_map(|module| {
+            let module_name = module["modulename"].as_str()?;
+            let file_name = Path::new(module_name).file_name()?.to_string_lossy();
+            if file_name == name {
+                module["base"].as_u64()
+            } else {
+                None
+            }
+        })
     }
 
-    if first_item {
-        current_address = parse_number(&resolved_addr)?;
+    fn module_names(&self) -> Vec<String> {
+        module_file_names(self.modules)
     }
 
-    Ok(current_address)
-}
-
-fn preemptive_module_resolution(
-    addr: &str,
-    modules: &[serde_json::Value],
-) -> Result<String, String> {
-    let mut resolved = String::from(addr);
-    for module in modules {
-        if let (Some(name), Some(base)) = (module["modulename"].as_str(), module["base"].as_u64()) {
-            let path = Path::new(name);
-            if let Some(file_name) = path.file_name() {
-                let file_name_str = file_name.to_string_lossy();
-                let escaped_name = regex::escape(&file_name_str);
-                let re = Regex::new(&format!(r"\b{}\b", escaped_name))
-                    .map_err(|e| format!("Regex error: {}", e))?;
-
-                resolved = re
-                    .replace_all(&resolved, |caps: &regex::Captures| {
-                        let matched = caps.get(0).unwrap().as_str();
-                        if resolved[caps.get(0).unwrap().end()..].starts_with('.') {
-                            matched.to_string()
-                        } else {
-                            format!("0x{:X}", base)
-                        }
-                    })
-                    .to_string();
-            }
+    fn read_memory(&self, address: u64, width: usize) -> Result<u64, String> {
+        let mut buffer = [0u8; 8];
+        let read = native_bridge::read_process_memory(
+            self.pid,
+            address as *mut libc::c_void,
+            width,
+            &mut buffer[..width],
+        )
+        .map_err(|e| format!("Failed to read memory at address {:#x}: {}", address, e))?;
+        if (read as usize) < width {
+            return Err(format!(
+                "Failed to read memory at address {:#x}: read {} of {} bytes",
+                address, read, width
+            ));
         }
+        Ok(u64::from_le_bytes(buffer))
     }
-    Ok(resolved)
 }
 
-fn parse_number(s: &str) -> Result<u64, String> {
-    let s = s.trim();
-    if s.starts_with("0x") {
-        u64::from_str_radix(&s[2..], 16)
-    } else {
-        s.parse::<u64>()
+// Evaluates an address expression against the modules and thread stacks of `pid`
+pub fn resolve_expression(pid: i32, expression: &str) -> Result<u64, String> {
+    let mut modules = native_bridge::enum_modules(pid)?;
+    // threadstackN resolves to the top of that thread's stack
+    if let Ok(stacks) = native_bridge::enum_thread_stacks(pid) {
+        modules.extend(stacks.iter().map(|stack| {
+            json!({
+                "modulename": stack["name"],
+                "base": stack["stack_top"]
+            })
+        }));
     }
-    .map_err(|e: ParseIntError| format!("Invalid number '{}': {}", s, e))
+    let context = ProcessContext {
+        pid,
+        modules: &modules,
+    };
+    expression::evaluate(expression, &context)
 }
 
-pub fn resolve_symbolic_address(
+// Target of requests that accept either a raw address or an expression; the expression wins
+pub fn resolve_target(
     pid: i32,
-    symbolic_addr: &str,
-    modules: &[serde_json::Value],
-) -> Result<usize, String> {
-    let resolved = resolve_nested_address(pid, symbolic_addr, modules)?;
-    Ok(resolved as usize)
+    address: Option<u64>,
+    expression: Option<&str>,
+) -> Result<u64, String> {
+    match (expression, address) {
+        (Some(expression), _) => resolve_expression(pid, expression),
+        (None, Some(address)) => Ok(address),
+        (None, None) => Err("Either address or expression is required".to_string()),
+    }
 }
+
 pub fn parse_directory_structure(raw_data: &str) -> Vec<FileItem> {
     let mut root_items = Vec::new();
     let mut stack: Vec<*mut FileItem> = Vec::new();
@@ -268,25 +232,49 @@ pub fn get_cache_directory(pid: i32) -> String {
     }
 }
 
-pub fn disassemble(bytecode: *const u8, length: usize, address: u64) -> String {
-    let bytes = unsafe { slice::from_raw_parts(bytecode, length) };
-    let cs = Capstone::new()
-        .arm64()
-        .mode(arch::arm64::ArchMode::Arm)
-        .detail(true)
-        .build()
-        .expect("Failed to create Capstone object");
-
-    let instructions = cs
-        .disasm_all(bytes, address)
-        .expect("Failed to disassemble");
-    let mut result = String::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    for i in instructions.iter() {
-        let mnemonic = i.mnemonic().unwrap_or("");
-        let op_str = i.op_str().unwrap_or("");
-        result.push_str(&format!("{:#x}: {} {}\n", i.address(), mnemonic, op_str));
+    #[test]
+    fn glob_patterns() {
+        let cases = [
+            // Literal patterns match the whole text only
+            ("libc.so.6", "libc.so.6", true),
+            ("libc.so", "libc.so.6", false),
+            ("c.so.6", "libc.so.6", false),
+            // `*` spans any run of characters, including none
+            ("*", "", true),
+            ("*", "libc.so.6", true),
+            ("lib*", "libc.so.6", true),
+            ("*.so", "libc.so.6", false),
+            ("*.so*", "libc.so.6", true),
+            ("lib*.so.*", "libc.so.6", true),
+            ("lib*.so", "lib.so", true),
+            ("*il2cpp*", "/data/app/lib/arm64/libil2cpp.so", true),
+            ("a*b*c", "axxbyyc", true),
+            ("a*b*c", "axxbyy", false),
+            ("**", "anything", true),
+            // `?` is exactly one character
+            ("libc.so.?", "libc.so.6", true),
+            ("libc.so.?", "libc.so.", false),
+            ("libc.so.?", "libc.so.66", false),
+            ("?", "", false),
+            ("lib?*.so", "libm.so", true),
+            // Matching is case sensitive
+            ("LIBC*", "libc.so.6", false),
+            ("libc*", "LIBC.SO.6", false),
+            ("", "", true),
+            ("", "a", false),
+        ];
+        for (pattern, text, expected) in cases {
+            assert_eq!(
+                glob_match(pattern, text),
+                expected,
+                "glob_match({:?}, {:?})",
+                pattern,
+                text
+            );
+        }
     }
-
-    result
 }
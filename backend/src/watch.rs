@@ -0,0 +1,461 @@
+use crate::disasm::{self, Arch};
+use crate::symbols;
+use capstone::arch::arm64::{Arm64Extender, Arm64Shift};
+use capstone::arch::ArchOperand;
+use capstone::prelude::*;
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+// Distinct instructions remembered per watchpoint; hits from any further ones are only counted
+const MAX_ACCESSORS: usize = 4096;
+// Widest single access a watchpoint hit is attributed from
+const MAX_ACCESS_SIZE: u64 = 64;
+
+lazy_static! {
+    static ref SESSIONS: RwLock<BTreeMap<u64, Session>> = RwLock::new(BTreeMap::new());
+}
+
+// "Find out what accesses this address": hits of one watchpoint folded per instruction
+struct Session {
+    size: u64,
+    active: bool,
+    // The watchpoint halts the thread, so its hits are reported as well as folded
+    stop: bool,
+    total_hits: u64,
+    dropped_hits: u64,
+    accessors: BTreeMap<u64, Accessor>,
+}
+
+struct Accessor {
+    count: u64,
+    instruction: String,
+    effective_address: Option<u64>,
+    registers: Value,
+}
+
+impl Session {
+    fn overlaps(&self, address: u64, accessed: u64) -> bool {
+        accessed < address.saturating_add(self.size)
+            && accessed.saturating_add(MAX_ACCESS_SIZE) > address
+    }
+}
+
+fn register_key(arch: Arch, name: &str) -> (String, u64) {
+    const LOW32: u64 = 0xffff_ffff;
+    match arch {
+        Arch::Arm64 => match name {
+            "x29" | "fp" => ("fp".to_string(), u64::MAX),
+            "x30" | "lr" => ("lr".to_string(), u64::MAX),
+            "wsp" => ("sp".to_string(), LOW32),
+            _ if name.starts_with('w') => (format!("x{}", &name[1..]), LOW32),
+            _ => (name.to_string(), u64::MAX),
+        },
+        _ => {
+            if let Some(number) = name.strip_prefix('r').and_then(|n| n.strip_suffix('d')) {
+                (format!("r{}", number), LOW32)
+            } else if let Some(rest) = name.strip_prefix('e') {
+                (format!("r{}", rest), LOW32)
+            } else {
+                (name.to_string(), u64::MAX)
+            }
+        }
+    }
+}
+
+// Value of a register as named by Capstone, taken from a register snapshot sent by the debugger
+pub fn register_value(arch: Arch, registers: &Value, name: &str) -> Option<u64> {
+    if name == "xzr" || name == "wzr" {
+        return Some(0);
+    }
+    let (key, mask) = register_key(arch, name);
+    let value = registers[key.as_str()]
+        .as_str()
+        .or_else(|| match key.as_str() {
+            "fp" => registers["x29"].as_str(),
+            _ => None,
+        })?;
+    u64::from_str_radix(value.trim_start_matches("0x"), 16)
+        .ok()
+        .map(|value| value & mask)
+}
+
+// Address the instruction's memory operand refers to, evaluated with `registers`. On x86 the
+// snapshot is taken after the access, so an instruction that overwrites its own base register
+// evaluates against the new value.
+fn effective_address(
+    cs: &Capstone,
+    arch: Arch,
+    insn: &capstone::Insn,
+    registers: &Value,
+) -> Option<u64> {
+    let detail = cs.insn_detail(insn).ok()?;
+    detail
+        .arch_detail()
+        .operands()
+        .iter()
+        .find_map(|operand| operand_address(cs, arch, insn, operand, registers))
+}
+
+// Address one operand of `insn` refers to if it is a memory operand
+pub fn operand_address(
+    cs: &Capstone,
+    arch: Arch,
+    insn: &capstone::Insn,
+    operand: &ArchOperand,
+    registers: &Value,
+) -> Option<u64> {
+    let next = insn.address() + insn.bytes().len() as u64;
+    let read = |reg: RegId| -> Option<u64> {
+        if reg.0 == 0 {
+            return Some(0);
+        }
+        let name = cs.reg_name(reg)?;
+        match name.as_str() {
+            "rip" => Some(next),
+            "eip" => Some(next & 0xffff_ffff),
+            _ => register_value(arch, registers, &name),
+        }
+    };
+
+    match operand {
+        ArchOperand::X86Operand(op) => match &op.op_type {
+            capstone::arch::x86::X86OperandType::Mem(mem) => {
+                // fs/gs bases are not part of the snapshot
+                if mem.segment().0 != 0 {
+                    return None;
+                }
+                let base = read(mem.base())?;
+                let index = read(mem.index())?;
+                Some(
+                    base.wrapping_add(index.wrapping_mul(mem.scale() as u64))
+                        .wrapping_add(mem.disp() as u64),
+                )
+            }
+            _ => None,
+        },
+        ArchOperand::Arm64Operand(op) => match &op.op_type {
+            capstone::arch::arm64::Arm64OperandType::Mem(mem) => {
+                let base = read(mem.base())?;
+                let mut index = read(mem.index())?;
+                index = match op.ext {
+                    Arm64Extender::ARM64_EXT_UXTW => index & 0xffff_ffff,
+                    Arm64Extender::ARM64_EXT_SXTW => index as u32 as i32 as i64 as u64,
+                    _ => index,
+                };
+                if let Arm64Shift::Lsl(amount) = op.shift {
+                    index <<= amount;
+                }
+                Some(
+                    base.wrapping_add(index)
+                        .wrapping_add(mem.disp() as i64 as u64),
+                )
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn instruction_text(address: u64, insn: &capstone::Insn) -> String {
+    let text = format!(
+        "{:#x}: {} {}",
+        address,
+        insn.mnemonic().unwrap_or(""),
+        insn.op_str().unwrap_or("")
+    );
+    text.trim_end().to_string()
+}
+
+// The instruction ending at `pc`, for x86 where data watchpoints trap after the access. Every
+// length that decodes to exactly `pc` is a candidate; the one whose operand reaches the watched
+// range wins, then the longest one touching memory at all.
+fn instruction_before(
+    cs: &Capstone,
+    arch: Arch,
+    code: &[u8],
+    pc: u64,
+    registers: &Value,
+    watched: impl Fn(u64) -> bool,
+) -> Option<(u64, String, Option<u64>)> {
+    let mut fallback = None;
+    for length in (1..=code.len()).rev() {
+        let start = pc - length as u64;
+        let Ok(instructions) = cs.disasm_count(&code[code.len() - length..], start, 1) else {
+            continue;
+        };
+        let Some(insn) = instructions.iter().next() else {
+            continue;
+        };
+        // lea computes an address without touching it
+        if insn.bytes().len() != length || insn.mnemonic() == Some("lea") {
+            continue;
+        }
+
+        let text = instruction_text(start, insn);
+        let effective = effective_address(cs, arch, insn, registers);
+        match effective {
+            Some(address) if watched(address) => return Some((start, text, effective)),
+            Some(_) if fallback.is_none() => fallback = Some((start, text, effective)),
+            _ => {}
+        }
+    }
+    fallback
+}
+
+fn accessing_instruction(
+    pid: i32,
+    arch: Arch,
+    pc: u64,
+    registers: &Value,
+    watched: impl Fn(u64) -> bool,
+) -> Result<(u64, String, Option<u64>), String> {
+    let cs = arch.capstone()?;
+    match arch {
+        Arch::X86 | Arch::X86_64 => {
+            let size = arch.max_instruction_size() as u64;
+            let code = disasm::read_code(pid, pc - size, size as usize);
+            if code.len() as u64 != size {
+                return Err(format!("Failed to read code before 0x{:x}", pc));
+            }
+            instruction_before(&cs, arch, &code, pc, registers, watched)
+                .ok_or_else(|| format!("No memory access decodes before 0x{:x}", pc))
+        }
+        _ => {
+            let code = disasm::read_code(pid, pc, arch.max_instruction_size());
+            let instructions = cs
+                .disasm_count(&code, pc, 1)
+                .map_err(|e| format!("Failed to disassemble: {}", e))?;
+            let insn = instructions
+                .iter()
+                .next()
+                .ok_or_else(|| format!("Failed to disassemble at 0x{:x}", pc))?;
+            let effective = effective_address(&cs, arch, insn, registers);
+            Ok((pc, instruction_text(pc, insn), effective))
+        }
+    }
+}
+
+// Fails while a watchpoint at `address` still logs into an active session
+pub fn start_session(address: u64, size: u64, stop: bool) -> Result<(), String> {
+    let mut sessions = SESSIONS.write().unwrap();
+    if sessions.get(&address).is_some_and(|session| session.active) {
+        return Err(format!("Address 0x{:x} is already being watched", address));
+    }
+    sessions.insert(
+        address,
+        Session {
+            size,
+            active: true,
+            stop,
+            total_hits: 0,
+            dropped_hits: 0,
+            accessors: BTreeMap::new(),
+        },
+    );
+    Ok(())
+}
+
+// Results stay queryable after the watchpoint is gone, until they are cleared
+pub fn end_session(address: u64) {
+    let mut sessions = SESSIONS.write().unwrap();
+    if let Some(session) = sessions.get_mut(&address) {
+        session.active = false;
+    }
+}
+
+// Folds a watchpoint hit into its session. Returns false when no aggregating watchpoint covers
+// the accessed address, or when the watchpoint halted the thread, in which case the hit is
+// reported raw.
+pub fn record_hit(pid: i32, registers: &Value) -> bool {
+    let parse = |key: &str| {
+        registers[key]
+            .as_str()
+            .and_then(|value| u64::from_str_radix(value.trim_start_matches("0x"), 16).ok())
+    };
+    let (Some(accessed), Some(pc)) = (parse("memory"), parse("pc")) else {
+        return false;
+    };
+
+    let watch_address = {
+        let sessions = SESSIONS.read().unwrap();
+        match sessions
+            .iter()
+            .find(|(address, session)| session.active && session.overlaps(**address, accessed))
+        {
+            Some((address, _)) => *address,
+            None => return false,
+        }
+    };
+
+    let arch = match symbols::cached_layout(pid) {
+        Ok(layout) => layout.arch(pid, pc),
+        Err(_) => disasm::detect_arch(pid, pc),
+    };
+    let watched = |address: u64| {
+        SESSIONS
+            .read()
+            .unwrap()
+            .get(&watch_address)
+            .is_some_and(|session| session.overlaps(watch_address, address))
+    };
+    let (address, instruction, effective_address) =
+        match accessing_instruction(pid, arch, pc, registers, watched) {
+            Ok(result) => result,
+            Err(e) => {
+                log::warn!("Watchpoint hit at 0x{:x} not attributed: {}", pc, e);
+                (pc, String::new(), None)
+            }
+        };
+
+    let mut sessions = SESSIONS.write().unwrap();
+    let Some(session) = sessions.get_mut(&watch_address) else {
+        return false;
+    };
+    session.total_hits += 1;
+    if !session.accessors.contains_key(&address) && session.accessors.len() >= MAX_ACCESSORS {
+        session.dropped_hits += 1;
+        return !session.stop;
+    }
+    let accessor = session.accessors.entry(address).or_insert(Accessor {
+        count: 0,
+        instruction,
+        effective_address,
+        registers: Value::Null,
+    });
+    accessor.count += 1;
+    accessor.effective_address = effective_address;
+    accessor.registers = registers.clone();
+    !session.stop
+}
+
+pub fn list_accesses(pid: Option<i32>, address: Option<u64>) -> Result<Value, String> {
+    let sessions = SESSIONS.read().unwrap();
+    if let Some(address) = address {
+        if !sessions.contains_key(&address) {
+            return Err(format!("No access log for watchpoint 0x{:x}", address));
+        }
+    }
+
+    let mut entries = Vec::new();
+    for (watch_address, session) in sessions.iter() {
+        if address.is_some_and(|address| address != *watch_address) {
+            continue;
+        }
+
+        let mut accessors: Vec<(&u64, &Accessor)> = session.accessors.iter().collect();
+        accessors.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(b.0)));
+        let addresses: Vec<u64> = accessors.iter().map(|(address, _)| **address).collect();
+        let infos = pid
+            .and_then(|pid| symbols::describe_addresses(pid, &addresses).ok())
+            .unwrap_or_default();
+
+        let accessors: Vec<Value> = accessors
+            .iter()
+            .enumerate()
+            .map(|(i, (address, accessor))| {
+                json!({
+                    "address": address,
+                    "count": accessor.count,
+                    "instruction": accessor.instruction,
+                    "effective_address": accessor.effective_address,
+                    "registers": accessor.registers,
+                    "address_info": infos.get(i).cloned().unwrap_or(Value::Null)
+                })
+            })
+            .collect();
+
+        entries.push(json!({
+            "address": watch_address,
+            "size": session.size,
+            "active": session.active,
+            "total_hits": session.total_hits,
+            "dropped_hits": session.dropped_hits,
+            "accessors": accessors
+        }));
+    }
+    Ok(json!({ "watchpoints": entries }))
+}
+
+pub fn clear_accesses(address: u64) -> Result<(), String> {
+    let mut sessions = SESSIONS.write().unwrap();
+    let session = sessions
+        .get(&address)
+        .ok_or_else(|| format!("No access log for watchpoint 0x{:x}", address))?;
+    if session.active {
+        let session = sessions.get_mut(&address).unwrap();
+        session.accessors.clear();
+        session.total_hits = 0;
+        session.dropped_hits = 0;
+    } else {
+        sessions.remove(&address);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn x86_64_instruction_before_pc() {
+        // mov qword ptr [rbx + rcx*8 + 0x10], rax
+        let code = [
+            0x90, 0x90, 0x90, 0x90, 0x90, 0x90, 0x90, 0x90, 0x90, 0x90, 0x48, 0x89, 0x44, 0xcb,
+            0x10,
+        ];
+        let pc = 0x401000 + code.len() as u64;
+        let registers = json!({ "rbx": "0x0000000000600000", "rcx": "0x0000000000000002" });
+        let cs = Arch::X86_64.capstone().unwrap();
+
+        let (address, text, effective) =
+            instruction_before(&cs, Arch::X86_64, &code, pc, &registers, |a| a == 0x600020)
+                .unwrap();
+        assert_eq!(address, 0x40100a);
+        assert_eq!(text, "0x40100a: mov qword ptr [rbx + rcx*8 + 0x10], rax");
+        assert_eq!(effective, Some(0x600020));
+    }
+
+    #[test]
+    fn arm64_effective_address() {
+        let cs = Arch::Arm64.capstone().unwrap();
+        let registers = json!({
+            "x1": "0x0000000000700000",
+            "x2": "0x00000000FFFFFFFF",
+            "fp": "0x0000000000800000"
+        });
+        let evaluate = |bytes: &[u8]| {
+            let instructions = cs.disasm_count(bytes, 0x1000, 1).unwrap();
+            let insn = instructions.iter().next().unwrap();
+            effective_address(&cs, Arch::Arm64, insn, &registers)
+        };
+
+        // ldr x0, [x1, #0x10]
+        assert_eq!(evaluate(&[0x20, 0x08, 0x40, 0xf9]), Some(0x700010));
+        // ldr x0, [x1, w2, sxtw #3]
+        assert_eq!(evaluate(&[0x20, 0xd8, 0x62, 0xf8]), Some(0x700000 - 8));
+        // str w0, [x29, #-4]
+        assert_eq!(evaluate(&[0xa0, 0xc3, 0x1f, 0xb8]), Some(0x800000 - 4));
+    }
+
+    #[test]
+    fn active_session_is_not_replaced() {
+        let address = 0xdead_0000;
+        assert!(start_session(address, 8, false).is_ok());
+        SESSIONS
+            .write()
+            .unwrap()
+            .get_mut(&address)
+            .unwrap()
+            .total_hits = 3;
+        assert!(start_session(address, 8, true).is_err());
+        assert_eq!(SESSIONS.read().unwrap()[&address].total_hits, 3);
+
+        end_session(address);
+        assert!(start_session(address, 8, true).is_ok());
+        assert!(SESSIONS.read().unwrap()[&address].stop);
+        end_session(address);
+        clear_accesses(address).unwrap();
+    }
+}
@@ -0,0 +1,512 @@
+use crate::disasm::{self, Arch, Instruction};
+use crate::native_bridge;
+use crate::ptrscan::region_bounds;
+use crate::request::XrefRequest;
+use crate::symbols;
+use crate::util;
+use capstone::arch::arm64::Arm64OperandType;
+use capstone::arch::ArchOperand;
+use capstone::prelude::*;
+use rayon::prelude::*;
+use serde_json::{json, Value};
+use std::path::Path;
+
+// Linear sweep window, bounds the instruction list Capstone holds at once
+const SWEEP_CHUNK_SIZE: usize = 64 * 1024;
+// Instructions after an ADRP searched for the ADD/LDR/STR that completes the address
+pub const ADRP_PAIR_WINDOW: usize = 8;
+
+struct CodeRegion {
+    start: u64,
+    end: u64,
+    arch: Arch,
+}
+
+struct CodeReference {
+    address: u64,
+    kind: &'static str,
+    instruction: String,
+    bytes: Vec<u8>,
+}
+
+impl CodeReference {
+    fn from_instruction(insn: &Instruction) -> CodeReference {
+        let kind = if insn.is_call {
+            "call"
+        } else if insn.is_jump {
+            "jump"
+        } else if matches!(insn.mnemonic.as_str(), "lea" | "adr" | "adrp") {
+            "address"
+        } else {
+            "memory"
+        };
+        CodeReference {
+            address: insn.address,
+            kind,
+            instruction: insn.text(),
+            bytes: insn.bytes.clone(),
+        }
+    }
+}
+
+fn references_target(insn: &Instruction, target: u64) -> bool {
+    insn.branch_target == Some(target) || insn.memory_target == Some(target)
+}
+
+fn code_regions(pid: i32, request: &XrefRequest) -> Result<Vec<CodeRegion>, String> {
+    let forced_arch = request.arch.as_deref().map(Arch::parse).transpose()?;
+    let modules = native_bridge::enum_modules(pid).unwrap_or_default();
+    let regions = native_bridge::enum_regions(pid)?
+        .iter()
+        .filter(|region| region["protection"].as_str().unwrap_or("").contains('x'))
+        .filter(|region| match &request.module {
+            Some(filter) => {
+                let file_path = region["file_path"].as_str().unwrap_or("");
+                let file_name = Path::new(file_path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                util::glob_match(filter, &file_name)
+            }
+            None => true,
+        })
+        .map(|region| {
+            let (start, end) = region_bounds(region);
+            CodeRegion {
+                start,
+                end,
+                arch: forced_arch.unwrap_or_else(|| disasm::detect_arch_in(pid, &modules, start)),
+            }
+        })
+        .filter(|region| region.end > region.start)
+        .collect();
+    Ok(regions)
+}
+
+// Offsets whose four bytes could encode `target` as a rel32 branch, a RIP-relative
+// displacement followed by up to four immediate bytes, or an absolute disp32
+fn x86_candidates(arch: Arch, code: &[u8], base: u64, target: u64) -> Vec<usize> {
+    (0..code.len().saturating_sub(3))
+        .filter(|&offset| {
+            let value = u32::from_le_bytes(code[offset..offset + 4].try_into().unwrap());
+            let end = base + offset as u64 + 4;
+            if arch == Arch::X86 {
+                return value as u64 == target
+                    || end.wrapping_add(value as u64) as u32 as u64 == target;
+            }
+            let signed = value as i32 as i64 as u64;
+            signed == target || target.wrapping_sub(end.wrapping_add(signed)) <= 4
+        })
+        .collect()
+}
+
+// Linear sweep without detail, re-decoding only the instructions that cover a candidate
+fn scan_x86(region: &CodeRegion, code: &[u8], target: u64) -> Result<Vec<CodeReference>, String> {
+    let candidates = x86_candidates(region.arch, code, region.start, target);
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let detailed = region.arch.capstone()?;
+    let mut sweep = region.arch.capstone()?;
+    sweep
+        .set_detail(false)
+        .map_err(|e| format!("Failed to configure Capstone: {}", e))?;
+
+    let mut results = Vec::new();
+    let mut next_candidate = 0;
+    let mut offset = 0;
+    while offset < code.len() && next_candidate < candidates.len() {
+        let chunk_end = (offset + SWEEP_CHUNK_SIZE).min(code.len());
+        let instructions = sweep
+            .disasm_all(&code[offset..chunk_end], region.start + offset as u64)
+            .map_err(|e| format!("Failed to disassemble: {}", e))?;
+        if instructions.is_empty() {
+            // Undecodable byte, resynchronize on the next one
+            offset += 1;
+            continue;
+        }
+
+        for insn in instructions.iter() {
+            let insn_offset = (insn.address() - region.start) as usize;
+            let insn_end = insn_offset + insn.bytes().len();
+            while next_candidate < candidates.len() && candidates[next_candidate] < insn_offset {
+                next_candidate += 1;
+            }
+            if next_candidate < candidates.len() && candidates[next_candidate] + 4 <= insn_end {
+                let decoded =
+                    disasm::decode_with(&detailed, region.arch, insn.bytes(), insn.address(), 1)?;
+                if let Some(decoded) = decoded.first() {
+                    if references_target(decoded, target) {
+                        results.push(CodeReference::from_instruction(decoded));
+                    }
+                }
+            }
+            offset = insn_end;
+        }
+    }
+    Ok(results)
+}
+
+pub enum PcRelative {
+    Address(u64),
+    Page(u64),
+    Literal { address: u64, is_pointer: bool },
+}
+
+fn sign_extend(value: u64, bits: u32) -> i64 {
+    ((value << (64 - bits)) as i64) >> (64 - bits)
+}
+
+// Decodes the PC-relative field of ADR, ADRP, direct branches and literal loads
+pub fn arm64_pc_relative(word: u32, pc: u64) -> Option<PcRelative> {
+    let w = word as u64;
+    let imm19 = || sign_extend((w >> 5) & 0x7ffff, 19) << 2;
+    let adr_imm = || sign_extend(((w >> 29) & 3) | (((w >> 5) & 0x7ffff) << 2), 21);
+
+    if word & 0x9f00_0000 == 0x9000_0000 {
+        let page = (pc & !0xfff).wrapping_add((adr_imm() << 12) as u64);
+        Some(PcRelative::Page(page))
+    } else if word & 0x9f00_0000 == 0x1000_0000 {
+        Some(PcRelative::Address(pc.wrapping_add(adr_imm() as u64)))
+    } else if word & 0x7c00_0000 == 0x1400_0000 {
+        // B, BL
+        let offset = sign_extend(w & 0x3ff_ffff, 26) << 2;
+        Some(PcRelative::Address(pc.wrapping_add(offset as u64)))
+    } else if word & 0xff00_0010 == 0x5400_0000 || word & 0x7e00_0000 == 0x3400_0000 {
+        // B.cond, CBZ, CBNZ
+        Some(PcRelative::Address(pc.wrapping_add(imm19() as u64)))
+    } else if word & 0x7e00_0000 == 0x3600_0000 {
+        // TBZ, TBNZ
+        let offset = sign_extend((w >> 5) & 0x3fff, 14) << 2;
+        Some(PcRelative::Address(pc.wrapping_add(offset as u64)))
+    } else if word & 0x3b00_0000 == 0x1800_0000 {
+        // LDR (literal); only the 64-bit general purpose form can load a pointer
+        Some(PcRelative::Literal {
+            address: pc.wrapping_add(imm19() as u64),
+            is_pointer: word >> 30 == 1 && word & (1 << 26) == 0,
+        })
+    } else {
+        None
+    }
+}
+
+fn arm64_operands(cs: &Capstone, insn: &capstone::Insn) -> Vec<Arm64OperandType> {
+    cs.insn_detail(insn)
+        .map(|detail| {
+            detail
+                .arch_detail()
+                .operands()
+                .into_iter()
+                .filter_map(|operand| match operand {
+                    ArchOperand::Arm64Operand(op) => Some(op.op_type),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// An ADRP with the instructions that complete its page into full addresses
+pub struct AdrpPair {
+    page: u64,
+    adrp: CodeReference,
+    // Each ADD or load/store consuming the page register, with the address it forms
+    completions: Vec<(u64, CodeReference)>,
+}
+
+impl AdrpPair {
+    // The first address the page is completed to, or the page itself when used alone
+    pub fn first_address(&self) -> u64 {
+        self.completions
+            .first()
+            .map_or(self.page, |(address, _)| *address)
+    }
+
+    // The instruction forming `target`; a page aligned target may be used through the ADRP alone
+    fn reference_to(self, target: u64) -> Option<CodeReference> {
+        let AdrpPair {
+            page,
+            adrp,
+            completions,
+        } = self;
+        completions
+            .into_iter()
+            .find(|(address, _)| *address == target)
+            .map(|(_, reference)| reference)
+            .or_else(|| (page == target).then_some(adrp))
+    }
+}
+
+// Follows the page register of the ADRP at the start of `code` into the ADDs and load/stores
+// that complete the address, until the register is overwritten
+pub fn arm64_adrp_pair(cs: &Capstone, code: &[u8], pc: u64) -> Option<AdrpPair> {
+    let window_end = (4 * (ADRP_PAIR_WINDOW + 1)).min(code.len());
+    let instructions = cs.disasm_all(&code[..window_end], pc).ok()?;
+    let mut iter = instructions.iter();
+    let adrp = iter.next()?;
+    let adrp_text = format!(
+        "{} {}",
+        adrp.mnemonic().unwrap_or(""),
+        adrp.op_str().unwrap_or("")
+    );
+    let (register, page) = match arm64_operands(cs, adrp).as_slice() {
+        [Arm64OperandType::Reg(register), Arm64OperandType::Imm(page)]
+            if adrp.mnemonic() == Some("adrp") =>
+        {
+            (*register, *page as u64)
+        }
+        _ => return None,
+    };
+
+    let mut completions = Vec::new();
+    for insn in iter {
+        let mnemonic = insn.mnemonic().unwrap_or("");
+        let operands = arm64_operands(cs, insn);
+        let resolved = match operands.as_slice() {
+            [Arm64OperandType::Reg(_), Arm64OperandType::Reg(base), Arm64OperandType::Imm(imm)]
+                if mnemonic == "add" && *base == register =>
+            {
+                Some(("address", page.wrapping_add(*imm as u64)))
+            }
+            _ => operands.iter().find_map(|operand| match operand {
+                Arm64OperandType::Mem(mem) if mem.base() == register && mem.index().0 == 0 => {
+                    Some(("memory", page.wrapping_add(mem.disp() as i64 as u64)))
+                }
+                _ => None,
+            }),
+        };
+        if let Some((kind, address)) = resolved {
+            completions.push((
+                address,
+                CodeReference {
+                    address: insn.address(),
+                    kind,
+                    instruction: format!(
+                        "{}; {} {}",
+                        adrp_text,
+                        mnemonic,
+                        insn.op_str().unwrap_or("")
+                    ),
+                    bytes: insn.bytes().to_vec(),
+                },
+            ));
+        }
+        // The pair ends once the page register is overwritten; stores name their source first
+        let overwrites =
+            matches!(operands.first(), Some(Arm64OperandType::Reg(r)) if *r == register);
+        if overwrites && !mnemonic.starts_with("st") {
+            break;
+        }
+    }
+
+    Some(AdrpPair {
+        page,
+        adrp: CodeReference {
+            address: pc,
+            kind: "address",
+            instruction: adrp_text,
+            bytes: adrp.bytes().to_vec(),
+        },
+        completions,
+    })
+}
+
+fn scan_arm64(region: &CodeRegion, code: &[u8], target: u64) -> Result<Vec<CodeReference>, String> {
+    let cs = Arch::Arm64.capstone()?;
+    let mut results = Vec::new();
+
+    for offset in (0..code.len().saturating_sub(3)).step_by(4) {
+        let pc = region.start + offset as u64;
+        let word = u32::from_le_bytes(code[offset..offset + 4].try_into().unwrap());
+        let confirm = |kind: Option<&'static str>| -> Result<Option<CodeReference>, String> {
+            let decoded = disasm::decode_with(&cs, Arch::Arm64, &code[offset..offset + 4], pc, 1)?;
+            Ok(decoded.first().and_then(|insn| {
+                let mut reference = CodeReference::from_instruction(insn);
+                match kind {
+                    Some(kind) => reference.kind = kind,
+                    None if !references_target(insn, target) => return None,
+                    None => {}
+                }
+                Some(reference)
+            }))
+        };
+
+        let reference = match arm64_pc_relative(word, pc) {
+            Some(PcRelative::Page(page)) if page == target & !0xfff => {
+                arm64_adrp_pair(&cs, &code[offset..], pc).and_then(|pair| pair.reference_to(target))
+            }
+            Some(PcRelative::Address(address)) if address == target => confirm(None)?,
+            Some(PcRelative::Literal { address, .. }) if address == target => confirm(None)?,
+            Some(PcRelative::Literal {
+                address,
+                is_pointer: true,
+            }) => {
+                // Literal pool entry holding the target, typically placed right after the function
+                let pool_offset = address.wrapping_sub(region.start) as usize;
+                let value = code
+                    .get(pool_offset..pool_offset.wrapping_add(8))
+                    .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()));
+                if value == Some(target) {
+                    confirm(Some("literal"))?
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        results.extend(reference);
+    }
+    Ok(results)
+}
+
+pub fn find_xrefs(pid: i32, request: &XrefRequest) -> Result<Value, String> {
+    let target = util::resolve_target(pid, request.address, request.expression.as_deref())?;
+    let regions = code_regions(pid, request)?;
+
+    let scanned: Vec<Result<Vec<CodeReference>, String>> = regions
+        .par_iter()
+        .map(|region| {
+            let code = disasm::read_code(pid, region.start, (region.end - region.start) as usize);
+            match region.arch {
+                Arch::X86 | Arch::X86_64 => scan_x86(region, &code, target),
+                Arch::Arm64 => scan_arm64(region, &code, target),
+                // 32-bit ARM code is not scanned
+                Arch::Arm | Arch::Thumb => Ok(Vec::new()),
+            }
+        })
+        .collect();
+
+    let mut references = Vec::new();
+    for result in scanned {
+        references.extend(result?);
+    }
+    references.sort_by_key(|reference| reference.address);
+    let truncated = references.len() > request.limit;
+    references.truncate(request.limit);
+
+    let addresses: Vec<u64> = references.iter().map(|r| r.address).collect();
+    let infos = symbols::describe_addresses(pid, &addresses).unwrap_or_default();
+    let results: Vec<Value> = references
+        .iter()
+        .zip(infos.iter().map(Some).chain(std::iter::repeat(None)))
+        .map(|(reference, info)| {
+            let info = info.cloned().unwrap_or(Value::Null);
+            json!({
+                "address": reference.address,
+                "kind": reference.kind,
+                "instruction": reference.instruction,
+                "bytes": hex::encode(&reference.bytes),
+                "module": info["module"],
+                "offset": info["offset"],
+                "expression": info["expression"],
+                "symbol_expression": info["symbol_expression"]
+            })
+        })
+        .collect();
+
+    Ok(json!({
+        "target": target,
+        "references": results,
+        "truncated": truncated,
+        "scanned_regions": regions.len(),
+        "scanned_bytes": regions.iter().map(|r| r.end - r.start).sum::<u64>()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arm64_region(words: &[u32], pool: u64) -> (CodeRegion, Vec<u8>) {
+        let mut code: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        code.extend_from_slice(&pool.to_le_bytes());
+        let region = CodeRegion {
+            start: 0x400000,
+            end: 0x400000 + code.len() as u64,
+            arch: Arch::Arm64,
+        };
+        (region, code)
+    }
+
+    fn scan(region: &CodeRegion, code: &[u8], target: u64) -> Vec<(u64, &'static str)> {
+        scan_arm64(region, code, target)
+            .unwrap()
+            .iter()
+            .map(|reference| (reference.address, reference.kind))
+            .collect()
+    }
+
+    #[test]
+    fn arm64_adrp_pairs_and_literal_pool() {
+        // adrp x0, 0x410000; add x0, x0, #0x40; ldr x1, [x0, #8]; adrp x2, 0x410000;
+        // ldr x2, [x2, #0x20]; ldr x3, =pool; ret; .word 0; pool: .quad 0x410040
+        let words = [
+            0x9000_0080,
+            0x9101_0000,
+            0xf940_0401,
+            0x9000_0082,
+            0xf940_1042,
+            0x5800_0063,
+            0xd65f_03c0,
+            0,
+        ];
+        let (region, code) = arm64_region(&words, 0x410040);
+
+        assert_eq!(
+            scan(&region, &code, 0x410040),
+            vec![(0x400004, "address"), (0x400014, "literal")]
+        );
+        assert_eq!(scan(&region, &code, 0x410020), vec![(0x400010, "memory")]);
+        assert!(scan(&region, &code, 0x410048).is_empty());
+    }
+
+    #[test]
+    fn adrp_pair_first_address() {
+        let cs = Arch::Arm64.capstone().unwrap();
+        let first_address = |words: &[u32]| {
+            let code: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+            arm64_adrp_pair(&cs, &code, 0).map(|pair| pair.first_address())
+        };
+        // adrp x0, #0x10000; add x0, x0, #0x40
+        assert_eq!(first_address(&[0x9000_0080, 0x9101_0000]), Some(0x10040));
+        // adrp x2, #0x10000; str x1, [x3]; ldr x2, [x2, #0x20]
+        assert_eq!(
+            first_address(&[0x9000_0082, 0xf900_0061, 0xf940_1042]),
+            Some(0x10020)
+        );
+        // adrp x0, #0x10000; mov x0, x1; add x1, x0, #0x40
+        assert_eq!(
+            first_address(&[0x9000_0080, 0xaa01_03e0, 0x9101_0001]),
+            Some(0x10000)
+        );
+        assert_eq!(first_address(&[0x9101_0000]), None);
+    }
+
+    #[test]
+    fn x86_64_rip_relative() {
+        // lea rax, [rip + 0x1000]; mov ecx, dword ptr [rip + 0xffa]; call 0x401000; ret
+        let code = [
+            0x48, 0x8d, 0x05, 0x00, 0x10, 0x00, 0x00, 0x8b, 0x0d, 0xfa, 0x0f, 0x00, 0x00, 0xe8,
+            0xee, 0x0f, 0x00, 0x00, 0xc3,
+        ];
+        let region = CodeRegion {
+            start: 0x400000,
+            end: 0x400000 + code.len() as u64,
+            arch: Arch::X86_64,
+        };
+        let kinds = |target| -> Vec<(u64, &'static str)> {
+            scan_x86(&region, &code, target)
+                .unwrap()
+                .iter()
+                .map(|reference| (reference.address, reference.kind))
+                .collect()
+        };
+
+        assert_eq!(
+            kinds(0x401007),
+            vec![(0x400000, "address"), (0x400007, "memory")]
+        );
+        assert_eq!(kinds(0x401000), vec![(0x40000d, "call")]);
+    }
+}